@@ -12,6 +12,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "anki/generic.proto",
         "anki/notetypes.proto",
         "anki/decks.proto",
+        "anki/import_export.proto",
     ];
 
     for f in &files {
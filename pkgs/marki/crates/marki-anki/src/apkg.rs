@@ -0,0 +1,195 @@
+//! Read-only access to `.apkg` deck packages.
+//!
+//! A package is a zip of a collection plus its media. Anki 2.1.50+ writes
+//! `collection.anki21b` (a zstd-compressed v18 collection), a
+//! zstd-compressed protobuf index named `media`, and each media file
+//! zstd-compressed under its index (`0`, `1`, …). Older exports carry a
+//! v11 `collection.anki2`/`collection.anki21` with a JSON media map; that
+//! schema predates the one this crate reads, so such packages are refused
+//! with a hint to re-export rather than half-imported.
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Zip entry holding the modern, zstd-compressed collection.
+const MODERN_COLLECTION: &str = "collection.anki21b";
+/// Zip entries used by pre-2.1.50 exports.
+const LEGACY_COLLECTIONS: &[&str] = &["collection.anki21", "collection.anki2"];
+/// Zip entry holding the media index.
+const MEDIA_INDEX: &str = "media";
+
+/// An opened package whose collection has been extracted to disk.
+pub struct Package {
+    archive: zip::ZipArchive<File>,
+    collection: PathBuf,
+    /// Media filename -> zip entry name.
+    media: HashMap<String, String>,
+}
+
+impl Package {
+    /// Open the package at `path` and decompress its collection into
+    /// `work_dir` (created if missing), ready for
+    /// [`Collection::open`](crate::Collection::open).
+    pub fn open(path: &Path, work_dir: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let mut archive = zip::ZipArchive::new(file)
+            .with_context(|| format!("{} is not a zip archive", path.display()))?;
+
+        if archive.index_for_name(MODERN_COLLECTION).is_none() {
+            if LEGACY_COLLECTIONS.iter().any(|n| archive.index_for_name(n).is_some()) {
+                bail!(
+                    "{} is a legacy package (collection schema v11); re-export it from \
+                     Anki 2.1.50 or newer with \"Support older Anki versions\" unticked",
+                    path.display()
+                );
+            }
+            bail!("{} contains no Anki collection", path.display());
+        }
+
+        std::fs::create_dir_all(work_dir)
+            .with_context(|| format!("create {}", work_dir.display()))?;
+        let collection = work_dir.join("collection.anki2");
+        {
+            let entry = archive.by_name(MODERN_COLLECTION)?;
+            let mut out = File::create(&collection)
+                .with_context(|| format!("create {}", collection.display()))?;
+            zstd::stream::copy_decode(entry, &mut out)
+                .with_context(|| format!("decompress {MODERN_COLLECTION}"))?;
+        }
+
+        let media = match archive.index_for_name(MEDIA_INDEX) {
+            Some(_) => {
+                let compressed = read_entry(&mut archive, MEDIA_INDEX)?;
+                let raw = zstd::decode_all(compressed.as_slice())
+                    .context("decompress media index")?;
+                decode_media_index(&raw)?
+            }
+            None => HashMap::new(),
+        };
+
+        Ok(Self { archive, collection, media })
+    }
+
+    /// Path of the extracted collection file.
+    pub fn collection_path(&self) -> &Path {
+        &self.collection
+    }
+
+    /// Bytes of the media file `name`, or `None` when the package does
+    /// not ship it.
+    pub fn read_media(&mut self, name: &str) -> Result<Option<Vec<u8>>> {
+        let Some(entry) = self.media.get(name) else {
+            return Ok(None);
+        };
+        let entry = entry.clone();
+        let compressed = read_entry(&mut self.archive, &entry)?;
+        let bytes = zstd::decode_all(compressed.as_slice())
+            .with_context(|| format!("decompress media {name}"))?;
+        Ok(Some(bytes))
+    }
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<Vec<u8>> {
+    let mut entry = archive
+        .by_name(name)
+        .with_context(|| format!("package entry {name}"))?;
+    let mut buf = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut buf).with_context(|| format!("read package entry {name}"))?;
+    Ok(buf)
+}
+
+/// Decode the protobuf media index into filename -> zip entry name.
+fn decode_media_index(raw: &[u8]) -> Result<HashMap<String, String>> {
+    use crate::proto::import_export::MediaEntries;
+    use prost::Message;
+
+    let entries = MediaEntries::decode(raw).context("decode media index")?;
+    Ok(entries
+        .entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, e)| {
+            let zip_name = e.legacy_zip_filename.map_or(i.to_string(), |n| n.to_string());
+            (e.name, zip_name)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::import_export::{MediaEntries, media_entries::MediaEntry};
+    use prost::Message;
+    use std::io::Write;
+
+    fn write_package(path: &Path, entries: &[(&str, Vec<u8>)]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        let opts = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for (name, bytes) in entries {
+            zip.start_file(*name, opts).unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn zstd(bytes: &[u8]) -> Vec<u8> {
+        zstd::encode_all(bytes, 0).unwrap()
+    }
+
+    #[test]
+    fn modern_package_extracts_collection_and_media() {
+        let dir = std::env::temp_dir().join(format!("marki-apkg-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = MediaEntries {
+            entries: vec![
+                MediaEntry {
+                    name: "flag.png".into(),
+                    size: 3,
+                    sha1: vec![],
+                    legacy_zip_filename: None,
+                },
+                MediaEntry {
+                    name: "hi.mp3".into(),
+                    size: 2,
+                    sha1: vec![],
+                    legacy_zip_filename: Some(7),
+                },
+            ],
+        };
+        let apkg = dir.join("deck.apkg");
+        write_package(
+            &apkg,
+            &[
+                (MODERN_COLLECTION, zstd(b"sqlite bytes")),
+                (MEDIA_INDEX, zstd(&index.encode_to_vec())),
+                ("0", zstd(b"png")),
+                ("7", zstd(b"hi")),
+            ],
+        );
+
+        let mut pkg = Package::open(&apkg, &dir.join("work")).unwrap();
+        assert_eq!(std::fs::read(pkg.collection_path()).unwrap(), b"sqlite bytes");
+        assert_eq!(pkg.read_media("flag.png").unwrap().unwrap(), b"png");
+        assert_eq!(pkg.read_media("hi.mp3").unwrap().unwrap(), b"hi");
+        assert!(pkg.read_media("missing.png").unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn legacy_package_is_refused_with_a_hint() {
+        let dir = std::env::temp_dir().join(format!("marki-apkg-legacy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let apkg = dir.join("old.apkg");
+        write_package(&apkg, &[("collection.anki2", b"v11".to_vec()), ("media", b"{}".to_vec())]);
+
+        let err = Package::open(&apkg, &dir.join("work")).err().unwrap().to_string();
+        assert!(err.contains("legacy package"), "got: {err}");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -10,10 +10,12 @@ use std::cmp::Ordering;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+pub mod apkg;
 pub mod deck;
 pub mod media;
 pub mod notes;
 pub mod notetype;
+pub mod remote;
 
 /// Current wall-clock time in whole seconds since the epoch. Anki stamps
 /// `notes.mod` (and most `mtime_secs` columns) in seconds.
@@ -45,6 +47,9 @@ pub mod proto {
     pub mod decks {
         include!(concat!(env!("OUT_DIR"), "/anki.decks.rs"));
     }
+    pub mod import_export {
+        include!(concat!(env!("OUT_DIR"), "/anki.import_export.rs"));
+    }
 }
 
 /// The only collection schema version we operate on. We refuse anything
@@ -151,6 +156,24 @@ impl Collection {
         Ok(rows)
     }
 
+    /// The CSS of the notetype named `name` (e.g. `marki:basic`), if it
+    /// exists.
+    pub fn notetype_css(&self, name: &str) -> Result<Option<String>> {
+        use crate::proto::notetypes::notetype::Config;
+        use prost::Message;
+
+        let blob: Option<Vec<u8>> = self
+            .db
+            .query_row("SELECT config FROM notetypes WHERE name = ?1", [name], |r| r.get(0))
+            .optional()?;
+        blob.map(|b| {
+            Config::decode(b.as_slice())
+                .map(|c| c.css)
+                .with_context(|| format!("decode notetype {name} config"))
+        })
+        .transpose()
+    }
+
     /// Read every `decks.kind` blob.
     pub fn deck_kinds(&self) -> Result<Vec<Vec<u8>>> {
         let mut stmt = self.db.prepare("SELECT kind FROM decks ORDER BY id")?;
@@ -221,20 +244,28 @@ impl Collection {
             .collect::<rusqlite::Result<Vec<_>>>()?;
 
         let mut card_stmt = self.db.prepare(
-            "SELECT c.id, d.name FROM cards c JOIN decks d ON d.id = c.did \
+            "SELECT c.id, d.name, c.type, c.due, c.ivl FROM cards c JOIN decks d ON d.id = c.did \
              WHERE c.nid = ?1 ORDER BY c.ord",
         )?;
 
         let mut out = Vec::with_capacity(rows.len());
         for (note_id, guid, mid, model_name, tags, flds) in rows {
-            let cards: Vec<(i64, String)> = card_stmt
-                .query_map([note_id], |r| Ok((r.get(0)?, r.get(1)?)))?
+            let cards: Vec<(i64, String, i64, i64, i64)> = card_stmt
+                .query_map([note_id], |r| {
+                    Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?))
+                })?
                 .collect::<rusqlite::Result<Vec<_>>>()?;
             let deck = cards
                 .first()
-                .map(|(_, native)| deck::native_to_human(native))
+                .map(|(_, native, ..)| deck::native_to_human(native))
                 .unwrap_or_default();
-            let card_ids = cards.into_iter().map(|(id, _)| id).collect();
+            let new_position = cards
+                .iter()
+                .filter(|(_, _, kind, ..)| *kind == CARD_TYPE_NEW)
+                .map(|(_, _, _, due, _)| *due)
+                .min();
+            let min_interval = cards.iter().map(|(.., ivl)| *ivl).min();
+            let card_ids = cards.into_iter().map(|(id, ..)| id).collect();
             out.push(RawManagedNote {
                 note_id,
                 guid,
@@ -244,11 +275,96 @@ impl Collection {
                 fields: notes::split_fields(&flds),
                 deck,
                 card_ids,
+                new_position,
+                min_interval,
             });
         }
         Ok(out)
     }
 
+    /// Every note in the collection, for `marki import`: notetype name and
+    /// kind, field names and values, tags, and the human deck of the
+    /// note's first card (empty if cardless). Ordered by note id, i.e.
+    /// creation time.
+    pub fn source_notes(&self) -> Result<Vec<SourceNote>> {
+        use crate::proto::notetypes::notetype::{Config, config::Kind};
+        use prost::Message;
+
+        let mut notetypes: std::collections::HashMap<i64, (String, bool, Vec<String>)> =
+            std::collections::HashMap::new();
+        let mut stmt = self.db.prepare("SELECT id, name, config FROM notetypes")?;
+        let rows = stmt.query_map([], |r| {
+            Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?, r.get::<_, Vec<u8>>(2)?))
+        })?;
+        for row in rows {
+            let (id, name, blob) = row?;
+            let cfg = Config::decode(blob.as_slice())
+                .with_context(|| format!("decode notetype {id} config"))?;
+            notetypes.insert(id, (name, cfg.kind == Kind::Cloze as i32, Vec::new()));
+        }
+        let mut stmt = self.db.prepare("SELECT ntid, name FROM fields ORDER BY ntid, ord")?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (ntid, name) = row?;
+            if let Some((_, _, names)) = notetypes.get_mut(&ntid) {
+                names.push(name);
+            }
+        }
+
+        let mut stmt = self
+            .db
+            .prepare("SELECT id, guid, mid, tags, flds FROM notes ORDER BY id")?;
+        let rows: Vec<(i64, String, i64, String, String)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut deck_stmt = self.db.prepare(
+            "SELECT d.name FROM cards c JOIN decks d ON d.id = c.did \
+             WHERE c.nid = ?1 ORDER BY c.ord LIMIT 1",
+        )?;
+
+        let mut out = Vec::with_capacity(rows.len());
+        for (note_id, guid, mid, tags, flds) in rows {
+            let (notetype, cloze, field_names) = notetypes
+                .get(&mid)
+                .cloned()
+                .with_context(|| format!("note {note_id} has unknown notetype {mid}"))?;
+            let deck = deck_stmt
+                .query_row([note_id], |r| r.get::<_, String>(0))
+                .optional()?
+                .map(|native| deck::native_to_human(&native))
+                .unwrap_or_default();
+            out.push(SourceNote {
+                note_id,
+                guid,
+                notetype,
+                cloze,
+                field_names,
+                fields: notes::split_fields(&flds),
+                tags: tags.split_whitespace().map(String::from).collect(),
+                deck,
+            });
+        }
+        Ok(out)
+    }
+
+    /// How many cards the note with `guid` has, or `None` if there is no
+    /// such note. `marki import` checks this before converting a note, which
+    /// keeps only its first card.
+    pub fn note_card_count(&self, guid: &str) -> Result<Option<usize>> {
+        let found: Option<i64> = self
+            .db
+            .query_row("SELECT id FROM notes WHERE guid = ?1", [guid], |r| r.get(0))
+            .optional()?;
+        let Some(note_id) = found else {
+            return Ok(None);
+        };
+        let n: i64 =
+            self.db.query_row("SELECT count(*) FROM cards WHERE nid = ?1", [note_id], |r| {
+                r.get(0)
+            })?;
+        Ok(Some(n as usize))
+    }
+
     /// Run a batch of mutations inside a single `BEGIN EXCLUSIVE` transaction
     /// in server-USN mode. Every row written by the closure is stamped with
     /// the collection's current `usn`; `col.usn` is incremented exactly once
@@ -291,6 +407,48 @@ impl Collection {
     }
 }
 
+/// `cards.type` of a card that has never been studied.
+const CARD_TYPE_NEW: i64 = 0;
+
+/// Key marki sets in a card's custom data (`cards.data`, `"cd"`) while it
+/// holds the card suspended for a prerequisite. Anki keeps custom data
+/// through edits and syncs, and caps keys at 8 bytes.
+pub const GATE_MARK: &str = "mgate";
+
+/// `cards.data` with [`GATE_MARK`] set or cleared, keeping every other
+/// key. Anki stores custom data as a JSON object serialised into the
+/// `cd` string; it is dropped once empty.
+fn with_gate_mark(data: &str, on: bool) -> String {
+    let mut outer: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(data).unwrap_or_default();
+    let mut custom: serde_json::Map<String, serde_json::Value> = outer
+        .get("cd")
+        .and_then(|v| v.as_str())
+        .and_then(|cd| serde_json::from_str(cd).ok())
+        .unwrap_or_default();
+    if on {
+        custom.insert(GATE_MARK.into(), 1.into());
+    } else {
+        custom.remove(GATE_MARK);
+    }
+    if custom.is_empty() {
+        outer.remove("cd");
+    } else {
+        let cd = serde_json::Value::Object(custom).to_string();
+        outer.insert("cd".into(), cd.into());
+    }
+    serde_json::Value::Object(outer).to_string()
+}
+
+/// Whether `cards.data` carries [`GATE_MARK`].
+fn has_gate_mark(data: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(data)
+        .ok()
+        .and_then(|v| v.get("cd")?.as_str().map(String::from))
+        .and_then(|cd| serde_json::from_str::<serde_json::Value>(&cd).ok())
+        .is_some_and(|cd| cd.get(GATE_MARK).is_some())
+}
+
 /// `graves.type` discriminants (`rslib` `GraveKind`): peers read these to
 /// learn what kind of object was deleted.
 const GRAVE_CARD: i64 = 0;
@@ -312,6 +470,29 @@ pub struct RawManagedNote {
     /// Human `::`-separated deck of the note's first card, empty if cardless.
     pub deck: String,
     pub card_ids: Vec<i64>,
+    /// New-card position (`due`) of the note's unstudied cards, `None` once
+    /// every card has been studied.
+    pub new_position: Option<i64>,
+    /// Smallest review interval (`ivl`, days) across the note's cards;
+    /// 0 while any card is new or learning, `None` if cardless.
+    pub min_interval: Option<i64>,
+}
+
+/// A note read for import, whatever its notetype. See
+/// [`Collection::source_notes`].
+pub struct SourceNote {
+    pub note_id: i64,
+    pub guid: String,
+    /// The notetype name, e.g. `Basic (and reversed card)`.
+    pub notetype: String,
+    /// Whether the notetype is a cloze type.
+    pub cloze: bool,
+    /// Field names in ord order, parallel to `fields`.
+    pub field_names: Vec<String>,
+    pub fields: Vec<String>,
+    pub tags: Vec<String>,
+    /// Human `::`-separated deck of the note's first card, empty if cardless.
+    pub deck: String,
 }
 
 /// Mutation handle scoped to one `transact` batch. Holds the current server
@@ -568,6 +749,27 @@ impl NoteWriter<'_> {
         Ok(())
     }
 
+    /// Move a note's unstudied cards to new-card position `pos`, advancing
+    /// `nextPos` past it so later notes still land behind. Studied cards
+    /// keep their due date; only `type = 0` cards move. Returns the number
+    /// of cards moved.
+    pub fn reposition_new_cards(&mut self, note_id: i64, pos: i64) -> Result<usize> {
+        let n = self
+            .tx
+            .execute(
+                "UPDATE cards SET due=?1, usn=?2, mod=?3 WHERE nid=?4 AND type=?5 AND due != ?1",
+                params![pos, self.usn, now_secs(), note_id, CARD_TYPE_NEW],
+            )
+            .with_context(|| format!("reposition cards of note {note_id}"))?;
+        if n > 0 {
+            if pos >= self.next_position()? {
+                self.set_next_position(pos + 1)?;
+            }
+            self.mutated = true;
+        }
+        Ok(n)
+    }
+
     /// Re-encode an existing note's fields and write them back: normalize,
     /// derive `csum`/`sfld`, join with `0x1f`, bump `mod`, and stamp `usn`.
     /// Scheduling columns and cards are deliberately left untouched -- a field
@@ -657,6 +859,68 @@ impl NoteWriter<'_> {
         Ok(n)
     }
 
+    /// Suspend every active card of a note until its prerequisites are
+    /// learned, marking each card it suspends in the card's custom data
+    /// ([`GATE_MARK`]) so [`Self::release_note_cards`] can tell them from
+    /// cards the user suspended. Returns the number of cards suspended.
+    pub fn gate_note_cards(&mut self, note_id: i64) -> Result<usize> {
+        let cards = self.card_data(note_id, "queue >= 0")?;
+        for (cid, data) in &cards {
+            self.tx
+                .execute(
+                    "UPDATE cards SET queue=-1, data=?1, usn=?2, mod=?3 WHERE id=?4",
+                    params![with_gate_mark(data, true), self.usn, now_secs(), cid],
+                )
+                .with_context(|| format!("gate card {cid} of note {note_id}"))?;
+        }
+        if !cards.is_empty() {
+            self.mutated = true;
+        }
+        Ok(cards.len())
+    }
+
+    /// Unsuspend the cards of a note that [`Self::gate_note_cards`]
+    /// suspended, restoring the queue their type implies as Anki does:
+    /// new and review cards go back to their own queue, (re)learning
+    /// cards to the intraday queue when `due` is a timestamp and the
+    /// day-learn queue otherwise. Cards suspended by hand stay suspended.
+    /// Returns the number of cards unsuspended.
+    pub fn release_note_cards(&mut self, note_id: i64) -> Result<usize> {
+        let cards: Vec<(i64, String)> = self
+            .card_data(note_id, "queue = -1")?
+            .into_iter()
+            .filter(|(_, data)| has_gate_mark(data))
+            .collect();
+        for (cid, data) in &cards {
+            self.tx
+                .execute(
+                    "UPDATE cards SET queue = CASE \
+                       WHEN type IN (0, 2) THEN type \
+                       WHEN due > 1000000000 THEN 1 ELSE 3 END, \
+                     data=?1, usn=?2, mod=?3 WHERE id=?4",
+                    params![with_gate_mark(data, false), self.usn, now_secs(), cid],
+                )
+                .with_context(|| format!("release card {cid} of note {note_id}"))?;
+        }
+        if !cards.is_empty() {
+            self.mutated = true;
+        }
+        Ok(cards.len())
+    }
+
+    /// `(id, data)` of a note's cards matching the SQL condition `filter`.
+    fn card_data(&self, note_id: i64, filter: &str) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self
+            .tx
+            .prepare(&format!("SELECT id, data FROM cards WHERE nid=?1 AND {filter}"))
+            .context("prepare card data query")?;
+        let rows = stmt
+            .query_map([note_id], |r| Ok((r.get(0)?, r.get(1)?)))
+            .with_context(|| format!("read cards of note {note_id}"))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .with_context(|| format!("read cards of note {note_id}"))
+    }
+
     /// Add a single tag to a note if absent, rewriting the tag string in
     /// canonical (sorted, space-wrapped) form and registering it. Used to mark
     /// a quarantined orphan.
@@ -682,6 +946,118 @@ impl NoteWriter<'_> {
         Ok(())
     }
 
+    /// Drop `tag` from a note's tags; a no-op when it is not there. The tag
+    /// stays registered, as Anki leaves unused tags until Check Database.
+    pub fn remove_tag_from_note(&mut self, note_id: i64, tag: &str) -> Result<()> {
+        let current: String = self
+            .tx
+            .query_row("SELECT tags FROM notes WHERE id = ?1", [note_id], |r| r.get(0))
+            .with_context(|| format!("read tags of note {note_id}"))?;
+        let list: Vec<String> = current.split_whitespace().map(String::from).collect();
+        if !list.iter().any(|t| t == tag) {
+            return Ok(());
+        }
+        let kept: Vec<String> = list.into_iter().filter(|t| t != tag).collect();
+        let canon = notes::canonical_tags(&kept);
+        self.tx
+            .execute(
+                "UPDATE notes SET tags=?1, usn=?2, mod=?3 WHERE id=?4",
+                params![canon, self.usn, now_secs(), note_id],
+            )
+            .with_context(|| format!("remove tag {tag:?} from note {note_id}"))?;
+        self.mutated = true;
+        Ok(())
+    }
+
+    /// The id of the note with `guid`, if any.
+    pub fn note_id_by_guid(&self, guid: &str) -> Result<Option<i64>> {
+        Ok(self
+            .tx
+            .query_row("SELECT id FROM notes WHERE guid = ?1", [guid], |r| r.get(0))
+            .optional()?)
+    }
+
+    /// Take over an existing note without touching its notetype, fields or
+    /// cards: give it a new `guid` and replace its tags. Used to adopt
+    /// notes whose notetype marki leaves alone (cloze).
+    pub fn adopt_note(&mut self, note_id: i64, guid: &str, tags: &[String]) -> Result<()> {
+        let tag_str = notes::canonical_tags(tags);
+        self.tx
+            .execute(
+                "UPDATE notes SET guid=?1, tags=?2, mod=?3, usn=?4 WHERE id=?5",
+                params![guid, tag_str, now_secs(), self.usn, note_id],
+            )
+            .with_context(|| format!("adopt note {note_id}"))?;
+        for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            self.register_tag(tag)?;
+        }
+        self.mutated = true;
+        Ok(())
+    }
+
+    /// Move an existing note onto notetype `mid` in place, keeping its id
+    /// and the review history of its first card. The note gets a new
+    /// `guid`, `fields` (encoded as in [`add_note`](Self::add_note)) and
+    /// `tags`. Its lowest-ord card becomes the new notetype's first card;
+    /// every other card is removed with a grave, since its template has no
+    /// counterpart. Returns the number of cards removed.
+    ///
+    /// Changing a note's notetype is a schema change in Anki (rslib's
+    /// `change_notetype` requires a full sync), so this bumps `col.scm`.
+    pub fn convert_note(
+        &mut self,
+        note_id: i64,
+        guid: &str,
+        mid: i64,
+        fields: Vec<String>,
+        sort_field_idx: u32,
+        tags: &[String],
+    ) -> Result<usize> {
+        let (norm, csum, sfld) = notes::prepare_fields(fields, sort_field_idx, true);
+        let flds = notes::join_fields(&norm);
+        let tag_str = notes::canonical_tags(tags);
+        self.tx
+            .execute(
+                "UPDATE notes SET guid=?1, mid=?2, mod=?3, usn=?4, tags=?5, flds=?6, sfld=?7, \
+                 csum=?8 WHERE id=?9",
+                params![guid, mid, now_secs(), self.usn, tag_str, flds, sfld, csum as i64, note_id],
+            )
+            .with_context(|| format!("convert note {note_id}"))?;
+        for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            self.register_tag(tag)?;
+        }
+
+        let cards: Vec<(i64, i64)> = {
+            let mut stmt =
+                self.tx.prepare("SELECT id, ord FROM cards WHERE nid = ?1 ORDER BY ord")?;
+            stmt.query_map([note_id], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let mut removed = 0;
+        for (i, (cid, ord)) in cards.iter().enumerate() {
+            if i == 0 {
+                if *ord != 0 {
+                    self.tx
+                        .execute(
+                            "UPDATE cards SET ord=0, usn=?1, mod=?2 WHERE id=?3",
+                            params![self.usn, now_secs(), cid],
+                        )
+                        .with_context(|| format!("renumber card {cid}"))?;
+                }
+                continue;
+            }
+            self.add_grave(*cid, GRAVE_CARD)?;
+            self.tx
+                .execute("DELETE FROM cards WHERE id = ?1", [cid])
+                .with_context(|| format!("delete card {cid}"))?;
+            removed += 1;
+        }
+
+        self.mutated = true;
+        self.schema_changed = true;
+        Ok(removed)
+    }
+
     /// The notetype id for a name (e.g. `marki:basic`), if it exists.
     pub fn notetype_id_by_name(&self, name: &str) -> Result<Option<i64>> {
         Ok(self
@@ -1570,5 +1946,194 @@ mod tests {
         assert_eq!(col.usn().unwrap(), usn_before + 1);
         // Left at `out` for the Check Database gate.
     }
-}
 
+    #[test]
+    fn convert_note_keeps_first_card_and_adopts_guid() {
+        let Some(src) = fixture() else {
+            eprintln!("skip: fixture copy not present");
+            return;
+        };
+        let out = src.with_file_name("collection.convert.anki2");
+        std::fs::copy(&src, &out).unwrap();
+
+        // A note with more than one card, e.g. "Basic (and reversed card)".
+        let (nid, card_count, first_card): (i64, i64, i64);
+        {
+            let col = Collection::open(&out).unwrap();
+            (nid, card_count, first_card) = col
+                .db
+                .query_row(
+                    "SELECT nid, count(*), \
+                     (SELECT c2.id FROM cards c2 WHERE c2.nid = c.nid ORDER BY c2.ord LIMIT 1) \
+                     FROM cards c GROUP BY nid HAVING count(*) > 1 LIMIT 1",
+                    [],
+                    |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+                )
+                .unwrap();
+            let sources = col.source_notes().unwrap();
+            assert_eq!(sources.len(), 323);
+            let n = sources.iter().find(|n| n.note_id == nid).unwrap();
+            assert_eq!(n.fields.len(), n.field_names.len());
+            assert_eq!(col.note_card_count(&n.guid).unwrap(), Some(card_count as usize));
+            assert_eq!(col.note_card_count("no-such-guid").unwrap(), None);
+        }
+
+        let mut col = Collection::open(&out).unwrap();
+        let removed = col
+            .transact(|w| {
+                let mid = w.ensure_model(&notetype::ModelSpec {
+                    name: "basic".into(),
+                    css: String::new(),
+                    card_names: vec!["Card".into()],
+                })?;
+                let fields = vec!["front".to_string(), "back".to_string()];
+                let tags = vec!["marki".to_string()];
+                let removed = w.convert_note(nid, "adopted-guid", mid, fields, 0, &tags)?;
+                assert_eq!(w.note_id_by_guid("adopted-guid")?, Some(nid));
+                Ok(removed)
+            })
+            .unwrap();
+        assert_eq!(removed as i64, card_count - 1);
+
+        let (cid, ord): (i64, i64) = col
+            .db
+            .query_row("SELECT id, ord FROM cards WHERE nid=?1", [nid], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((cid, ord), (first_card, 0));
+        // Left at `out` for the Check Database gate.
+    }
+
+    #[test]
+    fn reposition_and_suspension_respect_studied_cards() {
+        let path = std::env::temp_dir().join(format!("marki-repos-{}.anki2", std::process::id()));
+        crate::remote::standin::empty_collection(&path);
+        let mut col = Collection::open(&path).unwrap();
+        let (first, second) = col
+            .transact(|w| {
+                let mid = w.ensure_model(&notetype::ModelSpec {
+                    name: "basic".into(),
+                    css: String::new(),
+                    card_names: vec!["Card".into()],
+                })?;
+                let did = w.deck_id_for("course")?;
+                let add = |w: &mut NoteWriter, guid: &str| {
+                    let fields = vec![guid.to_string(), String::new()];
+                    w.add_note(mid, guid, fields, 0, &["marki".into()], did)
+                };
+                Ok((add(w, "one")?, add(w, "two")?))
+            })
+            .unwrap();
+        // The second note has been studied since.
+        col.db
+            .execute("UPDATE cards SET type=2, queue=2, due=400 WHERE nid=?1", [second])
+            .unwrap();
+
+        let moved = col
+            .transact(|w| {
+                Ok(w.reposition_new_cards(first, 7)? + w.reposition_new_cards(second, 8)?)
+            })
+            .unwrap();
+        assert_eq!(moved, 1);
+
+        let managed = col.managed_notes("marki").unwrap();
+        let pos = |nid| managed.iter().find(|n| n.note_id == nid).unwrap().new_position;
+        assert_eq!((pos(first), pos(second)), (Some(7), None));
+        let due: i64 = col
+            .db
+            .query_row("SELECT due FROM cards WHERE nid=?1", [second], |r| r.get(0))
+            .unwrap();
+        assert_eq!(due, 400);
+        // The next note added lands behind the moved one.
+        col.transact(|w| {
+            assert_eq!(w.next_position()?, 8);
+            Ok(())
+        })
+        .unwrap();
+
+        // Gating and releasing restores each card's own queue.
+        let queues = |col: &Collection| -> Vec<i64> {
+            let mut stmt = col.db.prepare("SELECT queue FROM cards ORDER BY nid").unwrap();
+            stmt.query_map([], |r| r.get(0)).unwrap().map(|q| q.unwrap()).collect()
+        };
+        col.transact(|w| {
+            w.gate_note_cards(first)?;
+            w.gate_note_cards(second)?;
+            w.add_tag_to_note(first, "marki::gated")?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(queues(&col), [-1, -1]);
+        col.transact(|w| {
+            assert_eq!(w.release_note_cards(first)? + w.release_note_cards(second)?, 2);
+            w.remove_tag_from_note(first, "marki::gated")?;
+            w.remove_tag_from_note(first, "marki::gated")
+        })
+        .unwrap();
+        assert_eq!(queues(&col), [0, 2]);
+        let data: Vec<String> = {
+            let mut stmt = col.db.prepare("SELECT data FROM cards").unwrap();
+            stmt.query_map([], |r| r.get(0)).unwrap().map(|d| d.unwrap()).collect()
+        };
+        assert!(data.iter().all(|d| d == "{}"), "{data:?}");
+        let managed = col.managed_notes("marki").unwrap();
+        assert!(managed.iter().all(|n| !n.tags.iter().any(|t| t == "marki::gated")));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn release_leaves_hand_suspended_cards_alone() {
+        let path = std::env::temp_dir().join(format!("marki-gate-{}.anki2", std::process::id()));
+        crate::remote::standin::empty_collection(&path);
+        let mut col = Collection::open(&path).unwrap();
+        let nid = col
+            .transact(|w| {
+                let mid = w.ensure_model(&notetype::ModelSpec {
+                    name: "basic".into(),
+                    css: String::new(),
+                    card_names: vec!["Card".into(), "Reverse".into()],
+                })?;
+                let did = w.deck_id_for("course")?;
+                let fields = ["hablar", "to speak", "to speak", "hablar"].map(String::from).to_vec();
+                w.add_note(mid, "hablar-yo", fields, 0, &["marki".into()], did)
+            })
+            .unwrap();
+        // The user suspended the reverse card, with custom data of their own.
+        col.db
+            .execute(
+                "UPDATE cards SET queue=-1, data='{\"cd\":\"{\\\"mine\\\":1}\"}' \
+                 WHERE nid=?1 AND ord=1",
+                [nid],
+            )
+            .unwrap();
+
+        let gated = col.transact(|w| w.gate_note_cards(nid)).unwrap();
+        assert_eq!(gated, 1, "the hand-suspended card is not gated again");
+        let released = col.transact(|w| w.release_note_cards(nid)).unwrap();
+        assert_eq!(released, 1);
+
+        let cards: Vec<(i64, String)> = {
+            let mut stmt = col.db.prepare("SELECT queue, data FROM cards ORDER BY ord").unwrap();
+            stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+                .unwrap()
+                .map(|c| c.unwrap())
+                .collect()
+        };
+        assert_eq!(cards[0], (0, "{}".to_string()));
+        assert_eq!(cards[1].0, -1, "the sibling stays suspended");
+        assert!(cards[1].1.contains("mine") && !has_gate_mark(&cards[1].1));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn gate_mark_keeps_other_custom_data() {
+        let marked = with_gate_mark(r#"{"pos":3,"cd":"{\"mine\":1}"}"#, true);
+        assert!(has_gate_mark(&marked), "{marked}");
+        let cleared = with_gate_mark(&marked, false);
+        assert!(!has_gate_mark(&cleared));
+        assert_eq!(cleared, r#"{"cd":"{\"mine\":1}","pos":3}"#);
+        assert_eq!(with_gate_mark(&with_gate_mark("{}", true), false), "{}");
+        assert!(!has_gate_mark(""));
+    }
+}
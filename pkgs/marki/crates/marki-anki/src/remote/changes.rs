@@ -0,0 +1,319 @@
+//! Reading a snapshot's pending changes in sync-protocol form.
+//!
+//! The snapshot is a server-mode collection, so every row a
+//! [`NoteWriter`](crate::NoteWriter) batch touched carries the usn the
+//! server will assign next. Selecting `usn = <that usn>` therefore yields
+//! exactly what marki changed, which is what a normal sync has to send.
+//! Notes, cards and graves travel as the positional arrays rslib's
+//! `NoteEntry`/`CardEntry` use; notetypes and decks travel in the legacy
+//! "schema 11" JSON shape, converted here from the protobuf blobs.
+
+use anyhow::{Context, Result};
+use prost::Message;
+use rusqlite::{Connection, params};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+use crate::proto::decks::deck::kind_container::Kind as DeckKind;
+use crate::proto::decks::deck::{Common, KindContainer};
+use crate::proto::notetypes::notetype::{Config, field, template};
+use crate::{GRAVE_CARD, GRAVE_NOTE, deck};
+
+/// `graves.type` for a deleted deck.
+const GRAVE_DECK: i64 = 2;
+
+/// Rows per `applyChunk` call, as rslib's client chunks them.
+pub const CHUNK_SIZE: usize = 250;
+
+/// Deletions to send with `start`, grouped by kind.
+pub fn graves(db: &Connection, usn: i64) -> Result<Value> {
+    let mut stmt = db.prepare("SELECT oid, type FROM graves WHERE usn = ?1")?;
+    let rows = stmt
+        .query_map([usn], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let of = |kind| {
+        rows.iter()
+            .filter(|(_, k)| *k == kind)
+            .map(|(oid, _)| *oid)
+            .collect()
+    };
+    let (cards, notes, decks): (Vec<i64>, Vec<i64>, Vec<i64>) =
+        (of(GRAVE_CARD), of(GRAVE_NOTE), of(GRAVE_DECK));
+    Ok(json!({ "cards": cards, "notes": notes, "decks": decks }))
+}
+
+/// The `applyChanges` payload: changed notetypes, decks and tags. Deck
+/// configs and collection config are never written by marki.
+pub fn unchunked(db: &Connection, usn: i64) -> Result<Value> {
+    Ok(json!({
+        "models": notetypes(db, usn)?,
+        "decks": [decks(db, usn)?, []],
+        "tags": tags(db, usn)?,
+    }))
+}
+
+/// Changed notes, then changed cards, as `applyChunk` payloads of at most
+/// [`CHUNK_SIZE`] rows each. The last chunk is marked `done`.
+pub fn chunks(db: &Connection, usn: i64) -> Result<Vec<Value>> {
+    let notes = note_entries(db, usn)?;
+    let cards = card_entries(db, usn)?;
+    let mut out = Vec::new();
+    for batch in notes.chunks(CHUNK_SIZE) {
+        out.push(json!({ "done": false, "revlog": [], "cards": [], "notes": batch }));
+    }
+    for batch in cards.chunks(CHUNK_SIZE) {
+        out.push(json!({ "done": false, "revlog": [], "cards": batch, "notes": [] }));
+    }
+    match out.last_mut() {
+        Some(last) => last["done"] = Value::Bool(true),
+        None => out.push(json!({ "done": true, "revlog": [], "cards": [], "notes": [] })),
+    }
+    Ok(out)
+}
+
+/// The `sanityCheck2` counts the server compares against its own after
+/// merging. Due counts are no longer checked and are sent as zeros.
+pub fn sanity_counts(db: &Connection) -> Result<Value> {
+    let count = |table: &str| -> Result<i64> {
+        let sql = format!("SELECT count(*) FROM {table}");
+        db.query_row(&sql, [], |r| r.get(0)).context(sql)
+    };
+    Ok(json!([
+        [0, 0, 0],
+        count("cards")?,
+        count("notes")?,
+        count("revlog")?,
+        count("graves")?,
+        count("notetypes")?,
+        count("decks")?,
+        count("deck_config")?,
+    ]))
+}
+
+/// Field and template counts of every notetype, by id.
+pub fn shapes(db: &Connection) -> Result<HashMap<i64, (i64, i64)>> {
+    let mut stmt = db.prepare(
+        "SELECT id, (SELECT count(*) FROM fields WHERE ntid = nt.id), \
+         (SELECT count(*) FROM templates WHERE ntid = nt.id) FROM notetypes nt",
+    )?;
+    let rows = stmt
+        .query_map([], |r| Ok((r.get(0)?, (r.get(1)?, r.get(2)?))))?
+        .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+    Ok(rows)
+}
+
+fn note_entries(db: &Connection, usn: i64) -> Result<Vec<Value>> {
+    let mut stmt = db.prepare(
+        "SELECT id, guid, mid, mod, usn, tags, flds, flags, data FROM notes WHERE usn = ?1",
+    )?;
+    let rows = stmt
+        .query_map([usn], |r| {
+            Ok(json!([
+                r.get::<_, i64>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, i64>(2)?,
+                r.get::<_, i64>(3)?,
+                r.get::<_, i64>(4)?,
+                r.get::<_, String>(5)?,
+                r.get::<_, String>(6)?,
+                // sfld and csum: the receiver recomputes both.
+                "",
+                "",
+                r.get::<_, i64>(7)?,
+                r.get::<_, String>(8)?,
+            ]))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+fn card_entries(db: &Connection, usn: i64) -> Result<Vec<Value>> {
+    let mut stmt = db.prepare(
+        "SELECT id, nid, did, ord, mod, usn, type, queue, due, ivl, factor, reps, lapses, \
+         left, odue, odid, flags, data FROM cards WHERE usn = ?1",
+    )?;
+    let rows = stmt
+        .query_map([usn], |r| {
+            let mut entry = Vec::with_capacity(18);
+            for i in 0..17 {
+                entry.push(Value::from(r.get::<_, i64>(i)?));
+            }
+            entry.push(Value::from(r.get::<_, String>(17)?));
+            Ok(Value::Array(entry))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+fn tags(db: &Connection, usn: i64) -> Result<Vec<String>> {
+    let mut stmt = db.prepare("SELECT tag FROM tags WHERE usn = ?1")?;
+    let rows = stmt
+        .query_map([usn], |r| r.get(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// Changed notetypes as `NotetypeSchema11` objects.
+fn notetypes(db: &Connection, usn: i64) -> Result<Vec<Value>> {
+    let mut stmt =
+        db.prepare("SELECT id, name, mtime_secs, config FROM notetypes WHERE usn = ?1")?;
+    let rows = stmt
+        .query_map([usn], |r| {
+            Ok((
+                r.get::<_, i64>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, i64>(2)?,
+                r.get(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<(i64, String, i64, Vec<u8>)>>>()?;
+
+    let mut out = Vec::with_capacity(rows.len());
+    for (id, name, mtime, blob) in rows {
+        let cfg = Config::decode(blob.as_slice())
+            .with_context(|| format!("decode notetype {name:?} config"))?;
+        let flds = fields(db, id)?;
+        let tmpls = templates(db, id)?;
+        let req: Vec<Value> = cfg
+            .reqs
+            .iter()
+            .map(|r| {
+                let kind = match r.kind {
+                    1 => "any",
+                    2 => "all",
+                    _ => "none",
+                };
+                json!([r.card_ord, kind, r.field_ords])
+            })
+            .collect();
+        out.push(json!({
+            "id": id,
+            "name": name,
+            "type": cfg.kind,
+            "mod": mtime,
+            "usn": usn,
+            "sortf": cfg.sort_field_idx,
+            "did": (cfg.target_deck_id_unused != 0).then_some(cfg.target_deck_id_unused),
+            "tmpls": tmpls,
+            "flds": flds,
+            "css": cfg.css,
+            "latexPre": cfg.latex_pre,
+            "latexPost": cfg.latex_post,
+            "latexsvg": cfg.latex_svg,
+            "req": req,
+            "originalStockKind": cfg.original_stock_kind,
+            "originalId": cfg.original_id,
+        }));
+    }
+    Ok(out)
+}
+
+fn fields(db: &Connection, ntid: i64) -> Result<Vec<Value>> {
+    let mut stmt =
+        db.prepare("SELECT ord, name, config FROM fields WHERE ntid = ?1 ORDER BY ord")?;
+    let rows = stmt
+        .query_map([ntid], |r| {
+            Ok((r.get::<_, u32>(0)?, r.get::<_, String>(1)?, r.get(2)?))
+        })?
+        .collect::<rusqlite::Result<Vec<(u32, String, Vec<u8>)>>>()?;
+    rows.into_iter()
+        .map(|(ord, name, blob)| {
+            let c = field::Config::decode(blob.as_slice())
+                .with_context(|| format!("decode field {name:?} config"))?;
+            Ok(json!({
+                "name": name,
+                "ord": ord,
+                "sticky": c.sticky,
+                "rtl": c.rtl,
+                "font": c.font_name,
+                "size": c.font_size,
+                "description": c.description,
+                "plainText": c.plain_text,
+                "collapsed": c.collapsed,
+                "excludeFromSearch": c.exclude_from_search,
+                "id": c.id,
+                "tag": c.tag,
+                "preventDeletion": c.prevent_deletion,
+            }))
+        })
+        .collect()
+}
+
+fn templates(db: &Connection, ntid: i64) -> Result<Vec<Value>> {
+    let mut stmt =
+        db.prepare("SELECT ord, name, config FROM templates WHERE ntid = ?1 ORDER BY ord")?;
+    let rows = stmt
+        .query_map([ntid], |r| {
+            Ok((r.get::<_, u32>(0)?, r.get::<_, String>(1)?, r.get(2)?))
+        })?
+        .collect::<rusqlite::Result<Vec<(u32, String, Vec<u8>)>>>()?;
+    rows.into_iter()
+        .map(|(ord, name, blob)| {
+            let c = template::Config::decode(blob.as_slice())
+                .with_context(|| format!("decode template {name:?} config"))?;
+            Ok(json!({
+                "name": name,
+                "ord": ord,
+                "qfmt": c.q_format,
+                "afmt": c.a_format,
+                "bqfmt": c.q_format_browser,
+                "bafmt": c.a_format_browser,
+                "did": (c.target_deck_id != 0).then_some(c.target_deck_id),
+                "bfont": c.browser_font_name,
+                "bsize": c.browser_font_size,
+                "id": c.id,
+            }))
+        })
+        .collect()
+}
+
+/// Changed normal decks as `DeckSchema11` objects. marki never edits
+/// filtered decks, so any that show up are left to a regular Anki sync.
+fn decks(db: &Connection, usn: i64) -> Result<Vec<Value>> {
+    let mut stmt =
+        db.prepare("SELECT id, name, mtime_secs, common, kind FROM decks WHERE usn = ?1")?;
+    let rows = stmt
+        .query_map(params![usn], |r| {
+            Ok((
+                r.get::<_, i64>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, i64>(2)?,
+                r.get(3)?,
+                r.get(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<(i64, String, i64, Vec<u8>, Vec<u8>)>>>()?;
+
+    let mut out = Vec::with_capacity(rows.len());
+    for (id, name, mtime, common, kind) in rows {
+        let common = Common::decode(common.as_slice())
+            .with_context(|| format!("decode deck {name:?} common"))?;
+        let kind = KindContainer::decode(kind.as_slice())
+            .with_context(|| format!("decode deck {name:?} kind"))?;
+        let Some(DeckKind::Normal(normal)) = kind.kind else {
+            continue;
+        };
+        let day = common.last_day_studied;
+        out.push(json!({
+            "id": id,
+            "mod": mtime,
+            "name": deck::native_to_human(&name),
+            "usn": usn,
+            "lrnToday": [day, common.learning_studied],
+            "revToday": [day, common.review_studied],
+            "newToday": [day, common.new_studied],
+            "timeToday": [day, common.milliseconds_studied],
+            "collapsed": common.study_collapsed,
+            "browserCollapsed": common.browser_collapsed,
+            "desc": normal.description,
+            "md": normal.markdown_description,
+            "dyn": 0,
+            "conf": normal.config_id,
+            "extendNew": normal.extend_new,
+            "extendRev": normal.extend_review,
+            "reviewLimit": normal.review_limit,
+            "newLimit": normal.new_limit,
+        }));
+    }
+    Ok(out)
+}
@@ -0,0 +1,128 @@
+//! Request framing for the v11 sync protocol.
+//!
+//! Every call is a `POST` to `<endpoint>sync/<method>` (collection) or
+//! `<endpoint>msync/<method>` (media). The body is zstd-compressed, and
+//! an `anki-sync` header carries a small JSON object with the protocol
+//! version, the host key from login, the client version and a session
+//! id. Responses come back zstd-compressed as well. This is the framing
+//! rslib's `HttpSyncClient` uses; anki-sync-server accepts nothing else.
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+/// Sync protocol version spoken here (Anki 2.1.57+).
+pub const SYNC_VERSION: u8 = 11;
+
+/// Identifies marki to the server in the `c` header field and in `meta`,
+/// in Anki's `<client>,<version>,<platform>` form.
+pub fn client_version() -> String {
+    format!(
+        "marki,{},{}",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS
+    )
+}
+
+/// Full downloads and uploads of large collections can take a while.
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The JSON carried in the `anki-sync` header.
+#[derive(Serialize)]
+struct Header<'a> {
+    v: u8,
+    k: &'a str,
+    c: &'a str,
+    s: &'a str,
+}
+
+/// A logged-in connection to one sync server.
+pub(crate) struct Http {
+    client: reqwest::blocking::Client,
+    /// Server root, always ending in `/`.
+    endpoint: String,
+    hkey: String,
+    session: String,
+}
+
+impl Http {
+    /// Log in with a username and password, exchanging them for the host
+    /// key every later request carries.
+    pub fn login(endpoint: &str, user: &str, password: &str) -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .context("build HTTP client")?;
+        let mut endpoint = endpoint.to_string();
+        if !endpoint.ends_with('/') {
+            endpoint.push('/');
+        }
+        let session = format!("{:x}", crate::now_millis());
+        let mut http = Self {
+            client,
+            endpoint,
+            hkey: String::new(),
+            session,
+        };
+
+        #[derive(Serialize)]
+        struct Login<'a> {
+            u: &'a str,
+            p: &'a str,
+        }
+        #[derive(serde::Deserialize)]
+        struct HostKey {
+            key: String,
+        }
+        let reply: HostKey = http
+            .json(
+                "sync/hostKey",
+                &Login {
+                    u: user,
+                    p: password,
+                },
+            )
+            .context("log in to sync server")?;
+        http.hkey = reply.key;
+        Ok(http)
+    }
+
+    /// Call a method with a JSON body and decode its JSON reply.
+    pub fn json<T: DeserializeOwned>(&self, path: &str, body: &impl Serialize) -> Result<T> {
+        let body = serde_json::to_vec(body).context("encode request")?;
+        let reply = self.post(path, &body)?;
+        serde_json::from_slice(&reply).with_context(|| format!("{path}: decode reply"))
+    }
+
+    /// Call a method with a raw body and return the raw (decompressed) reply.
+    pub fn post(&self, path: &str, body: &[u8]) -> Result<Vec<u8>> {
+        let header = serde_json::to_string(&Header {
+            v: SYNC_VERSION,
+            k: &self.hkey,
+            c: &client_version(),
+            s: &self.session,
+        })?;
+        let compressed = zstd::encode_all(body, 0).context("compress request")?;
+        let resp = self
+            .client
+            .post(format!("{}{path}", self.endpoint))
+            .header("anki-sync", header)
+            .header("content-type", "application/octet-stream")
+            .body(compressed)
+            .send()
+            .with_context(|| format!("{path}: send"))?;
+        let status = resp.status();
+        let bytes = resp
+            .bytes()
+            .with_context(|| format!("{path}: read reply"))?;
+        if status.as_u16() == 403 {
+            bail!("{path}: sync server refused the credentials");
+        }
+        if !status.is_success() {
+            let text = String::from_utf8_lossy(&bytes);
+            bail!("{path}: HTTP {status}: {}", text.trim());
+        }
+        zstd::decode_all(&bytes[..]).with_context(|| format!("{path}: decompress reply"))
+    }
+}
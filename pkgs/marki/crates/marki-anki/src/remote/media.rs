@@ -0,0 +1,155 @@
+//! Media upload over the sync protocol (`msync/…`).
+//!
+//! The server keeps its own media usn. A client begins a media sync to
+//! learn it, pages through `mediaChanges` from the last usn it saw to
+//! learn which files (and checksums) the server holds, then uploads what
+//! is missing as zip batches. What this client has seen is kept in a
+//! small JSON state file beside the snapshot, so steady-state cycles only
+//! fetch the changes since the previous one.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use super::http::Http;
+
+/// Files per uploaded zip, rslib's `MAX_MEDIA_FILES_IN_ZIP`.
+const FILES_PER_ZIP: usize = 25;
+
+/// Uncompressed bytes per uploaded zip, below rslib's upload limit.
+const BYTES_PER_ZIP: usize = 100 * 1024 * 1024;
+
+/// Server media as last seen by this client.
+#[derive(Default, Serialize, Deserialize)]
+pub struct MediaState {
+    usn: i64,
+    /// File name to sha1 (hex) of every file the server holds.
+    files: HashMap<String, String>,
+}
+
+impl MediaState {
+    pub fn load(path: &Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let bytes = serde_json::to_vec(self)?;
+        std::fs::write(path, bytes).with_context(|| format!("write {}", path.display()))
+    }
+}
+
+/// Legacy media replies wrap their payload as `{ "data": …, "err": "" }`.
+#[derive(Deserialize)]
+struct Reply<T> {
+    data: Option<T>,
+    #[serde(default)]
+    err: String,
+}
+
+impl<T> Reply<T> {
+    fn into_data(self, what: &str) -> Result<T> {
+        match self.data {
+            Some(d) if self.err.is_empty() => Ok(d),
+            _ => bail!("{what}: {}", self.err),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Begin {
+    usn: i64,
+}
+
+/// Bring `state` up to date with the server, then upload every file in
+/// `files` the server lacks or holds different bytes for. Returns the
+/// number of files uploaded.
+pub fn push(http: &Http, state: &mut MediaState, files: &[(&str, &[u8])]) -> Result<usize> {
+    let begin: Reply<Begin> = http.json("msync/begin", &serde_json::json!({}))?;
+    let server_usn = begin.into_data("media begin")?.usn;
+
+    while state.usn < server_usn {
+        let changes: Reply<Vec<(String, i64, String)>> = http.json(
+            "msync/mediaChanges",
+            &serde_json::json!({ "lastUsn": state.usn }),
+        )?;
+        let changes = changes.into_data("media changes")?;
+        if changes.is_empty() {
+            break;
+        }
+        for (name, usn, sha1) in changes {
+            if sha1.is_empty() {
+                state.files.remove(&name);
+            } else {
+                state.files.insert(name, sha1);
+            }
+            state.usn = state.usn.max(usn);
+        }
+    }
+
+    let wanted: Vec<(&str, &[u8], String)> = files
+        .iter()
+        .map(|(name, bytes)| (*name, *bytes, hex_sha1(bytes)))
+        .filter(|(name, _, sha1)| state.files.get(*name) != Some(sha1))
+        .collect();
+
+    let mut sent = 0;
+    let mut batch: Vec<&(&str, &[u8], String)> = Vec::new();
+    let mut batch_bytes = 0;
+    for entry in &wanted {
+        if !batch.is_empty()
+            && (batch.len() == FILES_PER_ZIP || batch_bytes + entry.1.len() > BYTES_PER_ZIP)
+        {
+            sent += upload(http, state, &batch)?;
+            batch.clear();
+            batch_bytes = 0;
+        }
+        batch_bytes += entry.1.len();
+        batch.push(entry);
+    }
+    if !batch.is_empty() {
+        sent += upload(http, state, &batch)?;
+    }
+    Ok(sent)
+}
+
+/// Upload one zip: entries `0`, `1`, … plus a `_meta` list mapping each
+/// file name to its entry.
+fn upload(http: &Http, state: &mut MediaState, batch: &[&(&str, &[u8], String)]) -> Result<usize> {
+    let mut buf = std::io::Cursor::new(Vec::new());
+    {
+        let mut zip = zip::ZipWriter::new(&mut buf);
+        let opts = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        let mut meta = Vec::with_capacity(batch.len());
+        for (i, (name, bytes, _)) in batch.iter().enumerate() {
+            zip.start_file(i.to_string(), opts)?;
+            zip.write_all(bytes)?;
+            meta.push((name.to_string(), Some(i.to_string())));
+        }
+        zip.start_file("_meta", opts)?;
+        zip.write_all(&serde_json::to_vec(&meta)?)?;
+        zip.finish()?;
+    }
+    let reply = http.post("msync/uploadChanges", buf.get_ref())?;
+    let reply: Reply<(usize, i64)> =
+        serde_json::from_slice(&reply).context("msync/uploadChanges: decode reply")?;
+    let (processed, usn) = reply.into_data("media upload")?;
+    for (name, _, sha1) in batch {
+        state.files.insert(name.to_string(), sha1.clone());
+    }
+    state.usn = usn;
+    Ok(processed)
+}
+
+fn hex_sha1(bytes: &[u8]) -> String {
+    Sha1::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
@@ -0,0 +1,514 @@
+//! Writing to a collection through a sync server instead of on disk.
+//!
+//! The rest of this crate edits the server's collection file in place,
+//! which needs a shell on the sync server's host. [`Remote`] gets the same
+//! result as an ordinary sync client: it downloads the collection into a
+//! local *snapshot*, lets a [`NoteWriter`](crate::NoteWriter) batch edit
+//! the snapshot exactly as it would edit the server's file, and then sends
+//! the rows that batch touched through a normal (incremental) sync.
+//!
+//! The snapshot is a server-mode collection, so the rows a batch writes
+//! are stamped with the usn the server will assign next (see
+//! [`changes`]). After a successful sync the snapshot matches the server
+//! again and is reused; it is downloaded afresh whenever the server's
+//! modification time shows that another client synced in between.
+//!
+//! A batch that changes an existing notetype's shape (appends fields and
+//! templates) is a schema change Anki cannot merge: every client must then
+//! take a one-way copy. Such a batch is only sent, as a full upload, when
+//! the caller allows it. New notetypes merge like any other change.
+
+mod changes;
+mod http;
+mod media;
+#[cfg(test)]
+pub(crate) mod standin;
+
+use anyhow::{Context, Result, bail};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::Collection;
+use http::Http;
+use media::MediaState;
+
+/// Where and as whom to sync.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    /// Server root, e.g. `https://sync.example.org/`.
+    pub endpoint: String,
+    pub user: String,
+    pub password: String,
+}
+
+/// The server's `meta` reply, trimmed to what the client needs.
+#[derive(Debug, Clone, Deserialize)]
+struct Meta {
+    #[serde(rename = "mod")]
+    modified: i64,
+    scm: i64,
+    usn: i64,
+    #[serde(default)]
+    msg: String,
+    #[serde(default = "yes")]
+    cont: bool,
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// The server state the snapshot was taken at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SnapshotState {
+    modified: i64,
+    scm: i64,
+    usn: i64,
+}
+
+/// The downloaded collection plus what is known about it.
+struct Snapshot {
+    col: Collection,
+    /// The server state it matches, short of any unsent batch.
+    state: SnapshotState,
+    /// Field and template counts per notetype before the batch, to tell
+    /// new notetypes and css edits apart from reshaped ones.
+    shapes: HashMap<i64, (i64, i64)>,
+}
+
+/// A collection reached through a sync server.
+pub struct Remote {
+    http: Http,
+    /// Holds the snapshot, its state file and the media state.
+    work_dir: PathBuf,
+    allow_full_upload: bool,
+    snapshot: Option<Snapshot>,
+}
+
+impl Remote {
+    /// Log in and prepare `work_dir` for the snapshot. Nothing is
+    /// downloaded until [`collection`](Self::collection) is called.
+    pub fn connect(creds: &Credentials, work_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(work_dir)
+            .with_context(|| format!("create {}", work_dir.display()))?;
+        let http = Http::login(&creds.endpoint, &creds.user, &creds.password)?;
+        Ok(Self {
+            http,
+            work_dir: work_dir.to_path_buf(),
+            allow_full_upload: false,
+            snapshot: None,
+        })
+    }
+
+    /// Allow batches that change a notetype's shape to be sent as a
+    /// one-way full upload. Other clients then have to download the whole
+    /// collection on their next sync, discarding anything they had not
+    /// synced yet.
+    pub fn allow_full_upload(mut self, allow: bool) -> Self {
+        self.allow_full_upload = allow;
+        self
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        self.work_dir.join("collection.anki2")
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.work_dir.join("snapshot.json")
+    }
+
+    fn media_state_path(&self) -> PathBuf {
+        self.work_dir.join("media.json")
+    }
+
+    fn meta(&self) -> Result<Meta> {
+        let meta: Meta = self.http.json(
+            "sync/meta",
+            &json!({ "v": http::SYNC_VERSION, "cv": http::client_version() }),
+        )?;
+        if !meta.cont {
+            bail!("sync server refused to sync: {}", meta.msg);
+        }
+        Ok(meta)
+    }
+
+    /// The collection as the server holds it now: the kept snapshot when
+    /// nobody synced since it was taken, else a fresh download.
+    pub fn collection(&mut self) -> Result<&mut Collection> {
+        let meta = self.meta()?;
+        let state = SnapshotState {
+            modified: meta.modified,
+            scm: meta.scm,
+            usn: meta.usn,
+        };
+        if let Some(snap) = &self.snapshot
+            && (snap.state != state || snap.col.usn()? != state.usn)
+        {
+            self.snapshot = None;
+        }
+        if self.snapshot.is_none() {
+            let recorded = std::fs::read(self.state_path())
+                .ok()
+                .and_then(|b| serde_json::from_slice::<SnapshotState>(&b).ok());
+            let mut col = None;
+            if recorded.as_ref() == Some(&state) && self.snapshot_path().exists() {
+                // A snapshot whose usn moved holds writes an interrupted
+                // cycle never sent; it no longer matches the server.
+                let kept = Collection::open(&self.snapshot_path())?;
+                if kept.usn()? == state.usn {
+                    col = Some(kept);
+                }
+            }
+            let col = match col {
+                Some(col) => col,
+                None => {
+                    self.download(&state)?;
+                    Collection::open(&self.snapshot_path())?
+                }
+            };
+            let shapes = changes::shapes(&col.db)?;
+            self.snapshot = Some(Snapshot { col, state, shapes });
+        }
+        Ok(&mut self.snapshot.as_mut().expect("snapshot loaded").col)
+    }
+
+    /// The snapshot as last read, without asking the server whether it is
+    /// still current; loads one when none is held. A batch written here
+    /// after another client synced is refused by [`push`](Self::push).
+    pub fn snapshot(&mut self) -> Result<&mut Collection> {
+        if self.snapshot.is_none() {
+            return self.collection();
+        }
+        Ok(&mut self.snapshot.as_mut().expect("snapshot loaded").col)
+    }
+
+    /// Replace the snapshot with a full copy of the server's collection.
+    fn download(&mut self, state: &SnapshotState) -> Result<()> {
+        tracing::info!("downloading collection from sync server");
+        let bytes = self.http.post("sync/download", b"{}")?;
+        let tmp = self.work_dir.join("collection.anki2.download");
+        std::fs::write(&tmp, &bytes).with_context(|| format!("write {}", tmp.display()))?;
+        std::fs::rename(&tmp, self.snapshot_path()).context("install downloaded snapshot")?;
+        // A stale WAL from an older snapshot must not be replayed over it.
+        for ext in ["anki2-wal", "anki2-shm"] {
+            std::fs::remove_file(self.snapshot_path().with_extension(ext)).ok();
+        }
+        std::fs::write(self.state_path(), serde_json::to_vec(state)?)
+            .context("record snapshot state")?;
+        Ok(())
+    }
+
+    /// Forget the snapshot so the next cycle starts from a fresh download.
+    fn discard(&mut self) {
+        self.snapshot = None;
+        std::fs::remove_file(self.state_path()).ok();
+    }
+
+    /// Send everything the last batch wrote to the snapshot. A no-op when
+    /// the batch wrote nothing. On failure the snapshot is discarded, since
+    /// it may no longer match the server.
+    pub fn push(&mut self) -> Result<()> {
+        let Some(snap) = &self.snapshot else {
+            return Ok(());
+        };
+        if snap.col.usn()? == snap.state.usn {
+            return Ok(());
+        }
+        let reshaped = changes::shapes(&snap.col.db)?
+            .iter()
+            .any(|(id, shape)| snap.shapes.get(id).is_some_and(|old| old != shape));
+        let result = if reshaped {
+            self.full_upload()
+        } else {
+            self.normal_sync()
+        };
+        if result.is_err() {
+            self.discard();
+        }
+        result
+    }
+
+    /// Incremental sync of the pending rows (usn = the server's usn).
+    fn normal_sync(&mut self) -> Result<()> {
+        let Snapshot { col, state, .. } = self.snapshot.as_ref().expect("checked by push");
+        let usn = state.usn;
+
+        let meta = self.meta()?;
+        if meta.modified != state.modified || meta.usn != usn {
+            bail!(
+                "the collection changed on the sync server during this cycle; retrying next cycle"
+            );
+        }
+
+        let graves = changes::graves(&col.db, usn)?;
+        let unchunked = changes::unchunked(&col.db, usn)?;
+        let chunks = changes::chunks(&col.db, usn)?;
+        let counts = changes::sanity_counts(&col.db)?;
+
+        let http = &self.http;
+        let sent = (|| -> Result<i64> {
+            let _: Value = http.json(
+                "sync/start",
+                &json!({ "minUsn": usn, "lnewer": false, "graves": graves }),
+            )?;
+            let _: Value = http.json("sync/applyChanges", &json!({ "changes": unchunked }))?;
+            // The server has nothing newer than the snapshot, but its chunk
+            // stream still has to be drained before ours is accepted.
+            loop {
+                let chunk: Value = http.json("sync/chunk", &json!({}))?;
+                if chunk["done"].as_bool().unwrap_or(true) {
+                    break;
+                }
+            }
+            for chunk in &chunks {
+                let _: Value = http.json("sync/applyChunk", &json!({ "chunk": chunk }))?;
+            }
+            let check: Value = http.json("sync/sanityCheck2", &json!({ "client": counts }))?;
+            if check["status"] != "ok" {
+                bail!("sync server sanity check failed: {check}");
+            }
+            http.json("sync/finish", &json!({}))
+        })();
+        let modified = match sent {
+            Ok(m) => m,
+            Err(e) => {
+                let _: Result<Value> = self.http.json("sync/abort", &json!({}));
+                return Err(e);
+            }
+        };
+
+        // The server now holds exactly the snapshot; note its new state. A
+        // new notetype bumped the snapshot's scm, but not the server's.
+        let state_path = self.state_path();
+        let snap = self.snapshot.as_mut().expect("checked by push");
+        snap.col
+            .db
+            .execute(
+                "UPDATE col SET mod = ?1, ls = ?1, scm = ?2",
+                params![modified, snap.state.scm],
+            )
+            .context("record sync time")?;
+        snap.state.modified = modified;
+        snap.state.usn = snap.col.usn()?;
+        snap.shapes = changes::shapes(&snap.col.db)?;
+        std::fs::write(state_path, serde_json::to_vec(&snap.state)?)
+            .context("record snapshot state")?;
+        Ok(())
+    }
+
+    /// One-way upload of the whole snapshot, for schema changes.
+    fn full_upload(&mut self) -> Result<()> {
+        if !self.allow_full_upload {
+            bail!(
+                "a notetype changed shape, which Anki can only sync as a one-way upload that \
+                 makes every other client download the collection; set \
+                 `allow_full_upload = true` for the sync server to allow it"
+            );
+        }
+        let meta = self.meta()?;
+        let Snapshot { col, state, .. } = self.snapshot.as_ref().expect("checked by push");
+        if meta.modified != state.modified || meta.usn != state.usn {
+            bail!(
+                "the collection changed on the sync server during this cycle; retrying next cycle"
+            );
+        }
+        tracing::warn!("notetype shape changed; uploading the whole collection (one-way)");
+        col.db
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")
+            .context("checkpoint snapshot")?;
+        let bytes = std::fs::read(self.snapshot_path()).context("read snapshot for upload")?;
+        let reply = self.http.post("sync/upload", &bytes)?;
+        if reply != b"OK" {
+            bail!(
+                "sync server rejected the upload: {}",
+                String::from_utf8_lossy(&reply)
+            );
+        }
+        // Upload replaces the server's collection wholesale; its new state is
+        // only known from meta, so take it on the next cycle.
+        self.discard();
+        Ok(())
+    }
+
+    /// Upload every file in `files` the server does not already hold with
+    /// the same contents. Returns the number uploaded.
+    pub fn push_media(&mut self, files: &[(&str, &[u8])]) -> Result<usize> {
+        if files.is_empty() {
+            return Ok(0);
+        }
+        let path = self.media_state_path();
+        let mut state = MediaState::load(&path);
+        let result = media::push(&self.http, &mut state, files);
+        state.save(&path)?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::standin::{self, StandIn};
+    use super::*;
+    use crate::notetype::ModelSpec;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("marki-remote-{name}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn serve(dir: &Path) -> (StandIn, Credentials) {
+        let path = dir.join("server.anki2");
+        standin::empty_collection(&path);
+        let server = StandIn::start(&path);
+        let creds = Credentials {
+            endpoint: server.endpoint.clone(),
+            user: standin::USER.into(),
+            password: standin::PASSWORD.into(),
+        };
+        (server, creds)
+    }
+
+    fn spec(cards: &[&str]) -> ModelSpec {
+        ModelSpec {
+            name: "capital".into(),
+            css: ".card {}".into(),
+            card_names: cards.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    fn count(db: &rusqlite::Connection, sql: &str) -> i64 {
+        db.query_row(sql, [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn wrong_password_is_refused() {
+        let dir = scratch("login");
+        let (_server, mut creds) = serve(&dir);
+        creds.password = "nope".into();
+        let err = Remote::connect(&creds, &dir.join("work")).err().unwrap();
+        assert!(
+            format!("{err:#}").contains("refused the credentials"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn batches_reach_the_server_and_the_snapshot_is_reused() {
+        let dir = scratch("sync");
+        let (server, creds) = serve(&dir);
+        let mut remote = Remote::connect(&creds, &dir.join("work")).unwrap();
+
+        let nid = remote
+            .collection()
+            .unwrap()
+            .transact(|w| {
+                let mid = w.ensure_model(&spec(&["Front"]))?;
+                let did = w.deck_id_for("geo::europe")?;
+                let fields = vec!["Paris".into(), "France".into()];
+                w.add_note(mid, "guid-1", fields, 0, &["marki".into()], did)
+            })
+            .unwrap();
+        remote.push().unwrap();
+
+        let db = server.open();
+        assert_eq!(count(&db, "SELECT count(*) FROM notes"), 1);
+        assert_eq!(count(&db, "SELECT count(*) FROM cards"), 1);
+        assert_eq!(count(&db, "SELECT usn FROM col"), 6);
+        {
+            let log = server.log.lock().unwrap();
+            assert_eq!(log.downloads, 1);
+            assert_eq!(log.changes[0]["models"][0]["name"], "marki:capital");
+            assert_eq!(log.changes[0]["decks"][0][0]["name"], "geo");
+        }
+
+        // Nobody synced in between: the snapshot is reused, and a removal
+        // travels as graves.
+        remote
+            .collection()
+            .unwrap()
+            .transact(|w| w.remove_note(nid))
+            .unwrap();
+        remote.push().unwrap();
+        assert_eq!(server.log.lock().unwrap().downloads, 1);
+        assert_eq!(count(&db, "SELECT count(*) FROM notes"), 0);
+        assert_eq!(count(&db, "SELECT count(*) FROM graves WHERE type = 1"), 1);
+
+        // An empty batch sends nothing.
+        remote.collection().unwrap().transact(|_| Ok(())).unwrap();
+        remote.push().unwrap();
+        assert_eq!(count(&db, "SELECT usn FROM col"), 7);
+
+        // A snapshot kept on disk survives a restart.
+        drop(remote);
+        let mut remote = Remote::connect(&creds, &dir.join("work")).unwrap();
+        remote.collection().unwrap();
+        assert_eq!(server.log.lock().unwrap().downloads, 1);
+    }
+
+    #[test]
+    fn another_clients_sync_forces_a_download() {
+        let dir = scratch("stale");
+        let (server, creds) = serve(&dir);
+        let mut remote = Remote::connect(&creds, &dir.join("work")).unwrap();
+        remote.collection().unwrap();
+
+        server
+            .open()
+            .execute("UPDATE col SET usn = usn + 1, mod = mod + 1", [])
+            .unwrap();
+        assert_eq!(remote.collection().unwrap().usn().unwrap(), 6);
+        assert_eq!(server.log.lock().unwrap().downloads, 2);
+    }
+
+    #[test]
+    fn reshaping_a_notetype_needs_a_full_upload() {
+        let dir = scratch("reshape");
+        let (server, creds) = serve(&dir);
+        let mut remote = Remote::connect(&creds, &dir.join("work")).unwrap();
+        remote
+            .collection()
+            .unwrap()
+            .transact(|w| w.ensure_model(&spec(&["A"])))
+            .unwrap();
+        remote.push().unwrap();
+
+        remote
+            .collection()
+            .unwrap()
+            .transact(|w| w.ensure_model(&spec(&["A", "B"])))
+            .unwrap();
+        let err = remote.push().unwrap_err();
+        assert!(format!("{err:#}").contains("allow_full_upload"), "{err:#}");
+        assert_eq!(server.log.lock().unwrap().uploads, 0);
+
+        let mut remote = remote.allow_full_upload(true);
+        remote
+            .collection()
+            .unwrap()
+            .transact(|w| w.ensure_model(&spec(&["A", "B"])))
+            .unwrap();
+        remote.push().unwrap();
+        assert_eq!(server.log.lock().unwrap().uploads, 1);
+        assert_eq!(count(&server.open(), "SELECT count(*) FROM fields"), 4);
+    }
+
+    #[test]
+    fn media_is_uploaded_once() {
+        let dir = scratch("media");
+        let (server, creds) = serve(&dir);
+        let mut remote = Remote::connect(&creds, &dir.join("work")).unwrap();
+        let files: [(&str, &[u8]); 2] = [("a.svg", b"<svg/>"), ("b.png", b"png")];
+        assert_eq!(remote.push_media(&files).unwrap(), 2);
+        assert_eq!(remote.push_media(&files).unwrap(), 0);
+        assert_eq!(server.log.lock().unwrap().media["a.svg"], b"<svg/>");
+
+        // A fresh client learns what the server holds from its changes.
+        std::fs::remove_file(dir.join("work/media.json")).unwrap();
+        let changed: [(&str, &[u8]); 2] = [("a.svg", b"<svg/>"), ("b.png", b"png2")];
+        assert_eq!(remote.push_media(&changed).unwrap(), 1);
+    }
+}
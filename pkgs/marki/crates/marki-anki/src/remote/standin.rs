@@ -0,0 +1,361 @@
+//! A local stand-in for anki-sync-server, for tests.
+//!
+//! It speaks the same framing as the real server (zstd bodies, `anki-sync`
+//! header, host-key login) over plain HTTP on a loopback port, and keeps
+//! its collection in a file built by [`empty_collection`]. Merging is
+//! deliberately simple: rows are stored as received and stamped with the
+//! server usn, notetypes and decks are stored by id and name only. That is
+//! enough to check what the client sends, and that the sanity check and
+//! usn bookkeeping line up.
+
+use rusqlite::{Connection, params};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const USER: &str = "user";
+pub const PASSWORD: &str = "secret";
+const HOST_KEY: &str = "hk";
+
+/// The subset of the v18 schema the writer and the stand-in touch.
+const SCHEMA: &str = "
+CREATE TABLE col (id integer PRIMARY KEY, crt integer NOT NULL, mod integer NOT NULL,
+  scm integer NOT NULL, ver integer NOT NULL, dty integer NOT NULL, usn integer NOT NULL,
+  ls integer NOT NULL, conf text NOT NULL, models text NOT NULL, decks text NOT NULL,
+  dconf text NOT NULL, tags text NOT NULL);
+INSERT INTO col VALUES (1, 0, 1000, 1000, 18, 0, 5, 0, '', '', '', '', '');
+CREATE TABLE notes (id integer PRIMARY KEY, guid text NOT NULL, mid integer NOT NULL,
+  mod integer NOT NULL, usn integer NOT NULL, tags text NOT NULL, flds text NOT NULL,
+  sfld integer NOT NULL, csum integer NOT NULL, flags integer NOT NULL, data text NOT NULL);
+CREATE TABLE cards (id integer PRIMARY KEY, nid integer NOT NULL, did integer NOT NULL,
+  ord integer NOT NULL, mod integer NOT NULL, usn integer NOT NULL, type integer NOT NULL,
+  queue integer NOT NULL, due integer NOT NULL, ivl integer NOT NULL,
+  factor integer NOT NULL, reps integer NOT NULL, lapses integer NOT NULL,
+  left integer NOT NULL, odue integer NOT NULL, odid integer NOT NULL,
+  flags integer NOT NULL, data text NOT NULL);
+CREATE TABLE revlog (id integer PRIMARY KEY, cid integer NOT NULL, usn integer NOT NULL);
+CREATE TABLE graves (oid integer NOT NULL, type integer NOT NULL, usn integer NOT NULL,
+  PRIMARY KEY (oid, type)) WITHOUT ROWID;
+CREATE TABLE deck_config (id integer PRIMARY KEY, name text NOT NULL COLLATE unicase,
+  mtime_secs integer NOT NULL, usn integer NOT NULL, config blob NOT NULL);
+INSERT INTO deck_config VALUES (1, 'Default', 0, 0, x'');
+CREATE TABLE config (KEY text NOT NULL PRIMARY KEY, usn integer NOT NULL,
+  mtime_secs integer NOT NULL, val blob NOT NULL) WITHOUT ROWID;
+CREATE TABLE fields (ntid integer NOT NULL, ord integer NOT NULL,
+  name text NOT NULL COLLATE unicase, config blob NOT NULL,
+  PRIMARY KEY (ntid, ord)) WITHOUT ROWID;
+CREATE TABLE templates (ntid integer NOT NULL, ord integer NOT NULL,
+  name text NOT NULL COLLATE unicase, mtime_secs integer NOT NULL, usn integer NOT NULL,
+  config blob NOT NULL, PRIMARY KEY (ntid, ord)) WITHOUT ROWID;
+CREATE TABLE notetypes (id integer PRIMARY KEY, name text NOT NULL COLLATE unicase,
+  mtime_secs integer NOT NULL, usn integer NOT NULL, config blob NOT NULL);
+CREATE TABLE decks (id integer PRIMARY KEY, name text NOT NULL COLLATE unicase,
+  mtime_secs integer NOT NULL, usn integer NOT NULL, common blob NOT NULL,
+  kind blob NOT NULL);
+INSERT INTO decks VALUES (1, 'Default', 0, 0, x'', x'');
+CREATE TABLE tags (tag text NOT NULL PRIMARY KEY COLLATE unicase, usn integer NOT NULL,
+  collapsed boolean NOT NULL, config blob NULL) WITHOUT ROWID;
+";
+
+/// Create an empty v18 collection (usn 5) at `path`.
+pub fn empty_collection(path: &Path) {
+    std::fs::remove_file(path).ok();
+    let db = Connection::open(path).unwrap();
+    crate::register_unicase(&db).unwrap();
+    db.execute_batch(SCHEMA).unwrap();
+}
+
+/// What the stand-in saw, for assertions.
+#[derive(Default)]
+pub struct Log {
+    pub downloads: usize,
+    pub uploads: usize,
+    /// Every `applyChanges` payload.
+    pub changes: Vec<Value>,
+    /// Media files received, by name.
+    pub media: HashMap<String, Vec<u8>>,
+}
+
+/// A running stand-in server.
+pub struct StandIn {
+    pub endpoint: String,
+    pub collection: PathBuf,
+    pub log: Arc<Mutex<Log>>,
+}
+
+impl StandIn {
+    /// Serve `collection` on a free loopback port until the test exits.
+    pub fn start(collection: &Path) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        let log = Arc::new(Mutex::new(Log::default()));
+        let server = Server {
+            path: collection.to_path_buf(),
+            log: Arc::clone(&log),
+            media_usn: 0,
+            media: Vec::new(),
+        };
+        let server = Arc::new(Mutex::new(server));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let reply = read_request(&mut stream).map(|(path, header, body)| {
+                    server.lock().unwrap().handle(&path, &header, &body)
+                });
+                let (status, body) = reply.unwrap_or((400, Vec::new()));
+                let body = zstd::encode_all(&body[..], 0).unwrap();
+                let head = format!(
+                    "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).ok();
+                stream.write_all(&body).ok();
+            }
+        });
+        Self {
+            endpoint,
+            collection: collection.to_path_buf(),
+            log,
+        }
+    }
+
+    /// Open the served collection, e.g. to inspect it or to play another
+    /// client that changes it.
+    pub fn open(&self) -> Connection {
+        let db = Connection::open(&self.collection).unwrap();
+        crate::register_unicase(&db).unwrap();
+        db
+    }
+}
+
+/// Read one request: its path, `anki-sync` header and decompressed body.
+fn read_request(stream: &mut std::net::TcpStream) -> Option<(String, String, Vec<u8>)> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let path = line
+        .split_whitespace()
+        .nth(1)?
+        .trim_start_matches('/')
+        .to_string();
+    let (mut len, mut header) = (0, String::new());
+    loop {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let l = line.trim_end();
+        if l.is_empty() {
+            break;
+        }
+        let (name, value) = l.split_once(':')?;
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => len = value.trim().parse().ok()?,
+            "anki-sync" => header = value.trim().to_string(),
+            _ => {}
+        }
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).ok()?;
+    Some((path, header, zstd::decode_all(&body[..]).ok()?))
+}
+
+struct Server {
+    path: PathBuf,
+    log: Arc<Mutex<Log>>,
+    media_usn: i64,
+    /// `(name, usn, sha1)` per media change.
+    media: Vec<(String, i64, String)>,
+}
+
+impl Server {
+    fn handle(&mut self, path: &str, header: &str, body: &[u8]) -> (u16, Vec<u8>) {
+        let header: Value = serde_json::from_str(header).unwrap_or(Value::Null);
+        let req: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+        if path == "sync/hostKey" {
+            return if req["u"] == USER && req["p"] == PASSWORD {
+                (200, json!({ "key": HOST_KEY }).to_string().into_bytes())
+            } else {
+                (403, Vec::new())
+            };
+        }
+        if header["k"] != HOST_KEY || header["v"] != 11 {
+            return (403, Vec::new());
+        }
+        match path {
+            "sync/download" => {
+                self.log.lock().unwrap().downloads += 1;
+                (200, std::fs::read(&self.path).unwrap())
+            }
+            "sync/upload" => {
+                self.log.lock().unwrap().uploads += 1;
+                std::fs::write(&self.path, body).unwrap();
+                (200, b"OK".to_vec())
+            }
+            "msync/uploadChanges" => self.media_upload(body),
+            _ => (200, self.call(path, &req).to_string().into_bytes()),
+        }
+    }
+
+    fn call(&mut self, path: &str, req: &Value) -> Value {
+        let db = Connection::open(&self.path).unwrap();
+        crate::register_unicase(&db).unwrap();
+        let usn: i64 = db
+            .query_row("SELECT usn FROM col", [], |r| r.get(0))
+            .unwrap();
+        match path {
+            "sync/meta" => {
+                let (m, scm): (i64, i64) = db
+                    .query_row("SELECT mod, scm FROM col", [], |r| {
+                        Ok((r.get(0)?, r.get(1)?))
+                    })
+                    .unwrap();
+                json!({ "mod": m, "scm": scm, "usn": usn, "ts": 0, "musn": self.media_usn,
+                        "msg": "", "cont": true, "hostNum": 0, "empty": false })
+            }
+            "sync/start" => {
+                let graves = &req["graves"];
+                for (kind, table) in [(0, "cards"), (1, "notes")] {
+                    let key = if kind == 0 { "cards" } else { "notes" };
+                    for id in graves[key].as_array().unwrap() {
+                        db.execute(&format!("DELETE FROM {table} WHERE id = ?1"), [id.as_i64()])
+                            .unwrap();
+                        db.execute(
+                            "INSERT OR IGNORE INTO graves VALUES (?1, ?2, ?3)",
+                            params![id.as_i64(), kind, usn],
+                        )
+                        .unwrap();
+                    }
+                }
+                json!({ "cards": [], "notes": [], "decks": [] })
+            }
+            "sync/applyChanges" => {
+                let changes = &req["changes"];
+                for nt in changes["models"].as_array().unwrap() {
+                    db.execute(
+                        "INSERT OR REPLACE INTO notetypes VALUES (?1, ?2, ?3, ?4, x'')",
+                        params![
+                            nt["id"].as_i64(),
+                            nt["name"].as_str(),
+                            nt["mod"].as_i64(),
+                            usn
+                        ],
+                    )
+                    .unwrap();
+                }
+                for deck in changes["decks"][0].as_array().unwrap() {
+                    let native = deck["name"].as_str().unwrap().replace("::", "\u{1f}");
+                    db.execute(
+                        "INSERT OR REPLACE INTO decks VALUES (?1, ?2, ?3, ?4, x'', x'')",
+                        params![deck["id"].as_i64(), native, deck["mod"].as_i64(), usn],
+                    )
+                    .unwrap();
+                }
+                for tag in changes["tags"].as_array().unwrap() {
+                    db.execute(
+                        "INSERT OR IGNORE INTO tags VALUES (?1, ?2, 0, NULL)",
+                        params![tag.as_str(), usn],
+                    )
+                    .unwrap();
+                }
+                self.log.lock().unwrap().changes.push(changes.clone());
+                json!({ "models": [], "decks": [[], []], "tags": [] })
+            }
+            "sync/chunk" => json!({ "done": true, "revlog": [], "cards": [], "notes": [] }),
+            "sync/applyChunk" => {
+                let chunk = &req["chunk"];
+                for n in chunk["notes"].as_array().unwrap() {
+                    db.execute(
+                        "INSERT OR REPLACE INTO notes VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, '', 0, \
+                         ?8, ?9)",
+                        params![
+                            n[0].as_i64(),
+                            n[1].as_str(),
+                            n[2].as_i64(),
+                            n[3].as_i64(),
+                            usn,
+                            n[5].as_str(),
+                            n[6].as_str(),
+                            n[9].as_i64(),
+                            n[10].as_str()
+                        ],
+                    )
+                    .unwrap();
+                }
+                for c in chunk["cards"].as_array().unwrap() {
+                    let mut vals: Vec<i64> = (0..17).map(|i| c[i].as_i64().unwrap()).collect();
+                    vals[5] = usn;
+                    db.execute(
+                        "INSERT OR REPLACE INTO cards VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, \
+                         ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+                        rusqlite::params_from_iter(
+                            vals.iter()
+                                .map(|v| rusqlite::types::Value::Integer(*v))
+                                .chain([rusqlite::types::Value::Text(
+                                    c[17].as_str().unwrap().to_string(),
+                                )]),
+                        ),
+                    )
+                    .unwrap();
+                }
+                Value::Null
+            }
+            "sync/sanityCheck2" => {
+                let server = super::changes::sanity_counts(&db).unwrap();
+                let (server, client) = (
+                    server.as_array().unwrap(),
+                    req["client"].as_array().unwrap(),
+                );
+                if server[1..] == client[1..] {
+                    json!({ "status": "ok" })
+                } else {
+                    json!({ "status": "bad", "c": client, "s": server })
+                }
+            }
+            "sync/finish" => {
+                db.execute("UPDATE col SET usn = usn + 1, mod = mod + 1", [])
+                    .unwrap();
+                let m: i64 = db
+                    .query_row("SELECT mod FROM col", [], |r| r.get(0))
+                    .unwrap();
+                json!(m)
+            }
+            "sync/abort" => Value::Null,
+            "msync/begin" => json!({ "data": { "sk": "s", "usn": self.media_usn }, "err": "" }),
+            "msync/mediaChanges" => {
+                let since = req["lastUsn"].as_i64().unwrap();
+                let list: Vec<_> = self.media.iter().filter(|(_, u, _)| *u > since).collect();
+                json!({ "data": list, "err": "" })
+            }
+            other => panic!("stand-in: unexpected call {other}"),
+        }
+    }
+
+    fn media_upload(&mut self, body: &[u8]) -> (u16, Vec<u8>) {
+        use sha1::{Digest, Sha1};
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(body)).unwrap();
+        let mut meta = String::new();
+        zip.by_name("_meta")
+            .unwrap()
+            .read_to_string(&mut meta)
+            .unwrap();
+        let meta: Vec<(String, Option<String>)> = serde_json::from_str(&meta).unwrap();
+        for (name, entry) in &meta {
+            let mut bytes = Vec::new();
+            zip.by_name(entry.as_deref().unwrap())
+                .unwrap()
+                .read_to_end(&mut bytes)
+                .unwrap();
+            self.media_usn += 1;
+            let sha1: String = Sha1::digest(&bytes)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            self.media.push((name.clone(), self.media_usn, sha1));
+            self.log.lock().unwrap().media.insert(name.clone(), bytes);
+        }
+        let reply = json!({ "data": [meta.len(), self.media_usn], "err": "" });
+        (200, reply.to_string().into_bytes())
+    }
+}
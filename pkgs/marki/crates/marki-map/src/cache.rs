@@ -6,20 +6,19 @@
 //! <cache_root>/render/<key>/
 //!     <layer>.svg               (one per layer)
 //!     sidecar.json
-//!     .ready                    (atomic completion marker)
+//!     .ready                    (atomic completion marker + manifest)
 //! ```
 //!
 //! Readers refuse to use a directory that's missing `.ready`, so a
-//! crash mid-write can't be observed as a "successful" cache hit.
+//! crash mid-write can't be observed as a "successful" cache hit. The
+//! marker format is shared with the other renderers; see
+//! [`marki_render::cache`].
 
 use crate::error::MapError;
+use marki_render::cache::READY_MARKER;
 use std::fs;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 
-/// Marker file that signals "this directory's contents are complete".
-const READY_MARKER: &str = ".ready";
-
 /// Compute the directory where a render with `cache_key` lives. Does
 /// not create or check the directory.
 pub fn render_dir(cache_root: &Path, cache_key: &str) -> PathBuf {
@@ -32,6 +31,12 @@ pub fn is_ready(cache_root: &Path, cache_key: &str) -> bool {
     render_dir(cache_root, cache_key).join(READY_MARKER).exists()
 }
 
+/// Record a cache hit by bumping the marker's mtime, which `marki cache
+/// prune` reads as "last used". Best-effort: a read-only cache is fine.
+pub fn touch(cache_root: &Path, cache_key: &str) {
+    marki_render::cache::touch(&render_dir(cache_root, cache_key));
+}
+
 /// One file to write inside a cache directory.
 pub struct CacheFile<'a> {
     pub name: &'a str,
@@ -53,28 +58,8 @@ pub fn write_atomic(
     if dir.join(READY_MARKER).exists() {
         return Ok(());
     }
-    fs::create_dir_all(&dir)?;
-
-    for f in files {
-        let p = dir.join(f.name);
-        // Tempfile + rename so concurrent readers can't see a partial
-        // file. Tempfile lives in the same dir to keep the rename
-        // atomic on every common filesystem.
-        let tmp = dir.join(format!(".{}.tmp", f.name));
-        {
-            let mut h = fs::File::create(&tmp)?;
-            h.write_all(f.bytes)?;
-            h.sync_all().ok();
-        }
-        fs::rename(&tmp, &p)?;
-    }
-
-    // Final marker — writing it last is the whole point.
-    let marker = dir.join(READY_MARKER);
-    let tmp_marker = dir.join(format!(".{READY_MARKER}.tmp"));
-    fs::File::create(&tmp_marker)?.sync_all().ok();
-    fs::rename(&tmp_marker, &marker)?;
-
+    let files: Vec<(&str, &[u8])> = files.iter().map(|f| (f.name, f.bytes)).collect();
+    marki_render::cache::write_atomic(&dir, &files)?;
     Ok(())
 }
 
@@ -163,6 +148,11 @@ mod tests {
         let mut listed = list_files(&root, key).unwrap();
         listed.sort();
         assert_eq!(listed, vec!["base.svg", "sidecar.json"]);
+
+        let marker = fs::read_to_string(render_dir(&root, key).join(READY_MARKER)).unwrap();
+        let manifest = marki_render::cache::parse_ready_manifest(&marker);
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest[0].0, marki_render::cache::content_digest(b"<svg/>"));
     }
 
     #[test]
@@ -0,0 +1,526 @@
+//! Choropleth layers: data table → class → ramp colour → legend.
+//!
+//! A choropleth layer carries a table of feature reference → value.
+//! Values are bucketed into `2..=9` classes (equal intervals, quantiles
+//! or explicit breaks), each class gets one colour interpolated from the
+//! ramp, and every feature is drawn under a per-class role
+//! (`class-0` … `class-8`) whose [`RoleStyle`] the pipeline injects into
+//! the layer's style. So `compose_layer` needs no special casing — a
+//! class is just another role.
+//!
+//! The legend is a small SVG fragment (swatch + range label per class)
+//! spliced into the same layer document, so it reveals with the data.
+
+use crate::compose::{LayerStyle, RoleStyle};
+use crate::dsl::{ChoroplethSpec, ClassMethod, InsetPosition, LegendSpec, MapSpec};
+use crate::error::MapError;
+use marki_render::escape_html as escape_attr;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Role names for classes, lowest first. Static so resolved layers can
+/// keep holding `&'static str` roles.
+pub const CLASS_ROLES: [&str; 9] = [
+    "class-0", "class-1", "class-2", "class-3", "class-4", "class-5", "class-6", "class-7",
+    "class-8",
+];
+
+/// Ramp used when neither the layer nor the theme supplies one.
+const FALLBACK_RAMP: [&str; 2] = ["#fee8c8", "#b30000"];
+
+/// Legend layout, in px.
+const LEGEND_MARGIN_PX: f64 = 8.0;
+const LEGEND_PAD_PX: f64 = 6.0;
+const LEGEND_ROW_PX: f64 = 16.0;
+const LEGEND_SWATCH_PX: f64 = 12.0;
+const LEGEND_FONT_PX: f64 = 11.0;
+/// Rough advance width of one label character at `LEGEND_FONT_PX`, for
+/// sizing the backdrop without a font engine.
+const LEGEND_CHAR_PX: f64 = 6.2;
+
+/// Fold every choropleth layer's CSV into its inline `data` table and
+/// clear `csv`. Relative paths resolve against `base_dir` (the card's
+/// directory) and must stay under `root` (the cards directory), so a card
+/// cannot read arbitrary files off the host. Inline entries win over CSV
+/// rows for the same reference.
+///
+/// Runs before the cache key is derived, so the key tracks the table's
+/// contents: editing the CSV re-renders, moving it does not.
+pub fn inline_tables(spec: &mut MapSpec, base_dir: &Path, root: &Path) -> Result<(), MapError> {
+    let root = root
+        .canonicalize()
+        .map_err(|e| MapError::Resolve(format!("cards directory {}: {e}", root.display())))?;
+    for lspec in spec.layers.values_mut() {
+        let Some(ch) = lspec.choropleth.as_mut() else {
+            continue;
+        };
+        let Some(csv) = ch.csv.take() else {
+            continue;
+        };
+        let path = base_dir.join(&csv).canonicalize().map_err(|e| {
+            MapError::Resolve(format!("choropleth csv {}: {e}", base_dir.join(&csv).display()))
+        })?;
+        if !path.starts_with(&root) {
+            return Err(MapError::Resolve(format!(
+                "choropleth csv {csv} is outside the cards directory"
+            )));
+        }
+        let text = std::fs::read_to_string(&path).map_err(|e| {
+            MapError::Resolve(format!("choropleth csv {}: {e}", path.display()))
+        })?;
+        let rows = parse_csv(&text, &ch.ref_column, &ch.value_column)
+            .map_err(|e| MapError::Parse(format!("choropleth csv {}: {e}", path.display())))?;
+        let inline = std::mem::take(&mut ch.data);
+        ch.data = rows.into_iter().collect();
+        ch.data.extend(inline);
+    }
+    Ok(())
+}
+
+/// Parse `text` as CSV with a header row and return `(ref, value)` for
+/// every data row.
+fn parse_csv(text: &str, ref_col: &str, value_col: &str) -> Result<Vec<(String, f64)>, String> {
+    let mut records = marki_render::csv::records(text)?.into_iter();
+    let header = records.next().ok_or("empty file")?;
+    let col = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| format!("no `{name}` column"))
+    };
+    let (ri, vi) = (col(ref_col)?, col(value_col)?);
+    let mut out = Vec::new();
+    for (n, fields) in records.enumerate() {
+        let get = |i: usize| fields.get(i).map(|f| f.trim()).unwrap_or("");
+        let r = get(ri);
+        if r.is_empty() {
+            return Err(format!("row {}: empty `{ref_col}`", n + 2));
+        }
+        let v: f64 = get(vi)
+            .parse()
+            .ok()
+            .filter(|v: &f64| v.is_finite())
+            .ok_or_else(|| format!("row {}: `{}` is not a number", n + 2, get(vi)))?;
+        out.push((r.to_string(), v));
+    }
+    Ok(out)
+}
+
+/// Resolved classing for one choropleth layer.
+#[derive(Debug, Clone)]
+pub struct Classes {
+    /// Class boundaries, one more than there are classes, ascending:
+    /// class `i` spans `bounds[i]` to `bounds[i + 1]`. Repeated values
+    /// can leave fewer classes than the spec asked for, and breaks at or
+    /// above the data maximum are dropped.
+    pub bounds: Vec<f64>,
+    /// One fill colour per class.
+    pub colors: Vec<String>,
+}
+
+impl Classes {
+    /// Derive classes from the spec's data. `theme_ramp` is used when
+    /// the layer sets no `ramp` of its own.
+    pub fn new(spec: &ChoroplethSpec, theme_ramp: &[String]) -> Result<Self, MapError> {
+        if let Some((r, v)) = spec.data.iter().find(|(_, v)| !v.is_finite()) {
+            return Err(MapError::Parse(format!("choropleth value for `{r}` is {v}, not a number")));
+        }
+        let mut values: Vec<f64> = spec.data.values().copied().collect();
+        if values.is_empty() {
+            return Err(MapError::Parse("choropleth has no numeric values".into()));
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let (lo, hi) = (values[0], values[values.len() - 1]);
+
+        let interior: Vec<f64> = if !spec.breaks.is_empty() {
+            // A break at or above the maximum would only add a top class
+            // that holds nothing (or just `hi`); the data range caps them.
+            spec.breaks.iter().copied().filter(|b| *b < hi).collect()
+        } else {
+            let n = spec.classes.clamp(2, 9) as usize;
+            match spec.method {
+                ClassMethod::Equal => {
+                    (1..n).map(|i| lo + (hi - lo) * i as f64 / n as f64).collect()
+                }
+                ClassMethod::Quantile => (1..n)
+                    .map(|i| {
+                        let idx = (values.len() * i / n).min(values.len() - 1);
+                        values[idx]
+                    })
+                    .collect(),
+            }
+        };
+        let mut bounds = Vec::with_capacity(interior.len() + 2);
+        bounds.push(lo.min(interior.first().copied().unwrap_or(lo)));
+        bounds.extend(interior);
+        // Repeated values give quantiles (and breaks) that coincide; each
+        // duplicate would be an empty class and a repeated legend row.
+        // The top class is closed, so `[hi, hi]` still holds `hi`.
+        bounds.dedup();
+        bounds.push(hi.max(*bounds.last().unwrap()));
+
+        let stops: Vec<String> = if !spec.ramp.is_empty() {
+            spec.ramp.clone()
+        } else if theme_ramp.len() >= 2 {
+            theme_ramp.to_vec()
+        } else {
+            FALLBACK_RAMP.iter().map(|s| s.to_string()).collect()
+        };
+        let colors = interpolate_ramp(&stops, bounds.len() - 1)?;
+        Ok(Self { bounds, colors })
+    }
+
+    /// Number of classes.
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Role for a value. Classes are half-open (`bounds[i] <= v <
+    /// bounds[i + 1]`) except the top one, which includes its upper
+    /// bound; values outside the data range clamp to the end classes.
+    pub fn role_for(&self, value: f64) -> &'static str {
+        let n = self.len();
+        let i = self.bounds[1..n]
+            .iter()
+            .position(|&b| value < b)
+            .unwrap_or(n - 1);
+        CLASS_ROLES[i]
+    }
+
+    /// Append one [`RoleStyle`] per class to `style`, borrowing stroke
+    /// and stroke width from the `outline` role so class borders match
+    /// the rest of the map.
+    pub fn install_roles(&self, style: &mut LayerStyle) {
+        let (stroke, sw) = style
+            .role("outline")
+            .map(|r| (r.stroke.clone(), r.stroke_width))
+            .unwrap_or_else(|| ("#333".to_string(), 1.0));
+        for (i, color) in self.colors.iter().enumerate() {
+            style.roles.push(RoleStyle {
+                role: CLASS_ROLES[i].to_string(),
+                fill: color.clone(),
+                stroke: stroke.clone(),
+                stroke_width: sw,
+                dash: None,
+            });
+        }
+    }
+}
+
+/// Interpolate `n` colours evenly along the ramp `stops` (linear RGB
+/// between consecutive stops).
+fn interpolate_ramp(stops: &[String], n: usize) -> Result<Vec<String>, MapError> {
+    let rgb: Vec<[f64; 3]> = stops
+        .iter()
+        .map(|s| parse_hex(s).ok_or_else(|| MapError::Parse(format!("bad ramp colour `{s}`"))))
+        .collect::<Result<_, _>>()?;
+    if rgb.len() < 2 {
+        return Err(MapError::Parse("choropleth ramp needs at least two colours".into()));
+    }
+    let segs = (rgb.len() - 1) as f64;
+    Ok((0..n)
+        .map(|i| {
+            let t = if n == 1 { 0.0 } else { i as f64 / (n - 1) as f64 } * segs;
+            let k = (t.floor() as usize).min(rgb.len() - 2);
+            let f = t - k as f64;
+            let (a, b) = (rgb[k], rgb[k + 1]);
+            let c = |j: usize| (a[j] + (b[j] - a[j]) * f).round() as u8;
+            format!("#{:02x}{:02x}{:02x}", c(0), c(1), c(2))
+        })
+        .collect())
+}
+
+/// Parse `#rgb` or `#rrggbb` into 0–255 channels.
+fn parse_hex(s: &str) -> Option<[f64; 3]> {
+    let h = s.strip_prefix('#')?;
+    let expand = |c: &str| u8::from_str_radix(c, 16).ok().map(f64::from);
+    match h.len() {
+        3 => {
+            let d: Vec<f64> = h
+                .chars()
+                .map(|c| expand(&c.to_string().repeat(2)))
+                .collect::<Option<_>>()?;
+            Some([d[0], d[1], d[2]])
+        }
+        6 => Some([expand(&h[0..2])?, expand(&h[2..4])?, expand(&h[4..6])?]),
+        _ => None,
+    }
+}
+
+/// Render the legend as an SVG fragment in absolute canvas coordinates.
+/// Returns an empty string when the legend is switched off.
+pub fn legend_svg(
+    classes: &Classes,
+    legend: &LegendSpec,
+    all_integer: bool,
+    canvas: (u32, u32),
+    style: &LayerStyle,
+) -> String {
+    if !legend.show || classes.is_empty() {
+        return String::new();
+    }
+    let decimals = legend.decimals.unwrap_or(if all_integer { 0 } else { 2 }) as usize;
+    let labels: Vec<String> = (0..classes.len())
+        .map(|i| {
+            format!(
+                "{:.*} – {:.*}",
+                decimals,
+                classes.bounds[i],
+                decimals,
+                classes.bounds[i + 1]
+            )
+        })
+        .collect();
+    let title_rows = if legend.title.is_some() { 1.0 } else { 0.0 };
+    let widest = labels
+        .iter()
+        .map(|l| l.chars().count() as f64 * LEGEND_CHAR_PX + LEGEND_SWATCH_PX + LEGEND_PAD_PX)
+        .chain(legend.title.iter().map(|t| t.chars().count() as f64 * LEGEND_CHAR_PX))
+        .fold(0.0, f64::max);
+    let box_w = widest + 2.0 * LEGEND_PAD_PX;
+    let box_h = (classes.len() as f64 + title_rows) * LEGEND_ROW_PX + 2.0 * LEGEND_PAD_PX;
+
+    let (w, h) = (canvas.0 as f64, canvas.1 as f64);
+    let right = (w - box_w - LEGEND_MARGIN_PX).max(0.0);
+    let bottom = (h - box_h - LEGEND_MARGIN_PX).max(0.0);
+    let (x, y) = match legend.position {
+        InsetPosition::TopLeft => (LEGEND_MARGIN_PX.min(right), LEGEND_MARGIN_PX.min(bottom)),
+        InsetPosition::TopRight => (right, LEGEND_MARGIN_PX.min(bottom)),
+        InsetPosition::BottomLeft => (LEGEND_MARGIN_PX.min(right), bottom),
+        InsetPosition::BottomRight => (right, bottom),
+    };
+
+    let bg = style.background.as_deref().unwrap_or("#fff");
+    let (ink, sw) = style
+        .role("outline")
+        .map(|r| (r.stroke.as_str(), r.stroke_width))
+        .unwrap_or(("#333", 1.0));
+    let ink = escape_attr(ink);
+
+    let mut out = String::with_capacity(512);
+    let _ = write!(
+        out,
+        "<g class=\"marki-map-legend\" font-family=\"sans-serif\" font-size=\"{LEGEND_FONT_PX}\">\
+         <rect x=\"{x:.2}\" y=\"{y:.2}\" width=\"{box_w:.2}\" height=\"{box_h:.2}\" \
+         fill=\"{bg}\" fill-opacity=\"0.85\" stroke=\"{ink}\" stroke-width=\"{sw}\"/>",
+        bg = escape_attr(bg),
+    );
+    let text_x = x + LEGEND_PAD_PX;
+    let mut row_y = y + LEGEND_PAD_PX;
+    if let Some(title) = &legend.title {
+        let _ = write!(
+            out,
+            "<text x=\"{text_x:.2}\" y=\"{:.2}\" fill=\"{ink}\" font-weight=\"bold\">{}</text>",
+            row_y + LEGEND_FONT_PX,
+            escape_attr(title),
+        );
+        row_y += LEGEND_ROW_PX;
+    }
+    for (color, label) in classes.colors.iter().zip(&labels) {
+        let _ = write!(
+            out,
+            "<rect x=\"{text_x:.2}\" y=\"{:.2}\" width=\"{LEGEND_SWATCH_PX}\" \
+             height=\"{LEGEND_SWATCH_PX}\" fill=\"{}\" stroke=\"{ink}\" stroke-width=\"0.5\"/>\
+             <text x=\"{:.2}\" y=\"{:.2}\" fill=\"{ink}\">{}</text>",
+            row_y + (LEGEND_ROW_PX - LEGEND_SWATCH_PX) * 0.5,
+            escape_attr(color),
+            text_x + LEGEND_SWATCH_PX + LEGEND_PAD_PX,
+            row_y + LEGEND_FONT_PX,
+            escape_attr(label),
+        );
+        row_y += LEGEND_ROW_PX;
+    }
+    out.push_str("</g>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn spec(values: &[(&str, f64)]) -> ChoroplethSpec {
+        ChoroplethSpec {
+            data: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect::<IndexMap<_, _>>(),
+            classes: 4,
+            ..ChoroplethSpec::default()
+        }
+    }
+
+    #[test]
+    fn equal_intervals_split_the_range() {
+        let s = spec(&[("a", 0.0), ("b", 10.0), ("c", 40.0)]);
+        let c = Classes::new(&s, &[]).unwrap();
+        assert_eq!(c.bounds, vec![0.0, 10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(c.len(), 4);
+        assert_eq!(c.role_for(0.0), "class-0");
+        assert_eq!(c.role_for(10.0), "class-1");
+        assert_eq!(c.role_for(40.0), "class-3");
+    }
+
+    #[test]
+    fn quantiles_balance_skewed_data() {
+        let mut s = spec(&[("a", 1.0), ("b", 2.0), ("c", 3.0), ("d", 1000.0)]);
+        s.method = ClassMethod::Quantile;
+        let c = Classes::new(&s, &[]).unwrap();
+        // One value per class instead of three in the bottom bucket.
+        let roles: Vec<_> = [1.0, 2.0, 3.0, 1000.0].iter().map(|v| c.role_for(*v)).collect();
+        assert_eq!(roles, vec!["class-0", "class-1", "class-2", "class-3"]);
+    }
+
+    #[test]
+    fn repeated_values_shrink_quantile_classes() {
+        let mut s = spec(&[
+            ("a", 1.0),
+            ("b", 1.0),
+            ("c", 1.0),
+            ("d", 1.0),
+            ("e", 1.0),
+            ("f", 1.0),
+            ("g", 2.0),
+            ("h", 3.0),
+        ]);
+        s.method = ClassMethod::Quantile;
+        let c = Classes::new(&s, &[]).unwrap();
+        assert_eq!(c.bounds, vec![1.0, 2.0, 3.0]);
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.role_for(1.0), "class-0");
+        assert_eq!(c.role_for(3.0), "class-1");
+
+        let flat = spec(&[("a", 5.0), ("b", 5.0)]);
+        let c = Classes::new(&flat, &[]).unwrap();
+        assert_eq!((c.bounds.clone(), c.len()), (vec![5.0, 5.0], 1));
+        assert_eq!(c.role_for(5.0), "class-0");
+    }
+
+    #[test]
+    fn explicit_breaks_override_classes() {
+        let mut s = spec(&[("a", 1.0), ("b", 50.0)]);
+        s.breaks = vec![10.0];
+        let c = Classes::new(&s, &[]).unwrap();
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.role_for(5.0), "class-0");
+        assert_eq!(c.role_for(10.0), "class-1");
+
+        // Breaks at or past the data maximum add no "200 – 200" row.
+        let mut s = spec(&[("a", 1.0), ("b", 200.0)]);
+        s.breaks = vec![100.0, 200.0, 300.0];
+        let c = Classes::new(&s, &[]).unwrap();
+        assert_eq!(c.bounds, vec![1.0, 100.0, 200.0]);
+        assert_eq!(c.role_for(200.0), "class-1");
+    }
+
+    #[test]
+    fn non_finite_values_are_rejected() {
+        let s = spec(&[("a", 1.0), ("b", f64::NAN)]);
+        let err = Classes::new(&s, &[]).unwrap_err();
+        assert!(err.to_string().contains("`b`"), "{err}");
+        assert!(Classes::new(&spec(&[("a", f64::INFINITY)]), &[]).is_err());
+        assert!(parse_csv("ref,value
+country/DEU,NaN
+", "ref", "value").is_err());
+        assert!(parse_csv("ref,value
+country/DEU,inf
+", "ref", "value").is_err());
+    }
+
+    #[test]
+    fn ramp_endpoints_are_exact() {
+        let stops = vec!["#000000".to_string(), "#fff".to_string()];
+        let colors = interpolate_ramp(&stops, 3).unwrap();
+        assert_eq!(colors, vec!["#000000", "#808080", "#ffffff"]);
+        assert!(interpolate_ramp(&["nope".to_string(), "#fff".to_string()], 2).is_err());
+    }
+
+    #[test]
+    fn theme_ramp_used_when_layer_has_none() {
+        let s = spec(&[("a", 0.0), ("b", 1.0)]);
+        let theme = vec!["#ff0000".to_string(), "#0000ff".to_string()];
+        let c = Classes::new(&s, &theme).unwrap();
+        assert_eq!(c.colors.first().unwrap(), "#ff0000");
+        assert_eq!(c.colors.last().unwrap(), "#0000ff");
+    }
+
+    #[test]
+    fn install_roles_adds_one_role_per_class() {
+        let s = spec(&[("a", 0.0), ("b", 1.0)]);
+        let c = Classes::new(&s, &[]).unwrap();
+        let mut style = LayerStyle::default();
+        c.install_roles(&mut style);
+        assert_eq!(style.roles.len(), 4);
+        assert_eq!(style.role("class-3").unwrap().fill, c.colors[3]);
+    }
+
+    #[test]
+    fn csv_parses_quoted_fields_and_custom_columns() {
+        let text = "name,iso,gdp\n\"Germany, Federal Republic\",country/DEU,48.7\n\nFrance,country/FRA,41\n";
+        let rows = parse_csv(text, "iso", "gdp").unwrap();
+        assert_eq!(
+            rows,
+            vec![("country/DEU".to_string(), 48.7), ("country/FRA".to_string(), 41.0)]
+        );
+        assert!(parse_csv(text, "missing", "gdp").is_err());
+        assert!(parse_csv("ref,value\ncountry/DEU,lots\n", "ref", "value").is_err());
+    }
+
+    #[test]
+    fn inline_tables_folds_csv_into_data() {
+        let dir = std::env::temp_dir().join(format!("marki-map-choro-{}", std::process::id()));
+        let cards = dir.join("cards");
+        std::fs::create_dir_all(&cards).unwrap();
+        std::fs::write(cards.join("gdp.csv"), "ref,value\ncountry/DEU,48\ncountry/FRA,41\n")
+            .unwrap();
+        std::fs::write(dir.join("secret.csv"), "ref,value\n").unwrap();
+        let mut spec = crate::dsl::parse_map_spec(
+            r#"
+[layers.base]
+features = ["continent/Europe"]
+[layers.gdp.choropleth]
+csv = "gdp.csv"
+[layers.gdp.choropleth.data]
+"country/FRA" = 42
+"#,
+        )
+        .unwrap();
+        let mut escaping = spec.clone();
+        inline_tables(&mut spec, &cards, &cards).unwrap();
+        let ch = spec.layers["gdp"].choropleth.as_ref().unwrap();
+        assert!(ch.csv.is_none());
+        assert_eq!(ch.data["country/DEU"], 48.0);
+        assert_eq!(ch.data["country/FRA"], 42.0, "inline entry wins");
+
+        let outside = |spec: &mut MapSpec, csv: String| {
+            spec.layers["gdp"].choropleth.as_mut().unwrap().csv = Some(csv);
+            inline_tables(spec, &cards, &cards).unwrap_err().to_string()
+        };
+        let err = outside(&mut escaping, "../secret.csv".into());
+        assert!(err.contains("outside the cards directory"), "{err}");
+        let err = outside(&mut escaping, dir.join("secret.csv").display().to_string());
+        assert!(err.contains("outside the cards directory"), "{err}");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn legend_lists_every_class() {
+        let s = spec(&[("a", 0.0), ("b", 40.0)]);
+        let c = Classes::new(&s, &[]).unwrap();
+        let legend = LegendSpec {
+            title: Some("GDP <USD>".into()),
+            ..LegendSpec::default()
+        };
+        let svg = legend_svg(&c, &legend, true, (600, 400), &LayerStyle::default());
+        assert!(svg.starts_with("<g class=\"marki-map-legend\""), "{svg}");
+        assert_eq!(svg.matches("<text").count(), 5, "title + 4 labels: {svg}");
+        assert!(svg.contains("0 – 10"), "integer labels: {svg}");
+        assert!(svg.contains("GDP &lt;USD&gt;"), "escaped title: {svg}");
+        let hidden = LegendSpec { show: false, ..LegendSpec::default() };
+        assert!(legend_svg(&c, &hidden, true, (600, 400), &LayerStyle::default()).is_empty());
+    }
+}
@@ -31,6 +31,8 @@ pub struct Feature<'a> {
 pub struct LayerStyle {
     pub background: Option<String>,
     pub roles: Vec<RoleStyle>,
+    /// Colour stops for choropleth classes (see [`crate::choropleth`]).
+    pub ramp: Vec<String>,
 }
 
 #[derive(Clone)]
@@ -39,6 +41,8 @@ pub struct RoleStyle {
     pub fill: String,
     pub stroke: String,
     pub stroke_width: f64,
+    /// SVG `stroke-dasharray`, for patterned lines (dashed routes).
+    pub dash: Option<String>,
 }
 
 impl LayerStyle {
@@ -151,11 +155,15 @@ pub fn compose_layer(
         let _ = write!(
             out,
             "<g fill=\"{fill}\" stroke=\"{stroke}\" stroke-width=\"{sw}\" \
-             stroke-linejoin=\"round\" stroke-linecap=\"round\">",
+             stroke-linejoin=\"round\" stroke-linecap=\"round\"",
             fill = escape_attr(&role_style.fill),
             stroke = escape_attr(&role_style.stroke),
             sw = role_style.stroke_width,
         );
+        if let Some(dash) = &role_style.dash {
+            let _ = write!(out, " stroke-dasharray=\"{}\"", escape_attr(dash));
+        }
+        out.push('>');
         for f in feats {
             if role == "hull" {
                 write_hull(&mut out, projector, f.geom, hull_radius_px);
@@ -175,6 +183,22 @@ pub fn compose_layer(
     out
 }
 
+/// Insert an overlay fragment (locator inset, choropleth legend) into a
+/// composed layer document, just before its closing `</svg>` so it
+/// stacks above the layer's own features.
+pub fn splice_overlay(layer_svg: &str, fragment: &str) -> String {
+    match layer_svg.rfind("</svg>") {
+        Some(i) => {
+            let mut out = String::with_capacity(layer_svg.len() + fragment.len());
+            out.push_str(&layer_svg[..i]);
+            out.push_str(fragment);
+            out.push_str(&layer_svg[i..]);
+            out
+        }
+        None => layer_svg.to_string(),
+    }
+}
+
 fn default_role_style(role: &str) -> RoleStyle {
     // Conservative defaults so a missing theme entry doesn't render
     // invisibly. Themes are expected to override.
@@ -184,13 +208,21 @@ fn default_role_style(role: &str) -> RoleStyle {
         "outline" => ("#eee", "#333"),
         "neighbor" => ("#ddd", "#888"),
         "coast" => ("none", "#36b"),
+        "marker" => ("#d334", "#d33"),
+        "route" | "route-dashed" | "route-dotted" => ("none", "#900"),
         _ => ("none", "#000"),
     };
+    let dash = match role {
+        "route-dashed" => Some("6 4"),
+        "route-dotted" => Some("0.1 3"),
+        _ => None,
+    };
     RoleStyle {
         role: role.to_string(),
         fill: fill.to_string(),
         stroke: stroke.to_string(),
         stroke_width: 1.0,
+        dash: dash.map(str::to_string),
     }
 }
 
@@ -631,15 +663,23 @@ mod tests {
         assert!(svg.contains("Z"), "{svg}");
     }
 
+    #[test]
+    fn splice_overlay_inserts_before_closing_tag() {
+        let out = splice_overlay("<svg><rect/></svg>", "<g/>");
+        assert_eq!(out, "<svg><rect/><g/></svg>");
+    }
+
     #[test]
     fn role_style_lookup() {
         let s = LayerStyle {
             background: None,
+            ramp: vec![],
             roles: vec![RoleStyle {
                 role: "highlight".into(),
                 fill: "#abc".into(),
                 stroke: "#000".into(),
                 stroke_width: 2.0,
+                dash: None,
             }],
         };
         assert!(s.role("highlight").is_some());
@@ -11,8 +11,9 @@
 //!     three-letter ISO code (`DEU`, `FRA`, …). Source: gbOpen ADM0.
 //!   * `adm1/<ISO3>/<NAME>`, `adm2/<ISO3>/<NAME>`, `adm3/<ISO3>/<NAME>`
 //!     — one administrative entry at the given geoBoundaries level,
-//!     keyed by the local `shapeName` (case-insensitive). Note: which
-//!     real-world division a level maps to varies by country (e.g.
+//!     keyed by the local `shapeName`. `adm<N>/<ISO 3166-2>` (e.g.
+//!     `adm1/DE-BY`) works where the data carries `shapeISO`. Note:
+//!     which real-world division a level maps to varies by country (e.g.
 //!     Italy ADM1 = statistical macroregions, ADM2 = regioni, ADM3 =
 //!     province), so authors choose the level that matches the country.
 //!   * `neighbors/<ISO3>` — every country sharing a border segment with
@@ -20,6 +21,22 @@
 //!     for island nations with no shared edges.
 //!   * `continent/<NAME>` and `subregion/<NAME>` — composite of every
 //!     country whose meta-CSV `Continent` / `UNSDG-subregion` matches.
+//!   * `place/<ISO3>/<NAME>` — a single point standing for the finest
+//!     admin unit of that name (ADM3, then ADM2, then ADM1), so `Paris`
+//!     lands on the commune rather than a same-named region.
+//!     `place/<ISO3>` is the country's point. Used for route endpoints.
+//!
+//! [`resolve_set`] evaluates set expressions ([`crate::set_expr`]) over
+//! the same index: `country/*`, `adm<N>/<ISO3>/*` and the composites
+//! expand to their member countries or units, which are combined,
+//! subtracted and filtered on `name`, `iso`, `continent` and `subregion`.
+//!
+//! Every country slot accepts the ISO3 code, the ISO2 code, the English
+//! `shapeName` or a bundled exonym (`Deutschland`, `UK`); unit names
+//! additionally match their ISO 3166-2 code and a small exonym table
+//! (`Bavaria` → `Bayern`). All matching goes through [`fold`], so case,
+//! diacritics, spaces and punctuation never matter (`Baden-Wurttemberg`
+//! finds `Baden-Württemberg`). A miss reports the closest candidates.
 //!
 //! The resolver caches the parsed dataset per process; subsequent
 //! lookups are in-memory.
@@ -30,24 +47,65 @@ use crate::data::geo_common::{
 };
 use crate::error::MapError;
 use crate::geometry::{BBox, Geometry, LonLat, Polygon};
-use std::collections::HashMap;
+use crate::set_expr::{SetExpr, SetOp};
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 use std::sync::{Mutex, OnceLock};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 /// Administrative levels bundled by the data derivation and exposed via
 /// `adm<N>/` references.
 const ADM_LEVELS: [u8; 3] = [1, 2, 3];
 
+/// Upper bound on the number of "did you mean" candidates in a miss.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Properties a set expression's `where` clause can test.
+const SET_PROPS: [&str; 4] = ["name", "iso", "continent", "subregion"];
+
+/// One admin unit plus its display name (for suggestions) and country.
+struct AdminUnit {
+    name: String,
+    iso: String,
+    feat: Feature,
+}
+
+/// One member of an expanded set expression. Ordered so that sets built
+/// from `HashMap` iteration still come out the same on every run.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Member {
+    /// ISO3.
+    Country(String),
+    /// Key into [`GbIndex::admin`].
+    Admin(u8, String, String),
+}
+
+/// A country's (continent, subregion) from the metadata CSV.
+type CountryGroups = (Option<String>, Option<String>);
+
 #[derive(Default)]
 struct GbIndex {
     /// Country polygon by ISO3 (gbOpen ADM0).
     countries: HashMap<String, Feature>,
-    /// Admin entries by (level, ISO3, lower_case shapeName).
-    admin: HashMap<(u8, String, String), Feature>,
+    /// ISO3 → ADM0 `shapeName`, for suggestions.
+    country_names: HashMap<String, String>,
+    /// Folded country key (ISO3, ISO2, name, exonym) → ISO3.
+    country_alias: HashMap<String, String>,
+    /// Admin entries by (level, ISO3, folded shapeName).
+    admin: HashMap<(u8, String, String), AdminUnit>,
+    /// Extra unit keys (ISO 3166-2 code, bare subdivision code, exonym)
+    /// by (level, ISO3, folded key) → folded shapeName in `admin`.
+    admin_alias: HashMap<(u8, String, String), String>,
+    /// Upper-case ISO 3166-2 code → (level, ISO3, folded shapeName), for
+    /// the country-less `adm<N>/<CODE>` form.
+    subdivisions: HashMap<String, (u8, String, String)>,
     /// Continent composites by lowercase continent name.
     continents: HashMap<String, Feature>,
     /// UN subregion composites by lowercase subregion name.
     subregions: HashMap<String, Feature>,
+    /// ISO3 → (continent, subregion).
+    country_groups: HashMap<String, CountryGroups>,
     /// ISO3 → bbox, used by the bbox-intersect neighbour fallback.
     country_bbox: HashMap<String, BBox>,
     /// ISO3 → sorted border-sharing ISO3s.
@@ -107,36 +165,19 @@ pub fn resolve_feature(name: &str) -> Result<Geometry, MapError> {
                 "country refs take no modifier; got `{name}`"
             )));
         }
-        let feat = idx
-            .countries
-            .get(rest)
-            .ok_or_else(|| MapError::Resolve(format!("unknown country: {rest}")))?;
-        return Ok(feat.geom.clone());
+        let iso = idx.country_iso(rest)?;
+        return Ok(idx.countries[iso].geom.clone());
     }
     for lvl in ADM_LEVELS {
         let prefix = format!("adm{lvl}/");
         if let Some(rest) = name.strip_prefix(&prefix) {
-            let (iso, unit) = rest
-                .split_once('/')
-                .ok_or_else(|| MapError::Resolve(format!("bad adm{lvl} ref: {name}")))?;
-            let key = (lvl, iso.to_string(), unit.to_lowercase());
-            return idx
-                .admin
-                .get(&key)
-                .map(|f| f.geom.clone())
-                .ok_or_else(|| MapError::Resolve(format!("unknown adm{lvl}: {iso}/{unit}")));
+            return idx.admin_unit(lvl, rest).map(|u| u.feat.geom.clone());
         }
     }
     if let Some(rest) = name.strip_prefix("neighbors/") {
-        idx.countries
-            .get(rest)
-            .ok_or_else(|| MapError::Resolve(format!("unknown country for neighbors: {rest}")))?;
-        let isos: Vec<&str> = match idx.neighbors.get(rest) {
-            Some(v) if !v.is_empty() => v.iter().map(|s| s.as_str()).collect(),
-            _ => fallback_bbox_neighbors(rest, idx),
-        };
+        let iso = idx.country_iso(rest)?;
         let mut polys: Vec<Polygon> = Vec::new();
-        for iso in isos {
+        for iso in idx.neighbor_isos(iso) {
             if let Some(feat) = idx.countries.get(iso) {
                 match &feat.geom {
                     Geometry::Polygon { outer, holes } => polys.push(Polygon {
@@ -174,9 +215,34 @@ pub fn resolve_feature(name: &str) -> Result<Geometry, MapError> {
             .map(|f| f.geom.clone())
             .ok_or_else(|| MapError::Resolve(format!("unknown subregion: {rest}")));
     }
+    if let Some(rest) = name.strip_prefix("place/") {
+        let feat = idx.place(rest)?;
+        return feat
+            .geom
+            .representative_point()
+            .map(Geometry::Point)
+            .ok_or_else(|| MapError::Resolve(format!("place has no geometry: {rest}")));
+    }
     Err(MapError::Resolve(format!("unsupported feature ref: {name}")))
 }
 
+/// Resolve a set expression to the combined geometry of its members.
+pub fn resolve_set(expr: &SetExpr) -> Result<Geometry, MapError> {
+    let idx = index()?;
+    let mut polys: Vec<Polygon> = Vec::new();
+    for m in idx.expand_set(expr)? {
+        match idx.member_geom(&m) {
+            Geometry::Polygon { outer, holes } => polys.push(Polygon {
+                outer: outer.clone(),
+                holes: holes.clone(),
+            }),
+            Geometry::MultiPolygon(ps) => polys.extend(ps.iter().cloned()),
+            _ => {}
+        }
+    }
+    Ok(Geometry::MultiPolygon(polys))
+}
+
 /// Fallback: ISOs whose bbox intersects the target's bbox. Used when the
 /// topological graph has no entries for the target (island nations).
 fn fallback_bbox_neighbors<'a>(iso: &str, idx: &'a GbIndex) -> Vec<&'a str> {
@@ -206,20 +272,21 @@ fn build_index(dir: &Path) -> Result<GbIndex, MapError> {
         let features = load_geojson(&entry)?;
         loaded_any = true;
         if lvl == 0 {
-            for (name, geom) in features {
-                let _ = name; // ADM0 shapeName is the country name; we key by ISO.
-                let feat = Feature::new(geom);
+            for rec in features {
+                let feat = Feature::new(rec.geom);
                 idx.country_bbox.insert(iso.clone(), feat.bbox);
                 idx.countries.insert(iso.clone(), feat);
+                if !rec.name.is_empty() {
+                    idx.country_names.insert(iso.clone(), rec.name);
+                }
             }
         } else if ADM_LEVELS.contains(&lvl) {
-            for (name, geom) in features {
-                let feat = Feature::new(geom);
-                let key = (lvl, iso.clone(), name.to_lowercase());
-                idx.admin.entry(key).or_insert(feat);
+            for rec in features {
+                idx.insert_admin(lvl, &iso, rec);
             }
         }
     }
+    idx.build_aliases();
 
     if !loaded_any {
         tracing::warn!(
@@ -266,9 +333,17 @@ fn parse_filename(path: &Path) -> Option<(String, u8)> {
     Some((iso.to_string(), lvl))
 }
 
-/// Parse a gbOpen GeoJSON FeatureCollection into `(shapeName, Geometry)`
-/// pairs. Only Polygon / MultiPolygon geometries are kept.
-fn load_geojson(path: &Path) -> Result<Vec<(String, Geometry)>, MapError> {
+/// One parsed GeoJSON feature: `shapeName`, optional `shapeISO`
+/// (ISO 3166-2, empty in parts of the dataset) and geometry.
+struct ShapeRecord {
+    name: String,
+    code: Option<String>,
+    geom: Geometry,
+}
+
+/// Parse a gbOpen GeoJSON FeatureCollection into [`ShapeRecord`]s. Only
+/// Polygon / MultiPolygon geometries are kept.
+fn load_geojson(path: &Path) -> Result<Vec<ShapeRecord>, MapError> {
     let bytes = std::fs::read(path)
         .map_err(|e| MapError::Resolve(format!("read {}: {e}", path.display())))?;
     let v: serde_json::Value = serde_json::from_slice(&bytes)
@@ -280,19 +355,22 @@ fn load_geojson(path: &Path) -> Result<Vec<(String, Geometry)>, MapError> {
 
     let mut out = Vec::with_capacity(features.len());
     for feat in features {
-        let name = feat
-            .get("properties")
-            .and_then(|p| p.get("shapeName"))
-            .and_then(|n| n.as_str())
-            .unwrap_or("")
-            .trim()
-            .to_string();
+        let prop = |key: &str| {
+            feat.get("properties")
+                .and_then(|p| p.get(key))
+                .and_then(|n| n.as_str())
+                .unwrap_or("")
+                .trim()
+                .to_string()
+        };
+        let name = prop("shapeName");
+        let code = Some(prop("shapeISO")).filter(|c| c.contains('-'));
         let geom = match feat.get("geometry") {
             Some(g) => g,
             None => continue,
         };
-        if let Some(g) = json_to_geometry(geom) {
-            out.push((name, g));
+        if let Some(geom) = json_to_geometry(geom) {
+            out.push(ShapeRecord { name, code, geom });
         }
     }
     Ok(out)
@@ -362,6 +440,512 @@ fn rings_to_geometry(rings: Vec<Vec<LonLat>>) -> Geometry {
     }
 }
 
+// ---------- name / alias lookup ----------
+
+impl GbIndex {
+    /// Index one admin unit under its folded `shapeName`, plus its ISO
+    /// 3166-2 code (full `DE-BY` and bare `BY`) when the data has one.
+    /// First entry wins on folded-name collisions.
+    fn insert_admin(&mut self, lvl: u8, iso: &str, rec: ShapeRecord) {
+        let key = fold(&rec.name);
+        if key.is_empty() || self.admin.contains_key(&(lvl, iso.to_string(), key.clone())) {
+            return;
+        }
+        if let Some(code) = &rec.code {
+            let upper = code.to_uppercase();
+            self.subdivisions
+                .entry(upper.clone())
+                .or_insert_with(|| (lvl, iso.to_string(), key.clone()));
+            let bare = upper.split_once('-').map(|(_, b)| b).unwrap_or("");
+            for alias in [fold(&upper), fold(bare)] {
+                if !alias.is_empty() {
+                    self.admin_alias
+                        .entry((lvl, iso.to_string(), alias))
+                        .or_insert_with(|| key.clone());
+                }
+            }
+        }
+        let feat = Feature::new(rec.geom);
+        let unit = AdminUnit { name: rec.name, iso: iso.to_string(), feat };
+        self.admin.insert((lvl, iso.to_string(), key), unit);
+    }
+
+    /// Populate `country_alias` (ISO3, ISO2, `shapeName`, bundled
+    /// exonyms) and the admin exonyms. Only targets present in the data
+    /// are registered; real names always beat aliases.
+    fn build_aliases(&mut self) {
+        for (iso, name) in &self.country_names {
+            self.country_alias.insert(fold(name), iso.clone());
+        }
+        for iso in self.countries.keys() {
+            self.country_alias.insert(fold(iso), iso.clone());
+        }
+        for (iso2, iso3) in ISO2_TO_ISO3 {
+            if self.countries.contains_key(*iso3) {
+                self.country_alias.entry(fold(iso2)).or_insert_with(|| iso3.to_string());
+            }
+        }
+        for (alias, iso3) in COUNTRY_EXONYMS {
+            if self.countries.contains_key(*iso3) {
+                self.country_alias.entry(fold(alias)).or_insert_with(|| iso3.to_string());
+            }
+        }
+        for (lvl, iso, name, aliases) in ADMIN_EXONYMS {
+            let target = fold(name);
+            if !self.admin.contains_key(&(*lvl, iso.to_string(), target.clone())) {
+                continue;
+            }
+            for alias in *aliases {
+                let key = (*lvl, iso.to_string(), fold(alias));
+                if !self.admin.contains_key(&key) {
+                    self.admin_alias.entry(key).or_insert_with(|| target.clone());
+                }
+            }
+        }
+    }
+
+    /// Map any accepted country spelling to its ISO3 key.
+    fn country_iso(&self, query: &str) -> Result<&str, MapError> {
+        if let Some((iso, _)) = self.countries.get_key_value(query) {
+            return Ok(iso);
+        }
+        let key = fold(query);
+        if let Some(iso) = self.country_alias.get(&key) {
+            return Ok(iso);
+        }
+        let candidates = self.country_alias.iter().map(|(alias, iso)| {
+            let label = match self.country_names.get(iso) {
+                Some(name) => format!("{name} ({iso})"),
+                None => iso.clone(),
+            };
+            (alias.as_str(), label)
+        });
+        Err(MapError::Resolve(format!(
+            "unknown country: {query}{}",
+            did_you_mean(&suggest(&key, candidates))
+        )))
+    }
+
+    /// Resolve the part of an `adm<N>/` ref after the prefix: either
+    /// `<country>/<unit>` or a bare ISO 3166-2 code.
+    fn admin_unit(&self, lvl: u8, rest: &str) -> Result<&AdminUnit, MapError> {
+        let (country, unit) = match rest.split_once('/') {
+            Some(v) => v,
+            None => return self.subdivision(lvl, rest),
+        };
+        let iso = self.country_iso(country)?;
+        let key = fold(unit);
+        let scoped = |k: &str| (lvl, iso.to_string(), k.to_string());
+        if let Some(u) = self.admin.get(&scoped(&key)) {
+            return Ok(u);
+        }
+        let via_alias = self.admin_alias.get(&scoped(&key));
+        if let Some(u) = via_alias.and_then(|k| self.admin.get(&scoped(k))) {
+            return Ok(u);
+        }
+        let names = self
+            .admin
+            .iter()
+            .filter(|((l, i, _), _)| *l == lvl && i == iso)
+            .map(|((_, _, k), u)| (k.as_str(), u.name.clone()));
+        let aliases = self
+            .admin_alias
+            .iter()
+            .filter(|((l, i, _), _)| *l == lvl && i == iso)
+            .filter_map(|((_, _, k), target)| {
+                self.admin.get(&scoped(target)).map(|u| (k.as_str(), u.name.clone()))
+            });
+        Err(MapError::Resolve(format!(
+            "unknown adm{lvl}: {iso}/{unit}{}",
+            did_you_mean(&suggest(&key, names.chain(aliases)))
+        )))
+    }
+
+    /// `place/` lookup: `<country>/<name>` searches the finest level
+    /// first, a bare `<country>` is the country itself.
+    fn place(&self, rest: &str) -> Result<&Feature, MapError> {
+        let (country, unit) = match rest.split_once('/') {
+            Some(v) => v,
+            None => return Ok(&self.countries[self.country_iso(rest)?]),
+        };
+        let iso = self.country_iso(country)?;
+        let key = fold(unit);
+        for &lvl in ADM_LEVELS.iter().rev() {
+            let scoped = |k: &str| (lvl, iso.to_string(), k.to_string());
+            if let Some(u) = self.admin.get(&scoped(&key)) {
+                return Ok(&u.feat);
+            }
+            let via_alias = self.admin_alias.get(&scoped(&key));
+            if let Some(u) = via_alias.and_then(|k| self.admin.get(&scoped(k))) {
+                return Ok(&u.feat);
+            }
+        }
+        let names = self
+            .admin
+            .iter()
+            .filter(|((_, i, _), _)| i == iso)
+            .map(|((_, _, k), u)| (k.as_str(), u.name.clone()));
+        Err(MapError::Resolve(format!(
+            "unknown place: {iso}/{unit}{}",
+            did_you_mean(&suggest(&key, names))
+        )))
+    }
+
+    /// Border-sharing countries of `iso`, or bbox-intersecting ones for
+    /// islands.
+    fn neighbor_isos(&self, iso: &str) -> Vec<&str> {
+        match self.neighbors.get(iso) {
+            Some(v) if !v.is_empty() => v.iter().map(|s| s.as_str()).collect(),
+            _ => fallback_bbox_neighbors(iso, self),
+        }
+    }
+
+    /// Members of a set expression: its terms combined left to right,
+    /// then filtered. Differences remove whole members and never cut
+    /// geometry, so a term that removes nothing (`country/DEU -
+    /// adm1/DEU/Bayern`) is an error rather than a silent no-op.
+    fn expand_set(&self, expr: &SetExpr) -> Result<BTreeSet<Member>, MapError> {
+        for f in &expr.filters {
+            if !SET_PROPS.contains(&f.prop.as_str()) {
+                return Err(MapError::Resolve(format!(
+                    "unknown property `{}` in set expression (known: {})",
+                    f.prop,
+                    SET_PROPS.join(", ")
+                )));
+            }
+        }
+        let mut set = BTreeSet::new();
+        for (op, term) in &expr.terms {
+            let members = self.members(term)?;
+            match op {
+                SetOp::Union => set.extend(members),
+                SetOp::Difference => {
+                    let before = set.len();
+                    set.retain(|m| !members.contains(m));
+                    if set.len() == before {
+                        return Err(MapError::Resolve(format!(
+                            "`- {term}` removes nothing; differences remove whole \
+                             countries or units of the same level"
+                        )));
+                    }
+                }
+            }
+        }
+        for f in &expr.filters {
+            let want = fold(&f.value);
+            set.retain(|m| {
+                let hit = self.member_prop(m, &f.prop).is_some_and(|v| fold(v) == want);
+                hit != f.negate
+            });
+        }
+        if set.is_empty() {
+            let terms: Vec<&str> = expr.terms.iter().map(|(_, t)| t.as_str()).collect();
+            return Err(MapError::Resolve(format!(
+                "set expression matched nothing: {}",
+                terms.join(", ")
+            )));
+        }
+        Ok(set)
+    }
+
+    /// Expand one set-expression term into its members.
+    fn members(&self, term: &str) -> Result<BTreeSet<Member>, MapError> {
+        let countries = |isos: &mut dyn Iterator<Item = &str>| {
+            isos.map(|i| Member::Country(i.to_string())).collect::<BTreeSet<_>>()
+        };
+        if term == "country/*" {
+            return Ok(countries(&mut self.countries.keys().map(String::as_str)));
+        }
+        if let Some(rest) = term.strip_prefix("country/") {
+            return Ok(countries(&mut std::iter::once(self.country_iso(rest)?)));
+        }
+        for lvl in ADM_LEVELS {
+            let Some(rest) = term.strip_prefix(&format!("adm{lvl}/")) else {
+                continue;
+            };
+            if let Some(country) = rest.strip_suffix("/*") {
+                let iso = self.country_iso(country)?;
+                let units: BTreeSet<_> = self
+                    .admin
+                    .keys()
+                    .filter(|(l, i, _)| *l == lvl && i == iso)
+                    .map(|(l, i, k)| Member::Admin(*l, i.clone(), k.clone()))
+                    .collect();
+                if units.is_empty() {
+                    return Err(MapError::Resolve(format!("no adm{lvl} units for {iso}")));
+                }
+                return Ok(units);
+            }
+            let u = self.admin_unit(lvl, rest)?;
+            return Ok(BTreeSet::from([Member::Admin(lvl, u.iso.clone(), fold(&u.name))]));
+        }
+        if let Some(rest) = term.strip_prefix("neighbors/") {
+            let iso = self.country_iso(rest)?;
+            return Ok(countries(&mut self.neighbor_isos(iso).into_iter()));
+        }
+        let group = |name: &str, pick: fn(&CountryGroups) -> &Option<String>| {
+            let want = name.to_lowercase();
+            self.country_groups
+                .iter()
+                .filter(|(iso, g)| {
+                    self.countries.contains_key(*iso)
+                        && pick(g).as_ref().is_some_and(|v| v.to_lowercase() == want)
+                })
+                .map(|(iso, _)| Member::Country(iso.clone()))
+                .collect::<BTreeSet<_>>()
+        };
+        if let Some(rest) = term.strip_prefix("continent/") {
+            let set = group(rest, |g| &g.0);
+            if set.is_empty() {
+                return Err(MapError::Resolve(format!("unknown continent: {rest}")));
+            }
+            return Ok(set);
+        }
+        if let Some(rest) = term.strip_prefix("subregion/") {
+            let set = group(rest, |g| &g.1);
+            if set.is_empty() {
+                return Err(MapError::Resolve(format!("unknown subregion: {rest}")));
+            }
+            return Ok(set);
+        }
+        Err(MapError::Resolve(format!(
+            "`{term}` can't be part of a set expression \
+             (use country/, adm<N>/, neighbors/, continent/ or subregion/)"
+        )))
+    }
+
+    /// A `where` property of a member. Units inherit their country's
+    /// `iso`, `continent` and `subregion`.
+    fn member_prop<'a>(&'a self, m: &'a Member, prop: &str) -> Option<&'a str> {
+        let iso = match m {
+            Member::Country(iso) | Member::Admin(_, iso, _) => iso.as_str(),
+        };
+        let groups = self.country_groups.get(iso);
+        match prop {
+            "name" => match m {
+                Member::Country(iso) => self.country_names.get(iso).map(String::as_str),
+                Member::Admin(..) => Some(self.member_unit(m)?.name.as_str()),
+            },
+            "iso" => Some(iso),
+            "continent" => groups?.0.as_deref(),
+            "subregion" => groups?.1.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn member_unit(&self, m: &Member) -> Option<&AdminUnit> {
+        match m {
+            Member::Admin(lvl, iso, key) => self.admin.get(&(*lvl, iso.clone(), key.clone())),
+            Member::Country(_) => None,
+        }
+    }
+
+    fn member_geom(&self, m: &Member) -> &Geometry {
+        match m {
+            Member::Country(iso) => &self.countries[iso].geom,
+            Member::Admin(..) => {
+                &self.member_unit(m).expect("members come from the index").feat.geom
+            }
+        }
+    }
+
+    /// `adm<N>/DE-BY`: look the unit up by ISO 3166-2 code alone.
+    fn subdivision(&self, lvl: u8, code: &str) -> Result<&AdminUnit, MapError> {
+        if !code.contains('-') {
+            return Err(MapError::Resolve(format!(
+                "bad adm{lvl} ref: adm{lvl}/{code} \
+                 (expected `<country>/<name>` or an ISO 3166-2 code)"
+            )));
+        }
+        match self.subdivisions.get(&code.to_uppercase()) {
+            Some((l, iso, key)) if *l == lvl => Ok(&self.admin[&(*l, iso.clone(), key.clone())]),
+            Some((l, _, _)) => Err(MapError::Resolve(format!(
+                "{code} is an adm{l} unit, not adm{lvl}"
+            ))),
+            None => Err(MapError::Resolve(format!("unknown ISO 3166-2 code: {code}"))),
+        }
+    }
+}
+
+/// Matching key for names and codes: NFKD-decompose, drop combining
+/// marks, lowercase, spell out letters that don't decompose (`ß`, `ø`,
+/// `æ`, …) and keep only alphanumerics. `Baden-Württemberg`,
+/// `baden wurttemberg` and `BADEN-WURTTEMBERG` all fold to the same key.
+fn fold(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.nfkd().filter(|c| !is_combining_mark(*c)) {
+        for c in c.to_lowercase() {
+            match c {
+                'ß' => out.push_str("ss"),
+                'æ' => out.push_str("ae"),
+                'œ' => out.push_str("oe"),
+                'þ' => out.push_str("th"),
+                'ø' => out.push('o'),
+                'ł' => out.push('l'),
+                'đ' => out.push('d'),
+                'ı' => out.push('i'),
+                c if c.is_alphanumeric() => out.push(c),
+                _ => {}
+            }
+        }
+    }
+    out
+}
+
+/// Up to [`MAX_SUGGESTIONS`] labels whose folded key is close to
+/// `query` (edit distance, or containment for 3+ char queries), best
+/// first. Duplicate labels (a unit reachable via several aliases) keep
+/// their best score.
+fn suggest<'a>(query: &str, candidates: impl Iterator<Item = (&'a str, String)>) -> Vec<String> {
+    let limit = (query.chars().count() / 3).max(2);
+    let mut best: HashMap<String, usize> = HashMap::new();
+    for (key, label) in candidates {
+        let d = if query.len() >= 3 && (key.contains(query) || query.contains(key)) {
+            1
+        } else {
+            edit_distance(query, key)
+        };
+        if d <= limit {
+            let slot = best.entry(label).or_insert(d);
+            *slot = (*slot).min(d);
+        }
+    }
+    let mut ranked: Vec<(usize, String)> = best.into_iter().map(|(l, d)| (d, l)).collect();
+    ranked.sort();
+    ranked.into_iter().take(MAX_SUGGESTIONS).map(|(_, l)| l).collect()
+}
+
+/// `"; did you mean `A`, `B`?"`, or empty when there's nothing close.
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        return String::new();
+    }
+    let list: Vec<String> = suggestions.iter().map(|s| format!("`{s}`")).collect();
+    format!("; did you mean {}?", list.join(", "))
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + usize::from(ca != *cb);
+            cur[j + 1] = sub.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// ISO 3166-1 alpha-2 → alpha-3. `XK`/`XKX` is the user-assigned code
+/// geoBoundaries uses for Kosovo.
+const ISO2_TO_ISO3: &[(&str, &str)] = &[
+    ("AD", "AND"), ("AE", "ARE"), ("AF", "AFG"), ("AG", "ATG"), ("AI", "AIA"), ("AL", "ALB"),
+    ("AM", "ARM"), ("AO", "AGO"), ("AQ", "ATA"), ("AR", "ARG"), ("AS", "ASM"), ("AT", "AUT"),
+    ("AU", "AUS"), ("AW", "ABW"), ("AX", "ALA"), ("AZ", "AZE"), ("BA", "BIH"), ("BB", "BRB"),
+    ("BD", "BGD"), ("BE", "BEL"), ("BF", "BFA"), ("BG", "BGR"), ("BH", "BHR"), ("BI", "BDI"),
+    ("BJ", "BEN"), ("BL", "BLM"), ("BM", "BMU"), ("BN", "BRN"), ("BO", "BOL"), ("BQ", "BES"),
+    ("BR", "BRA"), ("BS", "BHS"), ("BT", "BTN"), ("BV", "BVT"), ("BW", "BWA"), ("BY", "BLR"),
+    ("BZ", "BLZ"), ("CA", "CAN"), ("CC", "CCK"), ("CD", "COD"), ("CF", "CAF"), ("CG", "COG"),
+    ("CH", "CHE"), ("CI", "CIV"), ("CK", "COK"), ("CL", "CHL"), ("CM", "CMR"), ("CN", "CHN"),
+    ("CO", "COL"), ("CR", "CRI"), ("CU", "CUB"), ("CV", "CPV"), ("CW", "CUW"), ("CX", "CXR"),
+    ("CY", "CYP"), ("CZ", "CZE"), ("DE", "DEU"), ("DJ", "DJI"), ("DK", "DNK"), ("DM", "DMA"),
+    ("DO", "DOM"), ("DZ", "DZA"), ("EC", "ECU"), ("EE", "EST"), ("EG", "EGY"), ("EH", "ESH"),
+    ("ER", "ERI"), ("ES", "ESP"), ("ET", "ETH"), ("FI", "FIN"), ("FJ", "FJI"), ("FK", "FLK"),
+    ("FM", "FSM"), ("FO", "FRO"), ("FR", "FRA"), ("GA", "GAB"), ("GB", "GBR"), ("GD", "GRD"),
+    ("GE", "GEO"), ("GF", "GUF"), ("GG", "GGY"), ("GH", "GHA"), ("GI", "GIB"), ("GL", "GRL"),
+    ("GM", "GMB"), ("GN", "GIN"), ("GP", "GLP"), ("GQ", "GNQ"), ("GR", "GRC"), ("GS", "SGS"),
+    ("GT", "GTM"), ("GU", "GUM"), ("GW", "GNB"), ("GY", "GUY"), ("HK", "HKG"), ("HM", "HMD"),
+    ("HN", "HND"), ("HR", "HRV"), ("HT", "HTI"), ("HU", "HUN"), ("ID", "IDN"), ("IE", "IRL"),
+    ("IL", "ISR"), ("IM", "IMN"), ("IN", "IND"), ("IO", "IOT"), ("IQ", "IRQ"), ("IR", "IRN"),
+    ("IS", "ISL"), ("IT", "ITA"), ("JE", "JEY"), ("JM", "JAM"), ("JO", "JOR"), ("JP", "JPN"),
+    ("KE", "KEN"), ("KG", "KGZ"), ("KH", "KHM"), ("KI", "KIR"), ("KM", "COM"), ("KN", "KNA"),
+    ("KP", "PRK"), ("KR", "KOR"), ("KW", "KWT"), ("KY", "CYM"), ("KZ", "KAZ"), ("LA", "LAO"),
+    ("LB", "LBN"), ("LC", "LCA"), ("LI", "LIE"), ("LK", "LKA"), ("LR", "LBR"), ("LS", "LSO"),
+    ("LT", "LTU"), ("LU", "LUX"), ("LV", "LVA"), ("LY", "LBY"), ("MA", "MAR"), ("MC", "MCO"),
+    ("MD", "MDA"), ("ME", "MNE"), ("MF", "MAF"), ("MG", "MDG"), ("MH", "MHL"), ("MK", "MKD"),
+    ("ML", "MLI"), ("MM", "MMR"), ("MN", "MNG"), ("MO", "MAC"), ("MP", "MNP"), ("MQ", "MTQ"),
+    ("MR", "MRT"), ("MS", "MSR"), ("MT", "MLT"), ("MU", "MUS"), ("MV", "MDV"), ("MW", "MWI"),
+    ("MX", "MEX"), ("MY", "MYS"), ("MZ", "MOZ"), ("NA", "NAM"), ("NC", "NCL"), ("NE", "NER"),
+    ("NF", "NFK"), ("NG", "NGA"), ("NI", "NIC"), ("NL", "NLD"), ("NO", "NOR"), ("NP", "NPL"),
+    ("NR", "NRU"), ("NU", "NIU"), ("NZ", "NZL"), ("OM", "OMN"), ("PA", "PAN"), ("PE", "PER"),
+    ("PF", "PYF"), ("PG", "PNG"), ("PH", "PHL"), ("PK", "PAK"), ("PL", "POL"), ("PM", "SPM"),
+    ("PN", "PCN"), ("PR", "PRI"), ("PS", "PSE"), ("PT", "PRT"), ("PW", "PLW"), ("PY", "PRY"),
+    ("QA", "QAT"), ("RE", "REU"), ("RO", "ROU"), ("RS", "SRB"), ("RU", "RUS"), ("RW", "RWA"),
+    ("SA", "SAU"), ("SB", "SLB"), ("SC", "SYC"), ("SD", "SDN"), ("SE", "SWE"), ("SG", "SGP"),
+    ("SH", "SHN"), ("SI", "SVN"), ("SJ", "SJM"), ("SK", "SVK"), ("SL", "SLE"), ("SM", "SMR"),
+    ("SN", "SEN"), ("SO", "SOM"), ("SR", "SUR"), ("SS", "SSD"), ("ST", "STP"), ("SV", "SLV"),
+    ("SX", "SXM"), ("SY", "SYR"), ("SZ", "SWZ"), ("TC", "TCA"), ("TD", "TCD"), ("TF", "ATF"),
+    ("TG", "TGO"), ("TH", "THA"), ("TJ", "TJK"), ("TK", "TKL"), ("TL", "TLS"), ("TM", "TKM"),
+    ("TN", "TUN"), ("TO", "TON"), ("TR", "TUR"), ("TT", "TTO"), ("TV", "TUV"), ("TW", "TWN"),
+    ("TZ", "TZA"), ("UA", "UKR"), ("UG", "UGA"), ("UM", "UMI"), ("US", "USA"), ("UY", "URY"),
+    ("UZ", "UZB"), ("VA", "VAT"), ("VC", "VCT"), ("VE", "VEN"), ("VG", "VGB"), ("VI", "VIR"),
+    ("VN", "VNM"), ("VU", "VUT"), ("WF", "WLF"), ("WS", "WSM"), ("XK", "XKX"), ("YE", "YEM"),
+    ("YT", "MYT"), ("ZA", "ZAF"), ("ZM", "ZMB"), ("ZW", "ZWE"),
+];
+
+/// Country spellings that differ from the geoBoundaries English
+/// `shapeName`: endonyms, short forms and former names.
+const COUNTRY_EXONYMS: &[(&str, &str)] = &[
+    ("Deutschland", "DEU"), ("Österreich", "AUT"), ("Schweiz", "CHE"),
+    ("Suisse", "CHE"), ("Svizzera", "CHE"), ("España", "ESP"), ("Italia", "ITA"),
+    ("Nederland", "NLD"), ("Holland", "NLD"), ("België", "BEL"), ("Belgique", "BEL"),
+    ("Polska", "POL"), ("Česko", "CZE"), ("Czechia", "CZE"), ("Czech Republic", "CZE"),
+    ("Sverige", "SWE"), ("Norge", "NOR"), ("Suomi", "FIN"), ("Danmark", "DNK"),
+    ("Éire", "IRL"), ("Hellas", "GRC"), ("Türkiye", "TUR"), ("Turkey", "TUR"),
+    ("UK", "GBR"), ("Great Britain", "GBR"), ("United Kingdom", "GBR"), ("Britain", "GBR"),
+    ("United States", "USA"), ("America", "USA"), ("Russia", "RUS"),
+    ("Nippon", "JPN"), ("Zhongguo", "CHN"), ("South Korea", "KOR"), ("North Korea", "PRK"),
+    ("Vietnam", "VNM"), ("Laos", "LAO"), ("Iran", "IRN"), ("Syria", "SYR"), ("Burma", "MMR"),
+    ("Ivory Coast", "CIV"), ("Côte d'Ivoire", "CIV"), ("Swaziland", "SWZ"), ("Eswatini", "SWZ"),
+    ("Macedonia", "MKD"), ("North Macedonia", "MKD"), ("Moldova", "MDA"), ("Bolivia", "BOL"),
+    ("Venezuela", "VEN"), ("Tanzania", "TZA"), ("Taiwan", "TWN"), ("Vatican", "VAT"),
+    ("Cape Verde", "CPV"), ("East Timor", "TLS"), ("DR Congo", "COD"), ("DRC", "COD"),
+    ("Congo-Kinshasa", "COD"), ("Congo-Brazzaville", "COG"), ("Brasil", "BRA"), ("México", "MEX"),
+];
+
+/// Exonyms for admin units whose `shapeName` is the local form:
+/// (level, ISO3, shapeName, aliases). Entries whose target isn't in the
+/// loaded data are skipped.
+const ADMIN_EXONYMS: &[(u8, &str, &str, &[&str])] = &[
+    (1, "DEU", "Bayern", &["Bavaria"]),
+    (1, "DEU", "Niedersachsen", &["Lower Saxony"]),
+    (1, "DEU", "Nordrhein-Westfalen", &["North Rhine-Westphalia", "NRW"]),
+    (1, "DEU", "Rheinland-Pfalz", &["Rhineland-Palatinate"]),
+    (1, "DEU", "Sachsen", &["Saxony"]),
+    (1, "DEU", "Sachsen-Anhalt", &["Saxony-Anhalt"]),
+    (1, "DEU", "Thüringen", &["Thuringia"]),
+    (1, "DEU", "Hessen", &["Hesse"]),
+    (1, "DEU", "Mecklenburg-Vorpommern", &["Mecklenburg-Western Pomerania"]),
+    (1, "AUT", "Niederösterreich", &["Lower Austria"]),
+    (1, "AUT", "Oberösterreich", &["Upper Austria"]),
+    (1, "AUT", "Steiermark", &["Styria"]),
+    (1, "AUT", "Kärnten", &["Carinthia"]),
+    (1, "AUT", "Tirol", &["Tyrol"]),
+    (1, "AUT", "Wien", &["Vienna"]),
+    (1, "CHE", "Genève", &["Geneva", "Genf"]),
+    (1, "CHE", "Zürich", &["Zurich"]),
+    (1, "CHE", "Bern", &["Berne"]),
+    (1, "CHE", "Graubünden", &["Grisons"]),
+    (1, "CHE", "Ticino", &["Tessin"]),
+    (2, "ITA", "Lombardia", &["Lombardy"]),
+    (2, "ITA", "Piemonte", &["Piedmont"]),
+    (2, "ITA", "Toscana", &["Tuscany"]),
+    (2, "ITA", "Sardegna", &["Sardinia"]),
+    (2, "ITA", "Sicilia", &["Sicily"]),
+    (2, "ITA", "Puglia", &["Apulia"]),
+    (1, "ESP", "Andalucía", &["Andalusia"]),
+    (1, "ESP", "Cataluña", &["Catalonia", "Catalunya"]),
+    (1, "ESP", "Castilla y León", &["Castile and León"]),
+    (1, "ESP", "Castilla-La Mancha", &["Castile-La Mancha"]),
+    (1, "ESP", "País Vasco", &["Basque Country", "Euskadi"]),
+    (1, "BEL", "Vlaanderen", &["Flanders", "Flemish Region"]),
+    (1, "BEL", "Wallonie", &["Wallonia", "Walloon Region"]),
+];
+
 // ---------- continent / subregion grouping ----------
 
 /// Read `meta.csv` and fold every country into its `Continent` and
@@ -399,7 +983,7 @@ fn build_groups(dir: &Path, idx: &mut GbIndex) {
     };
 
     // ISO3 → (continent, subregion), taken from the ADM0 row.
-    let mut groups: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
+    let mut groups: HashMap<String, CountryGroups> = HashMap::new();
     for line in lines {
         let row = parse_csv_row(line);
         let get = |i: usize| row.get(i).map(|s| s.trim().to_string());
@@ -435,6 +1019,7 @@ fn build_groups(dir: &Path, idx: &mut GbIndex) {
     }
     fold_into_composites(continent_buckets, &mut idx.continents);
     fold_into_composites(subregion_buckets, &mut idx.subregions);
+    idx.country_groups = groups;
 }
 
 /// Minimal RFC-4180-ish CSV row tokenizer: handles double-quoted fields
@@ -538,6 +1123,153 @@ mod tests {
         assert_eq!(row[3], "a\"b");
     }
 
+    fn square() -> Geometry {
+        let pts = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)];
+        Geometry::Polygon {
+            outer: pts.iter().map(|&(lon, lat)| LonLat { lon, lat }).collect(),
+            holes: vec![],
+        }
+    }
+
+    /// Two countries, three German Länder (two with ISO 3166-2 codes).
+    fn tiny_index() -> GbIndex {
+        let mut idx = GbIndex::default();
+        for (iso, name) in [("DEU", "Germany"), ("FRA", "France")] {
+            idx.countries.insert(iso.to_string(), Feature::new(square()));
+            idx.country_names.insert(iso.to_string(), name.to_string());
+        }
+        for (name, code) in [
+            ("Bayern", Some("DE-BY")),
+            ("Baden-Württemberg", Some("DE-BW")),
+            ("Bremen", None),
+        ] {
+            let code = code.map(str::to_string);
+            let rec = ShapeRecord { name: name.to_string(), code, geom: square() };
+            idx.insert_admin(1, "DEU", rec);
+        }
+        idx.build_aliases();
+        idx
+    }
+
+    #[test]
+    fn fold_ignores_case_diacritics_and_punctuation() {
+        assert_eq!(fold("Baden-Württemberg"), "badenwurttemberg");
+        assert_eq!(fold("baden wurttemberg"), "badenwurttemberg");
+        assert_eq!(fold("Schleswig-Holstein"), fold("SCHLESWIG HOLSTEIN"));
+        assert_eq!(fold("Großglockner"), "grossglockner");
+        assert_eq!(fold("Søndergård"), "sondergard");
+    }
+
+    #[test]
+    fn country_accepts_iso3_iso2_name_and_exonym() {
+        let idx = tiny_index();
+        for q in ["DEU", "deu", "DE", "de", "Germany", "germany", "Deutschland"] {
+            assert_eq!(idx.country_iso(q).unwrap(), "DEU", "query {q}");
+        }
+        assert_eq!(idx.country_iso("FR").unwrap(), "FRA");
+        // ISO2 codes for countries absent from the data aren't registered.
+        assert!(idx.country_iso("JP").is_err());
+    }
+
+    #[test]
+    fn unknown_country_lists_suggestions() {
+        let idx = tiny_index();
+        let err = idx.country_iso("Germny").unwrap_err().to_string();
+        assert!(err.contains("unknown country: Germny"), "{err}");
+        assert!(err.contains("`Germany (DEU)`"), "{err}");
+        let err = idx.country_iso("Atlantis").unwrap_err().to_string();
+        assert!(!err.contains("did you mean"), "{err}");
+    }
+
+    #[test]
+    fn admin_matches_folded_names_codes_and_exonyms() {
+        let idx = tiny_index();
+        let name = |q: &str| idx.admin_unit(1, q).map(|u| u.name.clone()).unwrap();
+        assert_eq!(name("DEU/Baden-Wurttemberg"), "Baden-Württemberg");
+        assert_eq!(name("DE/baden württemberg"), "Baden-Württemberg");
+        assert_eq!(name("Germany/Bavaria"), "Bayern");
+        assert_eq!(name("DEU/DE-BY"), "Bayern");
+        assert_eq!(name("DEU/BY"), "Bayern");
+        assert_eq!(name("DE-BW"), "Baden-Württemberg");
+        assert_eq!(name("de-by"), "Bayern");
+        assert_eq!(name("DEU/bremen"), "Bremen");
+    }
+
+    #[test]
+    fn admin_miss_reports_closest_units() {
+        let idx = tiny_index();
+        let err = |lvl: u8, q: &str| match idx.admin_unit(lvl, q) {
+            Ok(u) => panic!("{q} unexpectedly resolved to {}", u.name),
+            Err(e) => e.to_string(),
+        };
+        let e = err(1, "DEU/Baden-Wuerttemberg");
+        assert!(e.contains("unknown adm1: DEU/Baden-Wuerttemberg"), "{e}");
+        assert!(e.contains("`Baden-Württemberg`"), "{e}");
+        let e = err(1, "DEU/Bayem");
+        assert!(e.contains("did you mean `Bayern`"), "{e}");
+        // Wrong level and bare non-codes are reported as such.
+        assert!(err(2, "DE-BY").contains("is an adm1 unit"));
+        assert!(err(1, "Bayern").contains("bad adm1 ref"));
+    }
+
+    #[test]
+    fn place_prefers_the_finest_level() {
+        let mut idx = tiny_index();
+        let far = Geometry::Polygon {
+            outer: [(10.0, 10.0), (12.0, 10.0), (12.0, 12.0), (10.0, 10.0)]
+                .iter()
+                .map(|&(lon, lat)| LonLat { lon, lat })
+                .collect(),
+            holes: vec![],
+        };
+        let rec = ShapeRecord { name: "Bremen".to_string(), code: None, geom: far };
+        idx.insert_admin(3, "DEU", rec);
+        let bremen = idx.place("DE/bremen").unwrap();
+        assert_eq!(bremen.bbox.min_lon, 10.0);
+        assert_eq!(idx.place("Germany/Bavaria").unwrap().bbox.max_lon, 1.0);
+        assert!(idx.place("FRA").is_ok());
+        let err = idx.place("DEU/Bremn").err().unwrap().to_string();
+        assert!(err.contains("unknown place: DEU/Bremn"), "{err}");
+        assert!(err.contains("`Bremen`"), "{err}");
+    }
+
+    #[test]
+    fn set_expressions_expand_subtract_and_filter() {
+        let mut idx = tiny_index();
+        for iso in ["DEU", "FRA"] {
+            let groups = (Some("Europe".to_string()), Some("Western Europe".to_string()));
+            idx.country_groups.insert(iso.to_string(), groups);
+        }
+        let expand = |expr: &str| idx.expand_set(&crate::set_expr::parse(expr).unwrap());
+        let names = |expr: &str| -> Vec<String> {
+            expand(expr)
+                .unwrap()
+                .iter()
+                .map(|m| idx.member_prop(m, "name").unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(names("adm1/DEU/* - adm1/DEU/Bavaria"), ["Baden-Württemberg", "Bremen"]);
+        assert_eq!(names("continent/europe - country/FRA"), ["Germany"]);
+        assert_eq!(names("country/FR + country/Deutschland"), ["Germany", "France"]);
+        let west = names("country/* where subregion = \"western europe\" and iso != DEU");
+        assert_eq!(west, ["France"]);
+        assert_eq!(names("adm1/DEU/* where name = Bremen"), ["Bremen"]);
+
+        let err = |expr: &str| expand(expr).err().unwrap().to_string();
+        assert!(err("country/DEU - adm1/DEU/Bayern").contains("removes nothing"));
+        assert!(err("country/* where population = 3").contains("unknown property"));
+        assert!(err("country/* where continent = Asia").contains("matched nothing"));
+        assert!(err("place/DEU/*").contains("can't be part of a set expression"));
+    }
+
+    #[test]
+    fn edit_distance_basics() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("bayern", "bayern"), 0);
+        assert_eq!(edit_distance("bayem", "bayern"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
     #[test]
     fn loads_feature_collection_from_str() {
         let fc = serde_json::json!({
@@ -4,7 +4,9 @@
 //!     the `geoboundaries-data` derivation + `GEOBOUNDARIES_DATA` env.
 //!   * Natural Earth — offline `coastline` only, via the
 //!     `natural-earth-data` derivation + `NATURAL_EARTH_DATA` env.
-//!   * Overpass — online, with content-addressable cache.
+//!   * Overpass — online, with content-addressable cache; or offline from
+//!     a read-only cache (`MARKI_OVERPASS=offline`) or a local `.osm.pbf`
+//!     extract (`OSM_PBF`).
 
 pub mod geo_common;
 pub mod geoboundaries;
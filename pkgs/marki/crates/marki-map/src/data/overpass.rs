@@ -14,12 +14,27 @@
 //!
 //! Failures are converted to [`MapError::Network`] / `Resolve`. The
 //! daemon turns each into a card-level failure and continues.
+//!
+//! Offline modes, for sandboxed Nix builds and CI where renders must be
+//! reproducible and the network is off:
+//!
+//!   * `OSM_PBF=<path>` — resolve refs from a local `.osm.pbf` extract
+//!     instead. No network, no cache reads or writes. [`prefetch`] reads
+//!     a whole render's refs in one batch of scans, and the results are
+//!     kept for the rest of the process.
+//!   * `MARKI_OVERPASS=offline` — treat `net/overpass/` as a read-only,
+//!     pre-populated cache. A miss is a [`MapError::Resolve`] naming the
+//!     missing entry, never a network call.
+//!
+//! `MARKI_OVERPASS=live` (the default) is the behaviour above.
 
 use crate::error::MapError;
 use crate::geometry::{best_outer_for, Geometry, LonLat, Polygon};
+use osmpbf::{Element, ElementReader, RelMemberType};
 use serde::Deserialize;
-use std::path::Path;
-use std::sync::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::time::{Duration, Instant};
 
 const USER_AGENT: &str = concat!(
@@ -35,18 +50,74 @@ const MIN_INTERVAL: Duration = Duration::from_millis(1100);
 /// Process-global last-request timestamp, for rate limiting.
 static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
 
+/// Where `relation/` and `way/` refs are resolved from.
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// overpass-api.de, cached under `net/overpass/`.
+    Live,
+    /// `net/overpass/` only, read-only.
+    Offline,
+    /// A local `.osm.pbf` extract.
+    Extract(PathBuf),
+}
+
+/// The configured [`Source`]. Reads `OSM_PBF` / `MARKI_OVERPASS` at
+/// first call; cached for the process lifetime.
+fn source() -> Result<&'static Source, MapError> {
+    static SOURCE: OnceLock<Result<Source, String>> = OnceLock::new();
+    let r = SOURCE.get_or_init(|| {
+        let pbf = std::env::var("OSM_PBF").ok();
+        let mode = std::env::var("MARKI_OVERPASS").ok();
+        source_from(mode.as_deref(), pbf.as_deref())
+    });
+    r.as_ref().map_err(|e| MapError::Resolve(e.clone()))
+}
+
+/// `OSM_PBF` wins over `MARKI_OVERPASS`; empty values count as unset.
+fn source_from(mode: Option<&str>, pbf: Option<&str>) -> Result<Source, String> {
+    if let Some(p) = pbf.filter(|p| !p.is_empty()) {
+        return Ok(Source::Extract(PathBuf::from(p)));
+    }
+    match mode.unwrap_or("") {
+        "" | "live" => Ok(Source::Live),
+        "offline" => Ok(Source::Offline),
+        other => Err(format!(
+            "MARKI_OVERPASS: expected `live` or `offline`, got `{other}`"
+        )),
+    }
+}
+
 /// Resolve `relation/<N>` or `way/<M>` to a [`Geometry`]. The
 /// `cache_root` is the daemon's cache dir; this function appends
 /// `net/overpass/` itself.
 pub fn resolve(reference: &str, cache_root: &Path) -> Result<Geometry, MapError> {
+    let source = source()?;
+    if let Source::Extract(_) = source {
+        return resolve_from_extract(reference);
+    }
+    resolve_cached(reference, cache_root, source)
+}
+
+/// The `Live` / `Offline` path: cache first, then (live only) the API.
+fn resolve_cached(
+    reference: &str,
+    cache_root: &Path,
+    source: &Source,
+) -> Result<Geometry, MapError> {
     let ql = build_query(reference)?;
     let cache_dir = cache_root.join("net").join("overpass");
-    std::fs::create_dir_all(&cache_dir)?;
     let cache_file = cache_dir.join(format!("{}.json", query_key(&ql)));
 
     let raw = if cache_file.exists() {
         std::fs::read(&cache_file)?
+    } else if *source == Source::Offline {
+        return Err(MapError::Resolve(format!(
+            "overpass offline: {reference} not cached (expected {}); \
+             populate the cache with a live render or set OSM_PBF",
+            cache_file.display()
+        )));
     } else {
+        std::fs::create_dir_all(&cache_dir)?;
         let bytes = http_post(&ql)?;
         // Atomic write: tempfile + rename so a crash mid-write doesn't
         // leave a half-file in the cache.
@@ -65,28 +136,43 @@ fn query_key(ql: &str) -> String {
     h.to_hex().as_str()[..16].to_string()
 }
 
-/// Build an Overpass QL query that returns the referenced object's
-/// geometry. We always request `out geom` so we get coordinates inline.
-fn build_query(reference: &str) -> Result<String, MapError> {
+/// The OSM object kinds we resolve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OsmKind {
+    Relation,
+    Way,
+}
+
+/// `relation/<N>` / `way/<M>` → kind and id.
+fn parse_ref(reference: &str) -> Result<(OsmKind, i64), MapError> {
     if let Some(rest) = reference.strip_prefix("relation/") {
         let n: i64 = rest
             .parse()
             .map_err(|_| MapError::Resolve(format!("bad relation id: {rest}")))?;
-        // `[out:json][timeout:25]; relation(<n>); out geom;` — returns
-        // the relation plus every member's geometry inline.
-        return Ok(format!("[out:json][timeout:25];relation({n});out geom;"));
+        return Ok((OsmKind::Relation, n));
     }
     if let Some(rest) = reference.strip_prefix("way/") {
         let n: i64 = rest
             .parse()
             .map_err(|_| MapError::Resolve(format!("bad way id: {rest}")))?;
-        return Ok(format!("[out:json][timeout:25];way({n});out geom;"));
+        return Ok((OsmKind::Way, n));
     }
     Err(MapError::Resolve(format!(
         "overpass: unsupported ref `{reference}`"
     )))
 }
 
+/// Build an Overpass QL query that returns the referenced object's
+/// geometry. We always request `out geom` so we get coordinates inline.
+fn build_query(reference: &str) -> Result<String, MapError> {
+    match parse_ref(reference)? {
+        // `[out:json][timeout:25]; relation(<n>); out geom;` — returns
+        // the relation plus every member's geometry inline.
+        (OsmKind::Relation, n) => Ok(format!("[out:json][timeout:25];relation({n});out geom;")),
+        (OsmKind::Way, n) => Ok(format!("[out:json][timeout:25];way({n});out geom;")),
+    }
+}
+
 /// POST a query to Overpass with backoff on 429/504. Returns the raw
 /// JSON bytes on success.
 fn http_post(ql: &str) -> Result<Vec<u8>, MapError> {
@@ -368,6 +454,214 @@ fn pt_eq(a: LonLat, b: LonLat) -> bool {
     (a.lon - b.lon).abs() < 1e-9 && (a.lat - b.lat).abs() < 1e-9
 }
 
+// ---------- local extract ----------
+
+/// Geometries already read from the extract, by ref. A miss is kept too
+/// (as its message), so a bad ref costs one scan per process, not one per
+/// render.
+static EXTRACTED: Mutex<Option<HashMap<String, Extracted>>> = Mutex::new(None);
+
+/// One ref's outcome from the extract; the error is kept as its message.
+type Extracted = Result<Geometry, String>;
+
+/// Read every `relation/` and `way/` ref in `refs` that is not yet known
+/// from the extract, in one batch. A render calls this with all its OSM
+/// refs up front so a card with several of them scans the extract once,
+/// not once per ref. Does nothing unless `OSM_PBF` is set.
+pub fn prefetch(refs: &[&str]) -> Result<(), MapError> {
+    let Source::Extract(pbf) = source()? else {
+        return Ok(());
+    };
+    let todo: Vec<&str> = {
+        let memo = EXTRACTED.lock().unwrap();
+        let mut seen = HashSet::new();
+        refs.iter()
+            .copied()
+            .filter(|r| memo.as_ref().is_none_or(|m| !m.contains_key(*r)) && seen.insert(*r))
+            .collect()
+    };
+    if todo.is_empty() {
+        return Ok(());
+    }
+    let found = read_extract(&todo, pbf)?;
+    EXTRACTED
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .extend(found);
+    Ok(())
+}
+
+/// Resolve a ref from a `.osm.pbf` extract, through [`prefetch`].
+fn resolve_from_extract(reference: &str) -> Result<Geometry, MapError> {
+    prefetch(&[reference])?;
+    let memo = EXTRACTED.lock().unwrap();
+    match memo.as_ref().and_then(|m| m.get(reference)) {
+        Some(Ok(g)) => Ok(g.clone()),
+        Some(Err(e)) => Err(MapError::Resolve(e.clone())),
+        None => Err(MapError::Internal(format!("{reference} missing after prefetch"))),
+    }
+}
+
+/// What the scans of [`read_extract`] collect for a batch of refs.
+#[derive(Default)]
+struct ExtractScan {
+    /// Relation id → `(way id, role)` members.
+    relations: HashMap<i64, Vec<(i64, String)>>,
+    /// Way id → node ids.
+    ways: HashMap<i64, Vec<i64>>,
+    nodes: HashMap<i64, NodeRef>,
+}
+
+/// Read `refs` from the extract. PBF has no random access, so this is up
+/// to three sequential scans for the whole batch — the relations (for
+/// their way members), the ways (for their node ids), the nodes (for
+/// coordinates) — after which each ref is shaped like an Overpass
+/// `out geom` response and decoded by the same code as the live path.
+/// A malformed ref fails the batch; one missing from the extract only
+/// fails itself.
+fn read_extract(
+    refs: &[&str],
+    pbf: &Path,
+) -> Result<Vec<(String, Extracted)>, MapError> {
+    let parsed: Vec<(&str, OsmKind, i64)> = refs
+        .iter()
+        .map(|r| parse_ref(r).map(|(kind, id)| (*r, kind, id)))
+        .collect::<Result<_, _>>()?;
+    let mut scan = ExtractScan::default();
+
+    // Pass 1: relation → (way id, role) members.
+    let wanted: HashSet<i64> = parsed
+        .iter()
+        .filter(|(_, kind, _)| *kind == OsmKind::Relation)
+        .map(|(_, _, id)| *id)
+        .collect();
+    if !wanted.is_empty() {
+        scan_extract(pbf, |el| match el {
+            Element::Relation(r) if wanted.contains(&r.id()) => {
+                let ways = r
+                    .members()
+                    .filter(|m| m.member_type == RelMemberType::Way)
+                    .map(|m| (m.member_id, m.role().unwrap_or("").to_string()))
+                    .collect();
+                scan.relations.insert(r.id(), ways);
+            }
+            _ => {}
+        })?;
+    }
+
+    // Pass 2: way → node ids.
+    let wanted: HashSet<i64> = parsed
+        .iter()
+        .filter(|(_, kind, _)| *kind == OsmKind::Way)
+        .map(|(_, _, id)| *id)
+        .chain(scan.relations.values().flatten().map(|(w, _)| *w))
+        .collect();
+    if !wanted.is_empty() {
+        scan_extract(pbf, |el| match el {
+            Element::Way(w) if wanted.contains(&w.id()) => {
+                scan.ways.insert(w.id(), w.refs().collect());
+            }
+            _ => {}
+        })?;
+    }
+
+    // Pass 3: node → coordinates.
+    let wanted: HashSet<i64> = scan.ways.values().flatten().copied().collect();
+    if !wanted.is_empty() {
+        scan_extract(pbf, |el| {
+            let (nid, lat, lon) = match el {
+                Element::Node(n) => (n.id(), n.lat(), n.lon()),
+                Element::DenseNode(n) => (n.id(), n.lat(), n.lon()),
+                _ => return,
+            };
+            if wanted.contains(&nid) {
+                scan.nodes.insert(nid, NodeRef { lat, lon });
+            }
+        })?;
+    }
+
+    Ok(parsed
+        .into_iter()
+        .map(|(r, kind, id)| (r.to_string(), assemble(&scan, kind, id, r, pbf)))
+        .collect())
+}
+
+/// Decode one ref from a batch's scan results.
+fn assemble(
+    scan: &ExtractScan,
+    kind: OsmKind,
+    id: i64,
+    reference: &str,
+    pbf: &Path,
+) -> Extracted {
+    let missing = || format!("{reference} not found in OSM extract {}", pbf.display());
+    let members = match kind {
+        OsmKind::Way if scan.ways.contains_key(&id) => vec![(id, String::new())],
+        OsmKind::Way => return Err(missing()),
+        OsmKind::Relation => scan.relations.get(&id).ok_or_else(missing)?.clone(),
+    };
+    decode_extract(kind, &members, &scan.ways, &scan.nodes, reference).map_err(|e| match e {
+        MapError::Resolve(m) => m,
+        other => other.to_string(),
+    })
+}
+
+/// Run `f` over every element of the extract.
+fn scan_extract(pbf: &Path, f: impl FnMut(Element<'_>)) -> Result<(), MapError> {
+    ElementReader::from_path(pbf)
+        .and_then(|r| r.for_each(f))
+        .map_err(|e| MapError::Resolve(format!("osm extract {}: {e}", pbf.display())))
+}
+
+/// Assemble the scanned pieces into an Overpass-shaped response and
+/// decode it. Ways or nodes cut off by the extract's boundary are
+/// dropped (with a warning) rather than failing the whole ref.
+fn decode_extract(
+    kind: OsmKind,
+    members: &[(i64, String)],
+    ways: &HashMap<i64, Vec<i64>>,
+    nodes: &HashMap<i64, NodeRef>,
+    reference: &str,
+) -> Result<Geometry, MapError> {
+    let mut missing = 0usize;
+    let mut geometry_of = |way: i64| -> Vec<NodeRef> {
+        let refs = match ways.get(&way) {
+            Some(r) => r,
+            None => {
+                missing += 1;
+                return Vec::new();
+            }
+        };
+        let pts: Vec<NodeRef> = refs.iter().filter_map(|n| nodes.get(n).copied()).collect();
+        if pts.len() < refs.len() {
+            missing += 1;
+        }
+        pts
+    };
+    let element = match kind {
+        OsmKind::Way => OverpassElement::Way { geometry: geometry_of(members[0].0) },
+        OsmKind::Relation => OverpassElement::Relation {
+            members: members
+                .iter()
+                .map(|(way, role)| RelationMember {
+                    member_type: "way".to_string(),
+                    role: role.clone(),
+                    geometry: geometry_of(*way),
+                })
+                .collect(),
+        },
+    };
+    if missing > 0 {
+        tracing::warn!("osm extract: {reference}: {missing} member way(s) incomplete in extract");
+    }
+    let resp = OverpassResponse { elements: vec![element] };
+    match kind {
+        OsmKind::Relation => decode_relation(&resp, reference),
+        OsmKind::Way => decode_way(&resp, reference),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -443,6 +737,87 @@ mod tests {
         assert!(matches!(g, Geometry::Polygon { .. }));
     }
 
+    #[test]
+    fn source_from_env_values() {
+        assert_eq!(source_from(None, None), Ok(Source::Live));
+        assert_eq!(source_from(Some("live"), Some("")), Ok(Source::Live));
+        assert_eq!(source_from(Some("offline"), None), Ok(Source::Offline));
+        assert_eq!(
+            source_from(Some("offline"), Some("/data/de.osm.pbf")),
+            Ok(Source::Extract(PathBuf::from("/data/de.osm.pbf")))
+        );
+        assert!(source_from(Some("sometimes"), None).is_err());
+    }
+
+    #[test]
+    fn offline_reads_cache_and_errors_on_miss() {
+        let root = std::env::temp_dir().join(format!("marki-map-overpass-{}", std::process::id()));
+        let dir = root.join("net").join("overpass");
+        std::fs::create_dir_all(&dir).unwrap();
+        let ql = build_query("way/1").unwrap();
+        let body = r#"{"elements":[{"type":"way","geometry":[
+            {"lat":0.0,"lon":0.0},{"lat":1.0,"lon":1.0}]}]}"#;
+        std::fs::write(dir.join(format!("{}.json", query_key(&ql))), body).unwrap();
+
+        let g = resolve_cached("way/1", &root, &Source::Offline).unwrap();
+        assert!(matches!(g, Geometry::LineString(_)));
+        let err = resolve_cached("way/2", &root, &Source::Offline).unwrap_err().to_string();
+        assert!(err.contains("overpass offline: way/2 not cached"), "{err}");
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn extract_pieces_decode_like_overpass() {
+        // A square split over two outer ways that share endpoints.
+        let nodes: HashMap<i64, NodeRef> = [
+            (1, (0.0, 0.0)),
+            (2, (0.0, 1.0)),
+            (3, (1.0, 1.0)),
+            (4, (1.0, 0.0)),
+        ]
+        .into_iter()
+        .map(|(id, (lat, lon))| (id, NodeRef { lat, lon }))
+        .collect();
+        let ways: HashMap<i64, Vec<i64>> =
+            [(10, vec![1, 2, 3]), (11, vec![3, 4, 1])].into_iter().collect();
+        let members = vec![(10, "outer".to_string()), (11, "outer".to_string())];
+        let g = decode_extract(OsmKind::Relation, &members, &ways, &nodes, "relation/7").unwrap();
+        match g {
+            Geometry::Polygon { outer, holes } => {
+                assert_eq!(outer.len(), 5);
+                assert!(holes.is_empty());
+            }
+            other => panic!("expected polygon, got {other:?}"),
+        }
+
+        let g = decode_extract(OsmKind::Way, &[(10, String::new())], &ways, &nodes, "way/10")
+            .unwrap();
+        assert!(matches!(g, Geometry::LineString(ref pts) if pts.len() == 3));
+    }
+
+    #[test]
+    fn one_scan_serves_every_ref_of_a_batch() {
+        // The relation and a way of its own share way 10.
+        let nodes = [(1, (0.0, 0.0)), (2, (0.0, 1.0)), (3, (1.0, 1.0)), (4, (1.0, 0.0))]
+            .into_iter()
+            .map(|(id, (lat, lon))| (id, NodeRef { lat, lon }))
+            .collect();
+        let scan = ExtractScan {
+            relations: [(7, vec![(10, "outer".to_string()), (11, "outer".to_string())])]
+                .into_iter()
+                .collect(),
+            ways: [(10, vec![1, 2, 3]), (11, vec![3, 4, 1])].into_iter().collect(),
+            nodes,
+        };
+        let pbf = Path::new("/x.osm.pbf");
+        let rel = assemble(&scan, OsmKind::Relation, 7, "relation/7", pbf).unwrap();
+        assert!(matches!(rel, Geometry::Polygon { .. }));
+        let way = assemble(&scan, OsmKind::Way, 10, "way/10", pbf).unwrap();
+        assert!(matches!(way, Geometry::LineString(ref pts) if pts.len() == 3));
+        let miss = assemble(&scan, OsmKind::Way, 12, "way/12", pbf).unwrap_err();
+        assert!(miss.contains("way/12 not found in OSM extract /x.osm.pbf"), "{miss}");
+    }
+
     #[test]
     fn query_key_is_stable() {
         let a = query_key("[out:json];relation(1);out geom;");
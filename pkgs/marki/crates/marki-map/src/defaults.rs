@@ -94,6 +94,11 @@ impl CompiledDefaults {
         })
     }
 
+    /// The cards directory rules match against, unless empty.
+    pub fn cards_dir(&self) -> Option<&Path> {
+        Some(self.cards_dir.as_path()).filter(|d| !d.as_os_str().is_empty())
+    }
+
     /// True when there is nothing to merge.
     pub fn is_empty(&self) -> bool {
         self.global.is_empty() && self.rules.is_empty()
@@ -24,6 +24,37 @@
 //! features = ["country/FJI"]   # rounded hull around the whole feature
 //! ```
 //!
+//! A *choropleth layer* fills each referenced feature from a colour
+//! ramp by its value, and draws a legend:
+//!
+//! ```toml
+//! [layers.gdp.choropleth]
+//! csv = "gdp.csv"           # or inline: [layers.gdp.choropleth.data]
+//! legend = { title = "GDP per capita (kUSD)" }
+//! ```
+//!
+//! *Routes* draw great-circle lines between places — voyages, trade
+//! routes, flight paths — and stretch the viewport like highlights:
+//!
+//! ```toml
+//! [layers.voyage]
+//! routes = [
+//!   { from = "place/PRT/Lisboa", via = ["point/18.4,-34.4"], to = "place/IND/Kozhikode" },
+//!   { points = [[-5.6, 36.0], [-74.0, 40.7]], style = "dashed" },
+//! ]
+//! ```
+//!
+//! An optional *locator inset* puts the map in context — a small globe
+//! (or wider regional outline) in one corner with the main viewport
+//! marked on it:
+//!
+//! ```toml
+//! [inset]
+//! projection = "globe"      # or "mercator"
+//! features = ["coastline"]  # what the inset draws; defaults to coastline
+//! position = "bottom-left"
+//! ```
+//!
 //! Fields and shapes are deliberately minimal — the renderer rejects
 //! anything it doesn't understand so authors get fast feedback.
 
@@ -54,6 +85,11 @@ pub struct MapSpec {
     /// which controls DOM stacking: earlier layers render underneath
     /// later ones. Authors should write `base` first.
     pub layers: IndexMap<String, LayerSpec>,
+
+    /// Optional locator inset showing where the viewport sits on a
+    /// globe or wider region. See [`InsetSpec`].
+    #[serde(default)]
+    pub inset: Option<InsetSpec>,
 }
 
 fn default_style() -> String {
@@ -201,6 +237,131 @@ pub struct LayerSpec {
     /// hull layer wherever you want it in TOML source order.
     #[serde(default)]
     pub hull: Option<HullSpec>,
+
+    /// Great-circle routes drawn on this layer with the theme's `route`
+    /// roles. Like highlights they stretch the viewport. See
+    /// [`RouteSpec`].
+    #[serde(default)]
+    pub routes: Vec<RouteSpec>,
+
+    /// Makes this a *choropleth layer*: every feature in the data table
+    /// is filled with the ramp colour of the class its value falls in,
+    /// and a legend is drawn into the layer's SVG. See
+    /// [`ChoroplethSpec`].
+    #[serde(default)]
+    pub choropleth: Option<ChoroplethSpec>,
+}
+
+/// Configuration for a choropleth layer.
+///
+/// Values come from an inline `data` table keyed by feature reference,
+/// from a CSV file, or both (inline entries win). Relative `csv` paths
+/// resolve against the card's directory. Before rendering, CSV rows are
+/// folded into `data` and `csv` is cleared, so the cache key follows
+/// the table's contents rather than its path.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChoroplethSpec {
+    /// Feature reference → value.
+    #[serde(default)]
+    pub data: IndexMap<String, f64>,
+
+    /// CSV file with a header row; see `ref_column` / `value_column`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csv: Option<String>,
+
+    /// CSV column holding the feature reference. Default `ref`.
+    #[serde(default = "default_ref_column")]
+    pub ref_column: String,
+
+    /// CSV column holding the value. Default `value`.
+    #[serde(default = "default_value_column")]
+    pub value_column: String,
+
+    /// Number of colour classes, `2..=9`. Ignored when `breaks` is set.
+    /// Default `5`.
+    #[serde(default = "default_classes")]
+    pub classes: u8,
+
+    /// How class boundaries are derived from the data. Default
+    /// [`ClassMethod::Equal`].
+    #[serde(default)]
+    pub method: ClassMethod,
+
+    /// Explicit interior class boundaries, ascending. `n` breaks give
+    /// `n + 1` classes and override `classes` / `method`.
+    #[serde(default)]
+    pub breaks: Vec<f64>,
+
+    /// Colour stops (`#rgb` / `#rrggbb`) interpolated into one colour
+    /// per class. Empty inherits the theme's `ramp`.
+    #[serde(default)]
+    pub ramp: Vec<String>,
+
+    /// Legend placement and labelling.
+    #[serde(default)]
+    pub legend: LegendSpec,
+}
+
+fn default_ref_column() -> String {
+    "ref".to_string()
+}
+fn default_value_column() -> String {
+    "value".to_string()
+}
+fn default_classes() -> u8 { 5 }
+
+/// Class-boundary method for a choropleth.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassMethod {
+    /// Equal-width intervals between the minimum and maximum value.
+    #[default]
+    Equal,
+    /// Roughly the same number of features per class. Better for
+    /// skewed data (GDP, population), where equal intervals leave most
+    /// features in the lowest class.
+    Quantile,
+}
+
+/// Legend drawn by a choropleth layer.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LegendSpec {
+    /// Draw the legend at all. Default `true`.
+    #[serde(default = "default_true")]
+    pub show: bool,
+
+    /// Optional heading above the swatches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Canvas corner. Default `bottom-right`.
+    #[serde(default = "default_legend_position")]
+    pub position: InsetPosition,
+
+    /// Decimal places in the class labels. Unset picks `0` for
+    /// all-integer data and `2` otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals: Option<u8>,
+}
+
+impl Default for LegendSpec {
+    fn default() -> Self {
+        Self {
+            show: true,
+            title: None,
+            position: default_legend_position(),
+            decimals: None,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+fn default_legend_position() -> InsetPosition {
+    InsetPosition::BottomRight
 }
 
 /// Configuration for a hull layer. The outward padding (and corner
@@ -249,6 +410,171 @@ fn default_hull_radius() -> f64 { 0.04 }
 fn default_hull_min_px() -> f64 { 10.0 }
 fn default_hull_max_frac() -> f64 { 0.20 }
 
+/// One route: a polyline whose legs follow great circles, densified
+/// before projection so it bends the way the real path does.
+///
+/// Stops are either feature references (`from`, any `via`, `to`) or an
+/// explicit `points` list of `[lon, lat]` pairs — not both. A stop may
+/// be `point/<lon>,<lat>`, `place/<ISO3>/<name>` (the finest admin unit
+/// of that name), or any other feature reference, which stands for the
+/// centroid of its largest polygon.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteSpec {
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Intermediate stops, in order.
+    #[serde(default)]
+    pub via: Vec<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Explicit `[lon, lat]` stops, GeoJSON order.
+    #[serde(default)]
+    pub points: Vec<[f64; 2]>,
+    #[serde(default)]
+    pub style: RouteStyle,
+}
+
+impl RouteSpec {
+    /// `from`, every `via`, then `to`.
+    pub fn stops(&self) -> impl Iterator<Item = &str> {
+        self.from
+            .iter()
+            .chain(&self.via)
+            .chain(&self.to)
+            .map(String::as_str)
+    }
+}
+
+/// Line pattern of a route; each maps to its own theme role.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl RouteStyle {
+    /// Theme role the route is drawn with.
+    pub fn role(self) -> &'static str {
+        match self {
+            RouteStyle::Solid => "route",
+            RouteStyle::Dashed => "route-dashed",
+            RouteStyle::Dotted => "route-dotted",
+        }
+    }
+}
+
+/// Locator inset: a small secondary map composed into one layer's SVG,
+/// with the main viewport marked on it. Zoomed-in cards (one German
+/// state, a Caribbean island) otherwise give no sense of where on the
+/// globe they are.
+///
+/// ```toml
+/// [inset]
+/// projection = "globe"          # "globe" (orthographic) | "mercator"
+/// features = ["coastline"]      # drawn with the layer's normal roles
+/// position = "bottom-left"      # any corner
+/// size = 0.3                    # fraction of the canvas's shorter side
+/// marker = "auto"               # "auto" | "rect" | "dot"
+/// layer = "base"                # which layer SVG carries the inset
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct InsetSpec {
+    /// How the inset is projected. Default [`InsetProjection::Globe`].
+    #[serde(default)]
+    pub projection: InsetProjection,
+
+    /// Geometry references drawn in the inset. Same vocabulary as a
+    /// layer's `features`; roles follow the base-layer rules
+    /// (`coastline` → `coast`, everything else → `outline`). A
+    /// `mercator` inset frames the union of these features. Default
+    /// `["coastline"]`.
+    #[serde(default = "default_inset_features")]
+    pub features: Vec<String>,
+
+    /// Canvas corner the inset sits in. Default `bottom-left`.
+    #[serde(default)]
+    pub position: InsetPosition,
+
+    /// Edge length of the (square) inset as a fraction of the rendered
+    /// canvas's shorter side. Default `0.3`.
+    #[serde(default = "default_inset_size")]
+    pub size: f64,
+
+    /// How the main viewport is marked. Default [`InsetMarker::Auto`].
+    #[serde(default)]
+    pub marker: InsetMarker,
+
+    /// Layer whose SVG carries the inset. Defaults to `base`, so the
+    /// inset is always visible; point it at an overlay layer to reveal
+    /// it with the answer instead.
+    #[serde(default = "default_inset_layer")]
+    pub layer: String,
+}
+
+impl Default for InsetSpec {
+    fn default() -> Self {
+        Self {
+            projection: InsetProjection::default(),
+            features: default_inset_features(),
+            position: InsetPosition::default(),
+            size: default_inset_size(),
+            marker: InsetMarker::default(),
+            layer: default_inset_layer(),
+        }
+    }
+}
+
+fn default_inset_features() -> Vec<String> {
+    vec!["coastline".to_string()]
+}
+fn default_inset_size() -> f64 { 0.3 }
+fn default_inset_layer() -> String {
+    "base".to_string()
+}
+
+/// Projection used by a locator inset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InsetProjection {
+    /// Orthographic globe centred on the main viewport.
+    #[default]
+    Globe,
+    /// Mercator outline framed to the inset's own features (e.g.
+    /// `continent/Europe`).
+    Mercator,
+}
+
+/// Canvas corner a locator inset is anchored to. Choropleth legends
+/// use it too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InsetPosition {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+/// How a locator inset marks the main viewport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InsetMarker {
+    /// Rectangle when the viewport is large enough on the inset to
+    /// read as one, otherwise a dot.
+    #[default]
+    Auto,
+    /// Outline of the viewport bbox.
+    Rect,
+    /// Dot at the viewport centre.
+    Dot,
+}
+
 /// Per-layer overrides for highlight styling. Any field left `None`
 /// inherits from the active theme's `highlight` role.
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -300,6 +626,22 @@ pub enum DslError {
     HullMinPx(f64),
     #[error("hull radius ({radius}) must not exceed max_frac ({max_frac})")]
     HullRadiusOverMax { radius: f64, max_frac: f64 },
+    #[error("choropleth classes must be between 2 and 9 (got {0})")]
+    ChoroplethClasses(usize),
+    #[error("choropleth breaks must be strictly ascending")]
+    ChoroplethBreaks,
+    #[error("choropleth has no data (set `data` or `csv`)")]
+    ChoroplethNoData,
+    #[error("choropleth ramp needs at least two colours")]
+    ChoroplethRamp,
+    #[error("a route needs `from` and `to`, or at least two `points` (not both)")]
+    RouteStops,
+    #[error("route point [{0}, {1}] is not a valid [lon, lat]")]
+    RoutePoint(f64, f64),
+    #[error("inset size must be in (0.0, 1.0] (got {0})")]
+    InsetSize(f64),
+    #[error("inset layer `{0}` is not one of the map's layers")]
+    InsetUnknownLayer(String),
 }
 
 /// Parse a `map` block body as TOML and validate it.
@@ -351,6 +693,51 @@ pub fn parse_map_spec(src: &str) -> Result<MapSpec, DslError> {
                 });
             }
         }
+        if let Some(ch) = &lspec.choropleth {
+            let classes = if ch.breaks.is_empty() {
+                ch.classes as usize
+            } else {
+                ch.breaks.len() + 1
+            };
+            if !(2..=9).contains(&classes) {
+                return Err(DslError::ChoroplethClasses(classes));
+            }
+            if ch.breaks.windows(2).any(|w| w[0] >= w[1]) {
+                return Err(DslError::ChoroplethBreaks);
+            }
+            if ch.data.is_empty() && ch.csv.is_none() {
+                return Err(DslError::ChoroplethNoData);
+            }
+            if ch.ramp.len() == 1 {
+                return Err(DslError::ChoroplethRamp);
+            }
+        }
+        for route in &lspec.routes {
+            let by_ref = route.stops().next().is_some();
+            let stops_ok = if route.points.is_empty() {
+                route.from.is_some() && route.to.is_some()
+            } else {
+                !by_ref && route.points.len() >= 2
+            };
+            if !stops_ok {
+                return Err(DslError::RouteStops);
+            }
+            if let Some(&[lon, lat]) = route
+                .points
+                .iter()
+                .find(|[lon, lat]| !((-180.0..=180.0).contains(lon) && (-90.0..=90.0).contains(lat)))
+            {
+                return Err(DslError::RoutePoint(lon, lat));
+            }
+        }
+    }
+    if let Some(inset) = &spec.inset {
+        if !(inset.size > 0.0 && inset.size <= 1.0) {
+            return Err(DslError::InsetSize(inset.size));
+        }
+        if !spec.layers.contains_key(&inset.layer) {
+            return Err(DslError::InsetUnknownLayer(inset.layer.clone()));
+        }
     }
     Ok(spec)
 }
@@ -820,4 +1207,212 @@ max_frac = 0.2
         let err = parse_map_spec(src).unwrap_err();
         assert!(matches!(err, DslError::HullRadiusOverMax { .. }), "got: {err:?}");
     }
+
+    // ---------- inset ----------
+
+    #[test]
+    fn inset_absent_by_default() {
+        let src = r#"
+[layers.base]
+features = ["country/DEU"]
+"#;
+        let s = parse_map_spec(src).unwrap();
+        assert!(s.inset.is_none());
+    }
+
+    #[test]
+    fn inset_parses_with_defaults() {
+        let src = r#"
+[inset]
+
+[layers.base]
+features = ["adm1/DEU/Bayern"]
+"#;
+        let s = parse_map_spec(src).unwrap();
+        let inset = s.inset.as_ref().unwrap();
+        assert_eq!(inset.projection, InsetProjection::Globe);
+        assert_eq!(inset.features, vec!["coastline".to_string()]);
+        assert_eq!(inset.position, InsetPosition::BottomLeft);
+        assert_eq!(inset.marker, InsetMarker::Auto);
+        assert_eq!(inset.layer, "base");
+        assert!((inset.size - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn inset_knobs_parse() {
+        let src = r#"
+[inset]
+projection = "mercator"
+features = ["continent/Europe"]
+position = "top-right"
+size = 0.25
+marker = "dot"
+layer = "answer"
+
+[layers.base]
+features = ["country/DEU"]
+[layers.answer]
+highlights = ["adm1/DEU/Bayern"]
+"#;
+        let s = parse_map_spec(src).unwrap();
+        let inset = s.inset.as_ref().unwrap();
+        assert_eq!(inset.projection, InsetProjection::Mercator);
+        assert_eq!(inset.position, InsetPosition::TopRight);
+        assert_eq!(inset.marker, InsetMarker::Dot);
+        assert_eq!(inset.layer, "answer");
+    }
+
+    #[test]
+    fn inset_size_out_of_range() {
+        let src = r#"
+[inset]
+size = 0.0
+[layers.base]
+features = ["coastline"]
+"#;
+        let err = parse_map_spec(src).unwrap_err();
+        assert!(matches!(err, DslError::InsetSize(_)), "got: {err:?}");
+    }
+
+    #[test]
+    fn inset_unknown_layer_rejected() {
+        let src = r#"
+[inset]
+layer = "nope"
+[layers.base]
+features = ["coastline"]
+"#;
+        let err = parse_map_spec(src).unwrap_err();
+        assert!(matches!(err, DslError::InsetUnknownLayer(_)), "got: {err:?}");
+    }
+
+    #[test]
+    fn inset_unknown_field_rejected() {
+        let src = r#"
+[inset]
+bogus = 1
+[layers.base]
+features = ["coastline"]
+"#;
+        let err = parse_map_spec(src).unwrap_err();
+        assert!(matches!(err, DslError::Toml(_)), "got: {err:?}");
+    }
+
+    // ---------- choropleth ----------
+
+    #[test]
+    fn choropleth_inline_data_parses() {
+        let src = r#"
+[layers.base]
+features = ["continent/Europe"]
+
+[layers.gdp.choropleth]
+method = "quantile"
+legend = { title = "GDP", position = "top-left" }
+[layers.gdp.choropleth.data]
+"country/DEU" = 48
+"country/FRA" = 41.5
+"#;
+        let s = parse_map_spec(src).unwrap();
+        let ch = s.layers["gdp"].choropleth.as_ref().unwrap();
+        assert_eq!(ch.data.len(), 2);
+        assert_eq!(ch.data["country/DEU"], 48.0);
+        assert_eq!(ch.method, ClassMethod::Quantile);
+        assert_eq!(ch.classes, 5);
+        assert_eq!(ch.legend.title.as_deref(), Some("GDP"));
+        assert_eq!(ch.legend.position, InsetPosition::TopLeft);
+        assert!(ch.legend.show);
+    }
+
+    #[test]
+    fn choropleth_csv_defaults_columns() {
+        let src = r#"
+[layers.base]
+features = ["continent/Europe"]
+[layers.pop.choropleth]
+csv = "pop.csv"
+"#;
+        let s = parse_map_spec(src).unwrap();
+        let ch = s.layers["pop"].choropleth.as_ref().unwrap();
+        assert_eq!(ch.csv.as_deref(), Some("pop.csv"));
+        assert_eq!(ch.ref_column, "ref");
+        assert_eq!(ch.value_column, "value");
+        assert_eq!(ch.legend.position, InsetPosition::BottomRight);
+    }
+
+    #[test]
+    fn choropleth_without_data_rejected() {
+        let src = r#"
+[layers.base]
+features = ["continent/Europe"]
+[layers.pop.choropleth]
+classes = 4
+"#;
+        let err = parse_map_spec(src).unwrap_err();
+        assert!(matches!(err, DslError::ChoroplethNoData), "got: {err:?}");
+    }
+
+    #[test]
+    fn choropleth_class_count_validated() {
+        let src = r#"
+[layers.base]
+features = ["continent/Europe"]
+[layers.pop.choropleth]
+csv = "pop.csv"
+classes = 12
+"#;
+        let err = parse_map_spec(src).unwrap_err();
+        assert!(matches!(err, DslError::ChoroplethClasses(12)), "got: {err:?}");
+    }
+
+    #[test]
+    fn choropleth_breaks_must_ascend() {
+        let src = r#"
+[layers.base]
+features = ["continent/Europe"]
+[layers.pop.choropleth]
+csv = "pop.csv"
+breaks = [10, 5]
+"#;
+        let err = parse_map_spec(src).unwrap_err();
+        assert!(matches!(err, DslError::ChoroplethBreaks), "got: {err:?}");
+    }
+
+    #[test]
+    fn routes_parse_with_refs_or_points() {
+        let src = r#"
+[layers.base]
+features = ["continent/Europe"]
+[layers.voyage]
+routes = [
+  { from = "place/PRT/Lisboa", via = ["point/18.4,-34.4"], to = "place/IND/Kozhikode" },
+  { points = [[-5.6, 36.0], [-74.0, 40.7]], style = "dashed" },
+]
+"#;
+        let s = parse_map_spec(src).unwrap();
+        let routes = &s.layers["voyage"].routes;
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].stops().count(), 3);
+        assert_eq!(routes[0].style.role(), "route");
+        assert_eq!(routes[1].style.role(), "route-dashed");
+    }
+
+    #[test]
+    fn route_stops_validated() {
+        let err = |route: &str| {
+            let src = format!("[layers.base]\nroutes = [{route}]\n");
+            parse_map_spec(&src).unwrap_err()
+        };
+        for bad in [
+            r#"{ from = "country/FRA" }"#,
+            r#"{ points = [[0, 0]] }"#,
+            r#"{ from = "country/FRA", to = "country/DEU", points = [[0, 0], [1, 1]] }"#,
+        ] {
+            assert!(matches!(err(bad), DslError::RouteStops), "{bad}");
+        }
+        let e = err("{ points = [[0, 0], [10, 95]] }");
+        assert!(matches!(e, DslError::RoutePoint(_, _)), "got: {e:?}");
+        let e = err(r#"{ from = "a", to = "b", style = "wavy" }"#);
+        assert!(matches!(e, DslError::Toml(_)), "got: {e:?}");
+    }
 }
@@ -183,6 +183,66 @@ impl Geometry {
         }
         bb
     }
+
+    /// One point standing for the whole geometry: the point itself, the
+    /// area centroid of the largest polygon (so a country with overseas
+    /// islands lands on its mainland), or the bbox centre of a line.
+    /// `None` for an empty geometry.
+    pub fn representative_point(&self) -> Option<LonLat> {
+        let outers: Vec<&[LonLat]> = match self {
+            Geometry::Point(p) => return Some(*p),
+            Geometry::Polygon { outer, .. } => vec![outer],
+            Geometry::MultiPolygon(polys) => polys.iter().map(|p| p.outer.as_slice()).collect(),
+            Geometry::LineString(_) | Geometry::MultiLineString(_) => Vec::new(),
+        };
+        let centroid = outers
+            .into_iter()
+            .filter_map(ring_centroid)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(c, _)| c);
+        centroid.or_else(|| {
+            let bb = self.bbox();
+            bb.min_lon.is_finite().then(|| LonLat {
+                lon: (bb.min_lon + bb.max_lon) / 2.0,
+                lat: (bb.min_lat + bb.max_lat) / 2.0,
+            })
+        })
+    }
+}
+
+/// Area centroid and absolute (planar lon/lat) area of a closed ring, or
+/// `None` when the ring is degenerate. Longitudes are unwrapped from the
+/// first vertex on, so a ring crossing ±180 (Fiji, Chukotka) is averaged
+/// where it lies rather than across the whole map; the centroid comes
+/// back normalised to ±180.
+fn ring_centroid(ring: &[LonLat]) -> Option<(LonLat, f64)> {
+    let first = ring.first()?;
+    // Offsets from the first vertex, each step taken the short way round.
+    let mut lon = 0.0;
+    let mut prev = first.lon;
+    let pts: Vec<(f64, f64)> = ring
+        .iter()
+        .map(|p| {
+            lon += crate::unwrap::rotate_lon(p.lon - prev, 0.0);
+            prev = p.lon;
+            (lon, p.lat)
+        })
+        .collect();
+    let (mut a2, mut cx, mut cy) = (0.0, 0.0, 0.0);
+    for w in pts.windows(2) {
+        let cross = w[0].0 * w[1].1 - w[1].0 * w[0].1;
+        a2 += cross;
+        cx += (w[0].0 + w[1].0) * cross;
+        cy += (w[0].1 + w[1].1) * cross;
+    }
+    if a2.abs() < 1e-12 {
+        return None;
+    }
+    let c = LonLat {
+        lon: crate::unwrap::rotate_lon(first.lon + cx / (3.0 * a2), 0.0),
+        lat: cy / (3.0 * a2),
+    };
+    Some((c, a2.abs() / 2.0))
 }
 
 #[cfg(test)]
@@ -219,6 +279,47 @@ mod tests {
         assert_eq!(bb.max_lat, 5.0);
     }
 
+    #[test]
+    fn representative_point_picks_the_largest_polygon() {
+        let square = |x: f64, side: f64| Polygon {
+            outer: [(0.0, 0.0), (side, 0.0), (side, side), (0.0, side), (0.0, 0.0)]
+                .iter()
+                .map(|&(lon, lat)| LonLat { lon: lon + x, lat })
+                .collect(),
+            holes: vec![],
+        };
+        let g = Geometry::MultiPolygon(vec![square(-60.0, 1.0), square(0.0, 4.0)]);
+        let p = g.representative_point().unwrap();
+        assert!((p.lon - 2.0).abs() < 1e-9 && (p.lat - 2.0).abs() < 1e-9);
+        let line = Geometry::LineString(vec![
+            LonLat { lon: 0.0, lat: 0.0 },
+            LonLat { lon: 10.0, lat: 4.0 },
+        ]);
+        let p = line.representative_point().unwrap();
+        assert_eq!((p.lon, p.lat), (5.0, 2.0));
+        assert!(Geometry::default().representative_point().is_none());
+    }
+
+    #[test]
+    fn centroid_of_a_ring_across_the_antimeridian() {
+        // Viti Levu-ish: 177°E to 179°W.
+        let outer: Vec<LonLat> = [
+            (177.0, -19.0),
+            (-179.0, -19.0),
+            (-179.0, -16.0),
+            (177.0, -16.0),
+            (177.0, -19.0),
+        ]
+        .iter()
+        .map(|&(lon, lat)| LonLat { lon, lat })
+        .collect();
+        let p = Geometry::Polygon { outer, holes: vec![] }
+            .representative_point()
+            .unwrap();
+        assert!((p.lon - 179.0).abs() < 1e-9, "{p:?}");
+        assert!((p.lat + 17.5).abs() < 1e-9, "{p:?}");
+    }
+
     #[test]
     fn bbox_intersection() {
         let a = BBox {
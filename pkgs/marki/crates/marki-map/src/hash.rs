@@ -8,10 +8,14 @@
 //!     RENDER_VERSION_MAP_le ||
 //!     theme_name ||
 //!     theme_bytes ||
+//!     raster? ||
 //!     canonical_toml_of(spec)
 //! )
 //! ```
 //!
+//! `raster` (the project's `[render] raster` settings) is only hashed
+//! when set, so non-rasterising projects keep their keys.
+//!
 //! `canonical_toml_of` re-serialises the parsed [`MapSpec`] so author
 //! whitespace tweaks don't bust the cache. Because we use an
 //! `IndexMap` for `layers`, layer order is preserved from the TOML
@@ -21,11 +25,16 @@
 use crate::dsl::MapSpec;
 use crate::error::MapError;
 use crate::version::RENDER_VERSION_MAP;
+use marki_render::raster::Raster;
 
 /// Compute the 16-hex-char cache key for a map render. The caller
 /// supplies the theme bytes (e.g. the loaded `atlas.toml`) so theme
 /// edits invalidate cached renders.
-pub fn cache_key(spec: &MapSpec, theme_bytes: &[u8]) -> Result<String, MapError> {
+pub fn cache_key(
+    spec: &MapSpec,
+    theme_bytes: &[u8],
+    raster: Option<&Raster>,
+) -> Result<String, MapError> {
     // Re-serialise to TOML for canonical form. We could also serialise
     // to a manually-canonicalised buffer of (key, value) pairs, but
     // toml's serializer is already deterministic for our `BTreeMap`-
@@ -37,6 +46,9 @@ pub fn cache_key(spec: &MapSpec, theme_bytes: &[u8]) -> Result<String, MapError>
     hasher.update(&RENDER_VERSION_MAP.to_le_bytes());
     hasher.update(spec.style.as_bytes());
     hasher.update(theme_bytes);
+    if let Some(r) = raster {
+        r.hash_into(&mut hasher);
+    }
     // Hash layer names in insertion order so that reordering layers
     // (which changes DOM stacking) produces a different cache key.
     // The canonical TOML alone doesn't capture order because the toml
@@ -69,8 +81,8 @@ size = [600, 400]
 features = ["country/DEU"]
 "#,
         );
-        let a = cache_key(&s, b"theme1").unwrap();
-        let b = cache_key(&s, b"theme1").unwrap();
+        let a = cache_key(&s, b"theme1", None).unwrap();
+        let b = cache_key(&s, b"theme1", None).unwrap();
         assert_eq!(a, b);
         assert_eq!(a.len(), 16);
     }
@@ -84,6 +96,7 @@ size = [600, 400]
 features = ["country/DEU"]
 "#),
             b"t",
+            None,
         )
         .unwrap();
         let b = cache_key(
@@ -93,6 +106,7 @@ size = [600, 400]
 features = ["country/FRA"]
 "#),
             b"t",
+            None,
         )
         .unwrap();
         assert_ne!(a, b);
@@ -107,8 +121,8 @@ size = [600, 400]
 features = []
 "#,
         );
-        let a = cache_key(&s, b"theme1").unwrap();
-        let b = cache_key(&s, b"theme2").unwrap();
+        let a = cache_key(&s, b"theme1", None).unwrap();
+        let b = cache_key(&s, b"theme2", None).unwrap();
         assert_ne!(a, b);
     }
 
@@ -125,6 +139,7 @@ features = []
 features = []
 "#),
             b"t",
+            None,
         )
         .unwrap();
         let b = cache_key(
@@ -136,8 +151,19 @@ features = []
 features = []
 "#),
             b"t",
+            None,
         )
         .unwrap();
         assert_ne!(a, b);
     }
+
+    #[test]
+    fn key_changes_with_raster_scale() {
+        let s = spec("[layers.base]\nfeatures = []\n");
+        let plain = cache_key(&s, b"t", None).unwrap();
+        let x2 = cache_key(&s, b"t", Some(&Raster { scale: 2.0 })).unwrap();
+        let x3 = cache_key(&s, b"t", Some(&Raster { scale: 3.0 })).unwrap();
+        assert_ne!(plain, x2);
+        assert_ne!(x2, x3);
+    }
 }
@@ -0,0 +1,464 @@
+//! Locator insets.
+//!
+//! A zoomed-in card (one German state, a Caribbean island) gives no
+//! sense of *where* it is. An inset is a small secondary map — an
+//! orthographic globe centred on the viewport, or a Mercator outline of
+//! a wider region — with the main viewport marked on it as a rectangle
+//! or dot. It is composed with the ordinary [`compose_layer`] and theme
+//! roles, then nested as an `<svg>` inside one layer's document, so it
+//! ships in the same media file and reveals with that layer.
+//!
+//! ```text
+//! inset features ──► (globe) drop far-side geometry
+//!                    (mercator) rotate + split + fit to features
+//!                ──► compose_layer(size × size) ──► nest at corner
+//! ```
+
+use crate::clip;
+use crate::compose::{Feature, LayerStyle, RenderDetail, compose_layer};
+use crate::dsl::{InsetMarker, InsetPosition, InsetProjection, InsetSpec};
+use crate::geometry::{BBox, Geometry, LonLat, Polygon};
+use crate::project::{Mercator, Orthographic, Projector};
+use crate::unwrap;
+use marki_render::escape_html as escape_attr;
+use std::fmt::Write;
+
+/// Gap between the inset frame and the canvas edge, in px.
+const INSET_MARGIN_PX: f64 = 8.0;
+
+/// Below this projected width or height (px) an `auto` marker draws a
+/// dot instead of a rectangle that would read as a smudge.
+const MIN_RECT_MARKER_PX: f64 = 4.0;
+
+/// Vertices per edge of the rectangle marker. The edges are densified so
+/// the rectangle curves correctly on the globe.
+const MARKER_EDGE_STEPS: usize = 16;
+
+/// One inset feature: `(geometry, role, faithful)`, as in a resolved
+/// layer minus the context flag (nothing in an inset moves the camera).
+pub type InsetFeature = (Geometry, &'static str, bool);
+
+/// Everything the inset needs from the main render.
+pub struct InsetInput<'a> {
+    pub spec: &'a InsetSpec,
+    /// Inset features, unrotated.
+    pub features: Vec<InsetFeature>,
+    /// Main map's visible viewport, in the rotated frame: longitudes sit
+    /// within 180° of `central` and may run past ±180.
+    pub viewport: BBox,
+    /// Central meridian the main map was rotated to.
+    pub central: f64,
+    /// Rendered main canvas size.
+    pub canvas: (u32, u32),
+    /// Theme styling; the inset reuses its roles and background.
+    pub style: &'a LayerStyle,
+    pub detail: RenderDetail,
+}
+
+/// Compose the inset as an SVG fragment (`<g class="marki-map-inset">`)
+/// positioned in absolute canvas coordinates, ready for
+/// [`crate::compose::splice_overlay`].
+pub fn compose_inset(input: InsetInput<'_>) -> String {
+    let (w, h) = (input.canvas.0 as f64, input.canvas.1 as f64);
+    let side = (input.spec.size * w.min(h)).round().max(1.0);
+    let (x, y) = corner(input.spec.position, (w, h), side);
+
+    let mut features = input.features;
+    let mut inset_style = input.style.clone();
+    inset_style.background = None;
+
+    let projector: Box<dyn Projector> = match input.spec.projection {
+        InsetProjection::Globe => {
+            let center = globe_center(input.viewport);
+            let ortho = Orthographic::new(center, side * 0.5 - 1.0, (side * 0.5, side * 0.5));
+            features = features
+                .into_iter()
+                .filter_map(|(g, role, faithful)| {
+                    cull_far_side(g, &ortho).map(|g| (g, role, faithful))
+                })
+                .collect();
+            Box::new(ortho)
+        }
+        InsetProjection::Mercator => {
+            let mut bb = input.viewport;
+            for (g, _, _) in &mut features {
+                if input.central.abs() > f64::EPSILON {
+                    unwrap::rotate_geometry(g, input.central);
+                }
+                let old = std::mem::take(g);
+                *g = unwrap::split_at_wrap(old, input.central);
+                bb.extend(g.bbox());
+            }
+            let padded = bb.padded(0.05);
+            let clip_bb = padded.padded(0.005);
+            for (g, _, _) in &mut features {
+                let old = std::mem::take(g);
+                *g = clip::clip_geometry(old, clip_bb);
+            }
+            Box::new(Mercator::fit(padded, (side, side)))
+        }
+    };
+
+    let marker = marker_geometry(input.spec.marker, input.viewport, &*projector);
+    features.push((marker, "marker", false));
+
+    let drawn: Vec<Feature<'_>> = features
+        .iter()
+        .map(|(g, role, faithful)| Feature {
+            geom: g,
+            role,
+            faithful: *faithful,
+        })
+        .collect();
+    let px = side.round() as u32;
+    let body = compose_layer(px, px, &inset_style, &*projector, &drawn, 0.0, input.detail);
+
+    let mut out = String::with_capacity(body.len() + 256);
+    out.push_str("<g class=\"marki-map-inset\">");
+    write_frame(&mut out, input.spec.projection, (x, y), side, input.style);
+    out.push_str(&nest_at(&body, x, y));
+    out.push_str("</g>");
+    out
+}
+
+/// Centre of the globe for a viewport in the rotated frame. The
+/// rotation only moves longitudes by whole turns, so the midpoint is
+/// already the right meridian; it is normalised to ±180 to match the
+/// unrotated inset features.
+fn globe_center(vp: BBox) -> LonLat {
+    LonLat {
+        lon: unwrap::rotate_lon((vp.min_lon + vp.max_lon) * 0.5, 0.0),
+        lat: ((vp.min_lat + vp.max_lat) * 0.5).clamp(-90.0, 90.0),
+    }
+}
+
+/// Top-left corner of a `side`-px inset anchored at `pos`.
+fn corner(pos: InsetPosition, (w, h): (f64, f64), side: f64) -> (f64, f64) {
+    let right = (w - side - INSET_MARGIN_PX).max(0.0);
+    let bottom = (h - side - INSET_MARGIN_PX).max(0.0);
+    let left = INSET_MARGIN_PX.min(right);
+    let top = INSET_MARGIN_PX.min(bottom);
+    match pos {
+        InsetPosition::TopLeft => (left, top),
+        InsetPosition::TopRight => (right, top),
+        InsetPosition::BottomLeft => (left, bottom),
+        InsetPosition::BottomRight => (right, bottom),
+    }
+}
+
+/// Turn a standalone `compose_layer` document into a nested `<svg>`
+/// placed at `(x, y)`. A nested `<svg>` clips to its own viewport, which
+/// keeps Mercator inset geometry inside the frame.
+fn nest_at(doc: &str, x: f64, y: f64) -> String {
+    match doc.strip_prefix("<svg ") {
+        Some(rest) => format!("<svg x=\"{x:.2}\" y=\"{y:.2}\" {rest}"),
+        None => doc.to_string(),
+    }
+}
+
+/// Draw the inset's backdrop: a disk for the globe, a rectangle for a
+/// Mercator outline. Filled with the theme background and stroked like
+/// the `outline` role so it reads as part of the same map.
+fn write_frame(
+    out: &mut String,
+    projection: InsetProjection,
+    (x, y): (f64, f64),
+    side: f64,
+    style: &LayerStyle,
+) {
+    let fill = style.background.as_deref().unwrap_or("#fff");
+    let (stroke, sw) = style
+        .role("outline")
+        .map(|r| (r.stroke.as_str(), r.stroke_width))
+        .unwrap_or(("#333", 1.0));
+    let fill = escape_attr(fill);
+    let stroke = escape_attr(stroke);
+    match projection {
+        InsetProjection::Globe => {
+            let r = side * 0.5 - 1.0;
+            let _ = write!(
+                out,
+                "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{r:.2}\" fill=\"{fill}\" \
+                 stroke=\"{stroke}\" stroke-width=\"{sw}\"/>",
+                x + side * 0.5,
+                y + side * 0.5,
+            );
+        }
+        InsetProjection::Mercator => {
+            let _ = write!(
+                out,
+                "<rect x=\"{x:.2}\" y=\"{y:.2}\" width=\"{side:.2}\" height=\"{side:.2}\" \
+                 fill=\"{fill}\" stroke=\"{stroke}\" stroke-width=\"{sw}\"/>"
+            );
+        }
+    }
+}
+
+/// Geometry marking the main viewport: its (densified) bbox outline, or
+/// its centre point. `auto` picks the rectangle only when it projects to
+/// at least [`MIN_RECT_MARKER_PX`] on both axes.
+fn marker_geometry(marker: InsetMarker, vp: BBox, p: &dyn Projector) -> Geometry {
+    let rect = Geometry::Polygon {
+        outer: densified_rect(vp),
+        holes: vec![],
+    };
+    let dot = Geometry::Point(LonLat {
+        lon: (vp.min_lon + vp.max_lon) * 0.5,
+        lat: (vp.min_lat + vp.max_lat) * 0.5,
+    });
+    match marker {
+        InsetMarker::Rect => rect,
+        InsetMarker::Dot => dot,
+        InsetMarker::Auto => {
+            let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+            let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+            if let Geometry::Polygon { outer, .. } = &rect {
+                for pt in outer {
+                    let (x, y) = p.project(*pt);
+                    min_x = min_x.min(x);
+                    min_y = min_y.min(y);
+                    max_x = max_x.max(x);
+                    max_y = max_y.max(y);
+                }
+            }
+            if max_x - min_x >= MIN_RECT_MARKER_PX && max_y - min_y >= MIN_RECT_MARKER_PX {
+                rect
+            } else {
+                dot
+            }
+        }
+    }
+}
+
+/// Closed ring tracing `bb`, with [`MARKER_EDGE_STEPS`] vertices per edge.
+fn densified_rect(bb: BBox) -> Vec<LonLat> {
+    let corners = [
+        LonLat { lon: bb.min_lon, lat: bb.min_lat },
+        LonLat { lon: bb.max_lon, lat: bb.min_lat },
+        LonLat { lon: bb.max_lon, lat: bb.max_lat },
+        LonLat { lon: bb.min_lon, lat: bb.max_lat },
+    ];
+    let mut ring = Vec::with_capacity(4 * MARKER_EDGE_STEPS + 1);
+    for i in 0..4 {
+        let a = corners[i];
+        let b = corners[(i + 1) % 4];
+        for s in 0..MARKER_EDGE_STEPS {
+            let t = s as f64 / MARKER_EDGE_STEPS as f64;
+            ring.push(LonLat {
+                lon: a.lon + (b.lon - a.lon) * t,
+                lat: a.lat + (b.lat - a.lat) * t,
+            });
+        }
+    }
+    ring.push(corners[0]);
+    ring
+}
+
+/// Drop geometry on the far side of the globe. Lines are cut into their
+/// visible runs (a hidden stretch would otherwise trace the limb);
+/// polygon components survive if any outer vertex is visible, with
+/// their hidden vertices pinned to the limb by the projector. Returns
+/// `None` when nothing is left.
+fn cull_far_side(g: Geometry, p: &Orthographic) -> Option<Geometry> {
+    let visible_runs = |line: &[LonLat]| -> Vec<Vec<LonLat>> {
+        let mut runs = Vec::new();
+        let mut cur: Vec<LonLat> = Vec::new();
+        for pt in line {
+            if p.is_visible(*pt) {
+                cur.push(*pt);
+            } else if !cur.is_empty() {
+                if cur.len() >= 2 {
+                    runs.push(std::mem::take(&mut cur));
+                } else {
+                    cur.clear();
+                }
+            }
+        }
+        if cur.len() >= 2 {
+            runs.push(cur);
+        }
+        runs
+    };
+    let any_visible = |ring: &[LonLat]| ring.iter().any(|pt| p.is_visible(*pt));
+    let out = match g {
+        Geometry::Point(pt) => p.is_visible(pt).then_some(Geometry::Point(pt))?,
+        Geometry::LineString(line) => Geometry::MultiLineString(visible_runs(&line)),
+        Geometry::MultiLineString(lines) => {
+            Geometry::MultiLineString(lines.iter().flat_map(|l| visible_runs(l)).collect())
+        }
+        Geometry::Polygon { outer, holes } => {
+            if !any_visible(&outer) {
+                return None;
+            }
+            let holes = holes.into_iter().filter(|h| any_visible(h)).collect();
+            Geometry::Polygon { outer, holes }
+        }
+        Geometry::MultiPolygon(polys) => Geometry::MultiPolygon(
+            polys
+                .into_iter()
+                .filter(|poly| any_visible(&poly.outer))
+                .map(|poly| Polygon {
+                    holes: poly.holes.into_iter().filter(|h| any_visible(h)).collect(),
+                    outer: poly.outer,
+                })
+                .collect(),
+        ),
+    };
+    match &out {
+        Geometry::MultiLineString(v) if v.is_empty() => None,
+        Geometry::MultiPolygon(v) if v.is_empty() => None,
+        _ => Some(out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compose::RoleStyle;
+
+    fn style() -> LayerStyle {
+        LayerStyle {
+            background: Some("#f4ecd8".into()),
+            ramp: vec![],
+            roles: vec![RoleStyle {
+                role: "outline".into(),
+                fill: "#e8dcb8".into(),
+                stroke: "#5a4632".into(),
+                stroke_width: 1.0,
+                dash: None,
+            }],
+        }
+    }
+
+    fn bavaria() -> BBox {
+        BBox {
+            min_lon: 8.9,
+            min_lat: 47.2,
+            max_lon: 13.9,
+            max_lat: 50.6,
+        }
+    }
+
+    fn input<'a>(spec: &'a InsetSpec, style: &'a LayerStyle) -> InsetInput<'a> {
+        InsetInput {
+            spec,
+            features: vec![],
+            viewport: bavaria(),
+            central: 0.0,
+            canvas: (600, 400),
+            style,
+            detail: RenderDetail::default(),
+        }
+    }
+
+    #[test]
+    fn globe_inset_draws_disk_and_nested_svg() {
+        let spec = InsetSpec::default();
+        let s = style();
+        let out = compose_inset(input(&spec, &s));
+        assert!(out.starts_with("<g class=\"marki-map-inset\">"), "{out}");
+        assert!(out.contains("<circle"), "globe frame is a disk: {out}");
+        // 0.3 × 400 = 120 px, bottom-left with an 8 px margin.
+        assert!(out.contains("<svg x=\"8.00\" y=\"272.00\""), "{out}");
+        assert!(out.contains("width=\"120\" height=\"120\""), "{out}");
+    }
+
+    #[test]
+    fn small_viewport_gets_dot_marker_on_globe() {
+        // Bavaria is a few px on a 120 px globe → auto picks the dot.
+        let spec = InsetSpec::default();
+        let s = style();
+        let ortho = Orthographic::new(LonLat { lon: 11.0, lat: 49.0 }, 59.0, (60.0, 60.0));
+        let g = marker_geometry(InsetMarker::Auto, bavaria(), &ortho);
+        assert!(matches!(g, Geometry::Point(_)));
+        // Forcing a rectangle still draws one.
+        let g = marker_geometry(InsetMarker::Rect, bavaria(), &ortho);
+        assert!(matches!(g, Geometry::Polygon { .. }));
+        let out = compose_inset(input(&spec, &s));
+        assert!(out.contains("r=\"2\""), "dot marker: {out}");
+    }
+
+    #[test]
+    fn mercator_inset_frames_with_rect_marker() {
+        let spec = InsetSpec {
+            projection: InsetProjection::Mercator,
+            position: InsetPosition::TopRight,
+            ..InsetSpec::default()
+        };
+        let s = style();
+        let mut inp = input(&spec, &s);
+        // A Europe-sized feature makes Bavaria a readable rectangle.
+        inp.features.push((
+            Geometry::Polygon {
+                outer: densified_rect(BBox {
+                    min_lon: -10.0,
+                    min_lat: 35.0,
+                    max_lon: 30.0,
+                    max_lat: 60.0,
+                }),
+                holes: vec![],
+            },
+            "outline",
+            false,
+        ));
+        let out = compose_inset(inp);
+        assert!(out.contains("<rect x=\"472.00\" y=\"8.00\""), "top-right frame: {out}");
+        assert_eq!(out.matches("fill-rule=\"evenodd\"").count(), 2, "feature + rect marker: {out}");
+    }
+
+    #[test]
+    fn globe_inset_follows_a_pacific_central_meridian() {
+        // A Fiji card: the main map rotated to 174.5°, so its viewport
+        // runs past +180 while the inset features stay unrotated.
+        let spec = InsetSpec {
+            marker: InsetMarker::Dot,
+            ..InsetSpec::default()
+        };
+        let s = style();
+        let mut inp = input(&spec, &s);
+        inp.central = 174.5;
+        inp.viewport = BBox {
+            min_lon: 176.0,
+            min_lat: -19.0,
+            max_lon: 182.0,
+            max_lat: -16.0,
+        };
+        inp.features.push((
+            Geometry::Polygon {
+                outer: densified_rect(BBox {
+                    min_lon: -179.9,
+                    min_lat: -19.0,
+                    max_lon: -178.0,
+                    max_lat: -16.0,
+                }),
+                holes: vec![],
+            },
+            "outline",
+            false,
+        ));
+        let c = globe_center(inp.viewport);
+        assert!((c.lon - 179.0).abs() < 1e-9 && (c.lat + 17.5).abs() < 1e-9, "{c:?}");
+        let out = compose_inset(inp);
+        // The viewport centre is the globe centre, and Fiji faces us.
+        assert!(out.contains("<circle cx=\"60.00\" cy=\"60.00\" r=\"2\"/>"), "{out}");
+        assert_eq!(out.matches("fill-rule=\"evenodd\"").count(), 1, "{out}");
+    }
+
+    #[test]
+    fn far_side_lines_are_cut() {
+        let p = Orthographic::new(LonLat { lon: 0.0, lat: 0.0 }, 50.0, (50.0, 50.0));
+        let line = Geometry::LineString(vec![
+            LonLat { lon: 0.0, lat: 0.0 },
+            LonLat { lon: 45.0, lat: 0.0 },
+            LonLat { lon: 135.0, lat: 0.0 }, // hidden
+            LonLat { lon: -45.0, lat: 0.0 },
+            LonLat { lon: -10.0, lat: 0.0 },
+        ]);
+        match cull_far_side(line, &p) {
+            Some(Geometry::MultiLineString(runs)) => assert_eq!(runs.len(), 2),
+            other => panic!("expected two visible runs, got {other:?}"),
+        }
+        let hidden = Geometry::Point(LonLat { lon: 180.0, lat: 0.0 });
+        assert!(cull_far_side(hidden, &p).is_none());
+    }
+}
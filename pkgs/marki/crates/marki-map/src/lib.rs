@@ -8,9 +8,11 @@
 //! Natural Earth (and, for OSM relation/way refs, the Overpass API),
 //! projects them to SVG units, composes one styled SVG per layer, and
 //! emits the bytes as `marki_render::Asset`s for the daemon to
-//! upload to Anki.
+//! upload to Anki. A project that opts into rasterisation ships each
+//! layer as a PNG instead; see [`MapRenderer::with_raster`].
 
 pub mod cache;
+pub mod choropleth;
 pub mod clip;
 pub mod cluster;
 pub mod compose;
@@ -21,8 +23,11 @@ pub mod embed;
 pub mod error;
 pub mod geometry;
 pub mod hash;
+pub mod inset;
 pub mod pipeline;
 pub mod project;
+pub mod route;
+pub mod set_expr;
 pub mod sidecar;
 pub mod simplify;
 pub mod style;
@@ -30,6 +35,7 @@ pub mod trim;
 pub mod unwrap;
 pub mod version;
 
+use marki_render::raster::Raster;
 use marki_render::{Fragment, Input, RenderCtx, RenderError, Renderer};
 
 pub use defaults::MapDefaults;
@@ -45,6 +51,8 @@ pub struct MapRenderer {
     /// Project-level DSL defaults + path rules, merged underneath each
     /// card's own block. Empty for a bare [`MapRenderer::new`].
     defaults: defaults::CompiledDefaults,
+    /// Rasterise layers to PNG. `None` ships the SVGs.
+    raster: Option<Raster>,
 }
 
 impl Default for MapRenderer {
@@ -57,6 +65,7 @@ impl MapRenderer {
     pub fn new() -> Self {
         Self {
             defaults: defaults::CompiledDefaults::empty(),
+            raster: None,
         }
     }
 
@@ -69,8 +78,16 @@ impl MapRenderer {
     ) -> Result<Self, String> {
         Ok(Self {
             defaults: defaults::CompiledDefaults::compile(defs, cards_dir)?,
+            raster: None,
         })
     }
+
+    /// Ship every layer as a PNG rasterised at `raster.scale`, cached
+    /// beside its SVG. The layered reveal markup is unchanged.
+    pub fn with_raster(mut self, raster: Raster) -> Self {
+        self.raster = Some(raster);
+        self
+    }
 }
 
 impl Renderer for MapRenderer {
@@ -78,8 +95,12 @@ impl Renderer for MapRenderer {
         MAP_LANG
     }
 
+    fn version(&self) -> u32 {
+        version::RENDER_VERSION_MAP
+    }
+
     fn render(&self, input: Input<'_>, ctx: &mut RenderCtx<'_>) -> Result<Fragment, RenderError> {
-        let spec = if self.defaults.is_empty() {
+        let mut spec: dsl::MapSpec = if self.defaults.is_empty() {
             input.deserialize()?
         } else {
             // Merge: project defaults (global + matching rules) underneath
@@ -91,7 +112,12 @@ impl Renderer for MapRenderer {
                 .try_into()
                 .map_err(|e: toml::de::Error| RenderError::Parse(e.to_string()))?
         };
-        Ok(pipeline::run(&spec, ctx.cache_dir)?)
+        let card_dir = ctx.source_path.parent().unwrap_or(std::path::Path::new("."));
+        // CSV tables must live under the cards directory (the card's own
+        // directory for a bare renderer, e.g. `marki render-map`).
+        let root = self.defaults.cards_dir().unwrap_or(card_dir);
+        choropleth::inline_tables(&mut spec, card_dir, root)?;
+        Ok(pipeline::run(&spec, ctx.cache_dir, self.raster.as_ref())?)
     }
 }
 
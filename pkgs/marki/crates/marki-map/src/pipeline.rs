@@ -19,20 +19,25 @@
 //! blank top/bottom strips.
 
 use crate::cache::{self, CacheFile};
+use crate::choropleth::{self, Classes};
 use crate::clip;
 use crate::cluster;
-use crate::compose::{Feature, RenderDetail, compose_layer};
+use crate::compose::{Feature, RenderDetail, compose_layer, splice_overlay};
 use crate::data::{geoboundaries, natural_earth, overpass};
-use crate::dsl::{MapSpec, RevealMode};
+use crate::dsl::{MapSpec, RevealMode, RouteSpec};
 use crate::embed::{EmbedLayer, embed_layers, resolve_reveals};
 use crate::error::MapError;
 use crate::geometry::{BBox, Geometry, LonLat};
 use crate::hash::cache_key;
+use crate::inset::{self, InsetFeature, InsetInput};
 use crate::project::{Mercator, Projector};
+use crate::route;
+use crate::set_expr;
 use crate::sidecar::{Sidecar, SidecarLayer};
 use crate::style::load as load_theme;
 use crate::trim;
 use crate::unwrap;
+use marki_render::raster::Raster;
 use marki_render::{AssetMime, Asset, Fragment};
 use std::collections::BTreeMap;
 use std::path::Path;
@@ -57,13 +62,17 @@ struct ResolvedLayer<'a> {
 ///
 /// On a cache hit, the SVGs and sidecar are read directly from disk
 /// and no resolve / project / compose work happens.
-pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
+///
+/// With `raster`, every layer SVG is also rasterised to a PNG, cached
+/// beside it and shipped in its place.
+pub fn run(spec: &MapSpec, cache_root: &Path, raster: Option<&Raster>) -> Result<Fragment, MapError> {
     let theme = load_theme(&spec.style)?;
-    let key = cache_key(spec, &theme.bytes)?;
+    let key = cache_key(spec, &theme.bytes, raster)?;
 
     if cache::is_ready(cache_root, &key) {
         tracing::debug!(key, "map cache hit");
-        return load_from_cache(spec, cache_root, &key);
+        cache::touch(cache_root, &key);
+        return load_from_cache(spec, cache_root, &key, raster);
     }
     tracing::debug!(
         key,
@@ -73,7 +82,9 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
     );
 
     // ---- Resolve.
-    let mut resolved = resolve_all_layers(spec, cache_root)?;
+    overpass::prefetch(&osm_refs(spec))?;
+    let mut resolved = resolve_all_layers(spec, &theme.style.ramp, cache_root)?;
+    let inset_features = resolve_inset(spec, cache_root)?;
     if tracing::enabled!(tracing::Level::TRACE) {
         for l in &resolved {
             tracing::trace!(layer = %l.name, features = l.features.len(), "resolved layer");
@@ -164,6 +175,23 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
         island_rel_frac: spec.viewport.island_rel_frac,
         simplify_px: spec.viewport.simplify_px,
     };
+    // ---- Locator inset: composed once, spliced into its host layer.
+    let inset_svg = match (&spec.inset, inset_features) {
+        (Some(ispec), Some(features)) => Some((
+            ispec.layer.as_str(),
+            inset::compose_inset(InsetInput {
+                spec: ispec,
+                features,
+                viewport: padded,
+                central,
+                canvas: (render_w, render_h),
+                style: &theme.style,
+                detail,
+            }),
+        )),
+        _ => None,
+    };
+
     let mut svg_files: Vec<(String, String, Vec<u8>)> = Vec::new();
     let reveals = resolve_reveals(&spec.layers);
     for layer in &resolved {
@@ -182,15 +210,28 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
         if layer.name != "base" {
             layer_style.background = None;
         }
+        // Choropleth layers get one role per class, filled from the ramp.
+        let lspec = &spec.layers[layer.name];
+        let classes = match &lspec.choropleth {
+            Some(ch) => Some(Classes::new(ch, &theme.style.ramp)?),
+            None => None,
+        };
+        if let Some(c) = &classes {
+            c.install_roles(&mut layer_style);
+        }
         // Apply per-layer highlight style overrides from the DSL. On a
-        // hull layer the same overrides target the `hull` role.
-        if let Some(ov) = &spec.layers[layer.name].style {
-            for role in layer_style
-                .roles
-                .iter_mut()
-                .filter(|r| r.role == "highlight" || r.role == "hull")
-            {
-                if let Some(f) = &ov.fill {
+        // hull layer the same overrides target the `hull` role; on a
+        // choropleth layer stroke overrides reach the class roles, whose
+        // fills stay with the ramp, and likewise the route roles, which
+        // are never filled.
+        if let Some(ov) = &lspec.style {
+            for role in layer_style.roles.iter_mut().filter(|r| {
+                is_stretch_role(&r.role) || r.role.starts_with("class-")
+            }) {
+                if let Some(f) = &ov.fill
+                    && !role.role.starts_with("class-")
+                    && !role.role.starts_with("route")
+                {
                     role.fill = f.clone();
                 }
                 if let Some(s) = &ov.stroke {
@@ -204,7 +245,7 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
         // Scale-aware halo radius: a fraction of the viewport diagonal,
         // floored at `min_px` and capped at `max_frac` of the diagonal.
         // Zero on non-hull layers (ignored by the composer).
-        let hull_radius_px = match &spec.layers[layer.name].hull {
+        let hull_radius_px = match &lspec.hull {
             Some(h) => {
                 let diag =
                     ((render_w as f64).powi(2) + (render_h as f64).powi(2)).sqrt();
@@ -215,7 +256,7 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
             }
             None => 0.0,
         };
-        let svg = compose_layer(
+        let mut svg = compose_layer(
             render_w,
             render_h,
             &layer_style,
@@ -224,6 +265,22 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
             hull_radius_px,
             detail,
         );
+        if let (Some(c), Some(ch)) = (&classes, &lspec.choropleth) {
+            let all_integer = ch.data.values().all(|v| v.fract() == 0.0);
+            let legend = choropleth::legend_svg(
+                c,
+                &ch.legend,
+                all_integer,
+                (render_w, render_h),
+                &theme.style,
+            );
+            svg = splice_overlay(&svg, &legend);
+        }
+        if let Some((host, fragment)) = &inset_svg
+            && *host == layer.name
+        {
+            svg = splice_overlay(&svg, fragment);
+        }
         tracing::trace!(
             layer = %layer.name,
             features = features.len(),
@@ -235,17 +292,32 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
         svg_files.push((layer.name.to_string(), cache_filename, svg.into_bytes()));
     }
 
+    // ---- Rasterise (opt-in). The PNGs replace the SVGs in the card
+    //      but both are cached, so `marki cache verify` covers either.
+    let png_files = match raster {
+        Some(r) => svg_files
+            .iter()
+            .map(|(name, _cache_name, svg)| {
+                let png = r.png(svg).map_err(MapError::Internal)?;
+                Ok((name.clone(), format!("{name}.png"), png))
+            })
+            .collect::<Result<Vec<_>, MapError>>()?,
+        None => Vec::new(),
+    };
+    let (ext, _) = layer_format(raster);
+    let emitted = if raster.is_some() { &png_files } else { &svg_files };
+
     // ---- Sidecar.
     let sidecar = Sidecar {
         width: render_w,
         height: render_h,
         requested_size: spec.size,
         projection: projection_name.into(),
-        layers: svg_files
+        layers: emitted
             .iter()
             .map(|(name, _cache_name, _bytes)| SidecarLayer {
                 name: name.clone(),
-                filename: layer_media_filename(&key, name),
+                filename: layer_media_filename(&key, name, ext),
                 reveal: reveals.get(name).copied().unwrap_or(RevealMode::Fade),
             })
             .collect(),
@@ -256,6 +328,7 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
     // ---- Persist to cache (atomic).
     let mut files: Vec<CacheFile<'_>> = svg_files
         .iter()
+        .chain(&png_files)
         .map(|(_name, cache_name, bytes)| CacheFile {
             name: cache_name.as_str(),
             bytes: bytes.as_slice(),
@@ -268,7 +341,7 @@ pub fn run(spec: &MapSpec, cache_root: &Path) -> Result<Fragment, MapError> {
     cache::write_atomic(cache_root, &key, &files)?;
 
     // ---- Build embed + assets.
-    Ok(build_block(&key, render_w, render_h, &reveals, &svg_files))
+    Ok(build_block(&key, render_w, render_h, &reveals, emitted, raster))
 }
 
 /// Pick the largest `(w, h)` within `budget` whose aspect equals
@@ -296,12 +369,22 @@ fn fit_canvas(budget: [u32; 2], aspect: f64) -> (u32, u32) {
 
 /// Compute the Anki-media filename for one layer. The content-addressed
 /// cache key prevents collisions between cards.
-fn layer_media_filename(key: &str, layer_name: &str) -> String {
-    format!("marki-map-{key}-{layer_name}.svg")
+fn layer_media_filename(key: &str, layer_name: &str, ext: &str) -> String {
+    format!("marki-map-{key}-{layer_name}.{ext}")
+}
+
+/// Extension and MIME type of the layer files a render ships: PNG when
+/// rasterising, SVG otherwise.
+fn layer_format(raster: Option<&Raster>) -> (&'static str, AssetMime) {
+    match raster {
+        Some(_) => ("png", AssetMime::ImagePng),
+        None => ("svg", AssetMime::SvgXml),
+    }
 }
 
 fn resolve_all_layers<'a>(
     spec: &'a MapSpec,
+    theme_ramp: &[String],
     cache_root: &Path,
 ) -> Result<Vec<ResolvedLayer<'a>>, MapError> {
     let mut out = Vec::with_capacity(spec.layers.len());
@@ -327,19 +410,81 @@ fn resolve_all_layers<'a>(
                 features.push((g, "hull", false, is_composite_ref(r)));
             }
         }
+        for r in &lspec.routes {
+            let g = resolve_route(r, cache_root)?;
+            features.push((g, r.style.role(), false, false));
+        }
+        if let Some(ch) = &lspec.choropleth {
+            let classes = Classes::new(ch, theme_ramp)?;
+            for (r, value) in &ch.data {
+                let g = resolve_one(r, cache_root)?;
+                features.push((g, classes.role_for(*value), false, is_composite_ref(r)));
+            }
+        }
         out.push(ResolvedLayer { name, features });
     }
     Ok(out)
 }
 
-/// Whether a feature ref is a composite — a continent, subregion or
-/// neighbour set, each a concatenation of independently-keyed but
-/// border-coincident member units (CGAZ). Composites must skip
-/// per-feature outline simplification, which would split their shared
-/// internal borders into double lines. (Island culling stays on — it
-/// only drops whole disconnected specks, never a shared land border.)
+/// Every OSM ref the spec names, so an extract-backed render can read
+/// them all in one batch (see [`overpass::prefetch`]).
+fn osm_refs(spec: &MapSpec) -> Vec<&str> {
+    let mut refs: Vec<&str> = Vec::new();
+    for lspec in spec.layers.values() {
+        refs.extend(lspec.features.iter().map(String::as_str));
+        refs.extend(lspec.context.iter().map(String::as_str));
+        refs.extend(lspec.highlights.iter().map(String::as_str));
+        if let Some(hull) = &lspec.hull {
+            refs.extend(hull.features.iter().map(String::as_str));
+        }
+        for r in &lspec.routes {
+            refs.extend(r.stops());
+        }
+        if let Some(ch) = &lspec.choropleth {
+            refs.extend(ch.data.iter().map(|(r, _)| r.as_str()));
+        }
+    }
+    if let Some(ispec) = &spec.inset {
+        refs.extend(ispec.features.iter().map(String::as_str));
+    }
+    refs.retain(|r| r.starts_with("relation/") || r.starts_with("way/"));
+    refs
+}
+
+/// Resolve the inset's own feature references, kept apart from the
+/// layers so they never influence the main viewport or central
+/// meridian. `None` when the spec has no inset.
+fn resolve_inset(
+    spec: &MapSpec,
+    cache_root: &Path,
+) -> Result<Option<Vec<InsetFeature>>, MapError> {
+    let Some(ispec) = &spec.inset else {
+        return Ok(None);
+    };
+    if !spec.layers.contains_key(&ispec.layer) {
+        return Err(MapError::Parse(format!(
+            "inset layer `{}` is not one of the map's layers",
+            ispec.layer
+        )));
+    }
+    let mut out = Vec::with_capacity(ispec.features.len());
+    for r in &ispec.features {
+        let g = resolve_one(r, cache_root)?;
+        out.push((g, role_for_feature_ref(r, "base"), is_composite_ref(r)));
+    }
+    Ok(Some(out))
+}
+
+/// Whether a feature ref is a composite — a continent, subregion,
+/// neighbour set or set expression, each a concatenation of
+/// independently-keyed but border-coincident member units (CGAZ).
+/// Composites must skip per-feature outline simplification, which would
+/// split their shared internal borders into double lines. (Island
+/// culling stays on — it only drops whole disconnected specks, never a
+/// shared land border.)
 fn is_composite_ref(r: &str) -> bool {
-    r.starts_with("continent/")
+    set_expr::is_set_expr(r)
+        || r.starts_with("continent/")
         || r.starts_with("subregion/")
         || r.starts_with("neighbors/")
 }
@@ -347,9 +492,15 @@ fn is_composite_ref(r: &str) -> bool {
 /// Resolve one feature reference. Centralised here so future sources
 /// can be added without touching the per-source loaders.
 fn resolve_one(r: &str, cache_root: &Path) -> Result<Geometry, MapError> {
+    if set_expr::is_set_expr(r) {
+        return geoboundaries::resolve_set(&set_expr::parse(r)?);
+    }
     if r == "coastline" {
         return natural_earth::resolve_feature(r);
     }
+    if let Some(rest) = r.strip_prefix("point/") {
+        return route::parse_point(rest).map(Geometry::Point);
+    }
     if r.starts_with("country/")
         || r.starts_with("adm1/")
         || r.starts_with("adm2/")
@@ -357,6 +508,7 @@ fn resolve_one(r: &str, cache_root: &Path) -> Result<Geometry, MapError> {
         || r.starts_with("neighbors/")
         || r.starts_with("continent/")
         || r.starts_with("subregion/")
+        || r.starts_with("place/")
     {
         return geoboundaries::resolve_feature(r);
     }
@@ -366,6 +518,30 @@ fn resolve_one(r: &str, cache_root: &Path) -> Result<Geometry, MapError> {
     Err(MapError::Resolve(format!("unsupported feature ref: {r}")))
 }
 
+/// Resolve a route's stops and trace the great circles between them.
+/// Feature-ref stops stand for their [`Geometry::representative_point`].
+fn resolve_route(r: &RouteSpec, cache_root: &Path) -> Result<Geometry, MapError> {
+    let stops = if r.points.is_empty() {
+        r.stops()
+            .map(|s| {
+                let p = resolve_one(s, cache_root)?
+                    .representative_point()
+                    .ok_or_else(|| MapError::Resolve(format!("route stop `{s}` is empty")))?;
+                Ok(LonLat { lon: norm180(p.lon), lat: p.lat })
+            })
+            .collect::<Result<Vec<_>, MapError>>()?
+    } else {
+        r.points.iter().map(|&[lon, lat]| LonLat { lon, lat }).collect()
+    };
+    route::geodesic(&stops)
+}
+
+/// Roles that stretch the viewport without being part of its focus:
+/// highlights, hulls and routes.
+fn is_stretch_role(role: &str) -> bool {
+    role == "highlight" || role == "hull" || role.starts_with("route")
+}
+
 /// Pick a stylistic role for a feature reference based on what kind of
 /// reference it is and which layer it lives on. Authors typically don't
 /// need to think about roles directly.
@@ -392,7 +568,7 @@ fn collect_focus_geoms<'a>(layers: &'a [ResolvedLayer<'_>]) -> Vec<&'a Geometry>
     let mut out = Vec::new();
     for l in layers {
         for (g, role, is_context, _) in &l.features {
-            if *is_context || *role == "highlight" || *role == "hull" {
+            if *is_context || is_stretch_role(role) {
                 continue;
             }
             out.push(g);
@@ -408,7 +584,7 @@ fn collect_focus_geoms<'a>(layers: &'a [ResolvedLayer<'_>]) -> Vec<&'a Geometry>
 ///     geometry whose dominant landmass should fill the canvas.
 ///   * **highlights** — always stretch the viewport to include them
 ///     (so a Hawaii highlight on a USA base correctly spans the
-///     Pacific).
+///     Pacific). Hulls and routes count as highlights here.
 ///   * **context** — drawn but excluded from the viewport entirely.
 ///
 /// Focus features go through [`cluster::main_cluster_bbox`] which picks
@@ -422,7 +598,7 @@ fn viewport_bbox(layers: &[ResolvedLayer<'_>], cluster_factor: f64) -> Result<BB
             if *is_context {
                 continue;
             }
-            if *role == "highlight" || *role == "hull" {
+            if is_stretch_role(role) {
                 highlights.push(g);
             } else {
                 focus.push(g);
@@ -564,11 +740,13 @@ fn build_block(
     render_w: u32,
     render_h: u32,
     reveals: &BTreeMap<String, RevealMode>,
-    svg_files: &[(String, String, Vec<u8>)],
+    layer_files: &[(String, String, Vec<u8>)],
+    raster: Option<&Raster>,
 ) -> Fragment {
-    let media_files: Vec<(String, String)> = svg_files
+    let (ext, mime) = layer_format(raster);
+    let media_files: Vec<(String, String)> = layer_files
         .iter()
-        .map(|(name, _cache_name, _)| (name.clone(), layer_media_filename(key, name)))
+        .map(|(name, _cache_name, _)| (name.clone(), layer_media_filename(key, name, ext)))
         .collect();
 
     let mut layers: Vec<EmbedLayer<'_>> = media_files
@@ -581,12 +759,12 @@ fn build_block(
         .collect();
     let embed = embed_layers(render_w, render_h, &layers);
 
-    let mut assets: Vec<Asset> = svg_files
+    let mut assets: Vec<Asset> = layer_files
         .iter()
         .map(|(name, _cache_name, bytes)| Asset {
-            filename: layer_media_filename(key, name),
+            filename: layer_media_filename(key, name, ext),
             bytes: bytes.clone(),
-            mime: AssetMime::SvgXml,
+            mime,
         })
         .collect();
 
@@ -601,6 +779,7 @@ fn load_from_cache(
     spec: &MapSpec,
     cache_root: &Path,
     key: &str,
+    raster: Option<&Raster>,
 ) -> Result<Fragment, MapError> {
     // Read sidecar first — its `layers` array is the authoritative
     // source for layer order (written in TOML/IndexMap order during
@@ -610,11 +789,12 @@ fn load_from_cache(
     let parsed: Sidecar = serde_json::from_slice(&sidecar_bytes)
         .map_err(|e| MapError::Internal(format!("sidecar parse: {e}")))?;
 
-    let mut svg_files: Vec<(String, String, Vec<u8>)> = Vec::new();
+    let (ext, _) = layer_format(raster);
+    let mut layer_files: Vec<(String, String, Vec<u8>)> = Vec::new();
     for layer in &parsed.layers {
-        let svg_name = format!("{}.svg", layer.name);
-        let bytes = cache::read_file(cache_root, key, &svg_name)?;
-        svg_files.push((layer.name.clone(), svg_name, bytes));
+        let file_name = format!("{}.{ext}", layer.name);
+        let bytes = cache::read_file(cache_root, key, &file_name)?;
+        layer_files.push((layer.name.clone(), file_name, bytes));
     }
 
     let reveals = resolve_reveals(&spec.layers);
@@ -623,7 +803,8 @@ fn load_from_cache(
         parsed.width,
         parsed.height,
         &reveals,
-        &svg_files,
+        &layer_files,
+        raster,
     ))
 }
 
@@ -660,6 +841,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn osm_refs_gathers_every_osm_ref_of_the_spec() {
+        let spec = crate::dsl::parse_map_spec(
+            r#"
+size = [600, 400]
+
+[inset]
+features = ["coastline", "way/3"]
+
+[layers.base]
+features = ["country/DEU", "relation/1"]
+context = ["way/2"]
+
+[layers.answer]
+highlights = ["relation/1"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(osm_refs(&spec), ["relation/1", "way/2", "relation/1", "way/3"]);
+    }
+
     #[test]
     fn central_meridian_europe_is_midpoint() {
         // −10°…40° → ≈15°E, same as the old raw-midpoint behaviour.
@@ -10,6 +10,9 @@
 //!     correction — i.e. `y = ln(tan(π/4 + lat/2))`. Standard for
 //!     world / continent views.
 //!
+//! Locator insets additionally use `Orthographic`, a globe view
+//! centred on the main map's viewport.
+//!
 //! Both projections "auto-fit" — they're constructed from a target
 //! bbox and an output `(width, height)` and produce SVG-pixel
 //! coordinates with the bbox occupying as much of the canvas as
@@ -163,6 +166,66 @@ impl Projector for Mercator {
     }
 }
 
+/// Orthographic (globe) projection centred on `center`, drawn as a disk
+/// of `radius` px around `origin`. Used by locator insets.
+///
+/// Points on the far hemisphere have no position on the visible disk;
+/// [`Projector::project`] pins them to the limb at the same azimuth so
+/// a polygon straddling the horizon closes along the globe's edge.
+/// Callers drop wholly hidden geometry first via [`Self::is_visible`].
+pub struct Orthographic {
+    pub center: LonLat,
+    pub radius: f64,
+    pub origin: (f64, f64),
+    sin_lat0: f64,
+    cos_lat0: f64,
+}
+
+impl Orthographic {
+    pub fn new(center: LonLat, radius: f64, origin: (f64, f64)) -> Self {
+        let lat0 = center.lat.to_radians();
+        Self {
+            center,
+            radius,
+            origin,
+            sin_lat0: lat0.sin(),
+            cos_lat0: lat0.cos(),
+        }
+    }
+
+    /// Cosine of the angular distance from the centre; negative on the
+    /// far hemisphere.
+    fn cos_c(&self, p: LonLat) -> f64 {
+        let lat = p.lat.to_radians();
+        let dlon = (p.lon - self.center.lon).to_radians();
+        self.sin_lat0 * lat.sin() + self.cos_lat0 * lat.cos() * dlon.cos()
+    }
+
+    /// Whether `p` lies on the visible (near) hemisphere.
+    pub fn is_visible(&self, p: LonLat) -> bool {
+        self.cos_c(p) >= 0.0
+    }
+}
+
+impl Projector for Orthographic {
+    fn project(&self, p: LonLat) -> (f64, f64) {
+        let lat = p.lat.to_radians();
+        let dlon = (p.lon - self.center.lon).to_radians();
+        let mut x = lat.cos() * dlon.sin();
+        let mut y = self.cos_lat0 * lat.sin() - self.sin_lat0 * lat.cos() * dlon.cos();
+        if self.cos_c(p) < 0.0 {
+            // Far side: push out to the limb along the same azimuth.
+            let len = (x * x + y * y).sqrt();
+            if len > 1e-12 {
+                x /= len;
+                y /= len;
+            }
+        }
+        // Y flipped.
+        (self.origin.0 + x * self.radius, self.origin.1 - y * self.radius)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -250,4 +313,20 @@ mod tests {
         );
         assert!(aspect < 1.0, "expected germany taller than wide, got {aspect}");
     }
+
+    #[test]
+    fn orthographic_centre_and_limb() {
+        let p = Orthographic::new(LonLat { lon: 10.0, lat: 50.0 }, 40.0, (50.0, 50.0));
+        let c = p.project(LonLat { lon: 10.0, lat: 50.0 });
+        assert!((c.0 - 50.0).abs() < 1e-9 && (c.1 - 50.0).abs() < 1e-9);
+        // North of the centre draws above it.
+        let n = p.project(LonLat { lon: 10.0, lat: 60.0 });
+        assert!(n.1 < 50.0);
+        // The antipode is hidden and pinned onto the limb.
+        let anti = LonLat { lon: -170.0, lat: -50.0 };
+        assert!(!p.is_visible(anti));
+        let far = p.project(LonLat { lon: -100.0, lat: 0.0 });
+        let r = ((far.0 - 50.0).powi(2) + (far.1 - 50.0).powi(2)).sqrt();
+        assert!((r - 40.0).abs() < 1e-6, "hidden point off the limb: r = {r}");
+    }
 }
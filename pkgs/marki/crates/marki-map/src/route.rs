@@ -0,0 +1,158 @@
+//! Great-circle route geometry.
+//!
+//! A route is a list of stops joined by the shortest path on the
+//! sphere. Under Mercator a great circle is a curve, so each leg is
+//! densified into short steps (about [`STEP_DEG`] of arc apart) before
+//! projection; the straight segments between them are indistinguishable
+//! from the true curve at card sizes.
+//!
+//! Every vertex comes out with its longitude in `(-180°, 180°]`, the
+//! same frame the data sources use. A leg that crosses the antimeridian
+//! therefore jumps by ~360° between two vertices, and the pipeline's
+//! rotate + [`crate::unwrap::split_at_wrap`] pass cuts it exactly as it
+//! cuts a wrapping coastline or country outline.
+
+use crate::error::MapError;
+use crate::geometry::{Geometry, LonLat};
+
+/// Target arc length between densified vertices, in degrees.
+pub const STEP_DEG: f64 = 1.0;
+
+/// Legs whose endpoints are closer than this to antipodal have no
+/// unique great circle.
+const ANTIPODAL_EPS: f64 = 1e-6;
+
+/// Parse the `<lon>,<lat>` tail of a `point/` reference.
+pub fn parse_point(s: &str) -> Result<LonLat, MapError> {
+    let bad = || MapError::Resolve(format!("bad point ref: point/{s} (want point/<lon>,<lat>)"));
+    let (lon, lat) = s.split_once(',').ok_or_else(bad)?;
+    let lon: f64 = lon.trim().parse().map_err(|_| bad())?;
+    let lat: f64 = lat.trim().parse().map_err(|_| bad())?;
+    if !((-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat)) {
+        return Err(bad());
+    }
+    Ok(LonLat { lon, lat })
+}
+
+/// Great-circle polyline through `stops`, densified for projection.
+pub fn geodesic(stops: &[LonLat]) -> Result<Geometry, MapError> {
+    let mut line = Vec::new();
+    for leg in stops.windows(2) {
+        densify_leg(leg[0], leg[1], &mut line)?;
+    }
+    if let Some(&last) = stops.last() {
+        line.push(to_lonlat(to_vec(last)));
+    }
+    Ok(Geometry::LineString(line))
+}
+
+/// Append the vertices of the leg `a → b`, excluding `b` itself.
+fn densify_leg(a: LonLat, b: LonLat, out: &mut Vec<LonLat>) -> Result<(), MapError> {
+    let (va, vb) = (to_vec(a), to_vec(b));
+    let dot = (va[0] * vb[0] + va[1] * vb[1] + va[2] * vb[2]).clamp(-1.0, 1.0);
+    let angle = dot.acos();
+    if std::f64::consts::PI - angle < ANTIPODAL_EPS {
+        return Err(MapError::Resolve(format!(
+            "route leg ({}, {}) → ({}, {}) joins antipodal points; add a `via` stop",
+            a.lon, a.lat, b.lon, b.lat
+        )));
+    }
+    if angle < 1e-12 {
+        out.push(to_lonlat(va));
+        return Ok(());
+    }
+    // Shave rounding noise so a 10° leg is 10 steps, not 11.
+    let steps = (angle.to_degrees() / STEP_DEG - 1e-9).ceil().max(1.0) as usize;
+    let sin = angle.sin();
+    for i in 0..steps {
+        let t = i as f64 / steps as f64;
+        let (wa, wb) = (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin);
+        out.push(to_lonlat([
+            wa * va[0] + wb * vb[0],
+            wa * va[1] + wb * vb[1],
+            wa * va[2] + wb * vb[2],
+        ]));
+    }
+    Ok(())
+}
+
+fn to_vec(p: LonLat) -> [f64; 3] {
+    let (lon, lat) = (p.lon.to_radians(), p.lat.to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+fn to_lonlat(v: [f64; 3]) -> LonLat {
+    let lat = v[2].clamp(-1.0, 1.0).asin().to_degrees();
+    let lon = v[1].atan2(v[0]).to_degrees();
+    // atan2 yields [-180, 180]; fold -180 onto 180 like `norm180`.
+    let lon = if lon <= -180.0 { lon + 360.0 } else { lon };
+    LonLat { lon, lat }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ll(lon: f64, lat: f64) -> LonLat {
+        LonLat { lon, lat }
+    }
+
+    fn line(g: Geometry) -> Vec<LonLat> {
+        match g {
+            Geometry::LineString(pts) => pts,
+            other => panic!("expected LineString, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_point_refs() {
+        let p = parse_point("2.35, 48.86").unwrap();
+        assert_eq!((p.lon, p.lat), (2.35, 48.86));
+        assert!(parse_point("48.86").is_err());
+        assert!(parse_point("200,0").is_err());
+        assert!(parse_point("x,y").is_err());
+    }
+
+    #[test]
+    fn equator_leg_is_densified_by_step() {
+        let pts = line(geodesic(&[ll(0.0, 0.0), ll(10.0, 0.0)]).unwrap());
+        assert_eq!(pts.len(), 11);
+        for (i, p) in pts.iter().enumerate() {
+            assert!(
+                (p.lon - i as f64).abs() < 1e-9 && p.lat.abs() < 1e-9,
+                "{p:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn transatlantic_leg_bows_poleward() {
+        // Madrid → New York: the great circle runs well north of both.
+        let pts = line(geodesic(&[ll(-3.7, 40.4), ll(-74.0, 40.7)]).unwrap());
+        assert!((pts.first().unwrap().lon - -3.7).abs() < 1e-9);
+        assert!((pts.last().unwrap().lat - 40.7).abs() < 1e-9);
+        let top = pts.iter().map(|p| p.lat).fold(f64::MIN, f64::max);
+        assert!(top > 45.0, "apex {top}");
+    }
+
+    #[test]
+    fn antimeridian_crossing_stays_in_range() {
+        // Tokyo → San Francisco crosses 180°: one ~360° jump, no detour
+        // across Eurasia.
+        let pts = line(geodesic(&[ll(139.7, 35.7), ll(-122.4, 37.8)]).unwrap());
+        assert!(pts.iter().all(|p| p.lon > -180.0 && p.lon <= 180.0));
+        let jumps = pts
+            .windows(2)
+            .filter(|w| (w[1].lon - w[0].lon).abs() > 180.0)
+            .count();
+        assert_eq!(jumps, 1);
+        assert!(pts.len() < 100, "{} vertices", pts.len());
+    }
+
+    #[test]
+    fn antipodal_leg_is_an_error() {
+        assert!(geodesic(&[ll(0.0, 0.0), ll(180.0, 0.0)]).is_err());
+        let pts = line(geodesic(&[ll(0.0, 0.0), ll(90.0, 0.0), ll(180.0, 0.0)]).unwrap());
+        assert!(pts.len() > 100);
+    }
+}
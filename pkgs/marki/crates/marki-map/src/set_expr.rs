@@ -0,0 +1,194 @@
+//! Feature set expressions: several geoBoundaries features named by one
+//! reference string.
+//!
+//! ```text
+//! adm1/DEU/*                                  every Land
+//! adm1/DEU/* - adm1/DEU/Bayern                every Land but Bavaria
+//! continent/Europe - country/RUS
+//! country/FRA + country/DEU + country/ITA
+//! country/* where subregion = "Western Africa"
+//! ```
+//!
+//! Terms are ordinary refs or `*` wildcards, combined left to right with
+//! ` + ` (union) and ` - ` (difference). Operators need whitespace on
+//! both sides, so hyphenated names (`Baden-Württemberg`,
+//! `South-Eastern Asia`) read as one term. A trailing `where` clause
+//! filters the result on member properties; several conditions join
+//! with `and`, and values compare like names do (case, diacritics and
+//! punctuation folded).
+//!
+//! This module only parses. [`crate::data::geoboundaries::resolve_set`]
+//! expands the terms into member features and combines them.
+
+use crate::error::MapError;
+
+/// How a term combines with the set built so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Union,
+    Difference,
+}
+
+/// One `where` condition: `<prop> = <value>` or `<prop> != <value>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub prop: String,
+    pub value: String,
+    pub negate: bool,
+}
+
+/// A parsed set expression. The first term is always a union.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetExpr {
+    pub terms: Vec<(SetOp, String)>,
+    pub filters: Vec<Filter>,
+}
+
+/// Whether `r` is a set expression rather than a single reference.
+pub fn is_set_expr(r: &str) -> bool {
+    r.contains('*') || r.contains(" + ") || r.contains(" - ") || r.contains(" where ")
+}
+
+/// Parse a set expression.
+pub fn parse(r: &str) -> Result<SetExpr, MapError> {
+    let bad = |why: &str| MapError::Resolve(format!("bad set expression `{r}`: {why}"));
+    let (set, clause) = match r.split_once(" where ") {
+        Some((set, clause)) => (set, Some(clause)),
+        None => (r, None),
+    };
+
+    // Words between operators form one term, so multi-word names
+    // (`subregion/Western Africa`) need no quoting.
+    let mut terms: Vec<(SetOp, String)> = Vec::new();
+    let mut pending = Some(SetOp::Union);
+    for word in set.split_whitespace() {
+        let op = match word {
+            "+" => SetOp::Union,
+            "-" => SetOp::Difference,
+            _ => {
+                if let Some(op) = pending.take() {
+                    terms.push((op, word.to_string()));
+                } else if let Some((_, term)) = terms.last_mut() {
+                    term.push(' ');
+                    term.push_str(word);
+                }
+                continue;
+            }
+        };
+        if pending.is_some() {
+            return Err(bad("operator without a term before it"));
+        }
+        pending = Some(op);
+    }
+    if pending.is_some() {
+        return Err(bad(if terms.is_empty() {
+            "no terms"
+        } else {
+            "operator without a term after it"
+        }));
+    }
+
+    let filters = match clause {
+        Some(c) => c
+            .split(" and ")
+            .map(|cond| {
+                parse_filter(cond).ok_or_else(|| bad(&format!("bad condition `{}`", cond.trim())))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    Ok(SetExpr { terms, filters })
+}
+
+/// `subregion = "Western Africa"`, `continent != Europe`.
+fn parse_filter(cond: &str) -> Option<Filter> {
+    let (prop, value, negate) = match cond.split_once("!=") {
+        Some((p, v)) => (p, v, true),
+        None => {
+            let (p, v) = cond.split_once('=')?;
+            (p, v, false)
+        }
+    };
+    let prop = prop.trim();
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    if prop.is_empty() || value.is_empty() || prop.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(Filter {
+        prop: prop.to_string(),
+        value: value.to_string(),
+        negate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(e: &SetExpr) -> Vec<(SetOp, &str)> {
+        e.terms.iter().map(|(op, t)| (*op, t.as_str())).collect()
+    }
+
+    #[test]
+    fn recognises_expressions() {
+        assert!(is_set_expr("adm1/DEU/*"));
+        assert!(is_set_expr("continent/Europe - country/RUS"));
+        assert!(is_set_expr(
+            "country/* where subregion = \"Western Africa\""
+        ));
+        assert!(!is_set_expr("adm1/DEU/Baden-Württemberg"));
+        assert!(!is_set_expr("subregion/South-Eastern Asia"));
+    }
+
+    #[test]
+    fn parses_unions_and_differences_left_to_right() {
+        let e = parse("subregion/Western Europe + country/ITA  -  country/FRA").unwrap();
+        assert_eq!(
+            terms(&e),
+            [
+                (SetOp::Union, "subregion/Western Europe"),
+                (SetOp::Union, "country/ITA"),
+                (SetOp::Difference, "country/FRA"),
+            ]
+        );
+        assert!(e.filters.is_empty());
+    }
+
+    #[test]
+    fn parses_where_clauses() {
+        let e = parse("country/* where subregion = \"Western Africa\" and iso != NGA").unwrap();
+        assert_eq!(terms(&e), [(SetOp::Union, "country/*")]);
+        assert_eq!(
+            e.filters,
+            [
+                Filter {
+                    prop: "subregion".into(),
+                    value: "Western Africa".into(),
+                    negate: false
+                },
+                Filter {
+                    prop: "iso".into(),
+                    value: "NGA".into(),
+                    negate: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn dangling_operators_and_bad_conditions_are_errors() {
+        for bad in [
+            "- country/RUS",
+            "continent/Europe -",
+            "country/FRA + - country/DEU",
+            "country/* where subregion",
+            "country/* where = Europe",
+        ] {
+            assert!(parse(bad).is_err(), "{bad}");
+        }
+    }
+}
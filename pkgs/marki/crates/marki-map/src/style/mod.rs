@@ -17,6 +17,8 @@ struct ThemeFile {
     background: Option<String>,
     #[serde(default)]
     role: Vec<RoleEntry>,
+    #[serde(default)]
+    ramp: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,6 +30,8 @@ struct RoleEntry {
     stroke: Option<String>,
     #[serde(default = "default_sw")]
     stroke_width: f64,
+    #[serde(default)]
+    dash: Option<String>,
 }
 
 fn default_sw() -> f64 {
@@ -64,8 +68,10 @@ pub fn load(name: &str) -> Result<LoadedTheme, MapError> {
                 fill: r.fill.unwrap_or_else(|| "none".into()),
                 stroke: r.stroke.unwrap_or_else(|| "#000".into()),
                 stroke_width: r.stroke_width,
+                dash: r.dash,
             })
             .collect(),
+        ramp: parsed.ramp,
     };
     Ok(LoadedTheme {
         style,
@@ -83,6 +89,8 @@ mod tests {
         assert!(t.style.background.is_some());
         assert!(t.style.role("highlight").is_some());
         assert!(t.style.role("outline").is_some());
+        assert!(t.style.role("route-dashed").and_then(|r| r.dash.as_ref()).is_some());
+        assert!(t.style.ramp.len() >= 2);
         assert!(!t.bytes.is_empty());
     }
 
@@ -97,5 +97,25 @@
 //!   islands) and never touches a shared land border — is back on,
 //!   removing the noisy speckle that `27` reintroduced (e.g. Chile's
 //!   southern archipelago).
+//! - `29` — Locator insets. An optional `[inset]` table composes a
+//!   small orthographic globe or Mercator outline into one layer's SVG
+//!   with the main viewport marked as a rectangle or dot. New `marker`
+//!   theme role.
+//! - `30` — Choropleth layers. A `[layers.<name>.choropleth]` table
+//!   fills features by value from a colour ramp (per-class `class-N`
+//!   roles) and draws a legend into the layer SVG. Themes gain a
+//!   `ramp`.
+//! - `31` — Great-circle routes. A layer's `routes` list draws densified
+//!   geodesic polylines between `point/`, `place/` or feature-ref stops
+//!   (or explicit coordinates), split at the wrap meridian and
+//!   stretching the viewport. New `route`, `route-dashed` and
+//!   `route-dotted` theme roles; roles gain an optional `dash`.
+//! - `32` — Centroids of rings that cross ±180 are taken on unwrapped
+//!   longitudes, so `place/FJI` route stops land on Fiji instead of
+//!   mid-map.
+//! - `33` — Quantile classes drop coinciding bounds, so repeated values
+//!   no longer leave empty classes and duplicate legend rows.
+//! - `34` — Choropleth breaks at or above the data maximum are dropped
+//!   instead of adding an empty top class.
 
-pub const RENDER_VERSION_MAP: u32 = 28;
+pub const RENDER_VERSION_MAP: u32 = 34;
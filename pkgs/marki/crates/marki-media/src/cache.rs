@@ -0,0 +1,55 @@
+//! On-disk cache for processed media (resized rasters, audio clips).
+//!
+//! ```text
+//! <cache_dir>/media/<key>/
+//!     output.<ext>
+//!     .ready                    (marker + manifest, see marki_render::cache)
+//! ```
+//!
+//! Callers derive `key` from [`RENDER_VERSION_MEDIA`](crate::version),
+//! the source bytes and every knob that influences the output, so a
+//! hit is always safe to reuse.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use marki_render::cache::{touch, write_atomic, READY_MARKER};
+
+use crate::error::MediaError;
+
+fn entry_dir(cache_root: &Path, key: &str) -> PathBuf {
+    cache_root.join("media").join(key)
+}
+
+/// Cached bytes for `key`, if a completed entry exists. Bumps the
+/// marker's mtime ("last used", read by `marki cache prune`).
+pub(crate) fn lookup(
+    cache_root: &Path,
+    key: &str,
+    name: &str,
+) -> Result<Option<Vec<u8>>, MediaError> {
+    let dir = entry_dir(cache_root, key);
+    if !dir.join(READY_MARKER).exists() {
+        return Ok(None);
+    }
+    touch(&dir);
+    Ok(Some(fs::read(dir.join(name))?))
+}
+
+/// Populate the entry for `key`. The `.ready` marker is written last; a
+/// crash mid-write leaves an entry that readers treat as a miss.
+pub(crate) fn store(
+    cache_root: &Path,
+    key: &str,
+    name: &str,
+    bytes: &[u8],
+) -> Result<(), MediaError> {
+    write_atomic(&entry_dir(cache_root, key), &[(name, bytes)])?;
+    Ok(())
+}
+
+/// Truncate a finished hasher to the 16-hex-char keys every render
+/// cache uses.
+pub(crate) fn short_key(hasher: &blake3::Hasher) -> String {
+    hasher.finalize().to_hex().as_str()[..16].to_string()
+}
@@ -0,0 +1,475 @@
+//! Audio clip ranges (`start` / `end`).
+//!
+//! WAV and MP3 are cut in-process without re-encoding: WAV on sample
+//! boundaries, MP3 on frame boundaries (~26 ms at 44.1 kHz — every frame
+//! overlapping the range is kept). The first kept MP3 frame may lean on
+//! the bit reservoir of a dropped one, which decoders render as a few
+//! milliseconds of silence; that is the usual trade-off of lossless MP3
+//! splitting.
+//!
+//! Other containers (OGG, M4A) go through the configured encoder binary,
+//! invoked ffmpeg-style:
+//!
+//! ```text
+//! <encoder> -hide_banner -loglevel error -y -ss <start> -i <in> [-t <dur>] -vn <out>
+//! ```
+//!
+//! Either way the clip is cached under `<cache_dir>/media/` (see
+//! [`crate::cache`]), keyed by the source bytes and the range, so the
+//! encoder only runs when either changes.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cache;
+use crate::dsl::ClipTime;
+use crate::error::MediaError;
+use crate::version::RENDER_VERSION_MEDIA;
+
+/// Clip `src` (a file with extension `ext`) to `[start, end)`.
+pub fn clip(
+    src: &[u8],
+    ext: &str,
+    start: Option<ClipTime>,
+    end: Option<ClipTime>,
+    encoder: Option<&Path>,
+    cache_root: &Path,
+) -> Result<Vec<u8>, MediaError> {
+    let start_ms = start.map_or(0, ClipTime::millis);
+    let end_ms = end.map(ClipTime::millis);
+    if let Some(e) = end_ms.filter(|&e| e <= start_ms) {
+        return Err(MediaError::Parse(format!(
+            "clip `end` ({e} ms) must be after `start` ({start_ms} ms)"
+        )));
+    }
+
+    let ext = ext.to_ascii_lowercase();
+    let key = cache_key(src, &ext, start_ms, end_ms);
+    let out_name = format!("output.{ext}");
+    if let Some(bytes) = cache::lookup(cache_root, &key, &out_name)? {
+        return Ok(bytes);
+    }
+
+    let bytes = match ext.as_str() {
+        "wav" => clip_wav(src, start_ms, end_ms)?,
+        "mp3" => clip_mp3(src, start_ms, end_ms)?,
+        _ => {
+            let encoder = encoder.ok_or_else(|| {
+                MediaError::Clip(format!(
+                    "clipping .{ext} needs an audio encoder; set `audio_encoder` \
+                     (e.g. ffmpeg) in marki config"
+                ))
+            })?;
+            run_encoder(encoder, src, &ext, start_ms, end_ms)?
+        }
+    };
+    cache::store(cache_root, &key, &out_name, &bytes)?;
+    Ok(bytes)
+}
+
+fn cache_key(src: &[u8], ext: &str, start_ms: u64, end_ms: Option<u64>) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&RENDER_VERSION_MEDIA.to_le_bytes());
+    hasher.update(b"clip");
+    hasher.update(blake3::hash(src).as_bytes());
+    hasher.update(ext.as_bytes());
+    hasher.update(&start_ms.to_le_bytes());
+    hasher.update(&end_ms.unwrap_or(u64::MAX).to_le_bytes());
+    cache::short_key(&hasher)
+}
+
+fn starts_past_end(start_ms: u64) -> MediaError {
+    MediaError::Clip(format!("clip `start` ({start_ms} ms) is past the end of the audio"))
+}
+
+// ---------------------------------------------------------------------------
+// WAV
+// ---------------------------------------------------------------------------
+
+/// Slice the `data` chunk of a RIFF/WAVE file and rebuild a minimal file
+/// around it (the `fmt ` chunk verbatim, then `data`).
+fn clip_wav(src: &[u8], start_ms: u64, end_ms: Option<u64>) -> Result<Vec<u8>, MediaError> {
+    let bad = |why: &str| MediaError::Clip(format!("not a usable WAV file: {why}"));
+    if src.len() < 12 || &src[..4] != b"RIFF" || &src[8..12] != b"WAVE" {
+        return Err(bad("missing RIFF/WAVE header"));
+    }
+
+    let mut fmt: Option<&[u8]> = None;
+    let mut data: Option<&[u8]> = None;
+    let mut pos = 12;
+    while pos + 8 <= src.len() {
+        let id = &src[pos..pos + 4];
+        let size = u32::from_le_bytes(src[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let body_start = pos + 8;
+        // Streaming writers leave the data size at 0xFFFFFFFF; clamp.
+        let body_end = body_start.saturating_add(size).min(src.len());
+        let body = &src[body_start..body_end];
+        match id {
+            b"fmt " => fmt = Some(body),
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // Chunks are word-aligned.
+        pos = body_end + (size & 1);
+    }
+    let fmt = fmt.filter(|f| f.len() >= 16).ok_or_else(|| bad("no `fmt ` chunk"))?;
+    let data = data.ok_or_else(|| bad("no `data` chunk"))?;
+
+    let sample_rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap()) as u64;
+    let block_align = u16::from_le_bytes(fmt[12..14].try_into().unwrap()) as u64;
+    if sample_rate == 0 || block_align == 0 {
+        return Err(bad("zero sample rate or block size"));
+    }
+
+    let frames = data.len() as u64 / block_align;
+    let first = start_ms * sample_rate / 1000;
+    let last = end_ms.map_or(frames, |e| (e * sample_rate / 1000).min(frames));
+    if first >= frames {
+        return Err(starts_past_end(start_ms));
+    }
+    let slice = &data[(first * block_align) as usize..(last * block_align) as usize];
+
+    let mut out = Vec::with_capacity(slice.len() + fmt.len() + 28);
+    let riff_len = 4 + (8 + fmt.len() + (fmt.len() & 1)) + (8 + slice.len() + (slice.len() & 1));
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(riff_len as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    for (id, body) in [(b"fmt ", fmt), (b"data", slice)] {
+        out.extend_from_slice(id);
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        if body.len() & 1 == 1 {
+            out.push(0);
+        }
+    }
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// MP3
+// ---------------------------------------------------------------------------
+
+/// One parsed MPEG audio (Layer III) frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mp3Frame {
+    len: usize,
+    samples: u64,
+    sample_rate: u64,
+}
+
+/// Layer III bitrates in kbit/s by header index: MPEG-1, then MPEG-2/2.5.
+const MP3_BITRATES_V1: [u64; 15] =
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+const MP3_BITRATES_V2: [u64; 15] =
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// Parse a Layer III frame header at the start of `b`. Free-format and
+/// reserved values are rejected.
+fn mp3_frame(b: &[u8]) -> Option<Mp3Frame> {
+    if b.len() < 4 || b[0] != 0xFF || b[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let version = (b[1] >> 3) & 3; // 0 = 2.5, 2 = 2, 3 = 1
+    let layer = (b[1] >> 1) & 3; // 1 = III
+    if version == 1 || layer != 1 {
+        return None;
+    }
+    let bitrate_idx = (b[2] >> 4) as usize;
+    let sr_idx = ((b[2] >> 2) & 3) as usize;
+    if bitrate_idx == 0 || bitrate_idx == 15 || sr_idx == 3 {
+        return None;
+    }
+    let padding = ((b[2] >> 1) & 1) as u64;
+    let (kbps, base_sr, samples, coeff) = if version == 3 {
+        (MP3_BITRATES_V1[bitrate_idx], [44100, 48000, 32000][sr_idx], 1152, 144_000)
+    } else {
+        (MP3_BITRATES_V2[bitrate_idx], [22050, 24000, 16000][sr_idx], 576, 72_000)
+    };
+    let sample_rate = if version == 0 { base_sr / 2 } else { base_sr };
+    let len = (coeff * kbps / sample_rate + padding) as usize;
+    Some(Mp3Frame { len, samples, sample_rate })
+}
+
+/// Size of a leading ID3v2 tag, if any.
+fn id3v2_len(b: &[u8]) -> usize {
+    if b.len() < 10 || &b[..3] != b"ID3" {
+        return 0;
+    }
+    // Synchsafe: 7 bits per byte.
+    let size = b[6..10].iter().fold(0usize, |acc, &x| (acc << 7) | (x & 0x7F) as usize);
+    let footer = if b[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+/// Keep every frame overlapping `[start, end)`. Tags and a leading
+/// Xing/Info frame (whose frame count would be wrong) are dropped.
+fn clip_mp3(src: &[u8], start_ms: u64, end_ms: Option<u64>) -> Result<Vec<u8>, MediaError> {
+    let mut pos = id3v2_len(src);
+    let mut sample = 0u64;
+    let mut out = Vec::new();
+    let mut first = true;
+    let mut seen_any = false;
+    while let Some(frame) = src.get(pos..).and_then(mp3_frame) {
+        let end = (pos + frame.len).min(src.len());
+        let bytes = &src[pos..end];
+        pos = end;
+        if first {
+            first = false;
+            let info = bytes.windows(4).any(|w| w == b"Xing" || w == b"Info");
+            if info {
+                continue;
+            }
+        }
+        seen_any = true;
+        let t0 = sample * 1000 / frame.sample_rate;
+        sample += frame.samples;
+        let t1 = sample * 1000 / frame.sample_rate;
+        if end_ms.is_some_and(|e| t0 >= e) {
+            break;
+        }
+        if t1 > start_ms {
+            out.extend_from_slice(bytes);
+        }
+    }
+    if !seen_any {
+        return Err(MediaError::Clip("no MPEG Layer III frames found in .mp3".into()));
+    }
+    if out.is_empty() {
+        return Err(starts_past_end(start_ms));
+    }
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// External encoder
+// ---------------------------------------------------------------------------
+
+fn run_encoder(
+    encoder: &Path,
+    src: &[u8],
+    ext: &str,
+    start_ms: u64,
+    end_ms: Option<u64>,
+) -> Result<Vec<u8>, MediaError> {
+    let work = mktempdir()?;
+    let result = encode_in(&work, encoder, src, ext, start_ms, end_ms);
+    let _ = fs::remove_dir_all(&work);
+    result
+}
+
+fn encode_in(
+    work: &Path,
+    encoder: &Path,
+    src: &[u8],
+    ext: &str,
+    start_ms: u64,
+    end_ms: Option<u64>,
+) -> Result<Vec<u8>, MediaError> {
+    let input = work.join(format!("input.{ext}"));
+    let output = work.join(format!("output.{ext}"));
+    fs::write(&input, src)?;
+
+    let secs = |ms: u64| format!("{}.{:03}", ms / 1000, ms % 1000);
+    let mut cmd = Command::new(encoder);
+    cmd.args(["-hide_banner", "-loglevel", "error", "-y", "-ss"])
+        .arg(secs(start_ms))
+        .arg("-i")
+        .arg(&input);
+    if let Some(e) = end_ms {
+        cmd.arg("-t").arg(secs(e - start_ms));
+    }
+    cmd.arg("-vn").arg(&output);
+
+    let outcome = cmd.output().map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            MediaError::Clip(format!("audio encoder not found: {}", encoder.display()))
+        } else {
+            MediaError::Io(e)
+        }
+    })?;
+    if !outcome.status.success() {
+        let stderr = String::from_utf8_lossy(&outcome.stderr);
+        return Err(MediaError::Clip(format!(
+            "audio encoder failed ({}):\n{}",
+            outcome.status,
+            stderr.trim()
+        )));
+    }
+    fs::read(&output).map_err(|e| {
+        MediaError::Clip(format!("audio encoder exited 0 but wrote no output: {e}"))
+    })
+}
+
+/// Fresh per-invocation directory under `$TMPDIR`, removed by the caller.
+fn mktempdir() -> Result<PathBuf, MediaError> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static N: AtomicU64 = AtomicU64::new(0);
+
+    let p = std::env::temp_dir().join(format!(
+        "marki-media-clip-{}-{}",
+        std::process::id(),
+        N.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&p)?;
+    Ok(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::SystemTime;
+
+    static N: AtomicU64 = AtomicU64::new(0);
+
+    fn tempdir() -> PathBuf {
+        let p = std::env::temp_dir().join(format!(
+            "marki-media-clip-test-{}-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos(),
+            N.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&p).unwrap();
+        p
+    }
+
+    /// Mono 16-bit PCM at 1 kHz: sample `i` holds the value `i`.
+    fn wav(samples: u16) -> Vec<u8> {
+        let data: Vec<u8> = (0..samples).flat_map(|i| i.to_le_bytes()).collect();
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        fmt.extend_from_slice(&1u16.to_le_bytes()); // mono
+        fmt.extend_from_slice(&1000u32.to_le_bytes()); // sample rate
+        fmt.extend_from_slice(&2000u32.to_le_bytes()); // byte rate
+        fmt.extend_from_slice(&2u16.to_le_bytes()); // block align
+        fmt.extend_from_slice(&16u16.to_le_bytes()); // bits
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&((4 + 8 + fmt.len() + 8 + data.len()) as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+        out.extend_from_slice(&fmt);
+        out.extend_from_slice(b"LIST\x04\x00\x00\x00junk");
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+        out
+    }
+
+    /// `n` MPEG-1 Layer III frames, 128 kbps @ 44.1 kHz (417 bytes,
+    /// 1152 samples each), behind an ID3v2 tag. Frame `i`'s first body
+    /// byte is `i`.
+    fn mp3(n: u8) -> Vec<u8> {
+        let mut out = b"ID3\x04\x00\x00\x00\x00\x00\x02xx".to_vec();
+        for i in 0..n {
+            let mut frame = vec![0u8; 417];
+            frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+            frame[4] = i;
+            out.extend_from_slice(&frame);
+        }
+        out
+    }
+
+    fn ms(n: u64) -> Option<ClipTime> {
+        Some(ClipTime(n))
+    }
+
+    #[test]
+    fn wav_cut_is_sample_exact() {
+        let root = tempdir();
+        let out = clip(&wav(2000), "wav", ms(500), ms(750), None, &root).unwrap();
+        let data_at = out.windows(4).position(|w| w == b"data").unwrap();
+        let len = u32::from_le_bytes(out[data_at + 4..data_at + 8].try_into().unwrap());
+        assert_eq!(len, 250 * 2);
+        let first = u16::from_le_bytes(out[data_at + 8..data_at + 10].try_into().unwrap());
+        assert_eq!(first, 500);
+        assert_eq!(u32::from_le_bytes(out[4..8].try_into().unwrap()) as usize, out.len() - 8);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn wav_open_ended_and_past_end() {
+        let root = tempdir();
+        let out = clip(&wav(1000), "wav", ms(900), None, None, &root).unwrap();
+        assert_eq!(out.len(), 12 + 8 + 16 + 8 + 100 * 2);
+        let err = clip(&wav(1000), "wav", ms(1500), None, None, &root).unwrap_err();
+        assert!(err.to_string().contains("past the end"), "{err}");
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn mp3_keeps_overlapping_frames() {
+        let root = tempdir();
+        let out = clip(&mp3(100), "mp3", ms(1000), ms(1500), None, &root).unwrap();
+        assert_eq!(out.len(), 20 * 417);
+        assert_eq!(out[4], 38);
+        assert_eq!(out[19 * 417 + 4], 57);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn mp3_drops_xing_header_frame() {
+        let mut src = mp3(3);
+        let tag = id3v2_len(&src);
+        src[tag + 36..tag + 40].copy_from_slice(b"Xing");
+        let out = clip_mp3(&src, 0, None).unwrap();
+        assert_eq!(out.len(), 2 * 417);
+        assert_eq!(out[4], 1);
+    }
+
+    #[test]
+    fn rejects_inverted_range_and_garbage() {
+        let root = tempdir();
+        let err = clip(&wav(10), "wav", ms(500), ms(500), None, &root).unwrap_err();
+        assert!(matches!(err, MediaError::Parse(_)));
+        let err = clip(b"not audio", "mp3", None, ms(10), None, &root).unwrap_err();
+        assert!(err.to_string().contains("Layer III"), "{err}");
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn other_formats_need_an_encoder() {
+        let root = tempdir();
+        let err = clip(b"OggS", "ogg", ms(0), ms(10), None, &root).unwrap_err();
+        assert!(err.to_string().contains("audio_encoder"), "{err}");
+        fs::remove_dir_all(&root).ok();
+    }
+
+    /// Fake encoder: copies its input (the arg after `-i`) to the output
+    /// (last arg) and appends the `-ss`/`-t` values, so the test can see
+    /// what it was asked to do.
+    #[cfg(unix)]
+    #[test]
+    fn encoder_round_trip_and_cache() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempdir();
+        let shim = root.join("enc.sh");
+        fs::write(
+            &shim,
+            "#!/bin/sh\nset -e\nwhile [ $# -gt 1 ]; do\n  case \"$1\" in\n    \
+             -ss) ss=$2;;\n    -t) t=$2;;\n    -i) in=$2;;\n  esac\n  shift\ndone\n\
+             { cat \"$in\"; printf ' ss=%s t=%s' \"$ss\" \"$t\"; } > \"$1\"\n",
+        )
+        .unwrap();
+        let mut perms = fs::metadata(&shim).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&shim, perms).unwrap();
+
+        let cache = root.join("cache");
+        let out = clip(b"OggS", "ogg", ms(1250), ms(3000), Some(&shim), &cache).unwrap();
+        assert_eq!(out, b"OggS ss=1.250 t=1.750");
+
+        // Cache hit survives the encoder disappearing.
+        fs::remove_file(&shim).unwrap();
+        let again = clip(b"OggS", "ogg", ms(1250), ms(3000), Some(&shim), &cache).unwrap();
+        assert_eq!(again, out);
+        let err = clip(b"OggS", "ogg", ms(0), ms(10), Some(&shim), &cache).unwrap_err();
+        assert!(err.to_string().contains("not found"), "{err}");
+        fs::remove_dir_all(&root).ok();
+    }
+}
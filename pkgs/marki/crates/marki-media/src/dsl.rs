@@ -5,24 +5,45 @@
 //! ```toml
 //! src = "circle/de"           # image — resolves to circle/de.svg
 //! src = "audio/morning"       # audio — resolves to audio/morning.mp3
+//! src = "signs/hello.mp4"     # video — resolves mp4, then webm
 //! src = "diagrams/foo.png"    # explicit extension forces exact match
-//! size = 200                  # optional; max-width in CSS px (images only)
+//! size = 200                  # optional; max-width in CSS px (images 200,
+//!                             # video 480; ignored on audio)
 //! alt = "German flag"         # optional; defaults to ""
+//! poster = "signs/hello"      # video only: image shown before playback
 //!
-//! # Audio-only knobs (silently ignored on images):
+//! # Raster-only knobs (SVGs pass through untouched; ignored on audio;
+//! # on video they apply to the poster):
+//! max_px = 800                # downscale so the longest side is ≤ 800 px
+//! format = "webp"             # "webp" | "jpeg" | "png"; default: keep
+//! quality = 80                # 1–100, jpeg output only; default 85
+//!                             # (png and webp are lossless; an error)
+//!
+//! # Audio and video knobs (silently ignored on images):
 //! controls = true             # default true
 //! loop = false                # default false
-//! autoplay = false            # default false
+//! autoplay = false            # default false; video autoplays muted
 //! preload = "auto"            # "none" | "metadata" | "auto"; default "auto"
+//!
+//! # Audio-only knobs:
+//! start = "0:01.5"            # clip start: seconds (1.5) or "[h:]m:ss[.fff]"
+//! end = 3                     # clip end, same forms; default: end of file
+//! anki_sound = false          # emit `[sound:…]` instead of <audio>
 //! ```
 //!
+//! With `anki_sound = true` the card gets Anki's native sound tag, so the
+//! desktop and mobile clients draw their own replay button and honour the
+//! deck's auto-play settings; `controls`, `loop`, `autoplay` and `preload`
+//! are then ignored.
+//!
 //! The `src` field is a path with an optional source prefix. If the
 //! first component matches a registered source name, the rest is looked
 //! up in that source's directory. Otherwise all sources are searched in
 //! registration order and the first match wins. If `src` has no
-//! extension, the resolver tries a fixed preference list of image and
-//! audio extensions (svg → png → webp → jpg → jpeg → gif → mp3 → ogg
-//! → m4a → wav). Type is inferred from the extension that wins.
+//! extension, the resolver tries a fixed preference list of image, audio
+//! and video extensions (svg → png → webp → jpg → jpeg → gif → mp3 → ogg
+//! → m4a → wav → mp4 → webm). Type is inferred from the extension that
+//! wins. `poster` resolves the same way and must land on an image.
 
 use serde::{Deserialize, Serialize};
 
@@ -35,8 +56,8 @@ pub struct MediaSpec {
     /// fixed preference list of image then audio extensions.
     pub src: String,
 
-    /// Max-width in CSS pixels for image renders. Defaults to 200.
-    /// Silently ignored when `src` resolves to audio.
+    /// Max-width in CSS pixels for image and video renders. Defaults to
+    /// 200 for images and 480 for video. Silently ignored on audio.
     #[serde(default)]
     pub size: Option<u32>,
 
@@ -44,22 +65,120 @@ pub struct MediaSpec {
     #[serde(default)]
     pub alt: Option<String>,
 
-    /// Audio: show built-in <audio> controls. Default true.
+    /// Video: poster image, resolved like `src`. Ignored on images and
+    /// audio.
+    #[serde(default)]
+    pub poster: Option<String>,
+
+    /// Raster: downscale so the longest side is at most this many pixels.
+    /// Never upscales.
+    #[serde(default)]
+    pub max_px: Option<u32>,
+
+    /// Raster: re-encode to this format. Defaults to the source format
+    /// (GIF becomes PNG once processed, keeping only the first frame).
+    #[serde(default)]
+    pub format: Option<RasterFormat>,
+
+    /// Raster: JPEG quality, 1–100. Defaults to [`DEFAULT_JPEG_QUALITY`].
+    /// PNG and WebP output is lossless, so setting it for either is an
+    /// error.
+    #[serde(default)]
+    pub quality: Option<u8>,
+
+    /// Audio/video: show the built-in player controls. Default true.
     #[serde(default = "default_true")]
     pub controls: bool,
 
-    /// Audio: loop on completion. Default false.
+    /// Audio/video: loop on completion. Default false.
     #[serde(default, rename = "loop")]
     pub r#loop: bool,
 
-    /// Audio: autoplay on card load. Default false.
+    /// Audio/video: autoplay on card load. Default false.
     #[serde(default)]
     pub autoplay: bool,
 
-    /// Audio: preload mode. Default `auto` — Anki stores media locally,
+    /// Audio/video: preload mode. Default `auto` — Anki stores media locally,
     /// so there's no fetch cost.
     #[serde(default = "default_preload_auto")]
     pub preload: PreloadMode,
+
+    /// Audio: clip start. Defaults to the beginning of the file.
+    #[serde(default)]
+    pub start: Option<ClipTime>,
+
+    /// Audio: clip end (exclusive). Defaults to the end of the file.
+    #[serde(default)]
+    pub end: Option<ClipTime>,
+
+    /// Audio: emit Anki's `[sound:file]` tag instead of an `<audio>`
+    /// element. Default false.
+    #[serde(default)]
+    pub anki_sound: bool,
+}
+
+/// A clip boundary, stored as whole milliseconds.
+///
+/// Deserialises from a number of seconds (`1.5`, `3`) or a clock string
+/// (`"1.5"`, `"0:01.5"`, `"1:02:03.250"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClipTime(pub u64);
+
+impl ClipTime {
+    pub fn millis(self) -> u64 {
+        self.0
+    }
+
+    fn from_secs(secs: f64) -> Result<Self, String> {
+        if !secs.is_finite() || secs < 0.0 {
+            return Err(format!("clip time must be a non-negative number, got {secs}"));
+        }
+        Ok(ClipTime((secs * 1000.0).round() as u64))
+    }
+
+    /// Parse `ss[.fff]`, `m:ss[.fff]` or `h:mm:ss[.fff]`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let bad = || format!("bad clip time `{s}`; expected seconds or [h:]m:ss[.fff]");
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        if parts.len() > 3 {
+            return Err(bad());
+        }
+        let (last, whole) = parts.split_last().ok_or_else(bad)?;
+        let secs: f64 = last.parse().map_err(|_| bad())?;
+        if !whole.is_empty() && !(0.0..60.0).contains(&secs) {
+            return Err(bad());
+        }
+        let mut total = secs;
+        for (i, p) in whole.iter().rev().enumerate() {
+            let n: u64 = p.parse().map_err(|_| bad())?;
+            total += n as f64 * 60f64.powi(i as i32 + 1);
+        }
+        Self::from_secs(total)
+    }
+}
+
+impl<'de> Deserialize<'de> for ClipTime {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Int(u64),
+            Float(f64),
+            Str(String),
+        }
+        let r = match Repr::deserialize(d)? {
+            Repr::Int(n) => Ok(ClipTime(n * 1000)),
+            Repr::Float(f) => ClipTime::from_secs(f),
+            Repr::Str(s) => ClipTime::parse(&s),
+        };
+        r.map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for ClipTime {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_f64(self.0 as f64 / 1000.0)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
@@ -80,6 +199,39 @@ impl PreloadMode {
     }
 }
 
+/// Output encoding for processed rasters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RasterFormat {
+    Webp,
+    Jpeg,
+    Png,
+}
+
+impl RasterFormat {
+    /// File extension (without dot) of the encoded output.
+    pub fn ext(self) -> &'static str {
+        match self {
+            RasterFormat::Webp => "webp",
+            RasterFormat::Jpeg => "jpg",
+            RasterFormat::Png => "png",
+        }
+    }
+}
+
+impl MediaSpec {
+    /// True when any raster knob is set, i.e. the source bytes may not be
+    /// shipped verbatim.
+    pub fn wants_raster_processing(&self) -> bool {
+        self.max_px.is_some() || self.format.is_some() || self.quality.is_some()
+    }
+
+    /// True when `start` or `end` asks for a sub-range of an audio file.
+    pub fn wants_clip(&self) -> bool {
+        self.start.is_some() || self.end.is_some()
+    }
+}
+
 fn default_true() -> bool {
     true
 }
@@ -91,6 +243,12 @@ fn default_preload_auto() -> PreloadMode {
 /// Default max-width for image renders.
 pub const DEFAULT_IMAGE_SIZE: u32 = 200;
 
+/// Default max-width for video renders.
+pub const DEFAULT_VIDEO_SIZE: u32 = 480;
+
+/// JPEG quality used when `quality` is unset.
+pub const DEFAULT_JPEG_QUALITY: u8 = 85;
+
 pub fn parse_media_spec(src: &str) -> Result<MediaSpec, toml::de::Error> {
     toml::from_str(src)
 }
@@ -171,6 +329,60 @@ preload = "metadata"
         assert!(err.to_string().contains("country") || err.to_string().contains("src"));
     }
 
+    #[test]
+    fn parses_raster_knobs() {
+        let spec = parse_media_spec(
+            r#"
+src = "photos/beach.jpg"
+max_px = 800
+format = "jpeg"
+quality = 70
+"#,
+        )
+        .unwrap();
+        assert_eq!(spec.max_px, Some(800));
+        assert_eq!(spec.format, Some(RasterFormat::Jpeg));
+        assert_eq!(spec.quality, Some(70));
+        assert!(spec.wants_raster_processing());
+        assert!(!parse_media_spec(r#"src = "x""#).unwrap().wants_raster_processing());
+        assert!(parse_media_spec("src = \"x\"\nformat = \"tiff\"").is_err());
+    }
+
+    #[test]
+    fn parses_clip_times() {
+        let spec = parse_media_spec(
+            r#"
+src = "audio/word"
+start = "0:01.5"
+end = 3
+anki_sound = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(spec.start, Some(ClipTime(1500)));
+        assert_eq!(spec.end, Some(ClipTime(3000)));
+        assert!(spec.anki_sound);
+        assert!(spec.wants_clip());
+
+        for (s, ms) in [("2.25", 2250), ("1:02:03.250", 3_723_250), ("10:00", 600_000)] {
+            assert_eq!(ClipTime::parse(s), Ok(ClipTime(ms)), "{s}");
+        }
+        let spec = parse_media_spec("src = \"x\"\nstart = 0.25").unwrap();
+        assert_eq!(spec.start, Some(ClipTime(250)));
+        for bad in ["", "1:75", "a:00", "1:2:3:4", "-1"] {
+            assert!(ClipTime::parse(bad).is_err(), "{bad}");
+        }
+        assert!(parse_media_spec("src = \"x\"\nend = -2.0").is_err());
+    }
+
+    #[test]
+    fn parses_video_poster() {
+        let spec = parse_media_spec("src = \"signs/hello\"\nposter = \"signs/hello-still\"")
+            .unwrap();
+        assert_eq!(spec.poster.as_deref(), Some("signs/hello-still"));
+        assert_eq!(parse_media_spec(r#"src = "x""#).unwrap().poster, None);
+    }
+
     #[test]
     fn preload_variants_parse() {
         for (s, want) in [
@@ -11,6 +11,10 @@ pub enum MediaError {
     NoSources,
     #[error("unsupported media extension `.{ext}` for `{src}`")]
     UnsupportedExt { src: String, ext: String },
+    #[error("image processing failed: {0}")]
+    Image(String),
+    #[error("audio clip failed: {0}")]
+    Clip(String),
     #[error("io: {0}")]
     Io(#[from] std::io::Error),
 }
@@ -23,6 +27,8 @@ impl From<MediaError> for marki_render::RenderError {
             MediaError::NotFound { .. } => B::Resolve(e.to_string()),
             MediaError::NoSources => B::Internal(e.to_string()),
             MediaError::UnsupportedExt { .. } => B::Resolve(e.to_string()),
+            MediaError::Image(_) => B::Internal(e.to_string()),
+            MediaError::Clip(_) => B::Internal(e.to_string()),
             MediaError::Io(e) => B::Io(e.to_string()),
         }
     }
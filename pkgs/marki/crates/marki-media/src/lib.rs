@@ -1,4 +1,4 @@
-//! `marki-media` — render `media` blocks to images or audio.
+//! `marki-media` — render `media` blocks to images, audio or video.
 //!
 //! Implements `marki_render::Renderer` for the lang token `media`.
 //! See `dsl.rs` for the TOML body format.
@@ -13,7 +13,7 @@
 //!
 //! Extensions are inferred when missing: the resolver tries a fixed
 //! preference list (svg → png → webp → jpg → jpeg → gif → mp3 → ogg
-//! → m4a → wav). Authors can also write the extension explicitly
+//! → m4a → wav → mp4 → webm). Authors can also write the extension explicitly
 //! (`src = "diagrams/foo.png"`) for exact matches.
 //!
 //! Resolved files are emitted as `Asset`s with content-addressed
@@ -21,9 +21,22 @@
 //! Anki's media collection. The rendered HTML references the asset by
 //! basename — no inline base64 — keeping HTML small and letting Anki's
 //! native media handling do its thing.
+//!
+//! Raster images can be downscaled and re-encoded on the way out
+//! (`max_px`, `format`, `quality`; see `raster.rs`), and audio can be
+//! cut to a `start`/`end` range (see `clip.rs`). Processed outputs are
+//! cached under `<cache_dir>/media/`; SVGs and unclipped audio are
+//! always shipped verbatim. Audio renders as an `<audio>` element, or as
+//! Anki's `[sound:…]` tag with `anki_sound = true`. Video renders as a
+//! `<video>` element, optionally with a `poster` image resolved through
+//! the same sources and emitted as a second asset.
 
 pub mod dsl;
 pub mod error;
+mod cache;
+mod clip;
+mod raster;
+mod version;
 
 use std::path::{Path, PathBuf};
 
@@ -41,11 +54,20 @@ const IMAGE_EXTS: &[&str] = &["svg", "png", "webp", "jpg", "jpeg", "gif"];
 /// Audio extensions, in resolution preference order.
 const AUDIO_EXTS: &[&str] = &["mp3", "ogg", "m4a", "wav"];
 
+/// Video extensions, in resolution preference order.
+const VIDEO_EXTS: &[&str] = &["mp4", "webm"];
+
+/// Every extension the resolver knows, in preference order.
+fn known_exts() -> impl Iterator<Item = &'static &'static str> {
+    IMAGE_EXTS.iter().chain(AUDIO_EXTS).chain(VIDEO_EXTS)
+}
+
 /// What kind of media a resolved file is. Drives renderer dispatch.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MediaClass {
     Image,
     Audio,
+    Video,
 }
 
 /// Media block renderer with multiple named sources.
@@ -55,11 +77,20 @@ pub enum MediaClass {
 pub struct MediaRenderer {
     /// Named sources in priority order: `(name, directory)`.
     sources: Vec<(String, PathBuf)>,
+    /// ffmpeg-compatible binary for clipping formats that can't be cut
+    /// in-process (OGG, M4A). `None` makes such clips an error.
+    audio_encoder: Option<PathBuf>,
 }
 
 impl MediaRenderer {
     pub fn new(sources: Vec<(String, PathBuf)>) -> Self {
-        Self { sources }
+        Self { sources, audio_encoder: None }
+    }
+
+    /// Use `binary` to clip audio the renderer can't cut losslessly.
+    pub fn with_audio_encoder(mut self, binary: PathBuf) -> Self {
+        self.audio_encoder = Some(binary);
+        self
     }
 }
 
@@ -68,46 +99,107 @@ impl Renderer for MediaRenderer {
         MEDIA_LANG
     }
 
-    fn render(&self, input: Input<'_>, _ctx: &mut RenderCtx<'_>) -> Result<Fragment, RenderError> {
+    fn version(&self) -> u32 {
+        version::RENDER_VERSION_MEDIA
+    }
+
+    fn render(&self, input: Input<'_>, ctx: &mut RenderCtx<'_>) -> Result<Fragment, RenderError> {
         let spec: dsl::MediaSpec = input.deserialize()?;
-        Ok(render_media(&self.sources, &spec)?)
+        let encoder = self.audio_encoder.as_deref();
+        Ok(render_media(&self.sources, &spec, encoder, ctx.cache_dir)?)
     }
 }
 
 fn render_media(
     sources: &[(String, PathBuf)],
     spec: &dsl::MediaSpec,
+    audio_encoder: Option<&Path>,
+    cache_dir: &Path,
 ) -> Result<Fragment, MediaError> {
     let (path, ext) = resolve(&spec.src, sources)?;
     let class = classify(ext).ok_or_else(|| MediaError::UnsupportedExt {
         src: spec.src.clone(),
         ext: ext.to_string(),
     })?;
-    let bytes = std::fs::read(&path)?;
 
-    let basename = path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("media");
-    let asset_filename = content_addressed_filename(&bytes, basename);
-    let mime = mime_for(ext);
+    let main = match class {
+        MediaClass::Image => image_asset(&path, ext, spec, cache_dir)?,
+        MediaClass::Audio if spec.wants_clip() => {
+            let bytes = std::fs::read(&path)?;
+            let bytes = clip::clip(&bytes, ext, spec.start, spec.end, audio_encoder, cache_dir)?;
+            build_asset(bytes, &path, ext)
+        }
+        MediaClass::Audio | MediaClass::Video => build_asset(std::fs::read(&path)?, &path, ext),
+    };
 
+    let mut assets = Vec::new();
     let html = match class {
-        MediaClass::Image => render_image_html(spec, &asset_filename),
-        MediaClass::Audio => render_audio_html(spec, &asset_filename),
+        MediaClass::Image => render_image_html(spec, &main.filename),
+        MediaClass::Audio => render_audio_html(spec, &main.filename),
+        MediaClass::Video => {
+            let poster = match spec.poster.as_deref() {
+                Some(poster) => Some(poster_asset(poster, sources, spec, cache_dir)?),
+                None => None,
+            };
+            let html = render_video_html(spec, &main.filename, poster.as_ref());
+            assets.extend(poster);
+            html
+        }
     };
+    assets.insert(0, main);
 
     Ok(Fragment {
         html,
         reveal: String::new(),
-        assets: vec![Asset {
-            filename: asset_filename,
-            bytes,
-            mime,
-        }],
+        assets,
     })
 }
 
+/// Read an image, applying the raster knobs unless it is an SVG. A
+/// format change renames the asset to the output extension.
+fn image_asset(
+    path: &Path,
+    ext: &'static str,
+    spec: &dsl::MediaSpec,
+    cache_dir: &Path,
+) -> Result<Asset, MediaError> {
+    let bytes = std::fs::read(path)?;
+    if ext == "svg" || !spec.wants_raster_processing() {
+        return Ok(build_asset(bytes, path, ext));
+    }
+    let out = raster::process(&bytes, ext, spec, cache_dir)?;
+    Ok(build_asset(out.bytes, &path.with_extension(out.ext), out.ext))
+}
+
+/// Resolve a video's `poster` through the same sources. It must be an
+/// image; the block's raster knobs apply to it.
+fn poster_asset(
+    poster: &str,
+    sources: &[(String, PathBuf)],
+    spec: &dsl::MediaSpec,
+    cache_dir: &Path,
+) -> Result<Asset, MediaError> {
+    let (path, ext) = resolve(poster, sources)?;
+    if classify(ext) != Some(MediaClass::Image) {
+        return Err(MediaError::Parse(format!(
+            "`poster` must be an image, but `{poster}` resolved to a .{ext} file"
+        )));
+    }
+    image_asset(&path, ext, spec, cache_dir)
+}
+
+fn build_asset(bytes: Vec<u8>, path: &Path, ext: &str) -> Asset {
+    let basename = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("media");
+    Asset {
+        filename: content_addressed_filename(&bytes, basename),
+        bytes,
+        mime: mime_for(ext),
+    }
+}
+
 // ---------------------------------------------------------------------------
 // HTML rendering
 // ---------------------------------------------------------------------------
@@ -127,6 +219,12 @@ fn render_image_html(spec: &dsl::MediaSpec, asset_filename: &str) -> String {
 }
 
 fn render_audio_html(spec: &dsl::MediaSpec, asset_filename: &str) -> String {
+    if spec.anki_sound {
+        // Anki expands the tag itself; escaping would break the match.
+        return format!(
+            "<div class=\"marki-media marki-audio\">[sound:{asset_filename}]</div>"
+        );
+    }
     let mut attrs = String::new();
     if spec.controls {
         attrs.push_str(" controls");
@@ -150,6 +248,40 @@ fn render_audio_html(spec: &dsl::MediaSpec, asset_filename: &str) -> String {
     )
 }
 
+fn render_video_html(
+    spec: &dsl::MediaSpec,
+    asset_filename: &str,
+    poster: Option<&Asset>,
+) -> String {
+    let mut attrs = String::new();
+    if spec.controls {
+        attrs.push_str(" controls");
+    }
+    if spec.r#loop {
+        attrs.push_str(" loop");
+    }
+    if spec.autoplay {
+        // Webviews (AnkiDroid, AnkiMobile) only autoplay muted, inline
+        // video; without these the knob silently does nothing.
+        attrs.push_str(" autoplay muted playsinline");
+    }
+    attrs.push_str(&format!(" preload=\"{}\"", spec.preload.as_str()));
+    if let Some(p) = poster {
+        attrs.push_str(&format!(" poster=\"{}\"", escape_attr(&p.filename)));
+    }
+    if let Some(alt) = spec.alt.as_deref().filter(|s| !s.is_empty()) {
+        attrs.push_str(&format!(" aria-label=\"{}\"", escape_attr(alt)));
+    }
+    let size = spec.size.unwrap_or(dsl::DEFAULT_VIDEO_SIZE);
+    format!(
+        "<div class=\"marki-media marki-video\" \
+         style=\"max-width:{size}px;width:100%;margin:0 auto;\">\
+         <video src=\"{src}\"{attrs} \
+         style=\"width:100%;height:auto;display:block;\"></video></div>",
+        src = escape_attr(asset_filename),
+    )
+}
+
 // ---------------------------------------------------------------------------
 // Asset filename
 // ---------------------------------------------------------------------------
@@ -176,6 +308,8 @@ pub fn classify(ext: &str) -> Option<MediaClass> {
         Some(MediaClass::Image)
     } else if AUDIO_EXTS.iter().any(|e| *e == ext) {
         Some(MediaClass::Audio)
+    } else if VIDEO_EXTS.iter().any(|e| *e == ext) {
+        Some(MediaClass::Video)
     } else {
         None
     }
@@ -192,6 +326,8 @@ fn mime_for(ext: &str) -> AssetMime {
         "ogg" => AssetMime::AudioOgg,
         "m4a" => AssetMime::AudioMp4,
         "wav" => AssetMime::AudioWav,
+        "mp4" => AssetMime::VideoMp4,
+        "webm" => AssetMime::VideoWebm,
         // classify() is checked first, so this branch is unreachable in
         // practice. Pick the most generic image bucket as a safe stub.
         _ => AssetMime::ImagePng,
@@ -254,11 +390,12 @@ fn source_dir<'a>(name: &str, sources: &'a [(String, PathBuf)]) -> Option<&'a Pa
 /// Try to resolve `path` inside `dir`. Returns the resolved file path
 /// and the matching extension (without dot).
 ///
-/// If `path` ends with a known extension (image or audio), only that
+/// If `path` ends with a known extension (image, audio or video), only that
 /// exact extension is tried (with case-insensitive fallback on the
 /// final filename component, matching the directory traversal at all
 /// levels). Otherwise the resolver tries each known extension in the
-/// fixed preference order — `IMAGE_EXTS` first, then `AUDIO_EXTS`.
+/// fixed preference order — `IMAGE_EXTS`, then `AUDIO_EXTS`, then
+/// `VIDEO_EXTS`.
 fn resolve_in_dir(dir: &Path, path: &str) -> Option<(PathBuf, &'static str)> {
     // Explicit-extension fast path.
     if let Some((stem, ext)) = split_known_ext(path) {
@@ -271,7 +408,7 @@ fn resolve_in_dir(dir: &Path, path: &str) -> Option<(PathBuf, &'static str)> {
     }
 
     // No extension — try each in preference order.
-    for ext in IMAGE_EXTS.iter().chain(AUDIO_EXTS.iter()) {
+    for ext in known_exts() {
         if let Some(p) = lookup_with_ext(dir, path, ext) {
             return Some((p, *ext));
         }
@@ -280,7 +417,7 @@ fn resolve_in_dir(dir: &Path, path: &str) -> Option<(PathBuf, &'static str)> {
 }
 
 /// Returns `Some((stem, ext))` if `path`'s last component has a known
-/// (image or audio) extension. Otherwise `None`.
+/// (image, audio or video) extension. Otherwise `None`.
 fn split_known_ext(path: &str) -> Option<(&str, &'static str)> {
     let dot = path.rfind('.')?;
     // Reject `.foo` segments inside parent directories (no slash after
@@ -288,7 +425,7 @@ fn split_known_ext(path: &str) -> Option<(&str, &'static str)> {
     // The rfind ensures we look at the last dot in the whole path —
     // good enough.
     let ext_lower = path[dot + 1..].to_ascii_lowercase();
-    for known in IMAGE_EXTS.iter().chain(AUDIO_EXTS.iter()) {
+    for known in known_exts() {
         if ext_lower == *known {
             return Some((&path[..dot], *known));
         }
@@ -359,9 +496,11 @@ mod tests {
         let circle = tmp.join("circle");
         let flags = tmp.join("flags");
         let audio = tmp.join("audio");
+        let clips = tmp.join("clips");
         std::fs::create_dir_all(&circle).unwrap();
         std::fs::create_dir_all(&flags).unwrap();
         std::fs::create_dir_all(&audio).unwrap();
+        std::fs::create_dir_all(&clips).unwrap();
 
         write_file(&circle, "de.svg", b"<svg>circle-de</svg>");
         write_file(&circle, "fr.svg", b"<svg>circle-fr</svg>");
@@ -370,11 +509,15 @@ mod tests {
         write_file(&flags, "us/ca.svg", b"<svg>flags-us-ca</svg>");
         write_file(&audio, "morning.mp3", b"ID3-fake-mp3");
         write_file(&audio, "evening.ogg", b"OggS-fake-ogg");
+        write_file(&clips, "wave.mp4", b"ftyp-fake-mp4");
+        write_file(&clips, "wave.svg", b"<svg>poster</svg>");
+        write_file(&clips, "sea.webm", b"webm-fake");
 
         vec![
             ("circle".into(), circle),
             ("flags".into(), flags),
             ("audio".into(), audio),
+            ("clips".into(), clips),
         ]
     }
 
@@ -497,6 +640,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn classify_video_extensions() {
+        for ext in VIDEO_EXTS {
+            assert_eq!(classify(ext), Some(MediaClass::Video), "{ext}");
+        }
+        assert_eq!(classify("WebM"), Some(MediaClass::Video));
+    }
+
     #[test]
     fn classify_unknown_returns_none() {
         assert_eq!(classify("txt"), None);
@@ -515,16 +666,8 @@ mod tests {
     fn renders_image_with_basename_ref() {
         let tmp = tempdir();
         let sources = make_sources(tmp.path());
-        let spec = dsl::MediaSpec {
-            src: "circle/de".into(),
-            size: Some(300),
-            alt: None,
-            controls: true,
-            r#loop: false,
-            autoplay: false,
-            preload: dsl::PreloadMode::Auto,
-        };
-        let out = render_media(&sources, &spec).unwrap();
+        let spec = dsl::parse_media_spec("src = \"circle/de\"\nsize = 300").unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
         assert!(out.html.contains("max-width:300px"));
         assert!(out.html.contains("<img "));
         assert!(out.html.contains("marki-media-"));
@@ -538,16 +681,8 @@ mod tests {
     fn image_default_size_is_200() {
         let tmp = tempdir();
         let sources = make_sources(tmp.path());
-        let spec = dsl::MediaSpec {
-            src: "circle/de".into(),
-            size: None,
-            alt: None,
-            controls: true,
-            r#loop: false,
-            autoplay: false,
-            preload: dsl::PreloadMode::Auto,
-        };
-        let out = render_media(&sources, &spec).unwrap();
+        let spec = dsl::parse_media_spec("src = \"circle/de\"").unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
         assert!(out.html.contains("max-width:200px"));
     }
 
@@ -555,16 +690,8 @@ mod tests {
     fn image_alt_defaults_to_empty() {
         let tmp = tempdir();
         let sources = make_sources(tmp.path());
-        let spec = dsl::MediaSpec {
-            src: "circle/de".into(),
-            size: None,
-            alt: None,
-            controls: true,
-            r#loop: false,
-            autoplay: false,
-            preload: dsl::PreloadMode::Auto,
-        };
-        let out = render_media(&sources, &spec).unwrap();
+        let spec = dsl::parse_media_spec("src = \"circle/de\"").unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
         assert!(out.html.contains("alt=\"\""));
     }
 
@@ -572,16 +699,9 @@ mod tests {
     fn image_alt_is_escaped() {
         let tmp = tempdir();
         let sources = make_sources(tmp.path());
-        let spec = dsl::MediaSpec {
-            src: "circle/de".into(),
-            size: None,
-            alt: Some(r#"a "quoted" <flag>"#.to_string()),
-            controls: true,
-            r#loop: false,
-            autoplay: false,
-            preload: dsl::PreloadMode::Auto,
-        };
-        let out = render_media(&sources, &spec).unwrap();
+        let spec =
+            dsl::parse_media_spec("src = \"circle/de\"\nalt = 'a \"quoted\" <flag>'").unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
         assert!(out.html.contains("&quot;quoted&quot;"));
         assert!(out.html.contains("&lt;flag&gt;"));
     }
@@ -590,16 +710,8 @@ mod tests {
     fn renders_audio_with_default_attrs() {
         let tmp = tempdir();
         let sources = make_sources(tmp.path());
-        let spec = dsl::MediaSpec {
-            src: "audio/morning".into(),
-            size: None,
-            alt: None,
-            controls: true,
-            r#loop: false,
-            autoplay: false,
-            preload: dsl::PreloadMode::Auto,
-        };
-        let out = render_media(&sources, &spec).unwrap();
+        let spec = dsl::parse_media_spec("src = \"audio/morning\"").unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
         assert!(out.html.contains("<audio "));
         assert!(out.html.contains(" controls"));
         assert!(out.html.contains("preload=\"auto\""));
@@ -613,16 +725,12 @@ mod tests {
     fn audio_respects_overrides() {
         let tmp = tempdir();
         let sources = make_sources(tmp.path());
-        let spec = dsl::MediaSpec {
-            src: "audio/morning".into(),
-            size: Some(300), // ignored for audio
-            alt: Some("morning".into()),
-            controls: false,
-            r#loop: true,
-            autoplay: true,
-            preload: dsl::PreloadMode::None,
-        };
-        let out = render_media(&sources, &spec).unwrap();
+        let spec = dsl::parse_media_spec(
+            "src = \"audio/morning\"\nsize = 300\nalt = \"morning\"\n\
+             controls = false\nloop = true\nautoplay = true\npreload = \"none\"",
+        )
+        .unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
         assert!(!out.html.contains(" controls"));
         assert!(out.html.contains(" loop"));
         assert!(out.html.contains(" autoplay"));
@@ -636,21 +744,121 @@ mod tests {
     fn emitted_asset_is_content_addressed() {
         let tmp = tempdir();
         let sources = make_sources(tmp.path());
-        let spec = dsl::MediaSpec {
-            src: "circle/de".into(),
-            size: None,
-            alt: None,
-            controls: true,
-            r#loop: false,
-            autoplay: false,
-            preload: dsl::PreloadMode::Auto,
-        };
-        let a = render_media(&sources, &spec).unwrap();
-        let b = render_media(&sources, &spec).unwrap();
+        let spec = dsl::parse_media_spec("src = \"circle/de\"").unwrap();
+        let a = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
+        let b = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
         assert_eq!(a.assets[0].filename, b.assets[0].filename);
         assert!(a.assets[0].filename.starts_with("marki-media-"));
     }
 
+    #[test]
+    fn renders_video_with_poster() {
+        let tmp = tempdir();
+        let sources = make_sources(tmp.path());
+        let spec = dsl::parse_media_spec(
+            "src = \"clips/wave.mp4\"\nposter = \"clips/wave\"\nautoplay = true\nloop = true",
+        )
+        .unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
+        assert_eq!(out.assets.len(), 2);
+        assert_eq!(out.assets[0].mime, AssetMime::VideoMp4);
+        assert_eq!(out.assets[1].mime, AssetMime::SvgXml);
+        assert!(out.html.contains("<video "));
+        assert!(out.html.contains(&format!("src=\"{}\"", out.assets[0].filename)));
+        assert!(out.html.contains(&format!("poster=\"{}\"", out.assets[1].filename)));
+        assert!(out.html.contains(" controls loop autoplay muted playsinline"));
+        assert!(out.html.contains("max-width:480px"));
+    }
+
+    #[test]
+    fn bare_video_name_resolves_after_images_and_audio() {
+        let tmp = tempdir();
+        let sources = make_sources(tmp.path());
+        let (_, ext) = resolve("clips/sea", &sources).unwrap();
+        assert_eq!(ext, "webm");
+        // `wave` has both an svg and an mp4; images win.
+        let (_, ext) = resolve("clips/wave", &sources).unwrap();
+        assert_eq!(ext, "svg");
+    }
+
+    #[test]
+    fn poster_must_be_an_image() {
+        let tmp = tempdir();
+        let sources = make_sources(tmp.path());
+        let spec =
+            dsl::parse_media_spec("src = \"clips/sea\"\nposter = \"audio/morning\"").unwrap();
+        let err = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap_err();
+        assert!(err.to_string().contains("must be an image"), "{err}");
+    }
+
+    #[test]
+    fn anki_sound_emits_sound_tag() {
+        let tmp = tempdir();
+        let sources = make_sources(tmp.path());
+        let spec = dsl::parse_media_spec("src = \"audio/evening\"\nanki_sound = true").unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
+        let name = &out.assets[0].filename;
+        assert!(out.html.contains(&format!("[sound:{name}]")), "{}", out.html);
+        assert!(!out.html.contains("<audio"));
+        assert_eq!(out.assets[0].mime, AssetMime::AudioOgg);
+    }
+
+    #[test]
+    fn clipped_audio_is_a_distinct_asset() {
+        let tmp = tempdir();
+        let sources = make_sources(tmp.path());
+        // 1 s of 8-bit mono PCM at 1 kHz.
+        let mut wav = b"RIFF\x00\x00\x00\x00WAVEfmt \x10\x00\x00\x00".to_vec();
+        wav.extend_from_slice(&[1, 0, 1, 0, 0xE8, 3, 0, 0, 0xE8, 3, 0, 0, 1, 0, 8, 0]);
+        wav.extend_from_slice(b"data\xE8\x03\x00\x00");
+        wav.extend(std::iter::repeat_n(0x80, 1000));
+        write_file(&sources[2].1, "word.wav", &wav);
+
+        let cache = tmp.path().join("cache");
+        let full = dsl::parse_media_spec("src = \"audio/word\"").unwrap();
+        let cut = dsl::parse_media_spec("src = \"audio/word\"\nstart = 0.2\nend = 0.5").unwrap();
+        let a = render_media(&sources, &full, None, &cache).unwrap();
+        let b = render_media(&sources, &cut, None, &cache).unwrap();
+        assert_eq!(a.assets[0].bytes, wav);
+        assert_eq!(b.assets[0].bytes.len(), 44 + 300);
+        assert_ne!(a.assets[0].filename, b.assets[0].filename);
+        assert!(b.assets[0].filename.ends_with("-word.wav"));
+        assert!(cache.join("media").is_dir());
+    }
+
+    #[test]
+    fn svg_ignores_raster_knobs() {
+        let tmp = tempdir();
+        let sources = make_sources(tmp.path());
+        let spec = dsl::parse_media_spec("src = \"circle/de\"\nmax_px = 10\nformat = \"png\"")
+            .unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
+        assert_eq!(out.assets[0].bytes, b"<svg>circle-de</svg>");
+        assert_eq!(out.assets[0].mime, AssetMime::SvgXml);
+        assert!(!tmp.path().join("cache/media").exists());
+    }
+
+    #[test]
+    fn raster_format_renames_asset() {
+        let tmp = tempdir();
+        let sources = make_sources(tmp.path());
+        let img = image::RgbaImage::from_pixel(64, 32, [0, 90, 200, 255].into());
+        let mut png = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        write_file(&sources[0].1, "logo.png", png.get_ref());
+
+        let spec = dsl::parse_media_spec("src = \"circle/logo\"\nmax_px = 16\nformat = \"webp\"")
+            .unwrap();
+        let out = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap();
+        let asset = &out.assets[0];
+        assert!(asset.filename.ends_with("-logo.webp"), "{}", asset.filename);
+        assert_eq!(asset.mime, AssetMime::ImageWebp);
+        assert!(out.html.contains("-logo.webp"));
+        let decoded = image::load_from_memory(&asset.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (16, 8));
+        assert!(tmp.path().join("cache/media").is_dir());
+    }
+
     #[test]
     fn unsupported_extension_errors() {
         let tmp = tempdir();
@@ -660,19 +868,11 @@ mod tests {
         std::fs::create_dir_all(&docs).unwrap();
         write_file(&docs, "spec.txt", b"hello");
         sources.push(("docs".into(), docs));
-        let spec = dsl::MediaSpec {
-            src: "docs/spec.txt".into(),
-            size: None,
-            alt: None,
-            controls: true,
-            r#loop: false,
-            autoplay: false,
-            preload: dsl::PreloadMode::Auto,
-        };
+        let spec = dsl::parse_media_spec("src = \"docs/spec.txt\"").unwrap();
         // resolve() doesn't recognise .txt as a known extension, so it
         // falls through to the bare-name search, which won't find any
         // matching extension either → NotFound.
-        let err = render_media(&sources, &spec).unwrap_err();
+        let err = render_media(&sources, &spec, None, &tmp.path().join("cache")).unwrap_err();
         assert!(matches!(err, MediaError::NotFound { .. }));
     }
 
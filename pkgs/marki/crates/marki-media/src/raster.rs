@@ -0,0 +1,301 @@
+//! Raster normalisation: decode → downscale → re-encode, in pure Rust.
+//!
+//! Only runs when a block sets `max_px`, `format` or `quality`; SVGs
+//! never reach this module. Results go through [`crate::cache`]; the
+//! key covers [`RENDER_VERSION_MEDIA`], the source bytes and every
+//! knob, so editing the source file or the block re-renders. When the
+//! image is already small enough and stays in its own format (with no
+//! explicit `quality`), the source bytes are returned as-is rather than
+//! re-encoded. Photos are turned upright by their EXIF orientation
+//! before anything else, since the re-encoded file carries no EXIF.
+//!
+//! `quality` only means something for JPEG output. PNG and WebP are
+//! written losslessly (the `image` crate has no lossy WebP encoder), so
+//! a `quality` on either is an error rather than a silent no-op.
+
+use std::io::Cursor;
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageReader};
+
+use crate::cache;
+use crate::dsl::{MediaSpec, RasterFormat, DEFAULT_JPEG_QUALITY};
+use crate::error::MediaError;
+use crate::version::RENDER_VERSION_MEDIA;
+
+/// A processed raster: encoded bytes plus the extension they carry.
+#[derive(Debug)]
+pub struct Raster {
+    pub bytes: Vec<u8>,
+    pub ext: &'static str,
+}
+
+/// Apply the spec's raster knobs to `src` (a file with extension
+/// `src_ext`), via the cache under `cache_root`.
+pub fn process(
+    src: &[u8],
+    src_ext: &str,
+    spec: &MediaSpec,
+    cache_root: &Path,
+) -> Result<Raster, MediaError> {
+    let source_format = format_for_ext(src_ext);
+    let target = spec.format.or(source_format).unwrap_or(RasterFormat::Png);
+    validate(spec, target)?;
+    let quality = spec.quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+
+    let key = cache_key(src, target, spec.max_px, quality);
+    let out_name = format!("output.{}", target.ext());
+    if let Some(bytes) = cache::lookup(cache_root, &key, &out_name)? {
+        return Ok(Raster { bytes, ext: target.ext() });
+    }
+
+    let img = decode_upright(src)
+        .map_err(|e| MediaError::Image(format!("decode {}: {e}", spec.src)))?;
+    let needs_resize = spec
+        .max_px
+        .is_some_and(|m| img.width().max(img.height()) > m);
+    if !needs_resize && Some(target) == source_format && spec.quality.is_none() {
+        return Ok(Raster { bytes: src.to_vec(), ext: target.ext() });
+    }
+
+    let img = match spec.max_px {
+        // `resize` fits within the box and keeps the aspect ratio.
+        Some(m) if needs_resize => img.resize(m, m, FilterType::Lanczos3),
+        _ => img,
+    };
+    let bytes = encode(&img, target, quality)
+        .map_err(|e| MediaError::Image(format!("encode {}: {e}", spec.src)))?;
+    cache::store(cache_root, &key, &out_name, &bytes)?;
+    Ok(Raster { bytes, ext: target.ext() })
+}
+
+fn validate(spec: &MediaSpec, target: RasterFormat) -> Result<(), MediaError> {
+    if spec.max_px == Some(0) {
+        return Err(MediaError::Parse("`max_px` must be at least 1".into()));
+    }
+    match spec.quality {
+        Some(q) if !(1..=100).contains(&q) => {
+            Err(MediaError::Parse(format!("`quality` must be 1–100, got {q}")))
+        }
+        Some(_) if target != RasterFormat::Jpeg => Err(MediaError::Parse(format!(
+            "`quality` only applies to jpeg output; {} is encoded losslessly",
+            target.ext()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Decode `src` and apply its EXIF orientation, so a portrait phone photo
+/// stays portrait once re-encoded without its EXIF.
+fn decode_upright(src: &[u8]) -> image::ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::new(Cursor::new(src))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// The re-encodable format a source extension maps to. GIF (and
+/// anything unknown) has none, so processed GIFs default to PNG.
+fn format_for_ext(ext: &str) -> Option<RasterFormat> {
+    match ext.to_ascii_lowercase().as_str() {
+        "png" => Some(RasterFormat::Png),
+        "jpg" | "jpeg" => Some(RasterFormat::Jpeg),
+        "webp" => Some(RasterFormat::Webp),
+        _ => None,
+    }
+}
+
+/// `blake3(RENDER_VERSION_MEDIA ∥ "raster" ∥ source ∥ format ∥ max_px ∥ quality)`.
+fn cache_key(src: &[u8], target: RasterFormat, max_px: Option<u32>, quality: u8) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&RENDER_VERSION_MEDIA.to_le_bytes());
+    hasher.update(b"raster");
+    hasher.update(blake3::hash(src).as_bytes());
+    hasher.update(target.ext().as_bytes());
+    hasher.update(&max_px.unwrap_or(0).to_le_bytes());
+    // Quality only changes JPEG output; keep lossless keys stable.
+    if target == RasterFormat::Jpeg {
+        hasher.update(&[quality]);
+    }
+    cache::short_key(&hasher)
+}
+
+fn encode(img: &DynamicImage, target: RasterFormat, quality: u8) -> image::ImageResult<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    match target {
+        RasterFormat::Jpeg => {
+            // JPEG has no alpha channel.
+            let rgb = img.to_rgb8();
+            JpegEncoder::new_with_quality(&mut out, quality).write_image(
+                &rgb,
+                rgb.width(),
+                rgb.height(),
+                image::ExtendedColorType::Rgb8,
+            )?;
+        }
+        RasterFormat::Png => {
+            let rgba = img.to_rgba8();
+            PngEncoder::new(&mut out).write_image(
+                &rgba,
+                rgba.width(),
+                rgba.height(),
+                image::ExtendedColorType::Rgba8,
+            )?;
+        }
+        RasterFormat::Webp => {
+            let rgba = img.to_rgba8();
+            WebPEncoder::new_lossless(&mut out).write_image(
+                &rgba,
+                rgba.width(),
+                rgba.height(),
+                image::ExtendedColorType::Rgba8,
+            )?;
+        }
+    }
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::parse_media_spec;
+    use image::{GenericImageView, ImageFormat, RgbaImage};
+    use marki_render::cache::READY_MARKER;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    fn png(w: u32, h: u32) -> Vec<u8> {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(w, h, [200, 40, 40, 255].into()));
+        let mut out = Cursor::new(Vec::new());
+        img.write_to(&mut out, ImageFormat::Png).unwrap();
+        out.into_inner()
+    }
+
+    fn cache_root() -> PathBuf {
+        let p = std::env::temp_dir().join(format!(
+            "marki-media-raster-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        fs::create_dir_all(&p).unwrap();
+        p
+    }
+
+    #[test]
+    fn downscales_longest_side_and_caches() {
+        let root = cache_root();
+        let spec = parse_media_spec("src = \"big.png\"\nmax_px = 50").unwrap();
+        let out = process(&png(200, 100), "png", &spec, &root).unwrap();
+        assert_eq!(out.ext, "png");
+        let img = image::load_from_memory(&out.bytes).unwrap();
+        assert_eq!(img.dimensions(), (50, 25));
+
+        let media = root.join("media");
+        let entries: Vec<_> = fs::read_dir(&media).unwrap().flatten().collect();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].path().join(READY_MARKER).exists());
+
+        // Second call is a cache hit with identical bytes.
+        let again = process(&png(200, 100), "png", &spec, &root).unwrap();
+        assert_eq!(again.bytes, out.bytes);
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn small_same_format_passes_through() {
+        let root = cache_root();
+        let src = png(20, 20);
+        let spec = parse_media_spec("src = \"s.png\"\nmax_px = 50").unwrap();
+        let out = process(&src, "png", &spec, &root).unwrap();
+        assert_eq!(out.bytes, src);
+        assert!(!root.join("media").exists());
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn converts_format() {
+        let root = cache_root();
+        for (fmt, ext, want) in [
+            ("jpeg", "jpg", ImageFormat::Jpeg),
+            ("webp", "webp", ImageFormat::WebP),
+        ] {
+            let spec = parse_media_spec(&format!("src = \"a.png\"\nformat = \"{fmt}\"")).unwrap();
+            let out = process(&png(30, 10), "png", &spec, &root).unwrap();
+            assert_eq!(out.ext, ext);
+            assert_eq!(image::guess_format(&out.bytes).unwrap(), want);
+        }
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn quality_changes_jpeg_key_only() {
+        let src = png(4, 4);
+        let a = cache_key(&src, RasterFormat::Jpeg, None, 80);
+        let b = cache_key(&src, RasterFormat::Jpeg, None, 60);
+        assert_ne!(a, b);
+        let c = cache_key(&src, RasterFormat::Png, None, 80);
+        let d = cache_key(&src, RasterFormat::Png, None, 60);
+        assert_eq!(c, d);
+    }
+
+    #[test]
+    fn rejects_bad_knobs() {
+        let root = cache_root();
+        for body in [
+            "max_px = 0",
+            "quality = 0",
+            "quality = 101",
+            "quality = 80",
+            "format = \"webp\"\nquality = 80",
+        ] {
+            let spec = parse_media_spec(&format!("src = \"a.png\"\n{body}")).unwrap();
+            let err = process(&png(4, 4), "png", &spec, &root).unwrap_err();
+            assert!(matches!(err, MediaError::Parse(_)), "{body}");
+        }
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn exif_orientation_is_applied() {
+        // A 40×20 JPEG whose EXIF says "rotate 90° clockwise to view".
+        let exif = [
+            0x49, 0x49, 0x2a, 0x00, 0x08, 0x00, 0x00, 0x00, // TIFF header, IFD at 8
+            0x01, 0x00, // one entry
+            0x12, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, // no next IFD
+        ];
+        let rgb = image::RgbImage::from_pixel(40, 20, [200, 40, 40].into());
+        let mut src = Cursor::new(Vec::new());
+        let mut enc = JpegEncoder::new_with_quality(&mut src, 90);
+        enc.set_exif_metadata(exif.to_vec()).unwrap();
+        enc.write_image(&rgb, 40, 20, image::ExtendedColorType::Rgb8)
+            .unwrap();
+
+        let root = cache_root();
+        let spec = parse_media_spec("src = \"p.jpg\"\nformat = \"png\"").unwrap();
+        let out = process(&src.into_inner(), "jpg", &spec, &root).unwrap();
+        let img = image::load_from_memory(&out.bytes).unwrap();
+        assert_eq!(img.dimensions(), (20, 40), "portrait stays portrait");
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn undecodable_source_errors() {
+        let root = cache_root();
+        let spec = parse_media_spec("src = \"a.png\"\nmax_px = 10").unwrap();
+        let err = process(b"not a png", "png", &spec, &root).unwrap_err();
+        assert!(matches!(err, MediaError::Image(_)));
+        fs::remove_dir_all(&root).ok();
+    }
+}
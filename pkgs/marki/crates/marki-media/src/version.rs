@@ -0,0 +1,5 @@
+//! Render-format version for processed rasters. Bump when the resize
+//! filter, encoder settings, or anything else that influences the cached
+//! output changes — this invalidates every existing cache entry on next
+//! run.
+pub const RENDER_VERSION_MEDIA: u32 = 2;
@@ -0,0 +1,120 @@
+//! The on-disk cache-entry contract shared by renderers and the daemon.
+//!
+//! Renderers that cache under `<cache_dir>/<namespace>/<key>/` mark a
+//! complete entry by writing [`READY_MARKER`] last. The marker's body is
+//! a manifest of the entry's files — one `<blake3-hex>  <name>` line
+//! each, `b3sum`-compatible — so `marki cache verify` can check an entry
+//! against its content without knowing the renderer. An empty marker
+//! (written before manifests existed) is still "ready", just
+//! unverifiable.
+//!
+//! Renderers also refresh the marker's mtime on every cache hit, which
+//! makes it the entry's "last used" time for `marki cache prune`.
+//! [`write_atomic`] and [`touch`] do both for single-directory entries.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Marker file that signals "this directory's contents are complete".
+pub const READY_MARKER: &str = ".ready";
+
+/// Full BLAKE3 hex digest of `bytes`, as recorded in the manifest.
+pub fn content_digest(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_hex().to_string()
+}
+
+/// Build the marker body for the given `(name, bytes)` files.
+pub fn ready_manifest(files: &[(&str, &[u8])]) -> String {
+    let mut out = String::new();
+    for (name, bytes) in files {
+        out.push_str(&content_digest(bytes));
+        out.push_str("  ");
+        out.push_str(name);
+        out.push('\n');
+    }
+    out
+}
+
+/// Parse a marker body into `(digest, name)` pairs. Blank and malformed
+/// lines are skipped.
+pub fn parse_ready_manifest(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|l| l.split_once("  "))
+        .filter(|(digest, name)| digest.len() == 64 && !name.is_empty())
+        .map(|(digest, name)| (digest.to_string(), name.to_string()))
+        .collect()
+}
+
+/// Atomically populate the cache directory. The `.ready` marker is
+/// written last; a crash mid-write leaves the directory in a never-
+/// ready state that future readers treat as a miss.
+pub fn write_atomic(dir: &Path, files: &[(&str, &[u8])]) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for (name, bytes) in files {
+        let tmp = dir.join(format!(".{name}.tmp"));
+        {
+            let mut h = fs::File::create(&tmp)?;
+            h.write_all(bytes)?;
+            h.sync_all().ok();
+        }
+        fs::rename(&tmp, dir.join(name))?;
+    }
+
+    let marker_tmp = dir.join(format!(".{READY_MARKER}.tmp"));
+    {
+        let mut h = fs::File::create(&marker_tmp)?;
+        h.write_all(ready_manifest(files).as_bytes())?;
+        h.sync_all().ok();
+    }
+    fs::rename(&marker_tmp, dir.join(READY_MARKER))?;
+
+    Ok(())
+}
+
+/// Bump the marker's mtime ("last used", read by `marki cache prune`).
+/// Best-effort.
+pub fn touch(dir: &Path) {
+    if let Ok(f) = fs::File::options().write(true).open(dir.join(READY_MARKER)) {
+        f.set_modified(SystemTime::now()).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_round_trip() {
+        let body = ready_manifest(&[("base.svg", b"<svg/>"), ("sidecar.json", b"{}")]);
+        let parsed = parse_ready_manifest(&body);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0], (content_digest(b"<svg/>"), "base.svg".to_string()));
+        assert_eq!(parsed[1].1, "sidecar.json");
+    }
+
+    #[test]
+    fn write_atomic_marks_ready_and_touch_refreshes() {
+        let dir = std::env::temp_dir().join(format!("marki-render-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        write_atomic(&dir, &[("out.svg", b"<svg/>")]).unwrap();
+        assert_eq!(fs::read(dir.join("out.svg")).unwrap(), b"<svg/>");
+        let marker = dir.join(READY_MARKER);
+        let body = fs::read_to_string(&marker).unwrap();
+        assert_eq!(parse_ready_manifest(&body), [(content_digest(b"<svg/>"), "out.svg".into())]);
+
+        let old = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1);
+        fs::File::options().write(true).open(&marker).unwrap().set_modified(old).unwrap();
+        touch(&dir);
+        assert!(fs::metadata(&marker).unwrap().modified().unwrap() > old);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_marker_has_no_entries() {
+        assert!(parse_ready_manifest("").is_empty());
+        assert!(parse_ready_manifest("garbage\n").is_empty());
+    }
+}
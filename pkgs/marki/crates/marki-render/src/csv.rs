@@ -0,0 +1,74 @@
+//! RFC 4180 CSV, shared by the renderers and the scripting data loader
+//! so a table parses the same wherever it is read.
+
+/// Split `text` into records of fields. Quoted fields may hold commas,
+/// newlines and doubled quotes; CRLF line ends, a leading BOM and blank
+/// lines are accepted. Records keep however many fields they had.
+pub fn records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    // Whether the current record has any content yet, so blank lines are
+    // skipped rather than read as one-empty-field records.
+    let mut started = false;
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => {
+                quoted = true;
+                started = true;
+            }
+            ',' => {
+                record.push(std::mem::take(&mut field));
+                started = true;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if started {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                started = false;
+            }
+            _ => {
+                field.push(c);
+                started = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".into());
+    }
+    if started {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields_may_span_lines() {
+        let got = records("a,b\r\n\n\"x, \"\"y\"\"\",\"two\nlines\"\nlast").unwrap();
+        assert_eq!(got, [vec!["a", "b"], vec!["x, \"y\"", "two\nlines"], vec!["last"]]);
+        assert!(records("\"open").is_err());
+        assert!(records("").unwrap().is_empty());
+    }
+}
@@ -17,11 +17,19 @@
 //! Both routes converge via [`Input::deserialize`], so a renderer implements
 //! its logic once and gets the script-side constructor for free.
 //!
-//! No I/O happens in this crate.
+//! [`cache`] pins down the on-disk cache-entry format the renderers write
+//! and the daemon inspects, and writes and touches entries for them.
+//! [`raster`] is the shared SVG → PNG step for projects that opt into PNG
+//! assets; it loads the system fonts once per process. [`csv`] is the one
+//! CSV parser for data tables. That is all the I/O this crate does: the
+//! rest is pure types.
 
 use std::path::Path;
 
+pub mod cache;
+pub mod csv;
 mod escape;
+pub mod raster;
 
 pub use escape::escape_html;
 
@@ -125,6 +133,8 @@ pub enum AssetMime {
     AudioOgg,
     AudioMp4,
     AudioWav,
+    VideoMp4,
+    VideoWebm,
     ApplicationJson,
 }
 
@@ -140,6 +150,8 @@ impl AssetMime {
             AssetMime::AudioOgg => "audio/ogg",
             AssetMime::AudioMp4 => "audio/mp4",
             AssetMime::AudioWav => "audio/wav",
+            AssetMime::VideoMp4 => "video/mp4",
+            AssetMime::VideoWebm => "video/webm",
             AssetMime::ApplicationJson => "application/json",
         }
     }
@@ -187,6 +199,14 @@ pub trait Renderer: Send + Sync {
     /// fenced-code lang and the script-side constructor name.
     fn lang(&self) -> &'static str;
 
+    /// Output-format version, bumped whenever the renderer would emit
+    /// different HTML for the same block. The daemon folds it into each
+    /// note's recorded inputs so `marki diff` can attribute a change to a
+    /// renderer upgrade rather than an edit.
+    fn version(&self) -> u32 {
+        0
+    }
+
     /// Render one block.
     fn render(&self, input: Input<'_>, ctx: &mut RenderCtx<'_>) -> Result<Fragment, RenderError>;
 }
@@ -0,0 +1,128 @@
+//! SVG → PNG rasterisation, for projects that opt in with
+//! `[render] raster = { scale = 2 }`.
+//!
+//! Some Anki clients, older AnkiDroid WebViews especially, draw large
+//! multi-layer SVGs slowly or wrongly. A renderer that emits SVG can pass
+//! each document through [`Raster::png`] and ship the PNG in its place;
+//! the markup around it (layer stacking, `reveal`) stays the same. The
+//! rendering happens in-process with `resvg`, so no extra binary is
+//! involved.
+//!
+//! System fonts are loaded once per process for the SVGs that carry live
+//! `<text>` (map legends). Typst output is already outlined.
+
+use std::sync::{Arc, OnceLock};
+
+use serde::Deserialize;
+
+/// Largest accepted `scale`; beyond this PNGs outgrow the SVGs they replace
+/// many times over.
+pub const MAX_SCALE: f32 = 8.0;
+
+/// Rasterisation settings. Renderers fold them into their cache keys, so
+/// changing the scale re-renders instead of serving PNGs of the old size.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Raster {
+    /// Device pixels per CSS pixel. The default, 2, stays sharp on
+    /// high-density phone screens.
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    2.0
+}
+
+impl Default for Raster {
+    fn default() -> Self {
+        Self {
+            scale: default_scale(),
+        }
+    }
+}
+
+impl Raster {
+    /// Check that `scale` is usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.scale.is_finite() && self.scale > 0.0 && self.scale <= MAX_SCALE {
+            Ok(())
+        } else {
+            Err(format!(
+                "raster scale must be in (0, {MAX_SCALE}], got {}",
+                self.scale
+            ))
+        }
+    }
+
+    /// Feed the settings to a cache-key hasher.
+    pub fn hash_into(&self, hasher: &mut blake3::Hasher) {
+        hasher.update(b"raster\0");
+        hasher.update(&self.scale.to_le_bytes());
+    }
+
+    /// Rasterise `svg` at `scale` device pixels per CSS pixel.
+    pub fn png(&self, svg: &[u8]) -> Result<Vec<u8>, String> {
+        let opts = resvg::usvg::Options {
+            fontdb: fonts(),
+            ..Default::default()
+        };
+        let tree =
+            resvg::usvg::Tree::from_data(svg, &opts).map_err(|e| format!("rasterise: {e}"))?;
+        let size = tree.size();
+        let px = |v: f32| ((v * self.scale).ceil() as u32).max(1);
+        let mut pixmap = resvg::tiny_skia::Pixmap::new(px(size.width()), px(size.height()))
+            .ok_or_else(|| format!("rasterise: {}x{} is too large", size.width(), size.height()))?;
+        let transform = resvg::tiny_skia::Transform::from_scale(self.scale, self.scale);
+        resvg::render(&tree, transform, &mut pixmap.as_mut());
+        pixmap.encode_png().map_err(|e| format!("rasterise: {e}"))
+    }
+
+    /// CSS width of a PNG made by [`Self::png`]: its pixel width over the
+    /// scale. `None` when `png` isn't a PNG.
+    pub fn css_width(&self, png: &[u8]) -> Option<u32> {
+        if png.len() < 24 || !png.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return None;
+        }
+        let px = u32::from_be_bytes(png[16..20].try_into().ok()?);
+        Some((px as f32 / self.scale).round() as u32)
+    }
+}
+
+/// System fonts, loaded on first use.
+fn fonts() -> Arc<resvg::usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<resvg::usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut db = resvg::usvg::fontdb::Database::new();
+            db.load_system_fonts();
+            Arc::new(db)
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &[u8] = br##"<svg xmlns="http://www.w3.org/2000/svg" width="30" height="20"><rect width="30" height="20" fill="#c64f3f"/></svg>"##;
+
+    #[test]
+    fn png_is_scaled_and_reports_its_css_width() {
+        let raster = Raster { scale: 2.0 };
+        let png = raster.png(SQUARE).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let px = u32::from_be_bytes(png[16..20].try_into().unwrap());
+        assert_eq!(px, 60);
+        assert_eq!(raster.css_width(&png), Some(30));
+        assert_eq!(raster.css_width(b"<svg/>"), None);
+    }
+
+    #[test]
+    fn bad_svg_and_bad_scale_are_errors() {
+        assert!(Raster::default().png(b"not svg").is_err());
+        assert!(Raster { scale: 0.0 }.validate().is_err());
+        assert!(Raster { scale: f32::NAN }.validate().is_err());
+        assert!(Raster::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,80 @@
+//! TOML body of a `tts` fenced block.
+//!
+//! Authors write things like:
+//!
+//! ```toml
+//! text = "el perro"           # what to say
+//! lang = "es"                 # optional; picks the voice from `tts_voices`
+//! voice = "es+f3"             # optional; overrides the language's voice
+//! speed = 0.8                 # optional; 1.0 is the voice's normal rate
+//! anki_sound = false          # emit `[sound:…]` instead of <audio>
+//! ```
+//!
+//! `voice` means whatever the configured synthesiser takes: an eSpeak NG
+//! voice name (`es`, `en-us+m3`) or a Piper model (`es_ES-davefx-medium`,
+//! looked up in `tts_voice_dir`). Without `voice`, the project's
+//! `tts_voices` table maps `lang` to one; eSpeak NG also accepts the
+//! language code itself as a voice.
+//!
+//! With `anki_sound = true` the card gets Anki's native sound tag, so the
+//! desktop and mobile clients draw their own replay button and honour the
+//! deck's auto-play settings, as for `media` blocks.
+
+use serde::{Deserialize, Serialize};
+
+/// Top-level tts block.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TtsSpec {
+    /// The text to speak.
+    pub text: String,
+
+    /// Language code used to pick a voice when `voice` is absent.
+    #[serde(default)]
+    pub lang: Option<String>,
+
+    /// Synthesiser voice, taken verbatim.
+    #[serde(default)]
+    pub voice: Option<String>,
+
+    /// Speaking rate as a multiple of the voice's normal rate. Default 1.
+    #[serde(default)]
+    pub speed: Option<f32>,
+
+    /// Emit Anki's `[sound:file]` tag instead of an `<audio>` element.
+    /// Default false.
+    #[serde(default)]
+    pub anki_sound: bool,
+}
+
+impl TtsSpec {
+    /// The speaking rate, checked to be a usable multiplier.
+    pub fn rate(&self) -> Result<f32, String> {
+        match self.speed {
+            None => Ok(1.0),
+            Some(s) if s.is_finite() && s > 0.0 && s <= 4.0 => Ok(s),
+            Some(s) => Err(format!("`speed` must be in (0, 4], got {s}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimal_block() {
+        let spec: TtsSpec = toml::from_str("text = \"hola\"").unwrap();
+        assert_eq!(spec.text, "hola");
+        assert!(spec.voice.is_none() && spec.lang.is_none());
+        assert!(!spec.anki_sound);
+        assert_eq!(spec.rate(), Ok(1.0));
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_bad_speed() {
+        assert!(toml::from_str::<TtsSpec>("text = \"x\"\npitch = 3").is_err());
+        let spec: TtsSpec = toml::from_str("text = \"x\"\nspeed = 0").unwrap();
+        assert!(spec.rate().is_err());
+    }
+}
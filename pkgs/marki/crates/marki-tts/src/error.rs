@@ -0,0 +1,31 @@
+//! Crate-local error type. Every variant is convertible into
+//! [`marki_render::RenderError`] at the trait boundary.
+
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TtsError {
+    #[error("speech synthesiser not found or not executable: {0}")]
+    BinaryNotFound(PathBuf),
+
+    #[error("no voice for language `{0}`; set `voice` or add it to `tts_voices`")]
+    NoVoice(String),
+
+    #[error("speech synthesis failed:\n{0}")]
+    Synth(String),
+
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<TtsError> for marki_render::RenderError {
+    fn from(e: TtsError) -> Self {
+        use marki_render::RenderError as B;
+        match e {
+            TtsError::BinaryNotFound(_) => B::Internal(e.to_string()),
+            TtsError::NoVoice(_) => B::Resolve(e.to_string()),
+            TtsError::Synth(_) => B::Internal(e.to_string()),
+            TtsError::Io(ref io) => B::Io(io.to_string()),
+        }
+    }
+}
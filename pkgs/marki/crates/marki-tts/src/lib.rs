@@ -0,0 +1,117 @@
+//! `marki-tts` — render `tts` blocks to spoken audio by shelling out to a
+//! local speech synthesiser.
+//!
+//! Implements `marki_render::Renderer` for the lang token `tts`. The
+//! block body is TOML (see `dsl.rs`): the text to speak plus an optional
+//! language, voice and speed. Vocabulary cards get a pronunciation
+//! without anyone recording each word.
+//!
+//! Two synthesisers are supported, both run as subprocesses like
+//! `marki-typst` runs `typst`: eSpeak NG (`espeak-ng`) and Piper
+//! (`piper`). Which command-line dialect to speak is guessed from the
+//! binary's name or set explicitly; see [`TtsSetup`]. Either way the
+//! text is written to the synthesiser's stdin and a WAV file comes back.
+//!
+//! Audio is cached at `<cache_dir>/tts/<key>/output.wav`, keyed by
+//! `blake3(RENDER_VERSION_TTS | engine | binary --version | voice |
+//! speed | text)`, and emitted as a content-addressed
+//! `marki-tts-<hash>.wav` [`marki_render::Asset`]. The card gets an
+//! `<audio>` element, or Anki's `[sound:…]` tag with `anki_sound = true`.
+//!
+//! The user controls the synthesiser binary and its voices — install
+//! them however you like and pass the path in via the marki config /
+//! `MARKI_TTS` env var.
+
+pub mod dsl;
+pub mod error;
+pub mod render;
+pub mod setup;
+pub mod version;
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use marki_render::{Fragment, Input, RenderCtx, RenderError, Renderer};
+
+pub use error::TtsError;
+pub use setup::{TtsEngine, TtsSetup};
+pub use version::RENDER_VERSION_TTS;
+
+/// Lang token this renderer handles: `tts`.
+pub const TTS_LANG: &str = "tts";
+
+/// Speech block renderer. Construct with [`TtsRenderer::new`] and
+/// register against the marki daemon's renderer registry.
+pub struct TtsRenderer {
+    setup: TtsSetup,
+    /// The binary's `--version` output, asked for once on first render.
+    version: OnceLock<String>,
+}
+
+impl TtsRenderer {
+    pub fn new(binary: PathBuf) -> Self {
+        Self::with_setup(TtsSetup::new(binary))
+    }
+
+    /// Renderer with a project's engine, voices and voice directory.
+    pub fn with_setup(setup: TtsSetup) -> Self {
+        Self {
+            setup,
+            version: OnceLock::new(),
+        }
+    }
+
+    fn binary_version(&self) -> Result<&str, TtsError> {
+        if let Some(v) = self.version.get() {
+            return Ok(v);
+        }
+        let v = render::binary_version(&self.setup)?;
+        Ok(self.version.get_or_init(|| v))
+    }
+}
+
+impl Renderer for TtsRenderer {
+    fn lang(&self) -> &'static str {
+        TTS_LANG
+    }
+
+    fn version(&self) -> u32 {
+        RENDER_VERSION_TTS
+    }
+
+    fn render(&self, input: Input<'_>, ctx: &mut RenderCtx<'_>) -> Result<Fragment, RenderError> {
+        let spec: dsl::TtsSpec = input.deserialize()?;
+        let speed = spec.rate().map_err(RenderError::Parse)?;
+        Ok(render::run(
+            &self.setup,
+            self.binary_version()?,
+            &spec,
+            speed,
+            ctx,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lang_token_is_tts() {
+        let r = TtsRenderer::new(PathBuf::from("/nonexistent"));
+        assert_eq!(r.lang(), "tts");
+    }
+
+    #[test]
+    fn bad_speed_is_a_parse_error() {
+        let r = TtsRenderer::new(PathBuf::from("/nonexistent"));
+        let mut ctx = RenderCtx {
+            source_path: &PathBuf::from("/tmp/x.md"),
+            cache_dir: &PathBuf::from("/tmp/cache"),
+        };
+        let err = r
+            .render(Input::Raw("text = \"x\"\nspeed = -1"), &mut ctx)
+            .unwrap_err();
+        assert!(matches!(err, RenderError::Parse(_)));
+    }
+}
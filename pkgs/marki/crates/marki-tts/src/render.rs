@@ -0,0 +1,351 @@
+//! Synthesis pipeline: spec → cache check → subprocess → cached WAV →
+//! audio HTML + emitted asset.
+//!
+//! The cache layout mirrors `marki-typst`'s — `<cache_dir>/tts/<key>/`
+//! holds `output.wav` plus a `.ready` marker written last, whose body is
+//! the shared manifest from [`marki_render::cache`]. Hits refresh the
+//! marker's mtime.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use marki_render::cache::{READY_MARKER, touch, write_atomic};
+use marki_render::escape_html as escape_attr;
+use marki_render::{Asset, AssetMime, Fragment, RenderCtx};
+
+use crate::dsl::TtsSpec;
+use crate::error::TtsError;
+use crate::setup::TtsSetup;
+use crate::version::RENDER_VERSION_TTS;
+
+/// File name written inside the cache dir.
+const WAV_NAME: &str = "output.wav";
+
+/// End-to-end render: returns the [`Fragment`] the daemon splices into
+/// the card. `version` is the synthesiser's `--version` output.
+pub fn run(
+    setup: &TtsSetup,
+    version: &str,
+    spec: &TtsSpec,
+    speed: f32,
+    ctx: &mut RenderCtx<'_>,
+) -> Result<Fragment, TtsError> {
+    let voice = setup.voice(spec.voice.as_deref(), spec.lang.as_deref())?;
+    let key = cache_key(setup, version, voice.as_deref(), speed, &spec.text);
+    let dir = ctx.cache_dir.join("tts").join(&key);
+
+    let wav = if dir.join(READY_MARKER).exists() {
+        touch(&dir);
+        fs::read(dir.join(WAV_NAME))?
+    } else {
+        let wav = synthesise(setup, voice.as_deref(), speed, &spec.text)?;
+        write_atomic(&dir, &[(WAV_NAME, &wav)])?;
+        wav
+    };
+    Ok(build_block(spec, wav))
+}
+
+/// `<binary> --version`, trimmed. Only a binary that can't be started is
+/// an error; whatever it prints is just cache-key material.
+pub fn binary_version(setup: &TtsSetup) -> Result<String, TtsError> {
+    let out = match Command::new(&setup.binary).arg("--version").output() {
+        Ok(o) => o,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(TtsError::BinaryNotFound(setup.binary.clone()));
+        }
+        Err(e) => return Err(TtsError::Io(e)),
+    };
+    let mut text = String::from_utf8_lossy(&out.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&out.stderr));
+    Ok(text.trim().to_string())
+}
+
+/// Compute `blake3(RENDER_VERSION_TTS ∥ engine ∥ binary version ∥ voice
+/// ∥ speed ∥ text)`, truncated to 16 hex chars like the other renderers'
+/// keys.
+fn cache_key(
+    setup: &TtsSetup,
+    version: &str,
+    voice: Option<&str>,
+    speed: f32,
+    text: &str,
+) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&RENDER_VERSION_TTS.to_le_bytes());
+    setup.hash_into(&mut hasher);
+    for part in [version, voice.unwrap_or("")] {
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hasher.update(&speed.to_le_bytes());
+    hasher.update(text.as_bytes());
+    let hex = hasher.finalize().to_hex();
+    hex.as_str()[..16].to_string()
+}
+
+/// Run the synthesiser with `text` on stdin and return the WAV it wrote.
+fn synthesise(
+    setup: &TtsSetup,
+    voice: Option<&str>,
+    speed: f32,
+    text: &str,
+) -> Result<Vec<u8>, TtsError> {
+    let out = temp_wav();
+    let result = synthesise_to(setup, voice, speed, text, &out);
+    let _ = fs::remove_file(&out);
+    result
+}
+
+fn synthesise_to(
+    setup: &TtsSetup,
+    voice: Option<&str>,
+    speed: f32,
+    text: &str,
+    out: &Path,
+) -> Result<Vec<u8>, TtsError> {
+    let mut cmd = setup.command(voice, speed, out);
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = match cmd.spawn() {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(TtsError::BinaryNotFound(setup.binary.clone()));
+        }
+        Err(e) => return Err(TtsError::Io(e)),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // A synthesiser that exits without reading stdin reports its own
+        // error below; a broken pipe here says nothing more.
+        let _ = stdin.write_all(text.as_bytes());
+        let _ = stdin.write_all(b"\n");
+    }
+    let outcome = child.wait_with_output()?;
+
+    if !outcome.status.success() {
+        let stderr = String::from_utf8_lossy(&outcome.stderr).into_owned();
+        let stdout = String::from_utf8_lossy(&outcome.stdout).into_owned();
+        let combined = if stdout.trim().is_empty() {
+            stderr
+        } else {
+            format!("{stderr}\n{stdout}")
+        };
+        return Err(TtsError::Synth(combined));
+    }
+    match fs::read(out) {
+        Ok(bytes) if !bytes.is_empty() => Ok(bytes),
+        Ok(_) => Err(TtsError::Synth(
+            "synthesiser exited 0 but wrote no audio".into(),
+        )),
+        Err(e) => Err(TtsError::Synth(format!(
+            "synthesiser exited 0 but its output is unreadable: {e}"
+        ))),
+    }
+}
+
+/// A fresh per-invocation output path under `$TMPDIR`.
+fn temp_wav() -> PathBuf {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static N: AtomicU64 = AtomicU64::new(0);
+
+    std::env::temp_dir().join(format!(
+        "marki-tts-{}-{}.wav",
+        std::process::id(),
+        N.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+/// Build the [`Fragment`] from synthesised WAV bytes.
+///
+/// The asset filename is content-addressed over the audio (matching
+/// `marki-media`'s scheme), so cards that say the same word with the
+/// same voice share one file. The markup follows `media` audio: an
+/// `<audio>` element labelled with the spoken text, or Anki's sound tag.
+fn build_block(spec: &TtsSpec, wav: Vec<u8>) -> Fragment {
+    let filename = asset_name(&wav);
+    let html = if spec.anki_sound {
+        // Anki expands the tag itself; escaping would break the match.
+        format!("<div class=\"marki-tts\">[sound:{filename}]</div>")
+    } else {
+        format!(
+            "<div class=\"marki-tts\">\
+             <audio src=\"{filename}\" controls preload=\"auto\" aria-label=\"{label}\" \
+             style=\"width:100%;display:block;\"></audio></div>",
+            label = escape_attr(&spec.text),
+        )
+    };
+
+    Fragment {
+        html,
+        reveal: String::new(),
+        assets: vec![Asset {
+            filename,
+            bytes: wav,
+            mime: AssetMime::AudioWav,
+        }],
+    }
+}
+
+/// Content-addressed media name for a WAV.
+fn asset_name(wav: &[u8]) -> String {
+    let hex = blake3::hash(wav).to_hex();
+    format!("marki-tts-{}.wav", &hex.as_str()[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static N: AtomicU64 = AtomicU64::new(0);
+
+    fn tempdir() -> PathBuf {
+        let p = std::env::temp_dir().join(format!(
+            "marki-tts-test-{}-{}",
+            std::process::id(),
+            N.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&p);
+        fs::create_dir_all(&p).unwrap();
+        p
+    }
+
+    fn make_executable(path: &Path) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = fs::metadata(path).unwrap().permissions();
+            perms.set_mode(0o755);
+            fs::set_permissions(path, perms).unwrap();
+        }
+    }
+
+    /// An `espeak-ng` stand-in that writes its voice and stdin to the
+    /// `-w` path, and counts its runs in `calls`.
+    fn shim(dir: &Path) -> PathBuf {
+        let bin = dir.join("espeak-ng");
+        let calls = dir.join("calls");
+        fs::write(
+            &bin,
+            format!(
+                "#!/bin/sh\n\
+                 if [ \"$1\" = --version ]; then echo 'shim 1.0'; exit 0; fi\n\
+                 echo x >> '{calls}'\n\
+                 voice=''; out=''\n\
+                 while [ $# -gt 0 ]; do\n\
+                   case \"$1\" in -v) voice=$2; shift;; -w) out=$2; shift;; esac; shift\n\
+                 done\n\
+                 {{ echo \"RIFF $voice\"; cat; }} > \"$out\"\n",
+                calls = calls.display()
+            ),
+        )
+        .unwrap();
+        make_executable(&bin);
+        bin
+    }
+
+    fn spec(text: &str) -> TtsSpec {
+        toml::from_str(&format!("text = \"{text}\"\nlang = \"es\"")).unwrap()
+    }
+
+    #[test]
+    fn cache_key_covers_every_input() {
+        let s = TtsSetup::new(PathBuf::from("espeak-ng"));
+        let base = cache_key(&s, "1.0", Some("es"), 1.0, "hola");
+        assert_eq!(base.len(), 16);
+        assert_eq!(base, cache_key(&s, "1.0", Some("es"), 1.0, "hola"));
+        assert_ne!(base, cache_key(&s, "1.1", Some("es"), 1.0, "hola"));
+        assert_ne!(base, cache_key(&s, "1.0", Some("de"), 1.0, "hola"));
+        assert_ne!(base, cache_key(&s, "1.0", Some("es"), 0.8, "hola"));
+        assert_ne!(base, cache_key(&s, "1.0", Some("es"), 1.0, "adiós"));
+        let piper = TtsSetup::new(PathBuf::from("piper"));
+        assert_ne!(base, cache_key(&piper, "1.0", Some("es"), 1.0, "hola"));
+    }
+
+    #[test]
+    fn synthesises_once_then_serves_the_cache() {
+        let dir = tempdir();
+        let setup = TtsSetup::new(shim(&dir));
+        let version = binary_version(&setup).unwrap();
+        assert_eq!(version, "shim 1.0");
+        let cache = dir.join("cache");
+        let source = dir.join("card.md");
+        let mut ctx = RenderCtx {
+            source_path: &source,
+            cache_dir: &cache,
+        };
+
+        let first = run(&setup, &version, &spec("el perro"), 1.0, &mut ctx).unwrap();
+        let second = run(&setup, &version, &spec("el perro"), 1.0, &mut ctx).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("calls"))
+                .unwrap()
+                .lines()
+                .count(),
+            1
+        );
+
+        let asset = &first.assets[0];
+        assert_eq!(asset.bytes, b"RIFF es\nel perro\n");
+        assert_eq!(asset.mime, AssetMime::AudioWav);
+        assert!(asset.filename.starts_with("marki-tts-") && asset.filename.ends_with(".wav"));
+        assert_eq!(second.assets[0].filename, asset.filename);
+        assert!(first.html.contains("<audio src=\"marki-tts-"));
+        assert!(first.html.contains("aria-label=\"el perro\""));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn anki_sound_emits_sound_tag() {
+        let dir = tempdir();
+        let setup = TtsSetup::new(shim(&dir));
+        let cache = dir.join("cache");
+        let source = dir.join("card.md");
+        let mut ctx = RenderCtx {
+            source_path: &source,
+            cache_dir: &cache,
+        };
+        let mut s = spec("gato");
+        s.anki_sound = true;
+        let frag = run(&setup, "v", &s, 1.0, &mut ctx).unwrap();
+        assert_eq!(
+            frag.html,
+            format!(
+                "<div class=\"marki-tts\">[sound:{}]</div>",
+                frag.assets[0].filename
+            )
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failures_carry_stderr() {
+        let dir = tempdir();
+        let bin = dir.join("espeak-ng");
+        fs::write(&bin, "#!/bin/sh\necho 'unknown voice' >&2\nexit 1\n").unwrap();
+        make_executable(&bin);
+        let setup = TtsSetup::new(bin);
+        let cache = dir.join("cache");
+        let source = dir.join("card.md");
+        let mut ctx = RenderCtx {
+            source_path: &source,
+            cache_dir: &cache,
+        };
+        match run(&setup, "v", &spec("x"), 1.0, &mut ctx) {
+            Err(TtsError::Synth(msg)) => assert!(msg.contains("unknown voice")),
+            other => panic!("expected Synth error, got {other:?}"),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn missing_binary_is_reported() {
+        let setup = TtsSetup::new(PathBuf::from("/nonexistent/espeak-ng"));
+        assert!(matches!(
+            binary_version(&setup),
+            Err(TtsError::BinaryNotFound(_))
+        ));
+    }
+}
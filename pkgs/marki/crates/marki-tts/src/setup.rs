@@ -0,0 +1,238 @@
+//! How the synthesiser is invoked for a project: the binary, which
+//! command-line dialect it speaks, the default voice per language, and
+//! where Piper models live.
+//!
+//! Everything here that can change the audio is folded into the cache
+//! key, along with the binary's own `--version` output, so upgrading the
+//! synthesiser or remapping a language's voice re-renders the affected
+//! cards instead of serving stale audio.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TtsError;
+
+/// eSpeak NG's default rate in words per minute; `speed` scales it.
+const ESPEAK_WPM: f32 = 175.0;
+
+/// Command-line dialect of the synthesiser binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TtsEngine {
+    /// `espeak-ng -v <voice> -s <wpm> -w <out.wav> --stdin`
+    EspeakNg,
+    /// `piper --model <voice.onnx> --length_scale <1/speed> --output_file <out.wav>`
+    Piper,
+}
+
+impl TtsEngine {
+    /// Guess the dialect from the binary's file name: anything called
+    /// `piper…` is Piper, everything else is taken to be eSpeak NG
+    /// (`espeak-ng`, `espeak`, or a wrapper script).
+    pub fn detect(binary: &Path) -> Self {
+        let name = binary
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.starts_with("piper") {
+            TtsEngine::Piper
+        } else {
+            TtsEngine::EspeakNg
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TtsEngine::EspeakNg => "espeak-ng",
+            TtsEngine::Piper => "piper",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TtsSetup {
+    /// Path to the synthesiser binary.
+    pub(crate) binary: PathBuf,
+    engine: TtsEngine,
+    /// Default voice per language code.
+    voices: BTreeMap<String, String>,
+    /// Directory Piper voice names are resolved against.
+    voice_dir: Option<PathBuf>,
+}
+
+impl TtsSetup {
+    /// Setup for `binary`, with the dialect guessed from its name.
+    pub fn new(binary: PathBuf) -> Self {
+        Self {
+            engine: TtsEngine::detect(&binary),
+            binary,
+            voices: BTreeMap::new(),
+            voice_dir: None,
+        }
+    }
+
+    pub fn with_engine(mut self, engine: TtsEngine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    pub fn with_voices(mut self, voices: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.voices = voices.into_iter().collect();
+        self
+    }
+
+    pub fn with_voice_dir(mut self, dir: PathBuf) -> Self {
+        self.voice_dir = Some(dir);
+        self
+    }
+
+    /// The voice argument for a block: its own `voice`, else the one
+    /// configured for its `lang`. eSpeak NG takes a bare language code as
+    /// a voice; Piper needs a model, so it has no fallback.
+    pub(crate) fn voice(
+        &self,
+        voice: Option<&str>,
+        lang: Option<&str>,
+    ) -> Result<Option<String>, TtsError> {
+        let chosen = voice.or_else(|| lang.and_then(|l| self.voices.get(l).map(String::as_str)));
+        let chosen = match (chosen, self.engine) {
+            (Some(v), _) => v,
+            (None, TtsEngine::EspeakNg) => return Ok(lang.map(str::to_string)),
+            (None, TtsEngine::Piper) => {
+                return Err(TtsError::NoVoice(lang.unwrap_or("(none)").to_string()));
+            }
+        };
+        Ok(Some(match self.engine {
+            TtsEngine::EspeakNg => chosen.to_string(),
+            TtsEngine::Piper => self.piper_model(chosen).to_string_lossy().into_owned(),
+        }))
+    }
+
+    /// `es_ES-davefx-medium` → `<voice_dir>/es_ES-davefx-medium.onnx`.
+    /// Names with an extension or a directory are kept as paths.
+    fn piper_model(&self, voice: &str) -> PathBuf {
+        let path = Path::new(voice);
+        let mut model = match &self.voice_dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        };
+        if model.extension().is_none() {
+            model.set_extension("onnx");
+        }
+        model
+    }
+
+    /// The synthesis command writing a WAV to `out`. The text goes to
+    /// stdin, so it can never be mistaken for a flag.
+    pub(crate) fn command(&self, voice: Option<&str>, speed: f32, out: &Path) -> Command {
+        let mut cmd = Command::new(&self.binary);
+        match self.engine {
+            TtsEngine::EspeakNg => {
+                if let Some(v) = voice {
+                    cmd.arg("-v").arg(v);
+                }
+                cmd.arg("-s")
+                    .arg(format!("{}", (ESPEAK_WPM * speed).round() as u32))
+                    .arg("-w")
+                    .arg(out)
+                    .arg("--stdin");
+            }
+            TtsEngine::Piper => {
+                if let Some(v) = voice {
+                    cmd.arg("--model").arg(v);
+                }
+                cmd.arg("--length_scale")
+                    .arg(format!("{}", 1.0 / speed))
+                    .arg("--output_file")
+                    .arg(out);
+            }
+        }
+        cmd
+    }
+
+    /// Feed the engine to a cache-key hasher. The voice is hashed by the
+    /// caller once resolved.
+    ///
+    /// Model paths, not model contents, are hashed: replacing an `.onnx`
+    /// file in place does not invalidate the cache.
+    pub(crate) fn hash_into(&self, hasher: &mut blake3::Hasher) {
+        hasher.update(self.engine.as_str().as_bytes());
+        hasher.update(b"\0");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn engine_follows_the_binary_name() {
+        assert_eq!(
+            TtsEngine::detect(Path::new("/nix/store/x/bin/piper")),
+            TtsEngine::Piper
+        );
+        assert_eq!(
+            TtsEngine::detect(Path::new("espeak-ng")),
+            TtsEngine::EspeakNg
+        );
+    }
+
+    #[test]
+    fn espeak_falls_back_to_the_language() {
+        let setup = TtsSetup::new(PathBuf::from("espeak-ng"))
+            .with_voices([("de".to_string(), "de+f2".to_string())]);
+        assert_eq!(
+            setup.voice(None, Some("de")).unwrap().as_deref(),
+            Some("de+f2")
+        );
+        assert_eq!(
+            setup.voice(None, Some("es")).unwrap().as_deref(),
+            Some("es")
+        );
+        assert_eq!(
+            setup.voice(Some("en-us"), Some("de")).unwrap().as_deref(),
+            Some("en-us")
+        );
+        let cmd = setup.command(Some("es"), 0.8, Path::new("/tmp/o.wav"));
+        assert_eq!(
+            args(&cmd),
+            ["-v", "es", "-s", "140", "-w", "/tmp/o.wav", "--stdin"]
+        );
+    }
+
+    #[test]
+    fn piper_needs_a_model() {
+        let setup = TtsSetup::new(PathBuf::from("piper"))
+            .with_voices([("es".to_string(), "es_ES-davefx-medium".to_string())])
+            .with_voice_dir(PathBuf::from("/voices"));
+        assert_eq!(
+            setup.voice(None, Some("es")).unwrap().as_deref(),
+            Some("/voices/es_ES-davefx-medium.onnx")
+        );
+        assert!(matches!(
+            setup.voice(None, Some("fr")),
+            Err(TtsError::NoVoice(_))
+        ));
+        let cmd = setup.command(Some("/voices/m.onnx"), 2.0, Path::new("/tmp/o.wav"));
+        assert_eq!(
+            args(&cmd),
+            [
+                "--model",
+                "/voices/m.onnx",
+                "--length_scale",
+                "0.5",
+                "--output_file",
+                "/tmp/o.wav"
+            ]
+        );
+    }
+}
@@ -0,0 +1,5 @@
+//! Render-format version for `tts` blocks. Bump when the synthesiser
+//! arguments, the embed HTML, or any other byte that influences the
+//! cached output changes — this invalidates every existing cache entry
+//! on next run.
+pub const RENDER_VERSION_TTS: u32 = 1;
@@ -13,6 +13,9 @@ pub enum TypstError {
 
     #[error("io: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Raster(String),
 }
 
 impl From<TypstError> for marki_render::RenderError {
@@ -22,6 +25,7 @@ impl From<TypstError> for marki_render::RenderError {
             TypstError::BinaryNotFound(_) => B::Internal(e.to_string()),
             TypstError::Compile(_) => B::Internal(e.to_string()),
             TypstError::Io(ref io) => B::Io(io.to_string()),
+            TypstError::Raster(s) => B::Internal(s),
         }
     }
 }
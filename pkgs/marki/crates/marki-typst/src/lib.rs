@@ -12,16 +12,30 @@
 //! `typst compile --format svg`, and emits the resulting SVG as an
 //! [`marki_render::Asset`].
 //!
+//! A project can share macros and styles through a preamble file
+//! (conventionally `.marki/lib/preamble.typ`) spliced in after that, and
+//! point Typst at extra font and package directories; see
+//! [`TypstSetup`]. Blocks that use the `reveal` helper are compiled a
+//! second time as a back variant that fades in on flip, like
+//! `marki-map` layers — see [`render`].
+//!
 //! Compiled SVGs are cached at `<cache_dir>/typst/<key>/output.svg`,
-//! keyed by `blake3(RENDER_VERSION_TYPST | preamble | source)`.
-//! Subsequent renders of the same block source skip the subprocess.
+//! keyed by `blake3(RENDER_VERSION_TYPST | preamble | setup | project
+//! preamble | source)`. Subsequent renders of the same block source skip
+//! the subprocess.
+//!
+//! A second renderer, [`TypstMathRenderer`] (lang `typst-math`), sets
+//! math spans from card prose (`$…$`, `$$…$$`) — inline ones as
+//! baseline-aligned SVGs. See [`math`].
 //!
 //! The user controls the `typst` binary path — they can install
 //! plugins, fonts, or pin a version however they like and pass it in
 //! via the marki config / `MARKI_TYPST` env var.
 
 pub mod error;
+pub mod math;
 pub mod render;
+pub mod setup;
 pub mod version;
 
 use std::path::PathBuf;
@@ -29,22 +43,31 @@ use std::path::PathBuf;
 use marki_render::{Fragment, Input, RenderCtx, RenderError, Renderer};
 
 pub use error::TypstError;
+pub use setup::{TypstSetup, PREAMBLE_FILE_NAME};
 pub use version::RENDER_VERSION_TYPST;
 
 /// Lang token this renderer handles: `typst`.
 pub const TYPST_LANG: &str = "typst";
 
+/// Lang token for math spans: `typst-math`.
+pub const TYPST_MATH_LANG: &str = "typst-math";
+
 /// Typst block renderer. Construct with [`TypstRenderer::new`] and
 /// register against the marki daemon's renderer registry.
 pub struct TypstRenderer {
-    /// Path to the `typst` CLI binary. The user supplies this — we
-    /// don't pin a version or require a particular install method.
-    binary: PathBuf,
+    /// How to invoke Typst. The user supplies the binary — we don't pin
+    /// a version or require a particular install method.
+    setup: TypstSetup,
 }
 
 impl TypstRenderer {
     pub fn new(binary: PathBuf) -> Self {
-        Self { binary }
+        Self::with_setup(TypstSetup::new(binary))
+    }
+
+    /// Renderer with project fonts, packages and preamble.
+    pub fn with_setup(setup: TypstSetup) -> Self {
+        Self { setup }
     }
 }
 
@@ -53,8 +76,57 @@ impl Renderer for TypstRenderer {
         TYPST_LANG
     }
 
+    fn version(&self) -> u32 {
+        RENDER_VERSION_TYPST
+    }
+
     fn render(&self, input: Input<'_>, ctx: &mut RenderCtx<'_>) -> Result<Fragment, RenderError> {
-        Ok(render::run(&self.binary, input.as_source()?, ctx)?)
+        Ok(render::run(&self.setup, input.as_source()?, ctx)?)
+    }
+}
+
+/// Math-span renderer. The daemon hands it each `$…$` from card prose
+/// as `Input::Spec { source, display }`; a fenced ` ```typst-math `
+/// block arrives as `Input::Raw` and is set as display math.
+pub struct TypstMathRenderer {
+    setup: TypstSetup,
+}
+
+impl TypstMathRenderer {
+    pub fn new(binary: PathBuf) -> Self {
+        Self::with_setup(TypstSetup::new(binary))
+    }
+
+    pub fn with_setup(setup: TypstSetup) -> Self {
+        Self { setup }
+    }
+}
+
+impl Renderer for TypstMathRenderer {
+    fn lang(&self) -> &'static str {
+        TYPST_MATH_LANG
+    }
+
+    fn version(&self) -> u32 {
+        RENDER_VERSION_TYPST
+    }
+
+    fn render(&self, input: Input<'_>, ctx: &mut RenderCtx<'_>) -> Result<Fragment, RenderError> {
+        let display = match &input {
+            Input::Raw(_) => true,
+            Input::Spec(toml::Value::Table(t)) => match t.get("display") {
+                None => false,
+                Some(toml::Value::Boolean(b)) => *b,
+                Some(v) => {
+                    return Err(RenderError::Parse(format!(
+                        "`display` must be a boolean, got {}",
+                        v.type_str()
+                    )))
+                }
+            },
+            Input::Spec(_) => false,
+        };
+        Ok(math::run(&self.setup, input.as_source()?, display, ctx)?)
     }
 }
 
@@ -67,5 +139,21 @@ mod tests {
     fn lang_token_is_typst() {
         let r = TypstRenderer::new(PathBuf::from("/nonexistent"));
         assert_eq!(r.lang(), "typst");
+        let m = TypstMathRenderer::new(PathBuf::from("/nonexistent"));
+        assert_eq!(m.lang(), "typst-math");
+    }
+
+    #[test]
+    fn math_renderer_rejects_non_bool_display() {
+        let m = TypstMathRenderer::new(PathBuf::from("/nonexistent"));
+        let mut t = toml::Table::new();
+        t.insert("source".into(), toml::Value::String("x".into()));
+        t.insert("display".into(), toml::Value::String("yes".into()));
+        let mut ctx = RenderCtx {
+            source_path: &PathBuf::from("/tmp/x.md"),
+            cache_dir: &PathBuf::from("/tmp/cache"),
+        };
+        let err = m.render(Input::Spec(toml::Value::Table(t)), &mut ctx).unwrap_err();
+        assert!(matches!(err, RenderError::Parse(_)));
     }
 }
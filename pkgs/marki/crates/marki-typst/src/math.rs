@@ -0,0 +1,293 @@
+//! Math spans: `$…$` / `$$…$$` from card prose, typeset by Typst.
+//!
+//! Each span is wrapped in its own tiny document and compiled to SVG.
+//! Inline spans also need their baseline so the image sits on the text
+//! line instead of floating above it: a zero-size `metadata` element is
+//! placed at the start of the equation, and `typst query` reads back its
+//! vertical position. The SVG's height minus that position is the depth
+//! below the baseline, which becomes a negative `vertical-align`.
+//!
+//! Sizes are emitted in `em` relative to the fixed 11pt text size set in
+//! [`MATH_PREAMBLE`], so the math scales with the card's font.
+//!
+//! The project preamble is spliced in ahead of [`MATH_PREAMBLE`], so its
+//! `#let` macros are usable inside `$…$` while the page and text setup
+//! here still win.
+//!
+//! Entries share the `typst` block cache — `<cache_dir>/typst/<key>/`
+//! with `output.svg`, plus a `baseline` file for inline spans and
+//! `output.png` when the setup rasterises — keyed on a distinct prefix
+//! so they never collide with block renders.
+
+use std::fs;
+
+use marki_render::cache::{touch, write_atomic};
+use marki_render::{escape_html, Fragment, RenderCtx};
+
+use crate::error::TypstError;
+use crate::render::{
+    cache_dir, compile_doc, hash_preamble, is_ready, variant_prelude, Output, PNG_NAME, SVG_NAME,
+};
+use crate::setup::TypstSetup;
+use crate::version::RENDER_VERSION_TYPST;
+
+/// Page and text setup for math spans. `bounds` edges make the page hug
+/// the ink, so the SVG height is the equation's real extent.
+const MATH_PREAMBLE: &str = "#set page(width: auto, height: auto, margin: 0pt, fill: none)\n\
+     #set text(size: 11pt, top-edge: \"bounds\", bottom-edge: \"bounds\")\n";
+
+/// Text size fixed by [`MATH_PREAMBLE`]; converts pt to em.
+const FONT_PT: f64 = 11.0;
+
+/// Label of the baseline probe.
+const BASELINE_LABEL: &str = "<marki-baseline>";
+
+/// File holding the probe's y position (pt) inside inline entries.
+const BASELINE_NAME: &str = "baseline";
+
+/// Render one math span. `display` spans become a centred block;
+/// inline spans a baseline-aligned `<img>`.
+pub fn run(
+    setup: &TypstSetup,
+    src: &str,
+    display: bool,
+    ctx: &mut RenderCtx<'_>,
+) -> Result<Fragment, TypstError> {
+    let src = src.trim();
+    let project = setup.project_preamble()?;
+    let key = cache_key(setup, &project, src, display);
+    let dir = cache_dir(ctx.cache_dir, &key);
+
+    let (out, baseline) = if is_ready(&dir) {
+        touch(&dir);
+        let out = Output::cached(setup, &dir, SVG_NAME, PNG_NAME)?;
+        let baseline = if display {
+            None
+        } else {
+            Some(fs::read_to_string(dir.join(BASELINE_NAME))?)
+        };
+        (out, baseline)
+    } else {
+        let doc = document(&project, src, display);
+        let query = (!display).then_some(BASELINE_LABEL);
+        let (svg, baseline) = compile_doc(setup, &doc, ctx.source_path, query)?;
+        let out = Output::compiled(setup, svg)?;
+        let mut files: Vec<_> = out.files(SVG_NAME, PNG_NAME).collect();
+        if let Some(b) = &baseline {
+            files.push((BASELINE_NAME, b.as_bytes()));
+        }
+        write_atomic(&dir, &files)?;
+        (out, baseline)
+    };
+
+    let baseline = match baseline {
+        Some(b) => Some(b.trim().parse::<f64>().map_err(|_| {
+            TypstError::Compile(format!("typst query returned a non-numeric baseline: {b}"))
+        })?),
+        None => None,
+    };
+    build_math(out, src, baseline)
+}
+
+/// Front-variant bindings (so a preamble that uses `reveal` still
+/// compiles), the project preamble, then [`MATH_PREAMBLE`] and the span.
+fn document(project: &str, src: &str, display: bool) -> String {
+    let mut doc = variant_prelude(false);
+    if !project.is_empty() {
+        doc.push_str(project);
+        if !project.ends_with('\n') {
+            doc.push('\n');
+        }
+    }
+    doc.push_str(MATH_PREAMBLE);
+    if display {
+        doc.push_str(&format!("$ {src} $\n"));
+    } else {
+        let probe = format!("#context [#metadata(here().position().y.pt()) {BASELINE_LABEL}]");
+        doc.push_str(&format!("${probe}{src}$\n"));
+    }
+    doc
+}
+
+/// `blake3(RENDER_VERSION_TYPST ∥ "math" ∥ display ∥ preamble ∥ setup ∥
+/// project preamble ∥ source)`, truncated to 16 hex chars like block
+/// keys.
+fn cache_key(setup: &TypstSetup, project: &str, src: &str, display: bool) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&RENDER_VERSION_TYPST.to_le_bytes());
+    hasher.update(b"math");
+    hasher.update(&[display as u8]);
+    hasher.update(MATH_PREAMBLE.as_bytes());
+    setup.hash_into(&mut hasher);
+    hash_preamble(&mut hasher, project);
+    hasher.update(src.as_bytes());
+    let hex = hasher.finalize().to_hex();
+    hex.as_str()[..16].to_string()
+}
+
+/// Build the fragment. `baseline` is the probe's y (pt from the top) for
+/// inline spans, `None` for display ones. Sizes always come from the SVG,
+/// whichever format ships.
+fn build_math(out: Output, src: &str, baseline: Option<f64>) -> Result<Fragment, TypstError> {
+    let height = svg_height_pt(&out.svg);
+    let (asset, size) = out.into_asset();
+    let filename = &asset.filename;
+    let alt = escape_html(src);
+    let html = match baseline {
+        Some(baseline) => {
+            let height = height.ok_or_else(|| {
+                TypstError::Compile("typst produced an SVG without a height".into())
+            })?;
+            let depth = (height - baseline).max(0.0);
+            format!(
+                "<img class=\"marki-typst-math\" src=\"{filename}\" alt=\"{alt}\" \
+                 style=\"height:{h:.3}em;vertical-align:-{d:.3}em;display:inline;\">",
+                h = height / FONT_PT,
+                d = depth / FONT_PT,
+            )
+        }
+        None => format!(
+            "<span class=\"marki-typst-math marki-typst-math-display\" \
+             style=\"display:block;text-align:center;\">\
+             <img src=\"{filename}\"{size} alt=\"{alt}\" style=\"max-width:100%;height:auto;\"></span>"
+        ),
+    };
+    Ok(Fragment {
+        html,
+        reveal: String::new(),
+        assets: vec![asset],
+    })
+}
+
+/// Height of the root `<svg>` in pt, from its `viewBox` (Typst writes
+/// user units in pt) or, failing that, a `height="…pt"` attribute.
+fn svg_height_pt(svg: &[u8]) -> Option<f64> {
+    let text = std::str::from_utf8(svg).ok()?;
+    let start = text.find("<svg")?;
+    let tag = &text[start..start + text[start..].find('>')?];
+    let attr = |name: &str| {
+        let at = tag.find(&format!(" {name}=\""))? + name.len() + 3;
+        Some(&tag[at..at + tag[at..].find('"')?])
+    };
+    let from_view_box = attr("viewBox")
+        .and_then(|vb| vb.split_whitespace().nth(3))
+        .and_then(|h| h.parse().ok());
+    if from_view_box.is_some() {
+        return from_view_box;
+    }
+    attr("height")?.trim_end_matches("pt").parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static N: AtomicU64 = AtomicU64::new(0);
+
+    fn tempdir() -> PathBuf {
+        let p = std::env::temp_dir().join(format!(
+            "marki-typst-math-test-{}-{}",
+            std::process::id(),
+            N.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&p);
+        fs::create_dir_all(&p).unwrap();
+        p
+    }
+
+    /// Fake `typst`: `compile` writes a 22pt-tall SVG to the last arg,
+    /// `query` prints a baseline of 16.5pt.
+    fn shim(dir: &Path) -> PathBuf {
+        let shim = dir.join("typst-shim.sh");
+        fs::write(
+            &shim,
+            "#!/bin/sh\nset -e\nif [ \"$1\" = query ]; then echo 16.5; exit 0; fi\n\
+             eval \"out=\\${$#}\"\n\
+             printf '%s' '<svg class=\"typst-doc\" viewBox=\"0 0 30 22\" width=\"30pt\" \
+             height=\"22pt\"></svg>' > \"$out\"\n",
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = fs::metadata(&shim).unwrap().permissions();
+            perms.set_mode(0o755);
+            fs::set_permissions(&shim, perms).unwrap();
+        }
+        shim
+    }
+
+    #[test]
+    fn inline_document_probes_baseline() {
+        let doc = document("", "x^2", false);
+        assert!(doc.contains(MATH_PREAMBLE));
+        assert!(doc.contains(
+            "$#context [#metadata(here().position().y.pt()) <marki-baseline>]x^2$"
+        ));
+        assert!(document("", "x^2", true).ends_with("$ x^2 $\n"));
+    }
+
+    #[test]
+    fn project_macros_precede_math_setup() {
+        let doc = document("#let RR = math.bb(\"R\")", "x in RR", false);
+        let project = doc.find("#let RR").unwrap();
+        assert!(project < doc.find(MATH_PREAMBLE).unwrap(), "{doc}");
+    }
+
+    #[test]
+    fn keys_differ_by_mode_and_source() {
+        let s = TypstSetup::new("typst".into());
+        assert_ne!(cache_key(&s, "", "x", false), cache_key(&s, "", "x", true));
+        assert_ne!(cache_key(&s, "", "x", false), cache_key(&s, "", "y", false));
+        assert_ne!(cache_key(&s, "", "x", false), cache_key(&s, "#let y = 1", "x", false));
+        assert_eq!(cache_key(&s, "", "x", false).len(), 16);
+    }
+
+    #[test]
+    fn reads_svg_height() {
+        assert_eq!(svg_height_pt(br#"<svg viewBox="0 0 10 12.5" height="99pt">"#), Some(12.5));
+        assert_eq!(svg_height_pt(br#"<?xml?><svg width="3pt" height="7pt">"#), Some(7.0));
+        assert_eq!(svg_height_pt(b"<svg>"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inline_span_is_baseline_aligned_and_cached() {
+        let work = tempdir();
+        let bin = shim(&work);
+        let card = work.join("card.md");
+        fs::write(&card, "").unwrap();
+        let cache = work.join("cache");
+        let mut ctx = RenderCtx { source_path: &card, cache_dir: &cache };
+
+        let frag = run(&TypstSetup::new(bin.clone()), "a < b", false, &mut ctx).unwrap();
+        assert_eq!(frag.assets.len(), 1);
+        // 22pt tall, baseline at 16.5pt → 5.5pt (0.5em) below the line.
+        assert!(frag.html.contains("height:2.000em;vertical-align:-0.500em;"), "{}", frag.html);
+        assert!(frag.html.contains("alt=\"a &lt; b\""), "{}", frag.html);
+
+        // Cache hit with the binary gone.
+        fs::remove_file(&bin).unwrap();
+        let again = run(&TypstSetup::new(bin), "a < b", false, &mut ctx).unwrap();
+        assert_eq!(again.html, frag.html);
+        assert_eq!(again.assets[0].filename, frag.assets[0].filename);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn display_span_is_a_centred_block() {
+        let work = tempdir();
+        let bin = shim(&work);
+        let card = work.join("card.md");
+        fs::write(&card, "").unwrap();
+        let cache = work.join("cache");
+        let mut ctx = RenderCtx { source_path: &card, cache_dir: &cache };
+
+        let frag = run(&TypstSetup::new(bin), "sum_i i", true, &mut ctx).unwrap();
+        assert!(frag.html.contains("marki-typst-math-display"));
+        assert!(frag.html.contains("text-align:center"));
+        assert!(!frag.html.contains("vertical-align"));
+    }
+}
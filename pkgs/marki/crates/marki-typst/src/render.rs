@@ -4,16 +4,36 @@
 //! The cache layout mirrors `marki-map`'s — `<cache_dir>/typst/<key>/`
 //! holds `output.svg` plus a `.ready` marker. The marker is written
 //! last, so a crash mid-write is observed as a cache miss on the next
-//! run rather than a partial hit.
+//! run rather than a partial hit. Its body is the shared manifest from
+//! [`marki_render::cache`], and hits refresh its mtime.
+//!
+//! ## Reveal
+//!
+//! Every document is prefixed with `#let marki-back = false` and a
+//! `reveal(body)` helper that hides `body` (keeping its space) unless
+//! `marki-back` is true. When the block or the project preamble mentions
+//! either name, the source is compiled a second time with
+//! `marki-back = true` and the two SVGs are stacked like `marki-map`
+//! layers: the back variant sits on top, transparent on the front side,
+//! and fades in when the card is flipped. Both variants live in one
+//! cache entry (`output.svg` + `back.svg`).
+//!
+//! ## Raster
+//!
+//! When the setup rasterises, each SVG's PNG is cached beside it
+//! (`output.png`, `back.png`) and shipped in its place, with a `width`
+//! that keeps it at the SVG's natural size.
 
 use std::fs;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use marki_render::cache::{touch, write_atomic, READY_MARKER};
+use marki_render::raster::Raster;
 use marki_render::{AssetMime, Asset, RenderCtx, Fragment};
 
 use crate::error::TypstError;
+use crate::setup::TypstSetup;
 use crate::version::RENDER_VERSION_TYPST;
 
 /// Preamble prepended to every user source before compilation.
@@ -26,103 +46,283 @@ use crate::version::RENDER_VERSION_TYPST;
 /// per property.
 const PREAMBLE: &str = "#set page(width: auto, height: auto, margin: 0pt, fill: none)\n";
 
-/// Marker file that signals "this directory's contents are complete".
-const READY_MARKER: &str = ".ready";
-
 /// File name written inside the cache dir.
-const SVG_NAME: &str = "output.svg";
+pub(crate) const SVG_NAME: &str = "output.svg";
+
+/// Back-variant SVG, present only in entries for blocks that reveal.
+const BACK_SVG_NAME: &str = "back.svg";
+
+/// Rasters of the two variants, present only when the setup rasterises.
+pub(crate) const PNG_NAME: &str = "output.png";
+const BACK_PNG_NAME: &str = "back.png";
+
+/// One compiled document: the SVG and, when the setup rasterises, its
+/// PNG with the SVG's CSS width.
+pub(crate) struct Output {
+    pub(crate) svg: Vec<u8>,
+    png: Option<(Vec<u8>, u32)>,
+}
+
+impl Output {
+    /// Wrap a freshly compiled SVG, rasterising it if asked to.
+    pub(crate) fn compiled(setup: &TypstSetup, svg: Vec<u8>) -> Result<Self, TypstError> {
+        let png = match setup.raster() {
+            Some(r) => Some(with_width(r, r.png(&svg).map_err(TypstError::Raster)?)?),
+            None => None,
+        };
+        Ok(Self { svg, png })
+    }
+
+    /// Read a variant back from a ready cache entry.
+    pub(crate) fn cached(
+        setup: &TypstSetup,
+        dir: &Path,
+        svg_name: &str,
+        png_name: &str,
+    ) -> Result<Self, TypstError> {
+        let svg = fs::read(dir.join(svg_name))?;
+        let png = match setup.raster() {
+            Some(r) => Some(with_width(r, fs::read(dir.join(png_name))?)?),
+            None => None,
+        };
+        Ok(Self { svg, png })
+    }
+
+    /// Cache files for this variant.
+    pub(crate) fn files<'a>(
+        &'a self,
+        svg_name: &'a str,
+        png_name: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a [u8])> {
+        let png = self.png.as_ref().map(|(bytes, _)| (png_name, bytes.as_slice()));
+        std::iter::once((svg_name, self.svg.as_slice())).chain(png)
+    }
+
+    /// The asset to ship, and the `<img>` attributes that size it: a
+    /// `width` for PNGs, so a 2× raster doesn't display at twice the
+    /// size, and nothing for SVGs.
+    pub(crate) fn into_asset(self) -> (Asset, String) {
+        match self.png {
+            Some((bytes, width)) => (
+                Asset { filename: asset_name(&bytes, "png"), bytes, mime: AssetMime::ImagePng },
+                format!(" width=\"{width}\""),
+            ),
+            None => (
+                Asset { filename: asset_name(&self.svg, "svg"), bytes: self.svg, mime: AssetMime::SvgXml },
+                String::new(),
+            ),
+        }
+    }
+}
+
+fn with_width(raster: &Raster, png: Vec<u8>) -> Result<(Vec<u8>, u32), TypstError> {
+    let width = raster
+        .css_width(&png)
+        .ok_or_else(|| TypstError::Raster("cached raster is not a PNG".into()))?;
+    Ok((png, width))
+}
 
 /// End-to-end render: returns the [`Fragment`] the daemon
 /// splices into the card.
 pub fn run(
-    binary: &Path,
+    setup: &TypstSetup,
     src: &str,
     ctx: &mut RenderCtx<'_>,
 ) -> Result<Fragment, TypstError> {
-    let key = cache_key(src);
+    let project = setup.project_preamble()?;
+    let reveals = uses_reveal(src) || uses_reveal(&project);
+    let key = cache_key(setup, &project, src);
     let dir = cache_dir(ctx.cache_dir, &key);
 
-    let svg_bytes = if is_ready(&dir) {
-        fs::read(dir.join(SVG_NAME))?
+    let (front, back) = if is_ready(&dir) {
+        touch(&dir);
+        let front = Output::cached(setup, &dir, SVG_NAME, PNG_NAME)?;
+        let back = if reveals {
+            Some(Output::cached(setup, &dir, BACK_SVG_NAME, BACK_PNG_NAME)?)
+        } else {
+            None
+        };
+        (front, back)
     } else {
-        let bytes = compile(binary, src, ctx.source_path)?;
-        write_atomic(&dir, &bytes)?;
-        bytes
+        let front = compile(setup, &project, src, false, ctx.source_path)?;
+        let front = Output::compiled(setup, front)?;
+        let mut files: Vec<_> = front.files(SVG_NAME, PNG_NAME).collect();
+        let back = if reveals {
+            let back = compile(setup, &project, src, true, ctx.source_path)?;
+            Some(Output::compiled(setup, back)?)
+        } else {
+            None
+        };
+        if let Some(back) = &back {
+            files.extend(back.files(BACK_SVG_NAME, BACK_PNG_NAME));
+        }
+        write_atomic(&dir, &files)?;
+        (front, back)
     };
 
-    Ok(build_block(svg_bytes))
+    // A block that only mentions the helpers (or whose preamble does)
+    // may compile identically both ways; then there is nothing to reveal.
+    Ok(match back {
+        Some(back) if back.svg != front.svg => build_reveal(front, back),
+        _ => build_block(front),
+    })
+}
+
+/// Whether `src` refers to the reveal helpers, so a back variant is
+/// worth compiling.
+fn uses_reveal(src: &str) -> bool {
+    src.contains("reveal") || src.contains("marki-back")
+}
+
+/// `#let` bindings for one variant: `marki-back` and the `reveal`
+/// helper. Placed ahead of the project preamble so it can build on them.
+pub(crate) fn variant_prelude(back: bool) -> String {
+    format!(
+        "#let marki-back = {back}\n\
+         #let reveal(body) = if marki-back {{ body }} else {{ hide(body) }}\n"
+    )
 }
 
-/// Compute `blake3(RENDER_VERSION_TYPST ∥ source)`, truncated to 16
-/// hex chars. Same width as `marki-map`'s render keys.
-fn cache_key(src: &str) -> String {
+/// Compute `blake3(RENDER_VERSION_TYPST ∥ PREAMBLE ∥ setup ∥ project
+/// preamble ∥ source)`, truncated to 16 hex chars. Same width as
+/// `marki-map`'s render keys.
+fn cache_key(setup: &TypstSetup, project: &str, src: &str) -> String {
     let mut hasher = blake3::Hasher::new();
     hasher.update(&RENDER_VERSION_TYPST.to_le_bytes());
     hasher.update(PREAMBLE.as_bytes());
+    setup.hash_into(&mut hasher);
+    hash_preamble(&mut hasher, project);
     hasher.update(src.as_bytes());
     let hex = hasher.finalize().to_hex();
     hex.as_str()[..16].to_string()
 }
 
-fn cache_dir(cache_root: &Path, key: &str) -> PathBuf {
+/// Length-prefix the project preamble so it can't run into the source.
+pub(crate) fn hash_preamble(hasher: &mut blake3::Hasher, project: &str) {
+    hasher.update(&(project.len() as u64).to_le_bytes());
+    hasher.update(project.as_bytes());
+}
+
+pub(crate) fn cache_dir(cache_root: &Path, key: &str) -> PathBuf {
     cache_root.join("typst").join(key)
 }
 
-fn is_ready(dir: &Path) -> bool {
+pub(crate) fn is_ready(dir: &Path) -> bool {
     dir.join(READY_MARKER).exists()
 }
 
-/// Run `typst compile --format svg` against `src` and return the
-/// SVG bytes. The Typst project root is set to the directory of the
-/// markdown source so `#image("foo.png")` resolves relative to the
-/// card.
-fn compile(binary: &Path, src: &str, source_path: &Path) -> Result<Vec<u8>, TypstError> {
-    // Build the on-disk source: preamble + user body. We isolate this
-    // in a per-invocation temp dir so `typst compile` doesn't pollute
-    // the cwd or the cache dir.
-    // Create the temp dir inside the card's parent directory so that
-    // the input file is contained within `--root` (required by Typst
-    // ≥ 0.12) while still allowing `#image("foo.png")` to resolve
-    // relative to the card.
+/// Run `typst compile --format svg` against one variant of `src` and
+/// return the SVG bytes. The Typst project root is set to the directory
+/// of the markdown source so `#image("foo.png")` resolves relative to
+/// the card.
+fn compile(
+    setup: &TypstSetup,
+    project: &str,
+    src: &str,
+    back: bool,
+    source_path: &Path,
+) -> Result<Vec<u8>, TypstError> {
+    Ok(compile_doc(setup, &document(project, src, back), source_path, None)?.0)
+}
+
+/// `PREAMBLE`, the variant bindings, the project preamble, then the
+/// block source.
+fn document(project: &str, src: &str, back: bool) -> String {
+    let mut doc = String::with_capacity(PREAMBLE.len() + project.len() + src.len() + 128);
+    doc.push_str(PREAMBLE);
+    doc.push_str(&variant_prelude(back));
+    if !project.is_empty() {
+        doc.push_str(project);
+        if !project.ends_with('\n') {
+            doc.push('\n');
+        }
+    }
+    doc.push_str(src);
+    // Trailing newline — harmless if the user already added one, and
+    // keeps Typst happy when they didn't.
+    if !src.ends_with('\n') {
+        doc.push('\n');
+    }
+    doc
+}
+
+/// Compile a complete Typst document to SVG and, when `query` names a
+/// label, also return `typst query`'s `--field value --one` output for
+/// it (used by inline math to read back its baseline).
+pub(crate) fn compile_doc(
+    setup: &TypstSetup,
+    doc: &str,
+    source_path: &Path,
+    query: Option<&str>,
+) -> Result<(Vec<u8>, Option<String>), TypstError> {
+    // We isolate the input in a per-invocation temp dir so `typst
+    // compile` doesn't pollute the cwd or the cache dir. The temp dir
+    // lives inside the card's parent directory so that the input file
+    // is contained within `--root` (required by Typst ≥ 0.12) while
+    // still allowing `#image("foo.png")` to resolve relative to the
+    // card.
     let root = source_path.parent().unwrap_or(Path::new("."));
     let work = mktempdir_in(root)?;
+    let result = compile_in(setup, doc, root, &work, query);
+    let _ = fs::remove_dir_all(&work);
+    result
+}
+
+fn compile_in(
+    setup: &TypstSetup,
+    doc: &str,
+    root: &Path,
+    work: &Path,
+    query: Option<&str>,
+) -> Result<(Vec<u8>, Option<String>), TypstError> {
     let input = work.join("input.typ");
-    {
-        let mut h = fs::File::create(&input)?;
-        h.write_all(PREAMBLE.as_bytes())?;
-        h.write_all(src.as_bytes())?;
-        // Trailing newline — harmless if the user already added one,
-        // and keeps Typst happy when they didn't.
-        if !src.ends_with('\n') {
-            h.write_all(b"\n")?;
-        }
-    }
+    fs::write(&input, doc)?;
     let output = work.join("output.svg");
 
-    let mut cmd = Command::new(binary);
-    cmd.arg("compile")
-        .arg("--format")
+    let mut cmd = setup.command("compile");
+    cmd.arg("--format")
         .arg("svg")
         .arg("--root")
         .arg(root)
         .arg(&input)
         .arg(&output);
+    invoke(&setup.binary, cmd)?;
 
-    let result = cmd.output();
-    let outcome = match result {
+    let bytes = fs::read(&output).map_err(|e| {
+        TypstError::Compile(format!(
+            "typst exited 0 but output.svg is unreadable: {e}"
+        ))
+    })?;
+
+    let queried = match query {
+        Some(selector) => {
+            let mut cmd = setup.command("query");
+            cmd.arg("--root")
+                .arg(root)
+                .arg(&input)
+                .arg(selector)
+                .arg("--field")
+                .arg("value")
+                .arg("--one");
+            let stdout = invoke(&setup.binary, cmd)?;
+            Some(String::from_utf8_lossy(&stdout).trim().to_string())
+        }
+        None => None,
+    };
+    Ok((bytes, queried))
+}
+
+/// Run one typst subcommand; returns stdout on success.
+fn invoke(binary: &Path, mut cmd: Command) -> Result<Vec<u8>, TypstError> {
+    let outcome = match cmd.output() {
         Ok(o) => o,
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            let _ = fs::remove_dir_all(&work);
             return Err(TypstError::BinaryNotFound(binary.to_path_buf()));
         }
-        Err(e) => {
-            let _ = fs::remove_dir_all(&work);
-            return Err(TypstError::Io(e));
-        }
+        Err(e) => return Err(TypstError::Io(e)),
     };
 
     if !outcome.status.success() {
-        let _ = fs::remove_dir_all(&work);
         let stderr = String::from_utf8_lossy(&outcome.stderr).into_owned();
         let stdout = String::from_utf8_lossy(&outcome.stdout).into_owned();
         // Typst writes diagnostics to stderr; stdout is usually empty
@@ -134,65 +334,29 @@ fn compile(binary: &Path, src: &str, source_path: &Path) -> Result<Vec<u8>, Typs
         };
         return Err(TypstError::Compile(combined));
     }
-
-    let bytes = fs::read(&output).map_err(|e| {
-        TypstError::Compile(format!(
-            "typst exited 0 but output.svg is unreadable: {e}"
-        ))
-    })?;
-
-    let _ = fs::remove_dir_all(&work);
-    Ok(bytes)
-}
-
-/// Atomically populate the cache directory. The `.ready` marker is
-/// written last; a crash mid-write leaves the directory in a never-
-/// ready state that future readers treat as a miss.
-fn write_atomic(dir: &Path, svg: &[u8]) -> Result<(), TypstError> {
-    fs::create_dir_all(dir)?;
-
-    let svg_tmp = dir.join(format!(".{SVG_NAME}.tmp"));
-    {
-        let mut h = fs::File::create(&svg_tmp)?;
-        h.write_all(svg)?;
-        h.sync_all().ok();
-    }
-    fs::rename(&svg_tmp, dir.join(SVG_NAME))?;
-
-    let marker_tmp = dir.join(format!(".{READY_MARKER}.tmp"));
-    fs::File::create(&marker_tmp)?.sync_all().ok();
-    fs::rename(&marker_tmp, dir.join(READY_MARKER))?;
-
-    Ok(())
+    Ok(outcome.stdout)
 }
 
-/// Build the [`Fragment`] from a cache key + SVG bytes.
-///
-/// Build the [`Fragment`] from rendered SVG bytes.
+/// Build the [`Fragment`] from a rendered document.
 ///
 /// The asset filename is content-addressed over the output bytes
 /// (matching `marki-media`'s scheme): two blocks that compile to the
 /// same SVG dedupe in Anki's media collection. The HTML wraps the
 /// `<img>` in a centered, max-width container; final visual sizing
 /// is the theme's responsibility.
-fn build_block(svg: Vec<u8>) -> Fragment {
-    let hex = blake3::hash(&svg).to_hex();
-    let short = &hex.as_str()[..8];
-    let filename = format!("marki-typst-{short}.svg");
+fn build_block(out: Output) -> Fragment {
+    let (asset, size) = out.into_asset();
     let html = format!(
         "<div class=\"marki-typst\" style=\"max-width:100%;margin:0 auto;\">\
-         <img src=\"{filename}\" \
-         style=\"max-width:100%;height:auto;display:block;margin:0 auto;\" alt=\"\"></div>"
+         <img src=\"{filename}\"{size} \
+         style=\"max-width:100%;height:auto;display:block;margin:0 auto;\" alt=\"\"></div>",
+        filename = asset.filename,
     );
 
     Fragment {
         html,
         reveal: String::new(),
-        assets: vec![Asset {
-            filename,
-            bytes: svg,
-            mime: AssetMime::SvgXml,
-        }],
+        assets: vec![asset],
     }
 }
 
@@ -218,6 +382,39 @@ fn mktempdir() -> Result<PathBuf, TypstError> {
     mktempdir_in(&std::env::temp_dir())
 }
 
+/// Stack the two variants: the front SVG sets the size, the back SVG
+/// is laid over it, hidden until the back side's `<style>` (the
+/// fragment's `reveal`) lifts the opacity.
+fn build_reveal(front: Output, back: Output) -> Fragment {
+    let (front, size) = front.into_asset();
+    let (back, _) = back.into_asset();
+    let (front_name, back_name) = (&front.filename, &back.filename);
+    let html = format!(
+        "<div class=\"marki-typst\" \
+         style=\"position:relative;width:fit-content;max-width:100%;margin:0 auto;\">\
+         <style>.marki-typst [data-reveal=\"fade\"]{{opacity:0;transition:opacity .5s ease;}}\
+         </style>\
+         <img data-reveal=\"none\" src=\"{front_name}\"{size} \
+         style=\"max-width:100%;height:auto;display:block;\" alt=\"\">\
+         <img data-reveal=\"fade\" src=\"{back_name}\" \
+         style=\"position:absolute;inset:0;width:100%;height:100%;pointer-events:none;\" \
+         alt=\"\"></div>"
+    );
+    let reveal = "<style>.marki-typst [data-reveal=\"fade\"]{opacity:1;}</style>".to_string();
+
+    Fragment {
+        html,
+        reveal,
+        assets: vec![front, back],
+    }
+}
+
+/// Content-addressed media name for an SVG or PNG.
+pub(crate) fn asset_name(bytes: &[u8], ext: &str) -> String {
+    let hex = blake3::hash(bytes).to_hex();
+    format!("marki-typst-{}.{ext}", &hex.as_str()[..8])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,27 +433,64 @@ mod tests {
         p
     }
 
+    fn setup(binary: &Path) -> TypstSetup {
+        TypstSetup::new(binary.to_path_buf())
+    }
+
+    fn make_executable(path: &Path) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = fs::metadata(path).unwrap().permissions();
+            perms.set_mode(0o755);
+            fs::set_permissions(path, perms).unwrap();
+        }
+    }
+
     #[test]
     fn cache_key_is_stable() {
-        let a = cache_key("= hello");
-        let b = cache_key("= hello");
+        let s = setup(Path::new("typst"));
+        let a = cache_key(&s, "", "= hello");
+        let b = cache_key(&s, "", "= hello");
         assert_eq!(a, b);
         assert_eq!(a.len(), 16);
     }
 
     #[test]
     fn cache_key_changes_with_source() {
-        assert_ne!(cache_key("= hello"), cache_key("= goodbye"));
+        let s = setup(Path::new("typst"));
+        assert_ne!(cache_key(&s, "", "= hello"), cache_key(&s, "", "= goodbye"));
+    }
+
+    #[test]
+    fn cache_key_changes_with_project_preamble() {
+        let s = setup(Path::new("typst"));
+        assert_ne!(cache_key(&s, "", "= hi"), cache_key(&s, "#let x = 1", "= hi"));
+        // The boundary between preamble and source is part of the key.
+        assert_ne!(cache_key(&s, "a", "b"), cache_key(&s, "", "ab"));
+    }
+
+    #[test]
+    fn document_orders_preamble_layers() {
+        let doc = document("#let x = 1", "= hi", true);
+        let page = doc.find("#set page").unwrap();
+        let flag = doc.find("#let marki-back = true").unwrap();
+        let project = doc.find("#let x = 1").unwrap();
+        let src = doc.find("= hi").unwrap();
+        assert!(page < flag && flag < project && project < src, "{doc}");
+        assert!(document("", "= hi", false).contains("#let marki-back = false"));
     }
 
     #[test]
     fn write_atomic_marks_ready() {
         let root = tempdir();
         let dir = cache_dir(&root, "deadbeef00000000");
-        write_atomic(&dir, b"<svg/>").unwrap();
+        write_atomic(&dir, &[(SVG_NAME, b"<svg/>")]).unwrap();
         assert!(is_ready(&dir));
         let got = fs::read(dir.join(SVG_NAME)).unwrap();
         assert_eq!(got, b"<svg/>");
+        let marker = fs::read_to_string(dir.join(READY_MARKER)).unwrap();
+        assert!(marker.ends_with(&format!("  {SVG_NAME}\n")), "{marker}");
     }
 
     #[test]
@@ -268,9 +502,24 @@ mod tests {
         assert!(!is_ready(&dir));
     }
 
+    fn svg_only(svg: &[u8]) -> Output {
+        Output { svg: svg.to_vec(), png: None }
+    }
+
+    #[test]
+    fn rasterised_block_ships_a_sized_png() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="10"><rect width="40" height="10"/></svg>"##;
+        let setup = setup(Path::new("typst")).with_raster(Raster::default());
+        let block = build_block(Output::compiled(&setup, svg.to_vec()).unwrap());
+        assert_eq!(block.assets.len(), 1);
+        assert_eq!(block.assets[0].mime, AssetMime::ImagePng);
+        assert!(block.assets[0].filename.ends_with(".png"));
+        assert!(block.html.contains(" width=\"40\""), "{}", block.html);
+    }
+
     #[test]
     fn build_block_emits_one_asset() {
-        let block = build_block(b"<svg/>".to_vec());
+        let block = build_block(svg_only(b"<svg/>"));
         assert_eq!(block.assets.len(), 1);
         assert!(block.assets[0].filename.starts_with("marki-typst-"));
         assert!(block.assets[0].filename.ends_with(".svg"));
@@ -281,9 +530,9 @@ mod tests {
 
     #[test]
     fn build_block_filename_is_content_addressed() {
-        let a = build_block(b"<svg>same</svg>".to_vec());
-        let b = build_block(b"<svg>same</svg>".to_vec());
-        let c = build_block(b"<svg>different</svg>".to_vec());
+        let a = build_block(svg_only(b"<svg>same</svg>"));
+        let b = build_block(svg_only(b"<svg>same</svg>"));
+        let c = build_block(svg_only(b"<svg>different</svg>"));
         assert_eq!(a.assets[0].filename, b.assets[0].filename);
         assert_ne!(a.assets[0].filename, c.assets[0].filename);
     }
@@ -298,7 +547,7 @@ mod tests {
             cache_dir: &work,
         };
         let r = run(
-            Path::new("/definitely/does/not/exist/typst-binary"),
+            &setup(Path::new("/definitely/does/not/exist/typst-binary")),
             "= hi",
             &mut ctx,
         );
@@ -343,13 +592,13 @@ mod tests {
             source_path: &card,
             cache_dir: &cache,
         };
-        let block = run(&shim, "= ignored", &mut ctx).unwrap();
+        let block = run(&setup(&shim), "= ignored", &mut ctx).unwrap();
         assert_eq!(block.assets.len(), 1);
         assert_eq!(block.assets[0].bytes, b"<svg data-shim=\"yes\"/>");
 
         // Second run: cache hit, even if the binary is removed.
         fs::remove_file(&shim).unwrap();
-        let block2 = run(&shim, "= ignored", &mut ctx).unwrap();
+        let block2 = run(&setup(&shim), "= ignored", &mut ctx).unwrap();
         assert_eq!(block2.assets[0].bytes, b"<svg data-shim=\"yes\"/>");
         assert_eq!(block.assets[0].filename, block2.assets[0].filename);
     }
@@ -379,10 +628,81 @@ mod tests {
             source_path: &card,
             cache_dir: &cache,
         };
-        let err = run(&shim, "broken source", &mut ctx).unwrap_err();
+        let err = run(&setup(&shim), "broken source", &mut ctx).unwrap_err();
         match err {
             TypstError::Compile(msg) => assert!(msg.contains("bad syntax")),
             other => panic!("expected Compile, got {other:?}"),
         }
     }
+
+    /// Shim that writes a different SVG for the back variant, so the
+    /// reveal path can be told apart from the plain one.
+    fn variant_shim(work: &Path) -> PathBuf {
+        let shim = work.join("typst-variants.sh");
+        fs::write(
+            &shim,
+            "#!/bin/sh\nset -e\neval \"out=\\${$#}\"\nn=$(($# - 1))\neval \"in=\\${$n}\"\n\
+             if grep -q 'marki-back = true' \"$in\"; then v=back; else v=front; fi\n\
+             printf '<svg data-variant=\"%s\"/>' \"$v\" > \"$out\"\n",
+        )
+        .unwrap();
+        make_executable(&shim);
+        shim
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reveal_blocks_stack_front_and_back() {
+        let work = tempdir();
+        let shim = variant_shim(&work);
+        let card = work.join("card.md");
+        fs::write(&card, "").unwrap();
+        let cache = work.join("cache");
+        let mut ctx = RenderCtx { source_path: &card, cache_dir: &cache };
+
+        let src = "Q: $1 + 1$ #reveal[$= 2$]";
+        let block = run(&setup(&shim), src, &mut ctx).unwrap();
+        assert_eq!(block.assets.len(), 2);
+        assert_eq!(block.assets[0].bytes, b"<svg data-variant=\"front\"/>");
+        assert_eq!(block.assets[1].bytes, b"<svg data-variant=\"back\"/>");
+        assert!(block.html.contains("data-reveal=\"fade\""), "{}", block.html);
+        assert!(block.reveal.contains("opacity:1"));
+
+        // Both variants come back from the cache.
+        fs::remove_file(&shim).unwrap();
+        let again = run(&setup(&shim), src, &mut ctx).unwrap();
+        assert_eq!(again.html, block.html);
+        assert_eq!(again.assets[1].bytes, block.assets[1].bytes);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn project_preamble_is_spliced_and_keyed() {
+        let work = tempdir();
+        // Echo the compiled document back as the "SVG".
+        let shim = work.join("typst-echo.sh");
+        fs::write(
+            &shim,
+            "#!/bin/sh\nset -e\neval \"out=\\${$#}\"\nn=$(($# - 1))\neval \"in=\\${$n}\"\n\
+             cat \"$in\" > \"$out\"\n",
+        )
+        .unwrap();
+        make_executable(&shim);
+        let preamble = work.join("preamble.typ");
+        fs::write(&preamble, "#let shout(x) = upper(x)\n").unwrap();
+        let card = work.join("card.md");
+        fs::write(&card, "").unwrap();
+        let cache = work.join("cache");
+        let mut ctx = RenderCtx { source_path: &card, cache_dir: &cache };
+
+        let s = setup(&shim).with_preamble_file(preamble.clone());
+        let first = run(&s, "#shout[hi]", &mut ctx).unwrap();
+        let doc = String::from_utf8(first.assets[0].bytes.clone()).unwrap();
+        assert!(doc.contains("#let shout(x) = upper(x)\n#shout[hi]"), "{doc}");
+
+        // Editing the preamble misses the cache.
+        fs::write(&preamble, "#let shout(x) = lower(x)\n").unwrap();
+        let second = run(&s, "#shout[hi]", &mut ctx).unwrap();
+        assert_ne!(first.assets[0].filename, second.assets[0].filename);
+    }
 }
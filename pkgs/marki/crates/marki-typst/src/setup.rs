@@ -0,0 +1,167 @@
+//! How Typst is invoked for a project: the binary, extra font and
+//! package directories, the shared preamble file, and whether the SVGs
+//! are rasterised to PNG.
+//!
+//! Both renderers hold one [`TypstSetup`]. Everything in it that can
+//! change the compiled output is folded into the cache key, so editing
+//! the preamble or pointing at another font directory re-renders the
+//! affected cards instead of serving stale SVGs.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use marki_render::raster::Raster;
+
+use crate::error::TypstError;
+
+/// Conventional preamble location, relative to the project's Lua/Typst
+/// library directory (`.marki/lib/`).
+pub const PREAMBLE_FILE_NAME: &str = "preamble.typ";
+
+#[derive(Debug, Clone)]
+pub struct TypstSetup {
+    /// Path to the `typst` CLI binary.
+    pub(crate) binary: PathBuf,
+    /// Extra font directories (`typst --font-path`), searched alongside
+    /// system fonts.
+    font_paths: Vec<PathBuf>,
+    /// Local package directory (`typst --package-path`), for `@local/…`
+    /// packages and vendored copies of `@preview/…` ones.
+    package_path: Option<PathBuf>,
+    /// Project preamble spliced into every document. Read on each render
+    /// so edits take effect without restarting `marki watch`.
+    preamble_file: Option<PathBuf>,
+    /// Ship PNGs rasterised from the SVGs instead of the SVGs.
+    raster: Option<Raster>,
+}
+
+impl TypstSetup {
+    pub fn new(binary: PathBuf) -> Self {
+        Self {
+            binary,
+            font_paths: Vec::new(),
+            package_path: None,
+            preamble_file: None,
+            raster: None,
+        }
+    }
+
+    pub fn with_font_paths(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.font_paths = dirs;
+        self
+    }
+
+    pub fn with_package_path(mut self, dir: PathBuf) -> Self {
+        self.package_path = Some(dir);
+        self
+    }
+
+    /// Use `path` as the project preamble. A missing file is not an
+    /// error — it just means there is no preamble yet.
+    pub fn with_preamble_file(mut self, path: PathBuf) -> Self {
+        self.preamble_file = Some(path);
+        self
+    }
+
+    /// Rasterise every compiled SVG to a PNG at `raster.scale` and ship
+    /// that instead. Both are cached.
+    pub fn with_raster(mut self, raster: Raster) -> Self {
+        self.raster = Some(raster);
+        self
+    }
+
+    pub(crate) fn raster(&self) -> Option<&Raster> {
+        self.raster.as_ref()
+    }
+
+    /// Current project preamble source, or `""` when none is configured
+    /// or the file does not exist.
+    pub(crate) fn project_preamble(&self) -> Result<String, TypstError> {
+        let Some(path) = &self.preamble_file else {
+            return Ok(String::new());
+        };
+        match fs::read_to_string(path) {
+            Ok(s) => Ok(s),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(TypstError::Io(e)),
+        }
+    }
+
+    /// `typst <subcommand>` with the font and package flags applied.
+    pub(crate) fn command(&self, subcommand: &str) -> Command {
+        let mut cmd = Command::new(&self.binary);
+        cmd.arg(subcommand);
+        for dir in &self.font_paths {
+            cmd.arg("--font-path").arg(dir);
+        }
+        if let Some(dir) = &self.package_path {
+            cmd.arg("--package-path").arg(dir);
+        }
+        cmd
+    }
+
+    /// Feed the output-affecting settings (font and package dirs, raster
+    /// scale) to a cache-key hasher. The preamble is hashed by the caller
+    /// alongside the source, since it has already been read by then.
+    ///
+    /// Paths, not directory contents, are hashed: adding a font to an
+    /// already-configured directory does not invalidate the cache.
+    pub(crate) fn hash_into(&self, hasher: &mut blake3::Hasher) {
+        for dir in &self.font_paths {
+            hasher.update(b"font-path\0");
+            hasher.update(dir.as_os_str().as_encoded_bytes());
+            hasher.update(b"\0");
+        }
+        if let Some(dir) = &self.package_path {
+            hasher.update(b"package-path\0");
+            hasher.update(dir.as_os_str().as_encoded_bytes());
+            hasher.update(b"\0");
+        }
+        if let Some(raster) = &self.raster {
+            raster.hash_into(hasher);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(setup: &TypstSetup) -> blake3::Hash {
+        let mut h = blake3::Hasher::new();
+        setup.hash_into(&mut h);
+        h.finalize()
+    }
+
+    #[test]
+    fn flags_follow_the_subcommand() {
+        let setup = TypstSetup::new(PathBuf::from("typst"))
+            .with_font_paths(vec![PathBuf::from("/f1"), PathBuf::from("/f2")])
+            .with_package_path(PathBuf::from("/pkgs"));
+        let cmd = setup.command("compile");
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+        assert_eq!(
+            args,
+            ["compile", "--font-path", "/f1", "--font-path", "/f2", "--package-path", "/pkgs"]
+        );
+    }
+
+    #[test]
+    fn font_and_package_dirs_change_the_key() {
+        let bare = TypstSetup::new(PathBuf::from("typst"));
+        let fonts = bare.clone().with_font_paths(vec![PathBuf::from("/f")]);
+        let pkgs = bare.clone().with_package_path(PathBuf::from("/f"));
+        assert_ne!(key(&bare), key(&fonts));
+        assert_ne!(key(&fonts), key(&pkgs));
+        let png = bare.clone().with_raster(Raster::default());
+        assert_ne!(key(&bare), key(&png));
+    }
+
+    #[test]
+    fn missing_preamble_file_is_empty() {
+        let setup = TypstSetup::new(PathBuf::from("typst"))
+            .with_preamble_file(PathBuf::from("/definitely/not/here/preamble.typ"));
+        assert_eq!(setup.project_preamble().unwrap(), "");
+    }
+}
@@ -7,4 +7,6 @@
 
 pub mod model;
 
-pub use model::{MARKER_TAG, ORPHAN_TAG, full_tag_set, hash_from_tags, strip_marker};
+pub use model::{
+    MARKER_TAG, ORPHAN_TAG, full_tag_set, hash_from_tags, inputs_from_tags, strip_marker,
+};
@@ -6,6 +6,13 @@
 //!     direct-SQLite writer sets `notes.guid`), so identity is no longer
 //!     carried in a tag.
 //!   * content hash -> stored as a tag `marki::hash:<hex>` on the note.
+//!   * input fingerprints -> `marki::inputs:<source>.<script>.<renderers>`,
+//!     recording what the stored fields were rendered from (see
+//!     [`crate::sync::diff::Inputs`]).
+//!   * prerequisite gating -> `marki::gated` on a note held back by
+//!     `#requires(...)`.
+//!   * owning target -> `marki::target:<name>` on notes a named target wrote
+//!     (see [`crate::sync::target`]).
 //!
 //! Every managed note also carries the marker tag `marki`, so a whole-tag
 //! match on ` marki ` returns exactly the set we are responsible for.
@@ -17,6 +24,14 @@ pub const MARKER_TAG: &str = "marki";
 /// Prefix of the content-hash tag: full form is `marki::hash:<16 hex>`.
 pub const HASH_TAG_PREFIX: &str = "marki::hash:";
 
+/// Prefix of the input-fingerprint tag: full form is
+/// `marki::inputs:<8 hex>.<8 hex>.<8 hex>`.
+pub const INPUTS_TAG_PREFIX: &str = "marki::inputs:";
+
+/// Prefix of the target tag: full form is `marki::target:<name>`. Notes
+/// the unnamed (top-level) target writes carry none.
+pub const TARGET_TAG_PREFIX: &str = "marki::target:";
+
 /// Tag applied to a note that has been quarantined (soft-deleted): it no
 /// longer has a matching `.md` source, so it was suspended and tagged
 /// rather than deleted. `tag:marki::orphan` lists them, and `marki prune`
@@ -25,6 +40,11 @@ pub const HASH_TAG_PREFIX: &str = "marki::hash:";
 /// reappears and the note is updated.
 pub const ORPHAN_TAG: &str = "marki::orphan";
 
+/// Tag on a note whose cards marki suspended because a `#requires(...)`
+/// prerequisite is not learned yet. Only notes carrying it are unsuspended
+/// again, so cards the user suspended stay suspended.
+pub const GATED_TAG: &str = "marki::gated";
+
 /// Remove the marker tag and every `marki::*` tag from a list.
 pub fn strip_marker(tags: &[String]) -> Vec<String> {
     tags.iter()
@@ -33,13 +53,15 @@ pub fn strip_marker(tags: &[String]) -> Vec<String> {
         .collect()
 }
 
-/// Produce the full tag set to store on a note: marker tag + hash tag + user
-/// tags (with any stray marker-namespace tags filtered out). Identity lives
-/// in the note's `guid`, not in a tag, so it is absent here.
-pub fn full_tag_set(user_tags: &[String], hash: &str) -> Vec<String> {
-    let mut out = Vec::with_capacity(user_tags.len() + 2);
+/// Produce the full tag set to store on a note: marker tag + hash tag +
+/// inputs tag + user tags (with any stray marker-namespace tags filtered
+/// out). Identity lives in the note's `guid`, not in a tag, so it is absent
+/// here.
+pub fn full_tag_set(user_tags: &[String], hash: &str, inputs: &str) -> Vec<String> {
+    let mut out = Vec::with_capacity(user_tags.len() + 3);
     out.push(MARKER_TAG.to_string());
     out.push(format!("{HASH_TAG_PREFIX}{hash}"));
+    out.push(format!("{INPUTS_TAG_PREFIX}{inputs}"));
     for t in user_tags {
         if !is_marker_tag(t) {
             out.push(t.clone());
@@ -54,6 +76,12 @@ pub fn hash_from_tags(tags: &[String]) -> Option<String> {
         .find_map(|t| t.strip_prefix(HASH_TAG_PREFIX).map(String::from))
 }
 
+/// Extract the input fingerprints from a note's tag list, if recorded.
+pub fn inputs_from_tags(tags: &[String]) -> Option<String> {
+    tags.iter()
+        .find_map(|t| t.strip_prefix(INPUTS_TAG_PREFIX).map(String::from))
+}
+
 fn is_marker_tag(tag: &str) -> bool {
     tag == MARKER_TAG || tag.starts_with("marki::")
 }
@@ -66,10 +94,11 @@ mod tests {
     fn full_tag_set_round_trip() {
         let user = vec!["geography".to_string(), "europe".to_string()];
         let hash = "a1b2c3d4e5f60718";
-        let full = full_tag_set(&user, hash);
+        let full = full_tag_set(&user, hash, "00000000.11111111.22222222");
 
         assert!(full.contains(&"marki".to_string()));
         assert!(full.contains(&format!("marki::hash:{hash}")));
+        assert_eq!(inputs_from_tags(&full).as_deref(), Some("00000000.11111111.22222222"));
 
         let back_user = strip_marker(&full);
         assert_eq!(user, back_user);
@@ -92,7 +121,7 @@ mod tests {
     #[test]
     fn stray_marker_tags_in_input_dont_duplicate_on_build() {
         let already = vec!["marki".into(), "x".into()];
-        let full = full_tag_set(&already, "deadbeefdeadbeef");
+        let full = full_tag_set(&already, "deadbeefdeadbeef", "");
         assert_eq!(full.iter().filter(|t| t.as_str() == "marki").count(), 1);
     }
 }
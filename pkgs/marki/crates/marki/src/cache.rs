@@ -0,0 +1,327 @@
+//! `marki cache` — inspect and trim the renderer cache dir.
+//!
+//! Each renderer owns one namespace under the cache root (see
+//! [`NAMESPACES`]). Directory namespaces hold one `<key>/` per render,
+//! completed by a `.ready` marker whose body is a content manifest and
+//! whose mtime is the entry's "last used" time (see
+//! [`marki_render::cache`]). That is all this module relies on, so it
+//! never needs to know how a renderer derives its keys:
+//!
+//!   * **stats** sums entries and bytes per namespace;
+//!   * **prune** deletes entries whose marker is older than a cut-off.
+//!     The caller picks the cut-off — "now, after a render pass that
+//!     touched every live entry" removes everything no current card
+//!     references;
+//!   * **verify** re-hashes every ready entry against its manifest.
+//!
+//! The Overpass response cache is reported but never pruned: it is
+//! source data rather than render output, and offline builds
+//! (`MARKI_OVERPASS=offline`) depend on it.
+
+use anyhow::{Context, Result};
+use marki_render::cache::{content_digest, parse_ready_manifest, READY_MARKER};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// How a namespace stores its entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// `<key>/` directories with a `.ready` marker. Prunable, verifiable.
+    Dir,
+    /// Plain files (e.g. cached HTTP responses). Reported only.
+    File,
+}
+
+/// One renderer's corner of the cache root.
+#[derive(Debug)]
+pub struct Namespace {
+    /// Renderer / source name shown to the user.
+    pub name: &'static str,
+    /// Path below the cache root.
+    pub dir: &'static str,
+    pub kind: EntryKind,
+}
+
+/// Every namespace a renderer writes under the cache root.
+pub const NAMESPACES: &[Namespace] = &[
+    Namespace { name: "map", dir: "render", kind: EntryKind::Dir },
+    Namespace { name: "typst", dir: "typst", kind: EntryKind::Dir },
+    Namespace { name: "media", dir: "media", kind: EntryKind::Dir },
+    Namespace { name: "tts", dir: "tts", kind: EntryKind::Dir },
+    Namespace { name: "overpass", dir: "net/overpass", kind: EntryKind::File },
+];
+
+/// Size of one namespace.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Usage {
+    /// Ready entries (or files, for [`EntryKind::File`]).
+    pub entries: usize,
+    /// Entry dirs without a `.ready` marker: crashed or in-flight writes.
+    pub incomplete: usize,
+    pub bytes: u64,
+}
+
+/// Per-namespace usage, in [`NAMESPACES`] order. Missing namespaces
+/// report zero.
+pub fn stats(cache_root: &Path) -> Result<Vec<(&'static Namespace, Usage)>> {
+    let mut out = Vec::new();
+    for ns in NAMESPACES {
+        let mut usage = Usage::default();
+        for entry in entries(cache_root, ns)? {
+            usage.bytes += disk_size(&entry)?;
+            match ns.kind {
+                EntryKind::Dir if !entry.join(READY_MARKER).exists() => usage.incomplete += 1,
+                _ => usage.entries += 1,
+            }
+        }
+        out.push((ns, usage));
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Default)]
+pub struct PruneOutcome {
+    pub removed: usize,
+    pub freed_bytes: u64,
+    pub kept: usize,
+}
+
+/// Delete every directory entry last used before `cutoff`: ready entries
+/// by their marker's mtime, incomplete ones by the directory's own mtime
+/// (so a write in flight right now is left alone). `dry_run` only counts.
+pub fn prune(cache_root: &Path, cutoff: SystemTime, dry_run: bool) -> Result<PruneOutcome> {
+    let mut outcome = PruneOutcome::default();
+    for ns in NAMESPACES.iter().filter(|ns| ns.kind == EntryKind::Dir) {
+        for entry in entries(cache_root, ns)? {
+            let marker = entry.join(READY_MARKER);
+            let stamp = if marker.exists() { &marker } else { &entry };
+            let last_used = fs::metadata(stamp)
+                .and_then(|m| m.modified())
+                .with_context(|| format!("stat {}", stamp.display()))?;
+            if last_used >= cutoff {
+                outcome.kept += 1;
+                continue;
+            }
+            outcome.freed_bytes += disk_size(&entry)?;
+            outcome.removed += 1;
+            if !dry_run {
+                fs::remove_dir_all(&entry)
+                    .with_context(|| format!("remove {}", entry.display()))?;
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyOutcome {
+    /// Entries whose every manifest line matched.
+    pub ok: usize,
+    /// Ready entries with an empty marker (written before manifests).
+    pub legacy: usize,
+    /// Entries that failed, with the reason.
+    pub corrupt: Vec<(PathBuf, String)>,
+    /// Corrupt entries deleted (`fix` only).
+    pub removed: usize,
+}
+
+/// Re-hash every ready directory entry against its manifest. With
+/// `fix`, corrupt entries are deleted so the next push re-renders them.
+pub fn verify(cache_root: &Path, fix: bool) -> Result<VerifyOutcome> {
+    let mut outcome = VerifyOutcome::default();
+    for ns in NAMESPACES.iter().filter(|ns| ns.kind == EntryKind::Dir) {
+        for entry in entries(cache_root, ns)? {
+            let marker = entry.join(READY_MARKER);
+            let body = match fs::read_to_string(&marker) {
+                Ok(b) => b,
+                Err(_) => continue, // incomplete; `prune` handles these
+            };
+            let manifest = parse_ready_manifest(&body);
+            if manifest.is_empty() {
+                outcome.legacy += 1;
+                continue;
+            }
+            match check_manifest(&entry, &manifest) {
+                None => outcome.ok += 1,
+                Some(reason) => {
+                    if fix {
+                        fs::remove_dir_all(&entry)
+                            .with_context(|| format!("remove {}", entry.display()))?;
+                        outcome.removed += 1;
+                    }
+                    outcome.corrupt.push((entry, reason));
+                }
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+/// First mismatch between `dir` and its manifest, if any.
+fn check_manifest(dir: &Path, manifest: &[(String, String)]) -> Option<String> {
+    for (digest, name) in manifest {
+        let bytes = match fs::read(dir.join(name)) {
+            Ok(b) => b,
+            Err(e) => return Some(format!("{name}: {e}")),
+        };
+        if content_digest(&bytes) != *digest {
+            return Some(format!("{name}: content does not match manifest"));
+        }
+    }
+    None
+}
+
+/// Entries of one namespace (dirs or files per its kind), sorted.
+fn entries(cache_root: &Path, ns: &Namespace) -> Result<Vec<PathBuf>> {
+    let dir = cache_root.join(ns.dir);
+    let rd = match fs::read_dir(&dir) {
+        Ok(rd) => rd,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("read {}", dir.display())),
+    };
+    let mut out = Vec::new();
+    for ent in rd {
+        let ent = ent.with_context(|| format!("read {}", dir.display()))?;
+        let ty = ent.file_type()?;
+        let wanted = match ns.kind {
+            EntryKind::Dir => ty.is_dir(),
+            EntryKind::File => ty.is_file(),
+        };
+        if wanted {
+            out.push(ent.path());
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// Apparent size of a file, or of every file below a directory.
+fn disk_size(path: &Path) -> Result<u64> {
+    let meta = fs::symlink_metadata(path).with_context(|| format!("stat {}", path.display()))?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut total = 0;
+    for ent in fs::read_dir(path).with_context(|| format!("read {}", path.display()))? {
+        total += disk_size(&ent?.path())?;
+    }
+    Ok(total)
+}
+
+/// `1536` → `1.5 KiB`.
+pub fn human_bytes(n: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut v = n as f64;
+    let mut unit = 0;
+    while v >= 1024.0 && unit + 1 < UNITS.len() {
+        v /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{n} B")
+    } else {
+        format!("{v:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use marki_render::cache::ready_manifest;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+
+    static N: AtomicU64 = AtomicU64::new(0);
+
+    fn tempdir() -> PathBuf {
+        let p = std::env::temp_dir().join(format!(
+            "marki-cache-cmd-{}-{}",
+            std::process::id(),
+            N.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&p);
+        fs::create_dir_all(&p).unwrap();
+        p
+    }
+
+    /// Write a ready entry `<ns>/<key>/` holding one file.
+    fn entry(root: &Path, ns: &str, key: &str, name: &str, bytes: &[u8]) -> PathBuf {
+        let dir = root.join(ns).join(key);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(name), bytes).unwrap();
+        fs::write(dir.join(READY_MARKER), ready_manifest(&[(name, bytes)])).unwrap();
+        dir
+    }
+
+    fn age(path: &Path, by: Duration) {
+        let f = fs::File::options().write(true).open(path).unwrap();
+        f.set_modified(SystemTime::now() - by).unwrap();
+    }
+
+    #[test]
+    fn stats_counts_per_namespace() {
+        let root = tempdir();
+        entry(&root, "render", "aaaa", "base.svg", b"12345");
+        entry(&root, "typst", "bbbb", "output.svg", b"123");
+        fs::create_dir_all(root.join("render").join("cccc")).unwrap();
+        fs::create_dir_all(root.join("net/overpass")).unwrap();
+        fs::write(root.join("net/overpass/x.json"), b"{}").unwrap();
+
+        let s = stats(&root).unwrap();
+        let by_name = |n: &str| s.iter().find(|(ns, _)| ns.name == n).unwrap().1.clone();
+        let map = by_name("map");
+        assert_eq!((map.entries, map.incomplete), (1, 1));
+        assert!(map.bytes >= 5);
+        assert_eq!(by_name("typst").entries, 1);
+        assert_eq!(by_name("overpass"), Usage { entries: 1, incomplete: 0, bytes: 2 });
+    }
+
+    #[test]
+    fn prune_removes_entries_unused_since_cutoff() {
+        let root = tempdir();
+        let old = entry(&root, "render", "old0", "base.svg", b"old");
+        let fresh = entry(&root, "typst", "new0", "output.svg", b"new");
+        age(&old.join(READY_MARKER), Duration::from_secs(3600));
+        fs::create_dir_all(root.join("net/overpass")).unwrap();
+        fs::write(root.join("net/overpass/x.json"), b"{}").unwrap();
+        let cutoff = SystemTime::now() - Duration::from_secs(60);
+
+        let dry = prune(&root, cutoff, true).unwrap();
+        assert_eq!((dry.removed, dry.kept), (1, 1));
+        assert!(old.exists());
+
+        let done = prune(&root, cutoff, false).unwrap();
+        assert_eq!(done.removed, 1);
+        assert!(!old.exists());
+        assert!(fresh.exists());
+        assert!(root.join("net/overpass/x.json").exists(), "overpass is never pruned");
+    }
+
+    #[test]
+    fn verify_flags_and_fixes_corrupt_entries() {
+        let root = tempdir();
+        entry(&root, "render", "good", "base.svg", b"<svg/>");
+        let bad = entry(&root, "render", "bad0", "base.svg", b"<svg/>");
+        fs::write(bad.join("base.svg"), b"<svg>tampered</svg>").unwrap();
+        let legacy = root.join("typst").join("leg0");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join(READY_MARKER), b"").unwrap();
+
+        let v = verify(&root, false).unwrap();
+        assert_eq!((v.ok, v.legacy, v.corrupt.len()), (1, 1, 1));
+        assert!(v.corrupt[0].1.contains("does not match"), "{:?}", v.corrupt);
+        assert!(bad.exists());
+
+        let v = verify(&root, true).unwrap();
+        assert_eq!(v.removed, 1);
+        assert!(!bad.exists());
+    }
+
+    #[test]
+    fn human_bytes_units() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
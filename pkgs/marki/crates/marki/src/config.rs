@@ -7,7 +7,8 @@
 //! `config.toml`, `models/`, `lib/`, and `media/` — lives inside `.marki/`,
 //! so a flashcard repo is fully self-contained. The collection it writes to
 //! is a `.anki2` file (with a sibling `media/` dir and `media.db`) named by
-//! the `collection` key.
+//! the `collection` key. Named `[targets.<name>]` sections publish a subset
+//! of the cards into further collections, one per `--target`.
 //!
 //! Config precedence: `--config` / `$MARKI_CONFIG` win; otherwise the
 //! nearest `.marki/config.toml`; otherwise the legacy global
@@ -53,6 +54,23 @@ pub struct Config {
     #[serde(default)]
     pub collection: Option<PathBuf>,
 
+    /// `[sync_server]`: reach the collection through a sync server instead
+    /// of the `collection` file, for servers marki has no shell on. Takes
+    /// precedence over `collection` when set.
+    #[serde(default)]
+    pub sync_server: Option<SyncServerConfig>,
+
+    /// `[targets.<name>]`: further collections this repo publishes into,
+    /// each a subset of the cards under its own deck prefix. Chosen with
+    /// `--target <name>`; without it, `collection`/`sync_server` is used.
+    #[serde(default)]
+    pub targets: IndexMap<String, TargetConfig>,
+
+    /// The `[targets]` entry selected with `--target`, if any. Never read
+    /// from TOML; see [`select_target`](Self::select_target).
+    #[serde(skip)]
+    pub target: Option<String>,
+
     /// Seconds between reconciliation heartbeat passes in watch mode.
     #[serde(default = "default_sync_interval", with = "duration_secs")]
     pub sync_interval: Duration,
@@ -61,7 +79,18 @@ pub struct Config {
     #[serde(default = "default_debounce_ms")]
     pub debounce_ms: u64,
 
-    /// Named media SVG/audio sources for the `media` block renderer.
+    /// Review interval, in days, every card of a `#requires(...)`
+    /// prerequisite needs before the notes requiring it are unsuspended.
+    /// Default 21, Anki's threshold for a mature card.
+    #[serde(default = "default_prerequisite_interval")]
+    pub prerequisite_interval: u32,
+
+    /// Unix socket `marki watch` listens on for `marki ctl`. Default: one
+    /// per repo and target under `$XDG_RUNTIME_DIR/marki/`.
+    #[serde(default)]
+    pub control_socket: Option<PathBuf>,
+
+    /// Named image/audio/video sources for the `media` block renderer.
     /// Each key is a source name usable as a prefix in the DSL
     /// (`src = "circle/de"`), and the value is the directory containing
     /// the media files. Order matters: when no prefix is given, sources
@@ -70,20 +99,69 @@ pub struct Config {
     #[serde(default)]
     pub media_sources: IndexMap<String, PathBuf>,
 
-    /// Path to the `typst` CLI binary used to render `typst` blocks.
-    /// When `None`, ` ```typst ` blocks fall through to syntax highlighting.
+    /// Path to the `typst` CLI binary used to render `typst` blocks and
+    /// `$…$` math. When `None`, ` ```typst ` blocks fall through to syntax
+    /// highlighting and math is left to MathJax.
     /// The user controls how Typst is installed (with which fonts,
     /// packages, or pinned version) — marki just invokes whatever path
     /// is configured here.
     #[serde(default)]
     pub typst_binary: Option<PathBuf>,
 
+    /// Extra font directories handed to Typst (`--font-path`), searched
+    /// alongside system fonts. Relative paths resolve against the
+    /// project root.
+    #[serde(default)]
+    pub typst_font_paths: Vec<PathBuf>,
+
+    /// Local Typst package directory (`--package-path`) for `@local/…`
+    /// packages or vendored `@preview/…` ones. Relative paths resolve
+    /// against the project root.
+    #[serde(default)]
+    pub typst_package_path: Option<PathBuf>,
+
+    /// ffmpeg-compatible binary the `media` renderer uses to clip audio
+    /// it can't cut losslessly in-process (OGG, M4A). WAV and MP3 clips
+    /// never need it. When `None`, such clips fail with a hint.
+    #[serde(default)]
+    pub audio_encoder: Option<PathBuf>,
+
+    /// Speech synthesiser (`espeak-ng` or `piper`) used to render `tts`
+    /// blocks. When `None`, ` ```tts ` blocks fall through to syntax
+    /// highlighting.
+    #[serde(default)]
+    pub tts_binary: Option<PathBuf>,
+
+    /// Command-line dialect of `tts_binary`. Guessed from its file name
+    /// when unset.
+    #[serde(default)]
+    pub tts_engine: Option<marki_tts::TtsEngine>,
+
+    /// Default voice per language code, for `tts` blocks that give a
+    /// `lang` but no `voice`.
+    #[serde(default)]
+    pub tts_voices: IndexMap<String, String>,
+
+    /// Directory Piper voice names resolve against. Relative paths
+    /// resolve against the project root.
+    #[serde(default)]
+    pub tts_voice_dir: Option<PathBuf>,
+
     /// Project-level defaults and path rules for `map` blocks. Merged
     /// underneath each card's own block (the author always wins). See
     /// [`marki_map::MapDefaults`].
     #[serde(default)]
     pub map: marki_map::MapDefaults,
 
+    /// `[highlight]`: theme, extra grammars and line numbering for
+    /// syntax-highlighted code blocks.
+    #[serde(default)]
+    pub highlight: HighlightConfig,
+
+    /// `[render]`: output settings shared by the image renderers.
+    #[serde(default)]
+    pub render: RenderConfig,
+
     /// The `.marki/` directory this config is anchored to (where
     /// `models/`, `lib/`, and `media/` live). Set during discovery; never
     /// read from the TOML file.
@@ -97,12 +175,105 @@ pub struct Config {
     pub project_root: PathBuf,
 }
 
+/// Syntax-highlighting settings. Relative paths resolve against `.marki/`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HighlightConfig {
+    /// Bundled syntect theme name (default `base16-ocean.dark`) or a path
+    /// to a `.tmTheme` file.
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Directory of extra `.sublime-syntax` grammars (Nix, Lean, Typst…).
+    /// Default: `<.marki>/syntaxes/`, used when it exists.
+    #[serde(default)]
+    pub syntaxes: Option<PathBuf>,
+    /// Number the lines of every code block, unless its fence says
+    /// `nolinenos`.
+    #[serde(default)]
+    pub line_numbers: bool,
+}
+
+/// Output settings shared by the map and typst renderers.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderConfig {
+    /// Ship PNGs instead of SVGs, rasterised at this scale. For clients
+    /// that draw large SVGs slowly or wrongly (older AnkiDroid WebViews).
+    #[serde(default)]
+    pub raster: Option<marki_render::raster::Raster>,
+}
+
+/// A sync server to push through, as an Anki client would sync with it.
+#[derive(Clone, Deserialize)]
+pub struct SyncServerConfig {
+    /// Server root, e.g. `https://anki.example.org/`.
+    pub url: String,
+    pub user: String,
+    /// Usually injected from the environment (`${ANKI_SYNC_PASSWORD}`)
+    /// rather than committed.
+    pub password: String,
+    /// Let a cycle that adds fields or cards to an existing model replace
+    /// the server's collection wholesale (Anki's one-way upload). Every
+    /// other client then has to download it, losing unsynced reviews.
+    /// Without this, such a cycle fails and nothing is sent.
+    #[serde(default)]
+    pub allow_full_upload: bool,
+}
+
+/// One `[targets.<name>]` section. Relative paths resolve against the
+/// project root; globs match card paths relative to `cards_dir`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetConfig {
+    /// Collection file (`.anki2`) this target writes.
+    #[serde(default)]
+    pub collection: Option<PathBuf>,
+    /// Sync server this target pushes through, instead of a file.
+    #[serde(default)]
+    pub sync_server: Option<SyncServerConfig>,
+    /// Where renderer assets go, with its database beside it
+    /// (`<dir>.db`). Default: the `media/` directory beside `collection`.
+    #[serde(default)]
+    pub media_dir: Option<PathBuf>,
+    /// Deck every card lands under, e.g. `Family` puts
+    /// `geography/europe/` cards in `Family::geography::europe`.
+    #[serde(default)]
+    pub deck_prefix: Option<String>,
+    /// Cards to publish (globset syntax). Empty means every card.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Cards to leave out, even when included.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl SyncServerConfig {
+    fn expand_env(&mut self, section: &str) -> anyhow::Result<()> {
+        self.url = expand_env_str(&self.url, &format!("{section}.url"))?;
+        self.user = expand_env_str(&self.user, &format!("{section}.user"))?;
+        self.password = expand_env_str(&self.password, &format!("{section}.password"))?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for SyncServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncServerConfig")
+            .field("url", &self.url)
+            .field("user", &self.user)
+            .field("allow_full_upload", &self.allow_full_upload)
+            .finish_non_exhaustive()
+    }
+}
+
 fn default_sync_interval() -> Duration {
     Duration::from_secs(300)
 }
 fn default_debounce_ms() -> u64 {
     250
 }
+fn default_prerequisite_interval() -> u32 {
+    21
+}
 
 mod duration_secs {
     use serde::Deserialize;
@@ -118,28 +289,30 @@ mod duration_secs {
         }
         match Repr::deserialize(d)? {
             Repr::Secs(n) => Ok(Duration::from_secs(n)),
-            Repr::Str(s) => parse_duration(&s).map_err(serde::de::Error::custom),
-        }
-    }
-
-    fn parse_duration(s: &str) -> Result<Duration, String> {
-        let s = s.trim();
-        let split = s
-            .find(|c: char| c.is_alphabetic())
-            .unwrap_or(s.len());
-        let (num, unit) = s.split_at(split);
-        let n: u64 = num.trim().parse().map_err(|_| format!("bad duration: {s}"))?;
-        let mul = match unit.trim() {
-            "" | "s" => 1,
-            "m" => 60,
-            "h" => 3600,
-            "d" => 86400,
-            other => return Err(format!("unknown unit: {other}")),
-        };
-        Ok(Duration::from_secs(n * mul))
+            Repr::Str(s) => super::parse_duration(&s).map_err(serde::de::Error::custom),
+        }
     }
 }
 
+/// Parse `"90"`, `"5m"`, `"12h"`, `"30d"` (bare number = seconds). Used
+/// for config durations and CLI flags like `cache prune --older-than`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| c.is_alphabetic())
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: u64 = num.trim().parse().map_err(|_| format!("bad duration: {s}"))?;
+    let mul = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        other => return Err(format!("unknown unit: {other}")),
+    };
+    Ok(Duration::from_secs(n * mul))
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -147,11 +320,25 @@ impl Default for Config {
             models_dir: None,
             lib_dir: None,
             collection: None,
+            sync_server: None,
+            targets: IndexMap::new(),
+            target: None,
             sync_interval: Duration::from_secs(300),
             debounce_ms: 250,
+            prerequisite_interval: default_prerequisite_interval(),
+            control_socket: None,
             media_sources: Default::default(),
             typst_binary: None,
+            typst_font_paths: Vec::new(),
+            typst_package_path: None,
+            audio_encoder: None,
+            tts_binary: None,
+            tts_engine: None,
+            tts_voices: IndexMap::new(),
+            tts_voice_dir: None,
             map: Default::default(),
+            highlight: Default::default(),
+            render: Default::default(),
             anchor_dir: PathBuf::new(),
             project_root: PathBuf::new(),
         }
@@ -241,6 +428,9 @@ impl Config {
         cfg.anchor_dir = disc.anchor_dir.clone();
         cfg.project_root = disc.project_root.clone();
         cfg.expand_env()?;
+        if let Some(r) = &cfg.render.raster {
+            r.validate().map_err(|e| anyhow::anyhow!("[render] {e}"))?;
+        }
         Ok(cfg)
     }
 
@@ -261,9 +451,47 @@ impl Config {
         if let Some(p) = self.typst_binary.as_mut() {
             expand_path(p, "typst_binary")?;
         }
+        for p in self.typst_font_paths.iter_mut() {
+            expand_path(p, "typst_font_paths")?;
+        }
+        if let Some(p) = self.typst_package_path.as_mut() {
+            expand_path(p, "typst_package_path")?;
+        }
+        if let Some(p) = self.audio_encoder.as_mut() {
+            expand_path(p, "audio_encoder")?;
+        }
+        if let Some(p) = self.tts_binary.as_mut() {
+            expand_path(p, "tts_binary")?;
+        }
+        if let Some(p) = self.tts_voice_dir.as_mut() {
+            expand_path(p, "tts_voice_dir")?;
+        }
         if let Some(p) = self.collection.as_mut() {
             expand_path(p, "collection")?;
         }
+        if let Some(p) = self.control_socket.as_mut() {
+            expand_path(p, "control_socket")?;
+        }
+        if let Some(server) = self.sync_server.as_mut() {
+            server.expand_env("sync_server")?;
+        }
+        for (name, target) in self.targets.iter_mut() {
+            if let Some(p) = target.collection.as_mut() {
+                expand_path(p, &format!("targets.{name}.collection"))?;
+            }
+            if let Some(p) = target.media_dir.as_mut() {
+                expand_path(p, &format!("targets.{name}.media_dir"))?;
+            }
+            if let Some(server) = target.sync_server.as_mut() {
+                server.expand_env(&format!("targets.{name}.sync_server"))?;
+            }
+        }
+        if let Some(t) = self.highlight.theme.as_mut() {
+            *t = expand_env_str(t, "highlight.theme")?;
+        }
+        if let Some(p) = self.highlight.syntaxes.as_mut() {
+            expand_path(p, "highlight.syntaxes")?;
+        }
         for (name, dir) in self.media_sources.iter_mut() {
             let key = format!("media_sources.{name}");
             expand_path(dir, &key)?;
@@ -287,6 +515,57 @@ impl Config {
             .unwrap_or_else(|| self.anchor_dir.join("lib"))
     }
 
+    /// How to run Typst for this project: the configured binary, font and
+    /// package directories, and the shared `<lib>/preamble.typ`. `None`
+    /// when no binary is configured.
+    pub fn typst_setup(&self) -> Option<marki_typst::TypstSetup> {
+        let binary = self.typst_binary.clone()?;
+        let fonts = self
+            .typst_font_paths
+            .iter()
+            .map(|p| self.anchor_relative(p.clone()))
+            .collect();
+        let mut setup = marki_typst::TypstSetup::new(binary)
+            .with_font_paths(fonts)
+            .with_preamble_file(self.resolved_lib_dir().join(marki_typst::PREAMBLE_FILE_NAME));
+        if let Some(p) = &self.typst_package_path {
+            setup = setup.with_package_path(self.anchor_relative(p.clone()));
+        }
+        if let Some(r) = self.render.raster {
+            setup = setup.with_raster(r);
+        }
+        Some(setup)
+    }
+
+    /// How to run the speech synthesiser for this project. `None` when no
+    /// binary is configured.
+    pub fn tts_setup(&self) -> Option<marki_tts::TtsSetup> {
+        let binary = self.tts_binary.clone()?;
+        let mut setup = marki_tts::TtsSetup::new(binary).with_voices(self.tts_voices.clone());
+        if let Some(engine) = self.tts_engine {
+            setup = setup.with_engine(engine);
+        }
+        if let Some(p) = &self.tts_voice_dir {
+            setup = setup.with_voice_dir(self.anchor_relative(p.clone()));
+        }
+        Some(setup)
+    }
+
+    /// Code-block highlighter from `[highlight]`, with theme and grammar
+    /// paths anchored to `.marki/`.
+    pub fn highlighter(&self) -> anyhow::Result<crate::highlighter::Highlighter> {
+        let syntaxes = match &self.highlight.syntaxes {
+            Some(p) => self.anchor_dir.join(p),
+            None => self.anchor_dir.join(crate::highlighter::SYNTAX_DIR),
+        };
+        crate::highlighter::Highlighter::load(
+            &self.anchor_dir,
+            self.highlight.theme.as_deref(),
+            Some(&syntaxes),
+            self.highlight.line_numbers,
+        )
+    }
+
     /// The built-in, git-tracked primary media directory: `<.marki>/media/`.
     pub fn builtin_media_dir(&self) -> PathBuf {
         self.anchor_dir.join("media")
@@ -301,24 +580,98 @@ impl Config {
         }
     }
 
-    /// Resolved collection file (`.anki2`), anchored to the project root when
-    /// the configured path is relative. `None` when unconfigured.
+    /// Make `[targets.<name>]` the collection every later accessor
+    /// resolves to. Fails on an unknown name or a target with nowhere to
+    /// write.
+    pub fn select_target(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(target) = self.targets.get(name) else {
+            let known: Vec<&str> = self.targets.keys().map(String::as_str).collect();
+            anyhow::bail!(
+                "no target `{name}` in config (known: {})",
+                if known.is_empty() { "none".to_string() } else { known.join(", ") }
+            );
+        };
+        if target.collection.is_none() && target.sync_server.is_none() {
+            anyhow::bail!("target `{name}` sets neither `collection` nor `sync_server`");
+        }
+        self.target = Some(name.to_string());
+        Ok(())
+    }
+
+    /// The selected target's section, if `--target` was given.
+    fn active_target(&self) -> Option<&TargetConfig> {
+        self.target.as_ref().and_then(|n| self.targets.get(n))
+    }
+
+    /// The selected target compiled for a cycle: the unnamed target
+    /// unless `--target` was given.
+    pub fn sync_target(&self) -> anyhow::Result<crate::sync::Target> {
+        match (&self.target, self.active_target()) {
+            (Some(name), Some(t)) => crate::sync::Target::compile(name, t),
+            _ => Ok(crate::sync::Target::all()),
+        }
+    }
+
+    /// The sync server to push through: the selected target's, else the
+    /// top-level `[sync_server]`.
+    pub fn resolved_sync_server(&self) -> Option<&SyncServerConfig> {
+        match self.active_target() {
+            Some(t) => t.sync_server.as_ref(),
+            None => self.sync_server.as_ref(),
+        }
+    }
+
+    /// Resolved collection file (`.anki2`) of the selected target, anchored
+    /// to the project root when the configured path is relative. `None`
+    /// when unconfigured.
     pub fn resolved_collection(&self) -> Option<PathBuf> {
-        self.collection.clone().map(|p| self.anchor_relative(p))
+        let p = match self.active_target() {
+            Some(t) => t.collection.clone(),
+            None => self.collection.clone(),
+        };
+        p.map(|p| self.anchor_relative(p))
     }
 
-    /// The `media/` directory beside the collection file, where renderer
-    /// assets are written. `None` when the collection is unconfigured.
+    /// The directory renderer assets are written to: the target's
+    /// `media_dir`, else the `media/` directory beside the collection file.
+    /// `None` when neither is configured.
     pub fn media_dir(&self) -> Option<PathBuf> {
+        if let Some(p) = self.active_target().and_then(|t| t.media_dir.clone()) {
+            return Some(self.anchor_relative(p));
+        }
         self.resolved_collection()
             .and_then(|c| c.parent().map(|p| p.join("media")))
     }
 
-    /// The server media database (`media.db`) beside the collection file.
-    /// `None` when the collection is unconfigured.
+    /// The server media database beside the media directory and named
+    /// after it: `media.db` for the default `media/`, `family-media.db`
+    /// for a target's `family-media/`. Each media directory gets its own
+    /// database, so targets with sibling media directories never share
+    /// one. `None` when the media directory is unconfigured.
     pub fn media_db_path(&self) -> Option<PathBuf> {
-        self.resolved_collection()
-            .and_then(|c| c.parent().map(|p| p.join("media.db")))
+        let dir = self.media_dir()?;
+        let mut name = dir.file_name()?.to_os_string();
+        name.push(".db");
+        Some(dir.with_file_name(name))
+    }
+
+    /// The `marki watch` control socket: `control_socket`, else a path
+    /// under the runtime directory derived from the project root and the
+    /// selected target, so every repo (and target) gets its own.
+    pub fn control_socket_path(&self) -> PathBuf {
+        if let Some(p) = &self.control_socket {
+            return self.anchor_relative(p.clone());
+        }
+        let key = format!(
+            "{}\0{}",
+            self.project_root.display(),
+            self.target.as_deref().unwrap_or("")
+        );
+        let hash = blake3::hash(key.as_bytes()).to_hex();
+        dirs::runtime_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("marki")
+            .join(format!("{}.sock", &hash[..16]))
     }
 
     /// Resolve a possibly-relative config path against the project root.
@@ -475,9 +828,46 @@ const STARTER_CONFIG: &str = r#"# marki project config — lives in `.marki/`, c
 # env interpolation to keep a volatile profile path out of the committed file:
 # collection = "${ANKI_COLLECTION:-~/.local/share/Anki2/User 1/collection.anki2}"
 
-# Path to the `typst` CLI for ```typst``` blocks. Pair with `nix shell`
-# and env interpolation so the volatile /nix/store path isn't committed:
+# Or reach the collection through an Anki sync server, as a client would,
+# when marki can't write the server's files. Keep the password out of git:
+# [sync_server]
+# url = "https://anki.example.org/"
+# user = "me"
+# password = "${ANKI_SYNC_PASSWORD}"
+# allow_full_upload = false  # adding a card to a model needs a one-way upload
+
+# Further collections to publish a subset of the cards into, chosen with
+# `marki push --target <name>`. Globs match paths relative to cards_dir.
+# [targets.family]
+# collection = "/srv/anki/family/collection.anki2"  # or a [targets.family.sync_server]
+# deck_prefix = "Family"
+# include = ["geography/**"]
+# exclude = ["**/draft-*.md"]
+
+# Path to the `typst` CLI for ```typst``` blocks and `$…$` math. Pair
+# with `nix shell` and env interpolation so the volatile /nix/store path
+# isn't committed:
 # typst_binary = "${TYPST_BIN:-typst}"
+#
+# Shared Typst macros and styles go in `.marki/lib/preamble.typ`, which is
+# spliced into every typst block and math span. Extra fonts and a local
+# package directory (relative paths resolve against the repo root):
+# typst_font_paths = ["fonts"]
+# typst_package_path = "typst-packages"
+
+# ffmpeg (or compatible) for clipping OGG/M4A audio in ```media``` blocks
+# (`start`/`end`). WAV and MP3 are cut in-process and don't need it:
+# audio_encoder = "${FFMPEG_BIN:-ffmpeg}"
+
+# Speech synthesiser for ```tts``` blocks: espeak-ng or piper (the kind is
+# guessed from the file name; set `tts_engine` for a wrapper script).
+# `tts_voices` picks a voice for blocks that only give a `lang`. Piper
+# voices are model names looked up in `tts_voice_dir`:
+# tts_binary = "${ESPEAK_BIN:-espeak-ng}"
+# tts_engine = "espeak-ng"            # or "piper"
+# tts_voice_dir = "voices"
+# [tts_voices]
+# es = "es+f3"                        # piper: "es_ES-davefx-medium"
 
 # Named media sources for ```media``` blocks. The built-in
 # `.marki/media/` directory is always searched FIRST; these add more.
@@ -500,6 +890,28 @@ const STARTER_CONFIG: &str = r#"# marki project config — lives in `.marki/`, c
 # match = "Geography/**"
 # [map.rules.defaults.viewport]
 # cluster_factor = 0.3
+
+# A card tagged `#requires(<id>)` stays suspended until every card of note
+# <id> has a review interval of at least this many days:
+# prerequisite_interval = 21
+
+# Where `marki watch` listens for `marki ctl status|push|pause|resume|reload`.
+# Defaults to a per-repo socket under $XDG_RUNTIME_DIR/marki/:
+# control_socket = "/run/user/1000/marki-cards.sock"
+
+# Syntax highlighting for code blocks. `theme` is a bundled syntect theme
+# or a .tmTheme file; extra .sublime-syntax grammars are loaded from
+# `.marki/syntaxes/`. Paths are relative to `.marki/`. Per block, a fence
+# like ```rust {3-5} linenos``` marks lines 3-5 and numbers every line.
+# [highlight]
+# theme = "InspiredGitHub"
+# line_numbers = false
+
+# Ship map layers and typst output as PNGs instead of SVGs, for clients
+# that draw large SVGs slowly or wrongly (older AnkiDroid WebViews).
+# `scale` is device pixels per CSS pixel (default 2, at most 8):
+# [render]
+# raster = { scale = 2 }
 "#;
 
 #[cfg(test)]
@@ -571,6 +983,71 @@ mod tests {
         assert_eq!(cfg.typst_binary, Some(PathBuf::from("typst")));
     }
 
+    #[test]
+    fn sync_server_password_comes_from_env() {
+        unsafe { std::env::set_var("MARKI_T_SYNC_PW", "hunter2") };
+        let mut cfg: Config = toml::from_str(
+            "[sync_server]\nurl = \"https://anki.example.org/\"\nuser = \"me\"\n\
+             password = \"${MARKI_T_SYNC_PW}\"\n",
+        )
+        .unwrap();
+        cfg.expand_env().unwrap();
+        let server = cfg.sync_server.as_ref().unwrap();
+        assert_eq!(server.password, "hunter2");
+        assert!(!server.allow_full_upload);
+        assert!(!format!("{cfg:?}").contains("hunter2"));
+    }
+
+    #[test]
+    fn targets_resolve_their_own_collection_and_media() {
+        let mut cfg: Config = toml::from_str(
+            "collection = \"main/collection.anki2\"\n\
+             [targets.family]\ncollection = \"/srv/family/collection.anki2\"\n\
+             media_dir = \"family-media\"\ndeck_prefix = \"Family\"\n\
+             include = [\"geography/**\"]\n\
+             [targets.work]\ncollection = \"/srv/work/collection.anki2\"\n\
+             media_dir = \"work-media\"\n",
+        )
+        .unwrap();
+        cfg.project_root = PathBuf::from("/proj");
+        assert_eq!(cfg.media_db_path(), Some(PathBuf::from("/proj/main/media.db")));
+        assert!(cfg.sync_target().unwrap().name().is_none());
+
+        assert!(cfg.select_target("school").is_err());
+        cfg.select_target("family").unwrap();
+        assert_eq!(
+            cfg.resolved_collection(),
+            Some(PathBuf::from("/srv/family/collection.anki2"))
+        );
+        assert_eq!(cfg.media_dir(), Some(PathBuf::from("/proj/family-media")));
+        assert_eq!(cfg.media_db_path(), Some(PathBuf::from("/proj/family-media.db")));
+        let target = cfg.sync_target().unwrap();
+        assert_eq!(target.deck("geography"), "Family::geography");
+
+        // Sibling media directories keep their databases apart.
+        cfg.select_target("work").unwrap();
+        assert_eq!(cfg.media_dir(), Some(PathBuf::from("/proj/work-media")));
+        assert_eq!(cfg.media_db_path(), Some(PathBuf::from("/proj/work-media.db")));
+    }
+
+    #[test]
+    fn typst_setup_anchors_dirs_to_project() {
+        let mut cfg = Config {
+            project_root: PathBuf::from("/proj"),
+            anchor_dir: PathBuf::from("/proj/.marki"),
+            ..Config::default()
+        };
+        assert!(cfg.typst_setup().is_none());
+
+        cfg.typst_binary = Some(PathBuf::from("typst"));
+        cfg.typst_font_paths = vec![PathBuf::from("fonts"), PathBuf::from("/abs/fonts")];
+        cfg.typst_package_path = Some(PathBuf::from("pkgs"));
+        let dbg = format!("{:?}", cfg.typst_setup().unwrap());
+        for want in ["/proj/fonts", "/abs/fonts", "/proj/pkgs", "/proj/.marki/lib/preamble.typ"] {
+            assert!(dbg.contains(want), "{want} missing from {dbg}");
+        }
+    }
+
     // ---------- discovery ----------
 
     #[test]
@@ -618,6 +1095,28 @@ mod tests {
         let _ = std::fs::remove_dir_all(&root);
     }
 
+    #[test]
+    fn render_raster_is_parsed_and_validated() {
+        let root = tmp("raster");
+        let anchor = root.join(".marki");
+        std::fs::create_dir_all(&anchor).unwrap();
+        let path = anchor.join("config.toml");
+        let disc = Discovery {
+            config_path: Some(path.clone()),
+            anchor_dir: anchor,
+            project_root: root.clone(),
+        };
+
+        std::fs::write(&path, "[render]\nraster = {}\n").unwrap();
+        let cfg = Config::load(&disc).unwrap();
+        assert_eq!(cfg.render.raster.map(|r| r.scale), Some(2.0));
+
+        std::fs::write(&path, "[render]\nraster = { scale = 0 }\n").unwrap();
+        let err = Config::load(&disc).unwrap_err().to_string();
+        assert!(err.contains("raster scale"), "{err}");
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
     // ---------- init scaffolder ----------
 
     #[test]
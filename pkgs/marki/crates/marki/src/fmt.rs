@@ -20,8 +20,8 @@
 //!
 //! * Every `#keyword` and `#keyword(args)` token in normal prose is
 //!   removed from the body and re-emitted on the trailing tag line.
-//! * Tokens inside code blocks (fenced or indented) and inline code
-//!   spans are left alone.
+//! * Tokens inside code blocks (fenced or indented), inline code spans
+//!   and a leading YAML front-matter block are left alone.
 //! * `#id(...)` lives first on the tag line. If the source already has
 //!   one it wins; otherwise the caller-supplied minted id is used.
 //! * Source order: tags appear on the final line in the order they
@@ -134,14 +134,18 @@ fn split_keyword(token: &str) -> Option<(&str, Option<&str>)> {
 /// inline code span.
 fn find_code_ranges(source: &str) -> Vec<Range<usize>> {
     let mut ranges = Vec::new();
-    let parser = Parser::new_ext(source, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH).into_offset_iter();
+    let opts = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+    let parser = Parser::new_ext(source, opts).into_offset_iter();
     let mut code_start: Option<usize> = None;
     for (event, range) in parser {
         match event {
-            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_) | CodeBlockKind::Indented)) => {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_) | CodeBlockKind::Indented))
+            | Event::Start(Tag::MetadataBlock(_)) => {
                 code_start = Some(range.start);
             }
-            Event::End(TagEnd::CodeBlock) => {
+            Event::End(TagEnd::CodeBlock) | Event::End(TagEnd::MetadataBlock(_)) => {
                 if let Some(start) = code_start.take() {
                     ranges.push(start..range.end);
                 }
@@ -355,6 +359,16 @@ mod tests {
         );
     }
 
+    #[test]
+    fn front_matter_is_left_alone() {
+        let src = "---\norder: 3\ncolor: \"#ffaa00\"\n---\n\nQ #geography\n";
+        let out = format_card(src, &"x".to_string());
+        assert_eq!(
+            out,
+            "---\norder: 3\ncolor: \"#ffaa00\"\n---\n\nQ\n\n#id(x) #geography\n"
+        );
+    }
+
     #[test]
     fn duplicate_tags_deduped() {
         let src = "body #foo #foo\n\n#foo\n";
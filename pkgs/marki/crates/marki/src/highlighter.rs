@@ -3,20 +3,46 @@
 //! Emits self-contained HTML with inline `style="..."` attributes on every
 //! token span, so the output needs no external CSS. Cards render correctly
 //! on stock Anki `Basic` / `Cloze` note types.
+//!
+//! The theme and grammar set come from the project's `[highlight]` config
+//! (see [`Highlighter::load`]); without one, syntect's bundled grammars
+//! and `base16-ocean.dark` are used. Per block, the fence info string
+//! after the lang token may ask for line numbers and marked lines:
+//!
+//! ````markdown
+//! ```rust {3-5,8} linenos
+//! ```
+//! ````
+//!
+//! `{…}` holds comma-separated line numbers or ranges to mark;
+//! `linenos` / `nolinenos` override the configured line-number default.
 
+use std::fmt::Write as _;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
-use syntect::highlighting::{Theme, ThemeSet};
-use syntect::html::highlighted_html_for_string;
-use syntect::parsing::SyntaxSet;
+
+use anyhow::{Context, Result};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{
+    IncludeBackground, highlighted_html_for_string, start_highlighted_html_snippet,
+    styled_line_to_highlighted_html,
+};
+use syntect::parsing::{SyntaxDefinition, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
 
 use marki_render::escape_html;
 
 static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
 
-static THEME: LazyLock<Theme> = LazyLock::new(|| {
-    let theme_set = ThemeSet::load_defaults();
-    theme_set.themes["base16-ocean.dark"].clone()
-});
+static THEME_SET: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Theme used when `[highlight] theme` is unset.
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Default directory, under `.marki/`, for extra `.sublime-syntax` files.
+pub const SYNTAX_DIR: &str = "syntaxes";
 
 /// Extra inline styles injected into every `<pre>` produced by syntect so
 /// that code blocks use a slightly smaller font and wrap instead of
@@ -24,37 +50,340 @@ static THEME: LazyLock<Theme> = LazyLock::new(|| {
 const CODE_BLOCK_EXTRA_STYLE: &str =
     "font-size:0.85em;white-space:pre-wrap;word-wrap:break-word;overflow-wrap:break-word;";
 
-/// Highlight `code` in `language`. Returns a self-contained
-/// `<pre style="...">…</pre>` with inline styles on every token span.
-///
-/// The returned `<pre>` carries additional styles for smaller text and
-/// word-wrapping so code doesn't overflow the card boundaries.
-pub fn highlight_code(code: &str, language: &str) -> String {
-    let syntax = SYNTAX_SET
-        .find_syntax_by_token(language)
-        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+/// Background for marked lines when the theme defines no `lineHighlight`.
+const FALLBACK_MARK: &str = "rgba(255,255,255,0.12)";
+
+/// A configured highlighter. `Default` is syntect's bundled grammars with
+/// [`DEFAULT_THEME`] and no line numbers.
+#[derive(Debug, Default)]
+pub struct Highlighter {
+    /// Bundled grammars plus the project's; `None` means bundled only.
+    syntaxes: Option<SyntaxSet>,
+    /// `None` means [`DEFAULT_THEME`].
+    theme: Option<Theme>,
+    /// Number lines unless a block says `nolinenos`.
+    line_numbers: bool,
+    /// Digest of everything above that differs from the default.
+    fingerprint: Option<String>,
+}
+
+impl Highlighter {
+    /// Build from `[highlight]` settings. `theme` is a bundled theme name
+    /// or a path to a `.tmTheme` file, relative to `base`; `syntax_dir`
+    /// holds extra `.sublime-syntax` grammars (a missing directory is
+    /// fine).
+    pub fn load(
+        base: &Path,
+        theme: Option<&str>,
+        syntax_dir: Option<&Path>,
+        line_numbers: bool,
+    ) -> Result<Self> {
+        let mut digest = blake3::Hasher::new();
+        let mut custom = line_numbers;
+        digest.update(&[line_numbers as u8]);
+
+        let theme = match theme {
+            None => None,
+            Some(spec) if is_theme_path(spec) => {
+                let path = base.join(spec);
+                let bytes = std::fs::read(&path)
+                    .with_context(|| format!("read theme {}", path.display()))?;
+                let theme = ThemeSet::load_from_reader(&mut std::io::Cursor::new(&bytes))
+                    .with_context(|| format!("parse theme {}", path.display()))?;
+                digest.update(b"theme-file\0");
+                digest.update(&bytes);
+                Some(theme)
+            }
+            Some(name) => {
+                let theme = THEME_SET.themes.get(name).cloned().with_context(|| {
+                    let known: Vec<_> = THEME_SET.themes.keys().map(String::as_str).collect();
+                    format!("unknown theme `{name}` (built-in: {})", known.join(", "))
+                })?;
+                digest.update(b"theme-name\0");
+                digest.update(name.as_bytes());
+                Some(theme)
+            }
+        };
+        custom |= theme.is_some();
+
+        let files = match syntax_dir {
+            Some(dir) => syntax_files(dir)?,
+            None => Vec::new(),
+        };
+        let syntaxes = if files.is_empty() {
+            None
+        } else {
+            let mut builder = SYNTAX_SET.clone().into_builder();
+            for path in &files {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("read grammar {}", path.display()))?;
+                let name = path.file_stem().and_then(|s| s.to_str());
+                let def = SyntaxDefinition::load_from_str(&text, true, name)
+                    .with_context(|| format!("parse grammar {}", path.display()))?;
+                digest.update(b"syntax\0");
+                digest.update(path.file_name().unwrap_or_default().as_encoded_bytes());
+                digest.update(&(text.len() as u64).to_le_bytes());
+                digest.update(text.as_bytes());
+                builder.add(def);
+            }
+            Some(builder.build())
+        };
+        custom |= syntaxes.is_some();
+
+        Ok(Self {
+            syntaxes,
+            theme,
+            line_numbers,
+            fingerprint: custom.then(|| digest.finalize().to_hex()[..16].to_string()),
+        })
+    }
 
-    let raw = highlighted_html_for_string(code, &SYNTAX_SET, syntax, &THEME)
-        .unwrap_or_else(|_| {
+    /// Short digest of the non-default settings, for mixing into note
+    /// content hashes so a theme or grammar change re-renders cards.
+    /// `None` for the default highlighter, which leaves hashes as they
+    /// were before highlighting was configurable.
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+
+    fn syntax_set(&self) -> &SyntaxSet {
+        self.syntaxes.as_ref().unwrap_or(&SYNTAX_SET)
+    }
+
+    fn theme(&self) -> &Theme {
+        self.theme
+            .as_ref()
+            .unwrap_or_else(|| &THEME_SET.themes[DEFAULT_THEME])
+    }
+
+    /// Highlight `code` in `language`, applying the fence `info` options.
+    /// Returns a self-contained `<pre style="...">…</pre>` with inline
+    /// styles on every token span.
+    ///
+    /// The returned `<pre>` carries additional styles for smaller text and
+    /// word-wrapping so code doesn't overflow the card boundaries.
+    pub fn highlight(&self, code: &str, language: &str, info: &str) -> String {
+        let opts = FenceOptions::parse(info);
+        let line_numbers = opts.line_numbers.unwrap_or(self.line_numbers);
+        let set = self.syntax_set();
+        let syntax = set
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| set.find_syntax_plain_text());
+
+        let raw = if line_numbers || !opts.marked.is_empty() {
+            self.highlight_lines(code, syntax, line_numbers, &opts.marked)
+        } else {
+            highlighted_html_for_string(code, set, syntax, self.theme()).ok()
+        };
+        let raw = raw.unwrap_or_else(|| {
             format!(
                 "<pre style=\"{CODE_BLOCK_EXTRA_STYLE}\">{}</pre>",
                 escape_html(code),
             )
         });
 
-    // syntect emits `<pre style="background-color:#...;">`. Inject our
-    // extra styles right after the opening `style="` so both the theme
-    // background *and* our sizing/wrapping rules apply.
-    if let Some(pos) = raw.find("<pre style=\"") {
-        let insert_at = pos + "<pre style=\"".len();
-        let mut patched = String::with_capacity(raw.len() + CODE_BLOCK_EXTRA_STYLE.len());
-        patched.push_str(&raw[..insert_at]);
-        patched.push_str(CODE_BLOCK_EXTRA_STYLE);
-        patched.push_str(&raw[insert_at..]);
-        patched
-    } else {
-        // Unexpected format — wrap in a styled <div> as fallback.
-        format!("<div style=\"{CODE_BLOCK_EXTRA_STYLE}\">{raw}</div>")
+        // syntect emits `<pre style="background-color:#...;">`. Inject our
+        // extra styles right after the opening `style="` so both the theme
+        // background *and* our sizing/wrapping rules apply.
+        if let Some(pos) = raw.find("<pre style=\"") {
+            let insert_at = pos + "<pre style=\"".len();
+            let mut patched = String::with_capacity(raw.len() + CODE_BLOCK_EXTRA_STYLE.len());
+            patched.push_str(&raw[..insert_at]);
+            patched.push_str(CODE_BLOCK_EXTRA_STYLE);
+            patched.push_str(&raw[insert_at..]);
+            patched
+        } else {
+            // Unexpected format — wrap in a styled <div> as fallback.
+            format!("<div style=\"{CODE_BLOCK_EXTRA_STYLE}\">{raw}</div>")
+        }
     }
+
+    /// Line-by-line rendering: each source line becomes a block-level
+    /// `<span>`, optionally prefixed with its number and tinted when it
+    /// falls in a marked range.
+    fn highlight_lines(
+        &self,
+        code: &str,
+        syntax: &SyntaxReference,
+        line_numbers: bool,
+        marked: &[RangeInclusive<usize>],
+    ) -> Option<String> {
+        let theme = self.theme();
+        let mark = theme
+            .settings
+            .line_highlight
+            .map(css_color)
+            .unwrap_or_else(|| FALLBACK_MARK.to_string());
+        let width = code.lines().count().max(1).to_string().len();
+
+        let (mut out, _) = start_highlighted_html_snippet(theme);
+        let mut lines = HighlightLines::new(syntax, theme);
+        for (i, line) in LinesWithEndings::from(code).enumerate() {
+            let n = i + 1;
+            let regions = lines.highlight_line(line, self.syntax_set()).ok()?;
+            // The newline is implied by the block span.
+            let regions: Vec<_> = regions
+                .into_iter()
+                .map(|(style, text)| (style, text.trim_end_matches(['\n', '\r'])))
+                .collect();
+            let body = styled_line_to_highlighted_html(&regions, IncludeBackground::No).ok()?;
+
+            out.push_str("<span style=\"display:block;");
+            if marked.iter().any(|r| r.contains(&n)) {
+                let _ = write!(out, "background-color:{mark};");
+            }
+            out.push_str("\">");
+            if line_numbers {
+                let _ = write!(
+                    out,
+                    "<span style=\"display:inline-block;min-width:{width}ch;margin-right:1em;\
+                     text-align:right;opacity:0.5;user-select:none;\">{n}</span>"
+                );
+            }
+            // Keep empty lines one line tall.
+            out.push_str(if body.is_empty() { " " } else { &body });
+            out.push_str("</span>");
+        }
+        out.push_str("</pre>");
+        Some(out)
+    }
+}
+
+/// Highlight `code` in `language` with the default highlighter.
+pub fn highlight_code(code: &str, language: &str) -> String {
+    Highlighter::default().highlight(code, language, "")
 }
 
+/// Per-block options from the fence info string.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FenceOptions {
+    line_numbers: Option<bool>,
+    marked: Vec<RangeInclusive<usize>>,
+}
+
+impl FenceOptions {
+    /// Parse `{3-5,8} linenos`-style info. Unknown words are ignored, so
+    /// info meant for other tools doesn't break highlighting.
+    fn parse(info: &str) -> Self {
+        let mut opts = Self::default();
+        let items = info
+            .split(|c: char| c.is_whitespace() || matches!(c, ',' | '{' | '}'))
+            .filter(|s| !s.is_empty());
+        for item in items {
+            match item {
+                "linenos" => opts.line_numbers = Some(true),
+                "nolinenos" => opts.line_numbers = Some(false),
+                _ => {
+                    let (a, b) = item.split_once('-').unwrap_or((item, item));
+                    if let (Ok(a), Ok(b)) = (a.parse::<usize>(), b.parse::<usize>()) {
+                        opts.marked.push(a.min(b)..=a.max(b));
+                    }
+                }
+            }
+        }
+        opts
+    }
+}
+
+/// Theme specs naming a file rather than a bundled theme.
+fn is_theme_path(spec: &str) -> bool {
+    spec.ends_with(".tmTheme") || spec.contains('/') || spec.contains(std::path::MAIN_SEPARATOR)
+}
+
+/// `.sublime-syntax` files directly in `dir`, sorted by name so the
+/// fingerprint is stable. A missing directory yields none.
+fn syntax_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("read {}", dir.display())),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "sublime-syntax") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn css_color(c: Color) -> String {
+    format!("rgba({},{},{},{:.3})", c.r, c.g, c.b, f64::from(c.a) / 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmp(name: &str) -> PathBuf {
+        let p = std::env::temp_dir().join(format!("marki-hl-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&p);
+        std::fs::create_dir_all(&p).unwrap();
+        p
+    }
+
+    #[test]
+    fn parses_fence_options() {
+        assert_eq!(FenceOptions::parse(""), FenceOptions::default());
+        let o = FenceOptions::parse("{3-5,8} linenos title=x");
+        assert_eq!(o.line_numbers, Some(true));
+        assert_eq!(o.marked, vec![3..=5, 8..=8]);
+        assert_eq!(FenceOptions::parse("{ 5-2 } nolinenos").marked, vec![2..=5]);
+        assert_eq!(FenceOptions::parse("nolinenos").line_numbers, Some(false));
+    }
+
+    #[test]
+    fn default_output_is_unchanged_by_empty_info() {
+        let h = Highlighter::default();
+        assert_eq!(h.highlight("fn x() {}\n", "rust", ""), highlight_code("fn x() {}\n", "rust"));
+        assert!(h.fingerprint().is_none());
+    }
+
+    #[test]
+    fn marks_and_numbers_lines() {
+        let h = Highlighter::default();
+        let html = h.highlight("a\nb\nc\n", "txt", "{2} linenos");
+        assert_eq!(html.matches("display:block;").count(), 3, "{html}");
+        assert_eq!(html.matches("background-color:rgba(").count(), 1, "{html}");
+        assert!(html.contains(">2</span>"), "{html}");
+        assert!(html.starts_with(&format!("<pre style=\"{CODE_BLOCK_EXTRA_STYLE}")));
+    }
+
+    #[test]
+    fn configured_line_numbers_can_be_turned_off_per_block() {
+        let h = Highlighter::load(Path::new("."), None, None, true).unwrap();
+        assert!(h.highlight("a\n", "txt", "").contains("user-select:none"));
+        assert!(!h.highlight("a\n", "txt", "nolinenos").contains("user-select:none"));
+        assert!(h.fingerprint().is_some());
+    }
+
+    #[test]
+    fn theme_by_name_changes_fingerprint_and_output() {
+        let named = |t| Highlighter::load(Path::new("."), Some(t), None, false);
+        let light = named("InspiredGitHub").unwrap();
+        let dark = named("Solarized (dark)").unwrap();
+        assert_ne!(light.fingerprint(), dark.fingerprint());
+        assert_ne!(light.highlight("x\n", "txt", ""), highlight_code("x\n", "txt"));
+        let err = named("no-such-theme").unwrap_err();
+        assert!(err.to_string().contains("InspiredGitHub"), "{err}");
+    }
+
+    #[test]
+    fn loads_extra_grammars() {
+        let dir = tmp("syntax");
+        std::fs::write(
+            dir.join("marki-test.sublime-syntax"),
+            "%YAML 1.2\n---\nname: MarkiTest\nfile_extensions: [mkt]\nscope: source.mkt\n\
+             contexts:\n  main:\n    - match: '\\bbeep\\b'\n      scope: keyword.control.mkt\n",
+        )
+        .unwrap();
+        let h = Highlighter::load(&dir, None, Some(&dir), false).unwrap();
+        assert!(h.syntax_set().find_syntax_by_token("mkt").is_some());
+        assert!(h.fingerprint().is_some());
+        // A missing directory just means no extra grammars.
+        let none = Highlighter::load(&dir, None, Some(&dir.join("missing")), false).unwrap();
+        assert!(none.fingerprint().is_none());
+    }
+}
@@ -0,0 +1,612 @@
+//! Best-effort conversion of Anki field HTML back to marki markdown.
+//!
+//! Anki fields are whatever the editor (or an add-on, or a shared deck's
+//! author) produced, so this is a forgiving tokenizer rather than a DOM
+//! parser: it walks tags and text left to right and keeps a small amount of
+//! block state (enclosing blockquotes and list items, the pending line
+//! break). Inline formatting maps to markdown, block tags to line and
+//! paragraph breaks, media to ```` ```media ```` blocks, and anything else
+//! is dropped while its text is kept.
+//!
+//! Text is escaped so it renders as it did in Anki: markdown punctuation is
+//! backslash-escaped, and `#` becomes `&#35;` because a bare `#word` would
+//! be lifted onto the tag line by `marki fmt`.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// One tag, a comment, or nothing: the tokenizer's unit of markup.
+static TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)<!--.*?-->|<(/?)([A-Za-z][A-Za-z0-9]*)((?:[^>"']|"[^"]*"|'[^']*')*)>"#)
+        .unwrap()
+});
+
+static ATTR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"([A-Za-z_:][-\w:.]*)\s*(?:=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#).unwrap()
+});
+
+static SOUND_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[sound:([^\]]+)\]").unwrap());
+
+static ENTITY_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9A-Fa-f]+|[A-Za-z]+);").unwrap());
+
+/// Extensions the media renderer resolves; anything else stays an inline
+/// markdown image.
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "svg", "png", "webp", "jpg", "jpeg", "gif", "mp3", "ogg", "m4a", "wav", "mp4", "webm",
+];
+
+/// Convert one field's HTML to markdown. `media` is called with the
+/// decoded filename of every local image and `[sound:…]` reference and
+/// returns the name it was stored under in `.marki/media/`, or `None` when
+/// it could not be imported, in which case the reference is kept inline.
+pub(crate) fn to_markdown(html: &str, media: &mut dyn FnMut(&str) -> Option<String>) -> String {
+    let mut w = Writer::default();
+    let mut pos = 0;
+    while let Some(m) = TAG_REGEX.find_at(html, pos) {
+        text(&mut w, &html[pos..m.start()], media);
+        pos = m.end();
+
+        let caps = TAG_REGEX.captures(m.as_str()).expect("matched above");
+        let Some(name) = caps.get(2) else {
+            continue; // comment
+        };
+        let name = name.as_str().to_ascii_lowercase();
+        let closing = !caps[1].is_empty();
+        let attrs = caps.get(3).map_or("", |a| a.as_str());
+
+        if !closing && matches!(name.as_str(), "pre" | "script" | "style") {
+            let close = format!("</{name}");
+            let rest = &html[pos..];
+            let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+            if name == "pre" {
+                w.code_block(&pre_text(&rest[..end]));
+            }
+            pos += end;
+            if let Some(gt) = html[pos..].find('>') {
+                pos += gt + 1;
+            }
+            continue;
+        }
+        element(&mut w, &name, closing, attrs, media);
+    }
+    text(&mut w, &html[pos..], media);
+    w.finish()
+}
+
+fn element(
+    w: &mut Writer,
+    name: &str,
+    closing: bool,
+    attrs: &str,
+    media: &mut dyn FnMut(&str) -> Option<String>,
+) {
+    match (name, closing) {
+        ("b" | "strong", false) => w.open("**"),
+        ("b" | "strong", true) => w.close("**"),
+        ("i" | "em", false) => w.open("*"),
+        ("i" | "em", true) => w.close("*"),
+        ("s" | "del" | "strike", false) => w.open("~~"),
+        ("s" | "del" | "strike", true) => w.close("~~"),
+        ("mark", false) => w.open("=="),
+        ("mark", true) => w.close("=="),
+        ("code", false) => {
+            w.open("`");
+            w.code += 1;
+        }
+        ("code", true) => {
+            w.code = w.code.saturating_sub(1);
+            w.close("`");
+        }
+        ("a", false) => {
+            let href = attr(attrs, "href").filter(|h| !h.is_empty());
+            if href.is_some() {
+                w.open("[");
+            }
+            w.links.push(href);
+        }
+        ("a", true) => {
+            if let Some(Some(href)) = w.links.pop() {
+                w.close_with("[", &format!("]({})", href.replace(' ', "%20")));
+            }
+        }
+        ("br", _) => w.hard_break(),
+        ("div", _) => w.brk(Break::Hard),
+        ("p" | "hr", _) => w.brk(Break::Para),
+        ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+            w.brk(Break::Para);
+            let level = name[1..].parse::<usize>().unwrap_or(1);
+            w.raw(&format!("{} ", "#".repeat(level)));
+        }
+        ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => w.brk(Break::Para),
+        ("blockquote", false) => {
+            w.brk(Break::Para);
+            w.prefixes.push("> ".into());
+        }
+        ("blockquote", true) => {
+            w.brk(Break::Para);
+            w.prefixes.pop();
+        }
+        ("ul" | "ol", false) => {
+            w.brk(if w.lists.is_empty() { Break::Para } else { Break::Line });
+            w.lists.push((name == "ol", 0));
+        }
+        ("ul" | "ol", true) => {
+            w.lists.pop();
+            w.brk(if w.lists.is_empty() { Break::Para } else { Break::Line });
+        }
+        ("li", false) => {
+            w.brk(Break::Line);
+            let marker = match w.lists.last_mut() {
+                Some((true, n)) => {
+                    *n += 1;
+                    format!("{n}. ")
+                }
+                _ => "- ".to_string(),
+            };
+            w.prefixes.push(" ".repeat(marker.len()));
+            w.marker = Some(marker);
+        }
+        ("li", true) => {
+            w.brk(Break::Line);
+            w.marker = None;
+            w.prefixes.pop();
+        }
+        ("tr", false) => {
+            w.brk(Break::Hard);
+            w.first_cell = true;
+        }
+        ("td" | "th", false) => {
+            if !w.first_cell {
+                w.raw(" | ");
+            }
+            w.first_cell = false;
+        }
+        ("table", _) => w.brk(Break::Para),
+        ("img", false) => {
+            let src = attr(attrs, "src").unwrap_or_default();
+            let alt = attr(attrs, "alt").unwrap_or_default();
+            match local_media_name(&src).and_then(|name| media(&name)) {
+                Some(stored) => w.media_block(&stored, &alt, false),
+                None => w.raw(&format!("![{}]({})", escape(&alt), src.replace(' ', "%20"))),
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Feed a run of text between tags, turning `[sound:…]` into media blocks.
+fn text(w: &mut Writer, raw: &str, media: &mut dyn FnMut(&str) -> Option<String>) {
+    let decoded = decode_entities(raw);
+    let mut pos = 0;
+    for caps in SOUND_REGEX.captures_iter(&decoded) {
+        let m = caps.get(0).expect("whole match");
+        let name = caps[1].trim();
+        let stored = local_media_name(name).and_then(|n| media(&n));
+        if let Some(stored) = stored {
+            w.text(&decoded[pos..m.start()]);
+            w.media_block(&stored, "", true);
+            pos = m.end();
+        }
+    }
+    w.text(&decoded[pos..]);
+}
+
+/// Text content of a `<pre>` element: `<br>` becomes a newline, other tags
+/// are dropped, entities decoded. Leading/trailing blank lines are trimmed.
+fn pre_text(inner: &str) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    for m in TAG_REGEX.find_iter(inner) {
+        out.push_str(&inner[pos..m.start()]);
+        if m.as_str().to_ascii_lowercase().starts_with("<br") {
+            out.push('\n');
+        }
+        pos = m.end();
+    }
+    out.push_str(&inner[pos..]);
+    decode_entities(&out).trim_matches('\n').to_string()
+}
+
+/// The value of attribute `name` in a tag's attribute string, decoded.
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    ATTR_REGEX.captures_iter(attrs).find_map(|c| {
+        if !c[1].eq_ignore_ascii_case(name) {
+            return None;
+        }
+        let v = c.get(2).or(c.get(3)).or(c.get(4)).map_or("", |v| v.as_str());
+        Some(decode_entities(v))
+    })
+}
+
+/// The collection-media filename `src` refers to, if it is a local file of
+/// a type the media renderer handles. Remote and `data:` URLs, paths and
+/// unsupported extensions yield `None`.
+fn local_media_name(src: &str) -> Option<String> {
+    if src.is_empty() || src.contains(':') || src.starts_with("//") {
+        return None;
+    }
+    let name = percent_decode(src);
+    if name.contains('/') || name.contains('\\') || name.starts_with('.') {
+        return None;
+    }
+    let ext = name.rsplit_once('.')?.1.to_ascii_lowercase();
+    MEDIA_EXTENSIONS.contains(&ext.as_str()).then_some(name)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(b) = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok())
+        {
+            out.push(b);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(out).unwrap_or_else(|_| s.to_string())
+}
+
+fn decode_entities(s: &str) -> String {
+    ENTITY_REGEX
+        .replace_all(s, |c: &regex::Captures| {
+            let body = &c[1];
+            let ch = if let Some(hex) = body.strip_prefix("#x").or(body.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = body.strip_prefix('#') {
+                dec.parse().ok().and_then(char::from_u32)
+            } else {
+                named_entity(body)
+            };
+            ch.map_or_else(|| c[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "middot" => '·',
+        "bull" => '•',
+        "deg" => '°',
+        "times" => '×',
+        "divide" => '÷',
+        "copy" => '©',
+        "reg" => '®',
+        "euro" => '€',
+        _ => return None,
+    })
+}
+
+/// Escape markdown punctuation in a run of prose.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '$' | '~' | '=' | '|' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '#' => out.push_str("&#35;"),
+            '&' if chars.peek().is_some_and(|n| n.is_ascii_alphanumeric() || *n == '#') => {
+                out.push_str("\\&");
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape what would start a block construct at the beginning of a line:
+/// list markers, blockquotes, and `1.`-style ordered markers.
+fn escape_line_start(word: &str) -> String {
+    if word.starts_with(['-', '+', '>']) {
+        return format!("\\{word}");
+    }
+    let digits = word.bytes().take_while(u8::is_ascii_digit).count();
+    if digits > 0 && matches!(word.as_bytes().get(digits), Some(b'.' | b')')) {
+        return format!("{}\\{}", &word[..digits], &word[digits..]);
+    }
+    word.to_string()
+}
+
+/// Pending separation before the next output, weakest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+enum Break {
+    #[default]
+    None,
+    /// A hard line break inside a paragraph (`<br>`, `<div>`).
+    Hard,
+    /// A plain newline between list items or code lines.
+    Line,
+    /// A blank line between blocks.
+    Para,
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    /// Per-line prefixes from enclosing blockquotes (`> `) and list items
+    /// (indent of the marker's width).
+    prefixes: Vec<String>,
+    /// Marker replacing the innermost list indent on an item's first line.
+    marker: Option<String>,
+    /// Enclosing lists: ordered flag and items seen so far.
+    lists: Vec<(bool, usize)>,
+    /// Enclosing links; `None` for anchors without an `href`.
+    links: Vec<Option<String>>,
+    /// Depth of `<code>`, inside which text is written verbatim.
+    code: usize,
+    first_cell: bool,
+    brk: Break,
+    /// Line prefix when `brk` was first requested. A blank line between
+    /// blocks carries only what it shares with the next line's prefix, so
+    /// entering or leaving a blockquote leaves a truly blank line.
+    brk_prefix: String,
+    /// Nothing written on the current line yet; `brk` and the prefix are
+    /// emitted before the next output.
+    line_empty: bool,
+    /// Whitespace seen since the last word.
+    space: bool,
+    /// Opening inline markers not yet followed by text. Whitespace moves
+    /// in front of them, and they vanish if the element turns out empty.
+    pending: String,
+}
+
+impl Writer {
+    fn brk(&mut self, b: Break) {
+        if self.line_empty {
+            self.brk = self.brk.max(b);
+        } else if !self.out.is_empty() {
+            self.line_empty = true;
+            self.brk = b;
+            self.brk_prefix = self.prefixes.concat();
+        }
+        self.space = false;
+    }
+
+    /// `<br>`: a second one in a row opens a new paragraph.
+    fn hard_break(&mut self) {
+        if self.line_empty && self.brk == Break::Hard {
+            self.brk = Break::Para;
+        } else {
+            self.brk(Break::Hard);
+        }
+    }
+
+    fn line_prefix(&mut self) -> String {
+        let mut prefix: String = self.prefixes.concat();
+        if let Some(marker) = self.marker.take() {
+            let indent = self.prefixes.last().map_or(0, String::len);
+            prefix.truncate(prefix.len() - indent);
+            prefix.push_str(&marker);
+        }
+        prefix
+    }
+
+    /// Emit the pending break and prefix, or the pending space. Returns
+    /// whether output now starts a fresh line.
+    fn begin(&mut self) -> bool {
+        if self.line_empty || self.out.is_empty() {
+            if !self.out.is_empty() {
+                match self.brk {
+                    Break::Para => {
+                        let now = self.prefixes.concat();
+                        let shared = now
+                            .char_indices()
+                            .zip(self.brk_prefix.chars())
+                            .find(|((_, a), b)| a != b)
+                            .map_or(now.len().min(self.brk_prefix.len()), |((i, _), _)| i);
+                        self.out.push('\n');
+                        self.out.push_str(now[..shared].trim_end());
+                        self.out.push('\n');
+                    }
+                    Break::Hard => self.out.push_str("\\\n"),
+                    Break::Line | Break::None => self.out.push('\n'),
+                }
+            }
+            let prefix = self.line_prefix();
+            self.out.push_str(&prefix);
+            self.line_empty = false;
+            self.brk = Break::None;
+            self.space = false;
+            true
+        } else {
+            if self.space {
+                self.out.push(' ');
+                self.space = false;
+            }
+            false
+        }
+    }
+
+    fn text(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        let mut words = s.split(char::is_whitespace).peekable();
+        while let Some(word) = words.next() {
+            if !word.is_empty() {
+                let fresh = self.begin();
+                let mut word = if self.code > 0 { word.to_string() } else { escape(word) };
+                if fresh && self.pending.is_empty() && self.code == 0 {
+                    word = escape_line_start(&word);
+                }
+                let pending = std::mem::take(&mut self.pending);
+                self.out.push_str(&pending);
+                self.out.push_str(&word);
+            }
+            if words.peek().is_some() && !self.line_empty {
+                self.space = true;
+            }
+        }
+    }
+
+    /// Write markup verbatim, as part of the current line.
+    fn raw(&mut self, s: &str) {
+        self.begin();
+        let pending = std::mem::take(&mut self.pending);
+        self.out.push_str(&pending);
+        self.out.push_str(s);
+    }
+
+    fn open(&mut self, marker: &str) {
+        self.pending.push_str(marker);
+    }
+
+    fn close(&mut self, marker: &str) {
+        self.close_with(marker, marker);
+    }
+
+    /// Close an inline element opened with `open`: drop both markers if it
+    /// held no text, else write `close` right after the last word.
+    fn close_with(&mut self, open: &str, close: &str) {
+        if self.pending.ends_with(open) {
+            self.pending.truncate(self.pending.len() - open.len());
+        } else {
+            self.out.push_str(close);
+        }
+    }
+
+    fn block_lines(&mut self, lines: &[String]) {
+        self.brk(Break::Para);
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                self.brk(Break::Line);
+            }
+            self.begin();
+            self.out.push_str(line);
+        }
+        self.brk(Break::Para);
+    }
+
+    fn code_block(&mut self, code: &str) {
+        let mut lines = vec!["```".to_string()];
+        lines.extend(code.lines().map(String::from));
+        lines.push("```".into());
+        self.block_lines(&lines);
+    }
+
+    fn media_block(&mut self, name: &str, alt: &str, anki_sound: bool) {
+        let mut lines = vec![
+            "```media".to_string(),
+            format!("src = {}", toml::Value::String(format!("media/{name}"))),
+        ];
+        if !alt.is_empty() {
+            lines.push(format!("alt = {}", toml::Value::String(alt.to_string())));
+        }
+        if anki_sound {
+            lines.push("anki_sound = true".into());
+        }
+        lines.push("```".into());
+        self.block_lines(&lines);
+    }
+
+    fn finish(self) -> String {
+        self.out.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn md(html: &str) -> String {
+        to_markdown(html, &mut |name| Some(name.to_string()))
+    }
+
+    #[test]
+    fn inline_formatting_keeps_whitespace_outside_markers() {
+        assert_eq!(md("The <b>capital </b>of <i>France</i>"), "The **capital** of *France*");
+        assert_eq!(md("<s>old</s> <code>a*b</code>"), "~~old~~ `a*b`");
+        assert_eq!(md("a<b> </b>b"), "a b");
+    }
+
+    #[test]
+    fn divs_and_breaks_become_hard_breaks_and_paragraphs() {
+        assert_eq!(md("<div>one</div><div>two</div>"), "one\\\ntwo");
+        assert_eq!(md("one<br><br>two"), "one\n\ntwo");
+        assert_eq!(md("<p>one</p><p>two</p>"), "one\n\ntwo");
+    }
+
+    #[test]
+    fn lists_nest_and_number() {
+        assert_eq!(
+            md("<ul><li>a<ol><li>x</li><li>y</li></ol></li><li>b</li></ul>"),
+            "- a\n  1. x\n  2. y\n- b"
+        );
+    }
+
+    #[test]
+    fn blockquote_and_heading() {
+        assert_eq!(md("<h2>Title</h2><blockquote>q<br>r</blockquote>"), "## Title\n\n> q\\\n> r");
+    }
+
+    #[test]
+    fn prose_is_escaped() {
+        assert_eq!(md("2*3 = [x] &amp; #tag"), "2\\*3 \\= \\[x\\] & &#35;tag");
+        assert_eq!(md("- not a list<br>1. nor this"), "\\- not a list\\\n1\\. nor this");
+        assert_eq!(md("a &lt;b&gt; $5"), "a \\<b> \\$5");
+    }
+
+    #[test]
+    fn links_and_entities() {
+        assert_eq!(md(r#"<a href="https://x.org/a b">site</a>"#), "[site](https://x.org/a%20b)");
+        assert_eq!(md("caf&eacute;&nbsp;&#233;&#x41;"), "caf\\&eacute; éA");
+    }
+
+    #[test]
+    fn pre_becomes_fenced_code() {
+        assert_eq!(
+            md("before<pre>fn main() {<br>  1 &lt; 2<br>}</pre>after"),
+            "before\n\n```\nfn main() {\n  1 < 2\n}\n```\n\nafter"
+        );
+    }
+
+    #[test]
+    fn local_images_and_sounds_become_media_blocks() {
+        let got = md(r#"<img src="flag%20de.png" alt="German flag"> [sound:hallo.mp3]"#);
+        assert_eq!(
+            got,
+            "```media\nsrc = \"media/flag de.png\"\nalt = \"German flag\"\n```\n\n\
+             ```media\nsrc = \"media/hallo.mp3\"\nanki_sound = true\n```"
+        );
+    }
+
+    #[test]
+    fn remote_or_missing_media_stays_inline() {
+        assert_eq!(md(r#"<img src="https://x.org/a.png">"#), "![](https://x.org/a.png)");
+        assert_eq!(md(r#"<img src="a.tiff" alt="t">"#), "![t](a.tiff)");
+        let got = to_markdown(r#"<img src="gone.png">[sound:gone.mp3]"#, &mut |_| None);
+        assert_eq!(got, "![](gone.png)\\[sound:gone.mp3\\]");
+    }
+
+    #[test]
+    fn scripts_styles_and_unknown_tags() {
+        assert_eq!(
+            md("<style>.x{}</style><span style=\"c\">kept</span><script>no()</script>"),
+            "kept"
+        );
+    }
+}
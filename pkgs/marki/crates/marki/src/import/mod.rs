@@ -0,0 +1,600 @@
+//! `marki import`: turn an existing Anki deck into a marki repository.
+//!
+//! Reads every note from an `.anki2` collection or an `.apkg` package and
+//! writes one card file per note, in a directory tree shaped like its deck
+//! (`Languages::German` → `languages/German/…` under the cards dir, the
+//! `Default` deck at the root). Field HTML is converted back to markdown
+//! where it can be (see [`html`]), referenced media is copied into
+//! `.marki/media/`, and each file gets a freshly minted `#id(...)`.
+//!
+//! Adoption then hands the original notes over to marki without losing
+//! their review history. In the configured collection, the note with the
+//! same guid has its guid set to the minted id and gains the marker tag.
+//! Because it carries no content-hash tag yet, the next `push` updates it
+//! in place instead of adding a duplicate:
+//!
+//!   * a cloze note keeps its notetype, fields and cards untouched;
+//!   * any other note is moved onto `marki:basic`, keeping its first card
+//!     (and that card's scheduling). Its other cards, e.g. the reverse card
+//!     of "Basic (and reversed card)", have no counterpart and would be
+//!     removed with their scheduling, so such a note is held back (neither
+//!     written nor adopted) unless [`ImportOptions::drop_extra_cards`] is
+//!     set.
+
+mod html;
+
+use anyhow::{Context, Result};
+use marki_anki::apkg::Package;
+use marki_anki::{Collection, SourceNote};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::anki::model::MARKER_TAG;
+use crate::config::Config;
+use crate::fmt::format_card;
+use crate::id::mint_id;
+use crate::tag::{Parsed, parse_token};
+
+/// A `{{cN::text}}` or `{{cN::text::hint}}` cloze deletion.
+static CLOZE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)\{\{c(\d+)::(.*?)(?:::(?:.*?))?\}\}").unwrap());
+
+/// Emphasis tags, stripped from cloze text so that only the deletions end
+/// up bold (marki clozes every emphasised span).
+static EMPHASIS_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)</?(?:b|strong|i|em)(?:\s[^>]*)?>").unwrap());
+
+/// Anki tags that survive as `#tag` tokens.
+static TAG_NAME_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z][\w:\-]*$").unwrap());
+
+/// Longest slug taken from a note's front, in characters.
+const MAX_SLUG_CHARS: usize = 60;
+
+/// Knobs for [`run`].
+#[derive(Debug, Default)]
+pub struct ImportOptions {
+    /// Only import notes in this deck or its subdecks.
+    pub deck: Option<String>,
+    /// Report what would be written without touching disk or collection.
+    pub dry_run: bool,
+    /// Skip adoption: write the files but leave the collection alone.
+    pub no_adopt: bool,
+    /// Adopt notes with more than one card anyway, deleting every card
+    /// but the first.
+    pub drop_extra_cards: bool,
+}
+
+/// Counts from one [`run`].
+#[derive(Debug, Default)]
+pub struct ImportOutcome {
+    /// Card files written.
+    pub written: usize,
+    /// Media files copied into `.marki/media/`.
+    pub media: usize,
+    /// Notes handed over to marki in the collection.
+    pub adopted: usize,
+    /// Notes skipped because marki already manages them.
+    pub skipped: usize,
+    /// Cards removed while converting notes onto `marki:basic` (on a dry
+    /// run, the cards that would be).
+    pub removed_cards: usize,
+    /// Card files a dry run would have written.
+    pub planned: Vec<PathBuf>,
+    /// Non-fatal problems: unconvertible tags, missing media, notes absent
+    /// from the collection.
+    pub warnings: Vec<String>,
+}
+
+/// Import `source` (`.anki2` or `.apkg`) into the project described by
+/// `cfg`, then adopt the imported notes in `cfg`'s collection.
+pub fn run(source: &Path, cfg: &Config, opts: &ImportOptions) -> Result<ImportOutcome> {
+    let is_package = source
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("apkg") || e.eq_ignore_ascii_case("colpkg"));
+
+    if is_package {
+        let work = std::env::temp_dir().join(format!("marki-import-{}", std::process::id()));
+        let work = WorkDir(work);
+        let package = Package::open(source, &work.0)?;
+        let mut col = Collection::open(package.collection_path())?;
+        import_from(&mut col, MediaSource::Package(package), None, cfg, opts)
+    } else {
+        let mut col = Collection::open(source)?;
+        let media = MediaSource::Dir(source.with_extension("media"));
+        import_from(&mut col, media, Some(source), cfg, opts)
+    }
+}
+
+/// Temporary extraction directory, removed on drop.
+struct WorkDir(PathBuf);
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Where referenced media comes from: the collection's `.media` folder or
+/// the package itself.
+enum MediaSource {
+    Dir(PathBuf),
+    Package(Package),
+}
+
+impl MediaSource {
+    fn read(&mut self, name: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            MediaSource::Dir(dir) => {
+                let path = dir.join(name);
+                if !path.is_file() {
+                    return Ok(None);
+                }
+                let bytes =
+                    std::fs::read(&path).with_context(|| format!("read {}", path.display()))?;
+                Ok(Some(bytes))
+            }
+            MediaSource::Package(p) => p.read_media(name),
+        }
+    }
+}
+
+/// Copies referenced media into `.marki/media/` once per name, renaming on
+/// a clash with different content.
+struct MediaCopier {
+    source: MediaSource,
+    dest: PathBuf,
+    dry_run: bool,
+    /// Source name -> stored name, or `None` if it could not be imported.
+    done: HashMap<String, Option<String>>,
+    copied: usize,
+    warnings: Vec<String>,
+}
+
+impl MediaCopier {
+    fn copy(&mut self, name: &str) -> Option<String> {
+        if let Some(stored) = self.done.get(name) {
+            return stored.clone();
+        }
+        let stored = match self.store(name) {
+            Ok(stored) => stored,
+            Err(e) => {
+                self.warnings.push(format!("media {name}: {e:#}"));
+                None
+            }
+        };
+        self.done.insert(name.to_string(), stored.clone());
+        stored
+    }
+
+    fn store(&mut self, name: &str) -> Result<Option<String>> {
+        let Some(bytes) = self.source.read(name)? else {
+            self.warnings.push(format!("media {name} is referenced but missing; kept inline"));
+            return Ok(None);
+        };
+        let mut stored = name.to_string();
+        let mut path = self.dest.join(&stored);
+        if path.exists() && std::fs::read(&path)? != bytes {
+            let hash = blake3::hash(&bytes).to_hex();
+            stored = match name.rsplit_once('.') {
+                Some((stem, ext)) => format!("{stem}-{}.{ext}", &hash[..8]),
+                None => format!("{name}-{}", &hash[..8]),
+            };
+            path = self.dest.join(&stored);
+        }
+        if !path.exists() {
+            if !self.dry_run {
+                std::fs::create_dir_all(&self.dest)
+                    .with_context(|| format!("create {}", self.dest.display()))?;
+                std::fs::write(&path, &bytes)
+                    .with_context(|| format!("write {}", path.display()))?;
+            }
+            self.copied += 1;
+        }
+        Ok(Some(stored))
+    }
+}
+
+/// A note written to disk and waiting to be adopted.
+struct Adoption {
+    source_guid: String,
+    guid: String,
+    cloze: bool,
+    fields: Vec<String>,
+    tags: Vec<String>,
+}
+
+fn import_from(
+    col: &mut Collection,
+    media: MediaSource,
+    source_path: Option<&Path>,
+    cfg: &Config,
+    opts: &ImportOptions,
+) -> Result<ImportOutcome> {
+    let mut outcome = ImportOutcome::default();
+    let mut copier = MediaCopier {
+        source: media,
+        dest: cfg.builtin_media_dir(),
+        dry_run: opts.dry_run,
+        done: HashMap::new(),
+        copied: 0,
+        warnings: Vec::new(),
+    };
+    let cards_dir = cfg.resolved_cards_dir();
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+    let mut adoptions = Vec::new();
+
+    // Opened up front so a note's cards are counted before its file is
+    // written: a note that would lose cards is held back entirely.
+    let target_path = if opts.no_adopt { None } else { cfg.resolved_collection() };
+    let target = match &target_path {
+        Some(p) if source_path.is_some_and(|s| same_file(s, p)) => Some(Target::Source),
+        Some(p) => Some(Target::Other(
+            Collection::open(p).with_context(|| format!("open collection {}", p.display()))?,
+        )),
+        None => None,
+    };
+    let (mut found, mut removals) = (0, 0);
+
+    for note in col.source_notes().context("read source notes")? {
+        if let Some(deck) = &opts.deck
+            && note.deck != *deck
+            && !note.deck.starts_with(&format!("{deck}::"))
+        {
+            continue;
+        }
+        if note.tags.iter().any(|t| t == MARKER_TAG) {
+            outcome.skipped += 1;
+            continue;
+        }
+        let cards = match &target {
+            Some(Target::Source) => col.note_card_count(&note.guid)?,
+            Some(Target::Other(t)) => t.note_card_count(&note.guid)?,
+            None => None,
+        };
+        let extra = extra_cards(note.cloze, cards);
+        if extra > 0 {
+            if !opts.drop_extra_cards {
+                outcome.warnings.push(format!(
+                    "note {}: its {} {} cards would become one marki:basic card; \
+                     held back (--drop-extra-cards converts it, deleting the others)",
+                    note.note_id,
+                    extra + 1,
+                    note.notetype
+                ));
+                continue;
+            }
+            removals += extra;
+        }
+        found += usize::from(cards.is_some());
+
+        let (body, front) = note_body(&note, &mut copier, &mut outcome.warnings);
+        let mut tag_tokens = Vec::new();
+        let mut kept_tags = Vec::new();
+        if note.cloze {
+            tag_tokens.push("#cloze".to_string());
+        }
+        for tag in &note.tags {
+            match convert_tag(tag) {
+                TagFate::Keep(token) => {
+                    tag_tokens.push(token);
+                    kept_tags.push(tag.clone());
+                }
+                TagFate::Drop => {}
+                TagFate::Skip => outcome
+                    .warnings
+                    .push(format!("note {}: tag `{tag}` has no #tag form; dropped", note.note_id)),
+            }
+        }
+
+        let guid = mint_id();
+        let source = if tag_tokens.is_empty() {
+            body
+        } else {
+            format!("{body}\n\n{}", tag_tokens.join(" "))
+        };
+        let content = format_card(&source, &guid);
+
+        let dir = deck_dir(&cards_dir, &note.deck);
+        let path = unique_path(&dir, &slug(&front, note.note_id), &mut claimed);
+        if opts.dry_run {
+            outcome.planned.push(path);
+        } else {
+            std::fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+            std::fs::write(&path, content).with_context(|| format!("write {}", path.display()))?;
+        }
+        outcome.written += 1;
+
+        adoptions.push(Adoption {
+            source_guid: note.guid.clone(),
+            guid,
+            cloze: note.cloze,
+            fields: basic_fields(&note),
+            tags: kept_tags,
+        });
+    }
+
+    outcome.media = copier.copied;
+    outcome.warnings.append(&mut copier.warnings);
+
+    if opts.no_adopt || adoptions.is_empty() {
+        return Ok(outcome);
+    }
+    let Some(target) = target else {
+        outcome.warnings.push(
+            "no collection configured; files were written but no notes were adopted".into(),
+        );
+        return Ok(outcome);
+    };
+    if opts.dry_run {
+        outcome.adopted = found;
+        outcome.removed_cards = removals;
+        warn_missing(adoptions.len() - found, &mut outcome);
+        return Ok(outcome);
+    }
+    // Drop the source's handle on the media package before writing.
+    drop(copier);
+    match target {
+        Target::Source => adopt(col, cfg, &adoptions, &mut outcome)?,
+        Target::Other(mut other) => adopt(&mut other, cfg, &adoptions, &mut outcome)?,
+    }
+    Ok(outcome)
+}
+
+/// The collection imported notes are adopted into.
+enum Target {
+    /// The collection being imported from.
+    Source,
+    Other(Collection),
+}
+
+/// Markdown body of a note, plus its front as markdown (for the slug).
+fn note_body(
+    note: &SourceNote,
+    copier: &mut MediaCopier,
+    warnings: &mut Vec<String>,
+) -> (String, String) {
+    let mut convert = |html: &str| html::to_markdown(html, &mut |name| copier.copy(name));
+    let first = note.fields.first().map(String::as_str).unwrap_or_default();
+
+    let front = if note.cloze {
+        let (text, numbers) = cloze_to_bold(first);
+        if !numbers.iter().enumerate().all(|(i, n)| *n as usize == i + 1) {
+            warnings.push(format!(
+                "note {}: clozes numbered {numbers:?}; marki numbers each bold span in order",
+                note.note_id
+            ));
+        }
+        convert(&text)
+    } else {
+        convert(first)
+    };
+    let back: Vec<String> = note.fields[1.min(note.fields.len())..]
+        .iter()
+        .map(|f| convert(f))
+        .filter(|md| !md.is_empty())
+        .collect();
+
+    let body = if back.is_empty() {
+        front.clone()
+    } else {
+        format!("{front}\n\n---\n\n{}", back.join("\n\n"))
+    };
+    (body, front)
+}
+
+/// Rewrite `{{cN::text}}` deletions as bold spans, stripping any other
+/// emphasis first. Returns the HTML and the cloze numbers in order.
+fn cloze_to_bold(html: &str) -> (String, Vec<u32>) {
+    let plain = EMPHASIS_REGEX.replace_all(html, "");
+    let mut numbers = Vec::new();
+    let out = CLOZE_REGEX.replace_all(&plain, |c: &regex::Captures| {
+        numbers.push(c[1].parse().unwrap_or(0));
+        format!("<b>{}</b>", &c[2])
+    });
+    (out.into_owned(), numbers)
+}
+
+/// Cards adoption would delete from a note that has `cards` cards in the
+/// target collection (`None`: not there, so nothing is adopted).
+fn extra_cards(cloze: bool, cards: Option<usize>) -> usize {
+    match cards {
+        Some(n) if !cloze => n.saturating_sub(1),
+        _ => 0,
+    }
+}
+
+/// Field values for a note converted onto `marki:basic`: the first field
+/// as the front, the rest joined as the back. Only a placeholder until the
+/// next push renders the card file.
+fn basic_fields(note: &SourceNote) -> Vec<String> {
+    let front = note.fields.first().cloned().unwrap_or_default();
+    let back: Vec<&str> = note.fields[1.min(note.fields.len())..]
+        .iter()
+        .map(String::as_str)
+        .filter(|f| !f.is_empty())
+        .collect();
+    vec![front, back.join("<br>")]
+}
+
+enum TagFate {
+    /// Emit as this `#tag` token.
+    Keep(String),
+    /// A marki bookkeeping tag; drop silently.
+    Drop,
+    /// Not expressible as a `#tag` (or it would be read as a system tag).
+    Skip,
+}
+
+fn convert_tag(tag: &str) -> TagFate {
+    if tag == MARKER_TAG || tag.starts_with("marki::") {
+        return TagFate::Drop;
+    }
+    if !TAG_NAME_REGEX.is_match(tag) {
+        return TagFate::Skip;
+    }
+    let token = format!("#{tag}");
+    match parse_token(&token) {
+        Parsed::AnkiTag(_) => TagFate::Keep(token),
+        _ => TagFate::Skip,
+    }
+}
+
+/// Directory for a deck: one path component per `::` level under the
+/// cards dir, with `Default` (and cardless notes) at the root.
+fn deck_dir(cards_dir: &Path, deck: &str) -> PathBuf {
+    if deck.is_empty() || deck == "Default" {
+        return cards_dir.to_path_buf();
+    }
+    deck.split("::").fold(cards_dir.to_path_buf(), |dir, part| {
+        let part = part.trim().replace(['/', '\\'], "-");
+        let part = if part.is_empty() || part.starts_with('.') { format!("_{part}") } else { part };
+        dir.join(part)
+    })
+}
+
+/// File stem from the first line of a note's front: lowercase
+/// alphanumerics joined by `-`, at most [`MAX_SLUG_CHARS`], falling back
+/// to `note-<id>`.
+fn slug(front: &str, note_id: i64) -> String {
+    let first_line = front.lines().find(|l| !l.trim().is_empty()).unwrap_or_default();
+    let mut out = String::new();
+    for c in first_line.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            out.push(c);
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+        if out.chars().count() >= MAX_SLUG_CHARS {
+            break;
+        }
+    }
+    let out = out.trim_end_matches('-');
+    if out.is_empty() { format!("note-{note_id}") } else { out.to_string() }
+}
+
+/// `<dir>/<stem>.md`, suffixed `-2`, `-3`, … until it names neither an
+/// existing file nor one already claimed this run.
+fn unique_path(dir: &Path, stem: &str, claimed: &mut HashSet<PathBuf>) -> PathBuf {
+    let mut n = 1;
+    loop {
+        let name = if n == 1 { format!("{stem}.md") } else { format!("{stem}-{n}.md") };
+        let path = dir.join(name);
+        if !path.exists() && claimed.insert(path.clone()) {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Hand the imported notes over to marki in one transaction.
+fn adopt(
+    col: &mut Collection,
+    cfg: &Config,
+    adoptions: &[Adoption],
+    outcome: &mut ImportOutcome,
+) -> Result<()> {
+    let spec = crate::sync::engine::basic_spec(&cfg.resolved_models_dir());
+    let mut missing = 0;
+    let (adopted, removed) = col
+        .transact(|w| {
+            let mut basic_mid = None;
+            let (mut adopted, mut removed) = (0, 0);
+            for a in adoptions {
+                let Some(note_id) = w.note_id_by_guid(&a.source_guid)? else {
+                    missing += 1;
+                    continue;
+                };
+                let mut tags = vec![MARKER_TAG.to_string()];
+                tags.extend(a.tags.iter().cloned());
+                if a.cloze {
+                    w.adopt_note(note_id, &a.guid, &tags)?;
+                } else {
+                    let mid = match basic_mid {
+                        Some(mid) => mid,
+                        None => *basic_mid.insert(w.ensure_model(&spec)?),
+                    };
+                    removed += w.convert_note(note_id, &a.guid, mid, a.fields.clone(), 0, &tags)?;
+                }
+                adopted += 1;
+            }
+            Ok((adopted, removed))
+        })
+        .context("adopt imported notes")?;
+    outcome.adopted = adopted;
+    outcome.removed_cards = removed;
+    warn_missing(missing, outcome);
+    Ok(())
+}
+
+fn warn_missing(missing: usize, outcome: &mut ImportOutcome) {
+    if missing > 0 {
+        outcome.warnings.push(format!(
+            "{missing} imported note(s) are not in the collection and were not adopted; \
+             import the package into Anki first to keep their scheduling"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clozes_become_bold_spans() {
+        let (html, numbers) =
+            cloze_to_bold("<b>Paris</b> is {{c1::the capital}} of {{c2::France::country}}");
+        assert_eq!(html, "Paris is <b>the capital</b> of <b>France</b>");
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn only_multi_card_basic_notes_lose_cards() {
+        assert_eq!(extra_cards(false, Some(2)), 1);
+        assert_eq!(extra_cards(false, Some(1)), 0);
+        assert_eq!(extra_cards(true, Some(3)), 0);
+        assert_eq!(extra_cards(false, None), 0);
+    }
+
+    #[test]
+    fn tags_convert_or_are_reported() {
+        assert!(matches!(convert_tag("geo::europe"), TagFate::Keep(t) if t == "#geo::europe"));
+        assert!(matches!(convert_tag("marki::hash:abc"), TagFate::Drop));
+        assert!(matches!(convert_tag("2024"), TagFate::Skip));
+        assert!(matches!(convert_tag("cloze"), TagFate::Skip));
+    }
+
+    #[test]
+    fn decks_map_to_directories() {
+        let root = Path::new("/cards");
+        assert_eq!(deck_dir(root, "Default"), PathBuf::from("/cards"));
+        assert_eq!(deck_dir(root, ""), PathBuf::from("/cards"));
+        assert_eq!(deck_dir(root, "Lang::German/Verbs"), PathBuf::from("/cards/Lang/German-Verbs"));
+        assert_eq!(deck_dir(root, "..::x"), PathBuf::from("/cards/_../x"));
+    }
+
+    #[test]
+    fn slugs_come_from_the_front() {
+        assert_eq!(slug("What is **the** capital of France?", 7), "what-is-the-capital-of-france");
+        assert_eq!(slug("```media\nsrc = \"x\"\n```", 7), "media");
+        assert_eq!(slug("", 7), "note-7");
+        assert_eq!(slug(&"a".repeat(100), 7).len(), MAX_SLUG_CHARS);
+    }
+
+    #[test]
+    fn unique_paths_do_not_collide() {
+        let dir = Path::new("/nonexistent-marki-import");
+        let mut claimed = HashSet::new();
+        assert_eq!(unique_path(dir, "a", &mut claimed), dir.join("a.md"));
+        assert_eq!(unique_path(dir, "a", &mut claimed), dir.join("a-2.md"));
+    }
+}
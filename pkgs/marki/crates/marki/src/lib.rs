@@ -1,10 +1,12 @@
 //! marki internals shared between the `main` binary and integration tests.
 
 pub mod anki;
+pub mod cache;
 pub mod config;
 pub mod fmt;
 pub mod highlighter;
 pub mod id;
+pub mod import;
 pub mod note;
 pub mod note_parser;
 pub mod render;
@@ -3,16 +3,18 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use marki_anki::Collection;
+use marki_anki::remote::{Credentials, Remote};
 use marki::config::Config;
 use marki::fmt as fmt_mod;
 use marki::render::Registry;
 use marki::scan::scan_dir_v2;
 use marki::scripting::engine::ScriptEngine;
-use marki::sync::reconcile;
+use marki::sync::{Backend, DirectBackend, ServerBackend, reconcile};
+use marki::watch::control::{self, Request};
 use marki::watch::{Tick, run as run_watch};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tracing_subscriber::EnvFilter;
 
 #[derive(Parser)]
@@ -21,7 +23,8 @@ use tracing_subscriber::EnvFilter;
     version,
     about = "Sync a markdown card repo with an Anki collection -- a one-shot CLI (optional watch daemon).",
     long_about = "marki keeps a directory of markdown flashcards in sync with an Anki \
-collection file (`.anki2`), which it writes directly.\n\nIt is repo-centric: run it from \
+collection file (`.anki2`), which it writes directly, or through an Anki sync server \
+configured as `[sync_server]`.\n\nIt is repo-centric: run it from \
 inside a flashcard repo and it discovers a hidden `.marki/` directory (git-style, walking up \
 from the current directory) holding the config, models, libraries and media that define your \
 cards. `marki init` scaffolds one.\n\n\
@@ -42,17 +45,45 @@ struct Cli {
     #[arg(long, env = "MARKI_COLLECTION", global = true)]
     collection: Option<PathBuf>,
 
+    /// Publish into the `[targets.<name>]` collection instead of the
+    /// top-level one: only the cards its globs select, under its deck
+    /// prefix.
+    #[arg(long, global = true, conflicts_with = "collection")]
+    target: Option<String>,
+
     /// Override the directory containing media files used by ```media``` blocks.
     /// Adds a single unnamed source (searched last, after any [media_sources]
     /// configured in the config file).
     #[arg(long, env = "MARKI_MEDIA_DIR", global = true)]
     media_dir: Option<PathBuf>,
 
-    /// Path to the `typst` CLI binary, used to render ```typst``` blocks.
-    /// When unset, ```typst``` blocks fall through to syntax highlighting.
+    /// Path to the `typst` CLI binary, used to render ```typst``` blocks
+    /// and `$…$` math. When unset, ```typst``` blocks fall through to syntax
+    /// highlighting and math is left to MathJax.
     #[arg(long, env = "MARKI_TYPST", global = true)]
     typst_binary: Option<PathBuf>,
 
+    /// Extra font directory for Typst (repeatable). Replaces the
+    /// configured `typst_font_paths` when given.
+    #[arg(long = "typst-font-path", env = "MARKI_TYPST_FONT_PATHS", value_delimiter = ':')]
+    #[arg(global = true)]
+    typst_font_paths: Vec<PathBuf>,
+
+    /// Local Typst package directory (`typst --package-path`).
+    #[arg(long, env = "MARKI_TYPST_PACKAGE_PATH", global = true)]
+    typst_package_path: Option<PathBuf>,
+
+    /// ffmpeg-compatible binary used to clip OGG/M4A audio in ```media```
+    /// blocks. WAV and MP3 clips are cut in-process.
+    #[arg(long, env = "MARKI_AUDIO_ENCODER", global = true)]
+    audio_encoder: Option<PathBuf>,
+
+    /// Speech synthesiser (`espeak-ng` or `piper`) used to render
+    /// ```tts``` blocks. When unset, ```tts``` blocks fall through to
+    /// syntax highlighting.
+    #[arg(long, env = "MARKI_TTS", global = true)]
+    tts_binary: Option<PathBuf>,
+
     /// Increase log verbosity. Repeat for more detail: `-v` enables
     /// `debug`, `-vv` enables `trace`. Overridden by an explicit
     /// `RUST_LOG`/env filter when one is set.
@@ -83,9 +114,27 @@ enum Cmd {
         prune: bool,
     },
     /// Long-running daemon: watch the cards directory and push on change.
+    /// Listens on a control socket for `marki ctl`, and speaks sd_notify
+    /// (readiness, status, watchdog) when run as a `Type=notify` unit.
     Watch,
+    /// Talk to a running `marki watch`: show its status, push now,
+    /// pause/resume syncing, or reload config and models.
+    Ctl {
+        request: Request,
+        /// Print the raw JSON status.
+        #[arg(long)]
+        json: bool,
+    },
     /// Read-only diff view (added / updated / moved / deleted / unformatted).
     Status,
+    /// Show what a push would change, per note: a unified diff of the
+    /// field HTML in the collection against the newly rendered fields,
+    /// and which input changed (source, model script, CSS, renderer
+    /// version). Read-only.
+    Diff {
+        /// Limit to these card files or directories (default: all).
+        paths: Vec<PathBuf>,
+    },
     /// Permanently delete notes previously quarantined (soft-deleted):
     /// every note tagged `marki::orphan`. Run this once you've confirmed
     /// the suspended notes really should be gone.
@@ -94,6 +143,41 @@ enum Cmd {
         #[arg(long)]
         dry_run: bool,
     },
+    /// Re-apply the new-card order to notes already in the collection:
+    /// cards not yet studied get consecutive positions following the repo
+    /// layout (directories and file names in natural order, `#order(n)`
+    /// or front-matter `order:` first within a directory). Studied cards
+    /// keep their schedule.
+    Reorder {
+        /// Report how many notes would move without touching Anki.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Convert an existing Anki deck into card files: one `.md` per note
+    /// in a deck-shaped tree, media copied into `.marki/media/`. The notes
+    /// are then adopted in the configured collection (new guid + marker
+    /// tag) so the next push updates them in place and keeps their
+    /// scheduling. Non-cloze notes move onto `marki:basic`, keeping only
+    /// their first card.
+    Import {
+        /// An `.anki2` collection (media read from the sibling
+        /// `<name>.media/`) or an `.apkg` package.
+        source: PathBuf,
+        /// Only import notes in this deck and its subdecks.
+        #[arg(long)]
+        deck: Option<String>,
+        /// List the files that would be written and count the cards
+        /// adoption would remove; touch nothing.
+        #[arg(long)]
+        dry_run: bool,
+        /// Write the files but leave the collection's notes alone.
+        #[arg(long)]
+        no_adopt: bool,
+        /// Also adopt notes with several cards (e.g. "Basic (and reversed
+        /// card)"), keeping only their first card and its scheduling.
+        #[arg(long)]
+        drop_extra_cards: bool,
+    },
     /// Render every external block in a single .md file to disk and
     /// print the resulting HTML on stdout. No Anki round-trip — useful
     /// for theme iteration.
@@ -111,10 +195,40 @@ enum Cmd {
         #[arg(long)]
         stdout: bool,
     },
+    /// Inspect and trim the renderer cache (`$XDG_CACHE_HOME/marki/`).
+    Cache {
+        #[command(subcommand)]
+        action: CacheCmd,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCmd {
+    /// Report entry count and size per renderer.
+    Stats,
+    /// Delete cache entries no current card renders. Runs a read-only
+    /// render pass over the repo first (needs the collection, like
+    /// `status`); refuses to prune if that pass had render errors.
+    Prune {
+        /// Skip the render pass; instead delete every entry not used
+        /// within this long (`30d`, `12h`, `90m`, bare seconds).
+        #[arg(long, value_parser = marki::config::parse_duration)]
+        older_than: Option<Duration>,
+        /// Report what would be deleted without deleting it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Re-hash every completed cache entry against the manifest in its
+    /// `.ready` marker. Exits non-zero when anything is corrupt.
+    Verify {
+        /// Delete corrupt entries so the next push re-renders them.
+        #[arg(long)]
+        fix: bool,
+    },
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
 
     // Verbosity: an explicit RUST_LOG/env filter always wins; otherwise
     // `-v` bumps the default level. Logs go to stderr so stdout stays
@@ -150,32 +264,40 @@ fn main() -> Result<()> {
     let cfg = load_config(&cli)?;
 
     // No subcommand → run a single push (one-shot first).
-    let cmd = cli.cmd.unwrap_or(Cmd::Push { prune: false });
+    let cmd = cli.cmd.take().unwrap_or(Cmd::Push { prune: false });
 
     match cmd {
         Cmd::Init => unreachable!("handled above"),
         Cmd::Fmt => cmd_fmt(&cfg),
         Cmd::Push { prune } => {
-            let mut col = open_collection(&cfg)?;
+            let mut backend = open_backend(&cfg)?;
             let registry = Arc::new(build_registry(&cfg));
             let mut script_engine = build_script_engine(&cfg);
-            cmd_push(&mut col, &cfg, &registry, &mut script_engine, prune)
+            cmd_push(backend.as_mut(), &cfg, &registry, &mut script_engine, prune)
         }
         Cmd::Status => {
-            let mut col = open_collection(&cfg)?;
+            let mut backend = open_backend(&cfg)?;
             let registry = Arc::new(build_registry(&cfg));
             let mut script_engine = build_script_engine(&cfg);
-            run_cycle(&mut col, &cfg, &registry, &mut script_engine, true, false)?;
+            run_cycle(backend.as_mut(), &cfg, &registry, &mut script_engine, true, false)?;
             Ok(())
         }
-        Cmd::Prune { dry_run } => cmd_prune(&cfg, dry_run),
-        Cmd::Watch => {
-            let mut col = open_collection(&cfg)?;
+        Cmd::Diff { paths } => {
+            let mut backend = open_backend(&cfg)?;
             let registry = Arc::new(build_registry(&cfg));
             let mut script_engine = build_script_engine(&cfg);
-            cmd_watch(&mut col, &cfg, &registry, &mut script_engine)
+            cmd_diff(backend.as_mut(), &cfg, &registry, &mut script_engine, &paths)
+        }
+        Cmd::Prune { dry_run } => cmd_prune(&cfg, dry_run),
+        Cmd::Reorder { dry_run } => cmd_reorder(&cfg, dry_run),
+        Cmd::Import { source, deck, dry_run, no_adopt, drop_extra_cards } => {
+            let opts = marki::import::ImportOptions { deck, dry_run, no_adopt, drop_extra_cards };
+            cmd_import(&source, &cfg, &opts)
         }
+        Cmd::Watch => cmd_watch(&cli, cfg),
+        Cmd::Ctl { request, json } => cmd_ctl(&cfg, request, json),
         Cmd::RenderMap { .. } => unreachable!("handled above"),
+        Cmd::Cache { action } => cmd_cache(&cfg, action),
     }
 }
 
@@ -183,8 +305,10 @@ fn main() -> Result<()> {
 /// registered when at least one media source exists — the built-in
 /// git-tracked `.marki/media/` directory (searched first) plus any
 /// `[media_sources]` from config. Otherwise ```media``` blocks fall
-/// through to plain code rendering. Likewise, the typst renderer is only
-/// registered when a typst binary is configured.
+/// through to plain code rendering. Likewise, the typst renderers are only
+/// registered when a typst binary is configured; with them, `$…$` spans in
+/// prose are typeset by Typst instead of left to MathJax. The tts
+/// renderer needs a configured speech synthesiser the same way.
 fn build_registry(cfg: &Config) -> Registry {
     let mut reg = Registry::new();
     let map_renderer =
@@ -195,8 +319,17 @@ fn build_registry(cfg: &Config) -> Registry {
                 marki_map::MapRenderer::new()
             }
         };
+    let map_renderer = match cfg.render.raster {
+        Some(r) => map_renderer.with_raster(r),
+        None => map_renderer,
+    };
     reg.register(Box::new(map_renderer));
 
+    match cfg.highlighter() {
+        Ok(h) => reg.set_highlighter(h),
+        Err(e) => tracing::warn!("invalid [highlight] config ({e:#}); using the default theme"),
+    }
+
     let mut sources: Vec<(String, std::path::PathBuf)> = Vec::new();
     // Built-in primary media dir, searched first when it exists.
     let builtin = cfg.builtin_media_dir();
@@ -210,11 +343,20 @@ fn build_registry(cfg: &Config) -> Registry {
     );
 
     if !sources.is_empty() {
-        reg.register(Box::new(marki_media::MediaRenderer::new(sources)));
+        let mut media = marki_media::MediaRenderer::new(sources);
+        if let Some(bin) = &cfg.audio_encoder {
+            media = media.with_audio_encoder(bin.clone());
+        }
+        reg.register(Box::new(media));
+    }
+
+    if let Some(setup) = cfg.typst_setup() {
+        reg.register(Box::new(marki_typst::TypstRenderer::with_setup(setup.clone())));
+        reg.register(Box::new(marki_typst::TypstMathRenderer::with_setup(setup)));
     }
 
-    if let Some(bin) = &cfg.typst_binary {
-        reg.register(Box::new(marki_typst::TypstRenderer::new(bin.clone())));
+    if let Some(setup) = cfg.tts_setup() {
+        reg.register(Box::new(marki_tts::TtsRenderer::with_setup(setup)));
     }
 
     reg
@@ -225,18 +367,47 @@ fn build_script_engine(cfg: &Config) -> ScriptEngine {
     let models_dir = cfg.resolved_models_dir();
     let lib_dir = cfg.resolved_lib_dir();
     let lib = if lib_dir.exists() { Some(lib_dir) } else { None };
-    ScriptEngine::new(models_dir, lib)
+    ScriptEngine::new(models_dir, lib).with_data_roots(
+        cfg.project_root.clone(),
+        &[cfg.resolved_cards_dir(), cfg.anchor_dir.clone()],
+    )
 }
 
 /// Open the configured Anki collection file, or fail with guidance when the
 /// `collection` key is unset.
 fn open_collection(cfg: &Config) -> Result<Collection> {
     let path = cfg.resolved_collection().context(
-        "no collection configured; set `collection` in .marki/config.toml or pass --collection",
+        "no collection configured; set `collection` or `[sync_server]` in .marki/config.toml \
+         or pass --collection",
     )?;
     Collection::open(&path).with_context(|| format!("open collection {}", path.display()))
 }
 
+/// Open what push/status/diff/watch/prune write to (for the selected
+/// target, if any): the sync server when one is configured, else the
+/// collection file with its media directory and `media.db`.
+fn open_backend(cfg: &Config) -> Result<Box<dyn Backend>> {
+    let Some(server) = cfg.resolved_sync_server() else {
+        let col = open_collection(cfg)?;
+        let media_dir = cfg.media_dir().context("derive media dir from collection")?;
+        let media_db = cfg.media_db_path().context("derive media db path from collection")?;
+        return Ok(Box::new(DirectBackend::new(col, media_dir, media_db)));
+    };
+    // One snapshot per server account, kept across runs so steady-state
+    // cycles skip the download.
+    let key = blake3::hash(format!("{}\0{}", server.url, server.user).as_bytes());
+    let work_dir = render_cache_dir().join("sync").join(&key.to_hex()[..16]);
+    let creds = Credentials {
+        endpoint: server.url.clone(),
+        user: server.user.clone(),
+        password: server.password.clone(),
+    };
+    let remote = Remote::connect(&creds, &work_dir)
+        .with_context(|| format!("connect to sync server {}", server.url))?
+        .allow_full_upload(server.allow_full_upload);
+    Ok(Box::new(ServerBackend::new(remote)))
+}
+
 /// Cache directory used by external block renderers. We default to
 /// `$XDG_CACHE_HOME/marki/` and fall back to `$HOME/.cache/marki/`.
 fn render_cache_dir() -> PathBuf {
@@ -274,15 +445,20 @@ fn load_config_for_render(cli: &Cli) -> Result<Config> {
     Ok(cfg)
 }
 
-/// Apply `--cards-dir`, `--anki-endpoint`, `--media-dir`, `--typst-binary`
-/// overrides on top of the loaded config. `--media-dir` adds a single
+/// Apply `--cards-dir`, `--anki-endpoint`, `--media-dir`, `--typst-*`,
+/// `--audio-encoder` overrides on top of the loaded config. `--media-dir` adds a single
 /// source searched after both the built-in media dir and config sources.
 fn apply_cli_overrides(cfg: &mut Config, cli: &Cli) -> Result<()> {
     if let Some(p) = &cli.cards_dir {
         cfg.cards_dir = p.clone();
     }
     if let Some(p) = &cli.collection {
+        // An explicit file wins over a configured sync server.
         cfg.collection = Some(p.clone());
+        cfg.sync_server = None;
+    }
+    if let Some(name) = &cli.target {
+        cfg.select_target(name)?;
     }
     if let Some(p) = &cli.media_dir {
         cfg.media_sources
@@ -292,11 +468,23 @@ fn apply_cli_overrides(cfg: &mut Config, cli: &Cli) -> Result<()> {
     if let Some(p) = &cli.typst_binary {
         cfg.typst_binary = Some(p.clone());
     }
+    if !cli.typst_font_paths.is_empty() {
+        cfg.typst_font_paths = cli.typst_font_paths.clone();
+    }
+    if let Some(p) = &cli.typst_package_path {
+        cfg.typst_package_path = Some(p.clone());
+    }
+    if let Some(p) = &cli.audio_encoder {
+        cfg.audio_encoder = Some(p.clone());
+    }
+    if let Some(p) = &cli.tts_binary {
+        cfg.tts_binary = Some(p.clone());
+    }
     Ok(())
 }
 
 fn run_cycle(
-    col: &mut Collection,
+    backend: &mut dyn Backend,
     cfg: &Config,
     registry: &Arc<Registry>,
     script_engine: &mut ScriptEngine,
@@ -308,8 +496,6 @@ fn run_cycle(
     let notes = scan_dir_v2(&cfg.cards_dir)?;
     let cache_dir = render_cache_dir();
     let models_dir = cfg.resolved_models_dir();
-    let media_dir = cfg.media_dir().context("derive media dir from collection")?;
-    let media_db = cfg.media_db_path().context("derive media db path from collection")?;
     tracing::debug!(
         notes = notes.len(),
         cards_dir = %cfg.cards_dir.display(),
@@ -318,31 +504,35 @@ fn run_cycle(
         prune,
         "starting reconcile cycle"
     );
+    let target = cfg.sync_target()?;
     let outcome = reconcile(
-        col,
+        backend,
         &cfg.cards_dir,
         &notes,
+        &target,
         script_engine,
         registry,
         &cache_dir,
         &models_dir,
-        &media_dir,
-        &media_db,
+        cfg.prerequisite_interval,
         dry_run,
         prune,
     )?;
     tracing::info!(
-        "cycle: +{} ~{} ->{} -{} (quarantined {}, skipped-prune {}, unformatted {}, {} errors)",
+        "cycle: +{} ~{} ->{} -{} (quarantined {}, skipped-prune {}, gated {}, released {}, \
+         unformatted {}, {} errors)",
         outcome.added,
         outcome.updated,
         outcome.moved,
         outcome.deleted,
         outcome.quarantined,
         outcome.skipped_prune,
+        outcome.gated,
+        outcome.released,
         outcome.unformatted,
         outcome.errors.len(),
     );
-    for e in &outcome.errors {
+    for e in outcome.errors.iter().chain(&outcome.warnings) {
         tracing::warn!("{e}");
     }
     Ok(outcome)
@@ -373,13 +563,13 @@ fn cmd_fmt(cfg: &Config) -> Result<()> {
 }
 
 fn cmd_push(
-    col: &mut Collection,
+    backend: &mut dyn Backend,
     cfg: &Config,
     registry: &Arc<Registry>,
     script_engine: &mut ScriptEngine,
     prune: bool,
 ) -> Result<()> {
-    let outcome = run_cycle(col, cfg, registry, script_engine, false, prune)?;
+    let outcome = run_cycle(backend, cfg, registry, script_engine, false, prune)?;
     // Surface failures with a non-zero exit so cron/systemd notices, instead
     // of silently "succeeding" while notes failed to render.
     if !outcome.errors.is_empty() {
@@ -392,16 +582,18 @@ fn cmd_push(
 }
 
 /// Permanently delete every note quarantined by a prior soft-delete
-/// (`tag:marki::orphan`). Separate, explicit, opt-in step. Reads the
-/// collection directly and removes the notes in one transaction.
+/// (`tag:marki::orphan`) of the selected target. Separate, explicit,
+/// opt-in step. Reads the collection and removes the notes in one
+/// transaction.
 fn cmd_prune(cfg: &Config, dry_run: bool) -> Result<()> {
     use marki::anki::model::{MARKER_TAG, ORPHAN_TAG};
 
-    let mut col = open_collection(cfg)?;
-    let managed = col.managed_notes(MARKER_TAG).context("read managed notes")?;
+    let target = cfg.sync_target()?;
+    let mut backend = open_backend(cfg)?;
+    let managed = backend.managed_notes(MARKER_TAG).context("read managed notes")?;
     let note_ids: Vec<i64> = managed
         .iter()
-        .filter(|n| n.tags.iter().any(|t| t == ORPHAN_TAG))
+        .filter(|n| target.owns(&n.tags) && n.tags.iter().any(|t| t == ORPHAN_TAG))
         .map(|n| n.note_id)
         .collect();
 
@@ -413,17 +605,220 @@ fn cmd_prune(cfg: &Config, dry_run: bool) -> Result<()> {
         println!("prune (dry-run): would delete {} quarantined note(s)", note_ids.len());
         return Ok(());
     }
-    col.transact(|w| {
-        for id in &note_ids {
-            w.remove_note(*id)?;
-        }
-        Ok(())
-    })
-    .context("delete quarantined notes")?;
+    backend
+        .transact(&mut |w| {
+            for id in &note_ids {
+                w.remove_note(*id)?;
+            }
+            Ok(())
+        })
+        .context("delete quarantined notes")?;
     println!("prune: deleted {} quarantined note(s)", note_ids.len());
     Ok(())
 }
 
+fn cmd_reorder(cfg: &Config, dry_run: bool) -> Result<()> {
+    let notes = scan_dir_v2(&cfg.cards_dir)?;
+    let mut backend = open_backend(cfg)?;
+    let moved = marki::sync::reorder(
+        backend.as_mut(),
+        &cfg.cards_dir,
+        &notes,
+        &cfg.sync_target()?,
+        dry_run,
+    )
+    .context("reorder new cards")?;
+    if dry_run {
+        println!("reorder (dry-run): would move {moved} note(s)");
+    } else {
+        println!("reorder: moved {moved} note(s)");
+    }
+    Ok(())
+}
+
+/// Print the field-level diff a push would apply, optionally limited to
+/// some card files or directories.
+fn cmd_diff(
+    backend: &mut dyn Backend,
+    cfg: &Config,
+    registry: &Arc<Registry>,
+    script_engine: &mut ScriptEngine,
+    paths: &[PathBuf],
+) -> Result<()> {
+    use marki::sync::diff::unified;
+
+    let mut notes = scan_dir_v2(&cfg.cards_dir)?;
+    if !paths.is_empty() {
+        let filters = paths
+            .iter()
+            .map(|p| p.canonicalize().with_context(|| format!("resolve {}", p.display())))
+            .collect::<Result<Vec<_>>>()?;
+        notes.retain(|sn| {
+            let path = sn.path.canonicalize().unwrap_or_else(|_| sn.path.clone());
+            filters.iter().any(|f| path.starts_with(f))
+        });
+    }
+    let report = marki::sync::diff(
+        backend,
+        &cfg.cards_dir,
+        &notes,
+        &cfg.sync_target()?,
+        script_engine,
+        registry,
+        &render_cache_dir(),
+        &cfg.resolved_models_dir(),
+    )?;
+
+    for (name, old, new) in &report.css {
+        println!("~ notetype {name}: css changed");
+        print!("{}", unified(old, new));
+    }
+    let root = &cfg.cards_dir;
+    for n in &report.notes {
+        let path = n.path.strip_prefix(root).unwrap_or(&n.path).display();
+        let why = match (&n.old_model, n.exists, &n.changed) {
+            (Some(old), _, _) => format!(
+                "notetype {old} -> {}; the note is re-added and loses its scheduling",
+                n.model
+            ),
+            (None, false, _) => "new note".to_string(),
+            (None, true, Some(changed)) if !changed.is_empty() => {
+                format!("changed: {}", changed.join(", "))
+            }
+            (None, true, Some(_)) => "changed: nothing recorded differs".to_string(),
+            (None, true, None) => "changed: unknown (inputs not recorded)".to_string(),
+        };
+        println!("{} {path} ({why})", if n.exists { '~' } else { '+' });
+        for (field, old, new) in &n.fields {
+            println!("--- {field} (collection)");
+            println!("+++ {field} (rendered)");
+            print!("{}", unified(old, new));
+        }
+    }
+
+    let added = report.notes.iter().filter(|n| !n.exists).count();
+    println!(
+        "diff: {} note(s) would change ({added} new), {} notetype(s) with new css, \
+         {} unformatted, {} error(s)",
+        report.notes.len(),
+        report.css.len(),
+        report.unformatted,
+        report.errors.len(),
+    );
+    for e in &report.errors {
+        eprintln!("warning: {e}");
+    }
+    Ok(())
+}
+
+fn cmd_import(source: &Path, cfg: &Config, opts: &marki::import::ImportOptions) -> Result<()> {
+    let outcome = marki::import::run(source, cfg, opts)
+        .with_context(|| format!("import {}", source.display()))?;
+    for path in &outcome.planned {
+        println!("would write {}", path.display());
+    }
+    println!(
+        "import{}: wrote {} file(s) ({} media), adopted {}, skipped {} already managed, \
+         removed {} extra card(s)",
+        if opts.dry_run { " (dry-run)" } else { "" },
+        outcome.written,
+        outcome.media,
+        outcome.adopted,
+        outcome.skipped,
+        outcome.removed_cards,
+    );
+    for w in &outcome.warnings {
+        eprintln!("warning: {w}");
+    }
+    Ok(())
+}
+
+fn cmd_cache(cfg: &Config, action: CacheCmd) -> Result<()> {
+    use marki::cache::{self, human_bytes};
+
+    let root = render_cache_dir();
+    match action {
+        CacheCmd::Stats => {
+            println!("cache: {}", root.display());
+            let mut total = 0;
+            for (ns, usage) in cache::stats(&root)? {
+                total += usage.bytes;
+                let incomplete = if usage.incomplete > 0 {
+                    format!(" ({} incomplete)", usage.incomplete)
+                } else {
+                    String::new()
+                };
+                println!(
+                    "  {:<9} {:>6} entries {:>11}{incomplete}",
+                    ns.name,
+                    usage.entries,
+                    human_bytes(usage.bytes)
+                );
+            }
+            println!("  {:<9} {:>26}", "total", human_bytes(total));
+            Ok(())
+        }
+        CacheCmd::Prune { older_than, dry_run } => {
+            let cutoff = match older_than {
+                Some(age) => SystemTime::now() - age,
+                None => {
+                    // Every entry a current card renders gets its marker
+                    // touched by this pass; whatever is older afterwards
+                    // is unreferenced. One second of slack for coarse
+                    // filesystem timestamps.
+                    let start = SystemTime::now() - Duration::from_secs(1);
+                    let mut backend = open_backend(cfg)?;
+                    let registry = Arc::new(build_registry(cfg));
+                    let mut script_engine = build_script_engine(cfg);
+                    let outcome = run_cycle(
+                        backend.as_mut(),
+                        cfg,
+                        &registry,
+                        &mut script_engine,
+                        true,
+                        false,
+                    )?;
+                    if !outcome.errors.is_empty() {
+                        anyhow::bail!(
+                            "render pass had {} error(s); nothing pruned (fix them or use --older-than)",
+                            outcome.errors.len()
+                        );
+                    }
+                    start
+                }
+            };
+            let outcome = cache::prune(&root, cutoff, dry_run)?;
+            let verb = if dry_run { "would delete" } else { "deleted" };
+            println!(
+                "cache prune{}: {verb} {} entr{} ({}), kept {}",
+                if dry_run { " (dry-run)" } else { "" },
+                outcome.removed,
+                if outcome.removed == 1 { "y" } else { "ies" },
+                human_bytes(outcome.freed_bytes),
+                outcome.kept,
+            );
+            Ok(())
+        }
+        CacheCmd::Verify { fix } => {
+            let outcome = cache::verify(&root, fix)?;
+            for (dir, reason) in &outcome.corrupt {
+                eprintln!("corrupt: {}: {reason}", dir.display());
+            }
+            println!(
+                "cache verify: {} ok, {} corrupt{}, {} without manifest",
+                outcome.ok,
+                outcome.corrupt.len(),
+                if fix { format!(" ({} deleted)", outcome.removed) } else { String::new() },
+                outcome.legacy,
+            );
+            if !outcome.corrupt.is_empty() && !fix {
+                anyhow::bail!("{} corrupt cache entr(ies); rerun with --fix", outcome.corrupt.len());
+            }
+            Ok(())
+        }
+    }
+}
+
 fn cmd_render_map(file: &Path, out: &Path, to_stdout: bool, registry: &Registry) -> Result<()> {
     use std::io::Write;
 
@@ -505,30 +900,81 @@ fn cmd_render_map(file: &Path, out: &Path, to_stdout: bool, registry: &Registry)
     Ok(())
 }
 
-fn cmd_watch(
-    col: &mut Collection,
-    cfg: &Config,
-    registry: &Arc<Registry>,
-    script_engine: &mut ScriptEngine,
-) -> Result<()> {
-    let debounce = Duration::from_millis(cfg.debounce_ms);
-    let heartbeat = cfg.sync_interval;
+fn cmd_watch(cli: &Cli, mut cfg: Config) -> Result<()> {
+    let mut backend = open_backend(&cfg)?;
+    let mut registry = Arc::new(build_registry(&cfg));
+    let mut script_engine = build_script_engine(&cfg);
+    // The watched directory and timers are fixed for the daemon's life;
+    // `reload` picks up everything else.
+    let root = cfg.cards_dir.clone();
+    let opts = marki::watch::Options {
+        debounce: Duration::from_millis(cfg.debounce_ms),
+        heartbeat: cfg.sync_interval,
+        control_socket: Some(cfg.control_socket_path()),
+    };
 
     tracing::info!(
         "watching {} (debounce={:?} heartbeat={:?})",
-        cfg.cards_dir.display(),
-        debounce,
-        heartbeat
+        root.display(),
+        opts.debounce,
+        opts.heartbeat
     );
 
-    run_watch(&cfg.cards_dir, debounce, heartbeat, |tick| {
+    run_watch(&root, &opts, |tick| {
         match tick {
             Tick::Filesystem => tracing::info!("cycle: triggered by filesystem change"),
             Tick::Heartbeat => tracing::info!("cycle: triggered by heartbeat"),
+            Tick::Push => {}
+            Tick::Reload => {
+                // Build everything before swapping, so a broken config
+                // leaves the running one in place.
+                let new_cfg = load_config(cli)?;
+                let new_backend = open_backend(&new_cfg)?;
+                registry = Arc::new(build_registry(&new_cfg));
+                script_engine = build_script_engine(&new_cfg);
+                backend = new_backend;
+                cfg = new_cfg;
+                tracing::info!("reloaded config and models");
+            }
         }
-        if let Err(e) = run_cycle(col, cfg, registry, script_engine, false, false) {
-            tracing::error!("cycle failed: {e:#}");
-        }
-        Ok(true)
+        run_cycle(backend.as_mut(), &cfg, &registry, &mut script_engine, false, false)
     })
 }
+
+/// Send one request to the `marki watch` serving this repo (and target)
+/// and print what it reports.
+fn cmd_ctl(cfg: &Config, request: Request, json: bool) -> Result<()> {
+    let status = control::request(&cfg.control_socket_path(), request)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+    let state = match (status.running, status.paused) {
+        (true, _) => "running a cycle",
+        (false, true) => "paused",
+        (false, false) => "watching",
+    };
+    println!(
+        "{state}; {} cycle(s) run, {} change(s) not yet synced",
+        status.cycles, status.pending_events
+    );
+    if let Some(last) = &status.last_cycle {
+        let ago = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+            .saturating_sub(last.finished_at);
+        println!(
+            "last: {} ({}s ago, took {}ms)",
+            marki::watch::summary(last),
+            ago,
+            last.duration_ms
+        );
+        for e in last.outcome.iter().flat_map(|o| &o.errors) {
+            println!("  error: {e}");
+        }
+        for w in last.outcome.iter().flat_map(|o| &o.warnings) {
+            println!("  warning: {w}");
+        }
+    }
+    Ok(())
+}
@@ -22,6 +22,12 @@ pub struct Note {
     pub id: Option<String>,
     /// Model name from `#model(name)`. Defaults to `"basic"`.
     pub model: String,
+    /// Marki ids of the notes that must be learned first, from
+    /// `#requires(id)` tags, in source order.
+    pub requires: Vec<String>,
+    /// New-card order from `#order(n)` or front-matter `order: n`. Notes
+    /// without one follow the numbered ones, by file name.
+    pub order: Option<i64>,
     /// Cloze algorithm from `#cloze(algo)`. Only meaningful when `model == "cloze"`.
     pub cloze_algorithm: ClozeAlgorithm,
     /// Ordered list of every block in the document.
@@ -40,6 +46,8 @@ pub struct Note {
     pub source: String,
     /// Path to the `.md` file (for error messages and relative media resolution).
     pub source_path: PathBuf,
+    /// Top-level `key: value` pairs of the YAML front-matter, if any.
+    pub front_matter: HashMap<String, String>,
     /// Non-fatal warnings collected during parsing (e.g. malformed tags).
     pub warnings: Vec<String>,
 }
@@ -73,34 +81,90 @@ pub enum TagValue {
 }
 
 /// One structural block of the document.
+///
+/// Prose blocks carry the `$…$` spans found in them as `math`, in
+/// document order. Their `html` already holds each span as MathJax
+/// (see [`MathSpan`]), so it is usable as-is; the renderer may swap the
+/// spans for Typst output.
 #[derive(Debug, Clone)]
 pub enum Block {
     Heading {
         level: u8,
         text: String,
         html: String,
+        math: Vec<MathSpan>,
     },
     Paragraph {
         text: String,
         html: String,
+        math: Vec<MathSpan>,
     },
     List {
         items: Vec<ListItem>,
         ordered: bool,
         html: String,
+        math: Vec<MathSpan>,
     },
     CodeBlock {
         lang: Option<String>,
+        /// Rest of the fence info string after the lang token, trimmed
+        /// (e.g. `{3-5} linenos` for ` ```rust {3-5} linenos `).
+        info: String,
         source: String,
     },
     Blockquote {
         text: String,
         html: String,
+        math: Vec<MathSpan>,
     },
     ThematicBreak,
     Table {
         html: String,
+        math: Vec<MathSpan>,
+    },
+    /// Obsidian/GitHub callout: a blockquote opening with `[!kind]`.
+    Callout {
+        /// Lowercased callout type (`note`, `tip`, `warning`, …).
+        kind: String,
+        /// Custom title from the marker line; empty means "use the kind".
+        title: String,
+        title_html: String,
+        /// `Some(false)` for `[!kind]-` (collapsed), `Some(true)` for
+        /// `[!kind]+` (expanded), `None` when not foldable.
+        fold: Option<bool>,
+        text: String,
+        html: String,
+        math: Vec<MathSpan>,
+    },
+    /// Footnote definition (`[^label]: …`). References in prose render
+    /// as `<sup>` markers carrying the same `number`.
+    Footnote {
+        label: String,
+        /// 1-based, in order of the label's first appearance — usually
+        /// its first reference.
+        number: u32,
+        text: String,
+        html: String,
+        math: Vec<MathSpan>,
     },
+    DefinitionList {
+        items: Vec<Definition>,
+        html: String,
+        math: Vec<MathSpan>,
+    },
+}
+
+/// One math span in prose: `$…$` (inline) or `$$…$$` (display).
+///
+/// In the block's `html` each span appears, in order, as
+/// `<span class="marki-math">\(…\)</span>` — or with an extra
+/// `marki-math-display` class and `\[…\]` — which Anki's MathJax
+/// typesets on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathSpan {
+    /// Span body without the dollar delimiters.
+    pub source: String,
+    pub display: bool,
 }
 
 /// One item inside a list block.
@@ -108,6 +172,16 @@ pub enum Block {
 pub struct ListItem {
     pub text: String,
     pub html: String,
+    /// Task-list state: `Some(done)` for `- [ ]` / `- [x]` items.
+    pub checked: Option<bool>,
+}
+
+/// One term of a definition list with its definitions.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub term: String,
+    pub term_html: String,
+    pub details: Vec<ListItem>,
 }
 
 // ---- Note convenience methods ----
@@ -185,6 +259,30 @@ impl Note {
             .collect()
     }
 
+    /// All callouts in document order.
+    pub fn callouts(&self) -> Vec<&Block> {
+        self.blocks
+            .iter()
+            .filter(|b| matches!(b, Block::Callout { .. }))
+            .collect()
+    }
+
+    /// All footnote definitions in document order.
+    pub fn footnotes(&self) -> Vec<&Block> {
+        self.blocks
+            .iter()
+            .filter(|b| matches!(b, Block::Footnote { .. }))
+            .collect()
+    }
+
+    /// All definition lists in document order.
+    pub fn definition_lists(&self) -> Vec<&Block> {
+        self.blocks
+            .iter()
+            .filter(|b| matches!(b, Block::DefinitionList { .. }))
+            .collect()
+    }
+
     /// Get a tag value by name.
     pub fn tag(&self, name: &str) -> Option<&TagValue> {
         self.tags.get(name)
@@ -209,6 +307,9 @@ impl Block {
             Block::Blockquote { text, .. } => text,
             Block::ThematicBreak => "",
             Block::Table { .. } => "",
+            Block::Callout { text, .. } => text,
+            Block::Footnote { text, .. } => text,
+            Block::DefinitionList { .. } => "",
         }
     }
 
@@ -222,6 +323,26 @@ impl Block {
             Block::Blockquote { html, .. } => html,
             Block::ThematicBreak => "",
             Block::Table { html, .. } => html,
+            Block::Callout { html, .. } => html,
+            Block::Footnote { html, .. } => html,
+            Block::DefinitionList { html, .. } => html,
+        }
+    }
+
+    /// Lowercase variant name (`"paragraph"`, `"code_block"`,
+    /// `"callout"`, …), for scripts that walk a section generically.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Block::Heading { .. } => "heading",
+            Block::Paragraph { .. } => "paragraph",
+            Block::List { .. } => "list",
+            Block::CodeBlock { .. } => "code_block",
+            Block::Blockquote { .. } => "blockquote",
+            Block::ThematicBreak => "thematic_break",
+            Block::Table { .. } => "table",
+            Block::Callout { .. } => "callout",
+            Block::Footnote { .. } => "footnote",
+            Block::DefinitionList { .. } => "definition_list",
         }
     }
 
@@ -233,6 +354,21 @@ impl Block {
         }
     }
 
+    /// Math spans in a prose block, in document order.
+    pub fn math(&self) -> &[MathSpan] {
+        match self {
+            Block::Heading { math, .. }
+            | Block::Paragraph { math, .. }
+            | Block::List { math, .. }
+            | Block::Blockquote { math, .. }
+            | Block::Table { math, .. }
+            | Block::Callout { math, .. }
+            | Block::Footnote { math, .. }
+            | Block::DefinitionList { math, .. } => math,
+            Block::CodeBlock { .. } | Block::ThematicBreak => &[],
+        }
+    }
+
     /// Source code for code blocks; `None` for everything else.
     pub fn source(&self) -> Option<&str> {
         match self {
@@ -251,28 +387,34 @@ mod tests {
             Block::Paragraph {
                 text: "Question text".into(),
                 html: "Question text".into(),
+                math: vec![],
             },
             Block::CodeBlock {
                 lang: Some("map".into()),
+                info: String::new(),
                 source: "[layers.base]\nfeatures = [\"country/DEU\"]".into(),
             },
             Block::ThematicBreak,
             Block::Paragraph {
                 text: "Answer text".into(),
                 html: "Answer text".into(),
+                math: vec![],
             },
             Block::List {
                 items: vec![
-                    ListItem { text: "Fact 1".into(), html: "Fact 1".into() },
-                    ListItem { text: "Fact 2".into(), html: "Fact 2".into() },
+                    ListItem { text: "Fact 1".into(), html: "Fact 1".into(), checked: None },
+                    ListItem { text: "Fact 2".into(), html: "Fact 2".into(), checked: None },
                 ],
                 ordered: false,
                 html: "<ul><li>Fact 1</li><li>Fact 2</li></ul>".into(),
+                math: vec![],
             },
         ];
         Note {
             id: Some("abc123".into()),
             model: "basic".into(),
+            requires: Vec::new(),
+            order: None,
             section_ranges: section_ranges(&blocks),
             blocks,
             tags: {
@@ -285,6 +427,7 @@ mod tests {
             cloze_algorithm: ClozeAlgorithm::default(),
             source: String::new(),
             source_path: PathBuf::new(),
+            front_matter: HashMap::new(),
             warnings: Vec::new(),
         }
     }
@@ -345,6 +488,7 @@ mod tests {
             level: 2,
             text: "Title".into(),
             html: "<strong>Title</strong>".into(),
+            math: vec![],
         };
         assert_eq!(b.text(), "Title");
         assert_eq!(b.html(), "<strong>Title</strong>");
@@ -355,6 +499,7 @@ mod tests {
     fn code_block_accessors() {
         let b = Block::CodeBlock {
             lang: Some("map".into()),
+            info: String::new(),
             source: "toml stuff".into(),
         };
         assert_eq!(b.lang(), Some("map"));
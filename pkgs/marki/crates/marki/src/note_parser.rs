@@ -6,13 +6,38 @@
 //!
 //! Tags are extracted into `Note::tags` and `Note::anki_tags` during
 //! parsing and stripped from block content.
+//!
+//! `$…$` and `$$…$$` in prose become MathJax spans in the block HTML and
+//! are also recorded as [`MathSpan`]s, so the renderer can typeset them
+//! with Typst instead. Tags inside a span are left alone.
+//!
+//! The Obsidian extensions are recognised too: footnotes, definition
+//! lists, task lists, `==highlight==` and `> [!kind]` callouts.
+//!
+//! A leading `---`-fenced YAML block is front-matter, not a card section.
+//! Only its top-level `key: value` lines are read (into
+//! [`Note::front_matter`]); `order:` sets the note's new-card order unless
+//! an `#order(n)` tag does.
 
-use crate::note::{Block, ListItem, Note, TagValue};
+use crate::note::{Block, Definition, ListItem, MathSpan, Note, TagValue};
 use crate::tag::{ClozeAlgorithm, Parsed, SystemTag, TAG_REGEX, parse_token};
 use marki_render::escape_html;
-use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use pulldown_cmark::{
+    CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd, TextMergeStream,
+};
+use regex::Regex;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::LazyLock;
+
+/// `[!kind]` at the start of a blockquote, with an optional fold marker.
+static CALLOUT_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[!([A-Za-z][\w-]*)\]([+-]?)[ \t]*").unwrap());
+
+/// `==text==`, Obsidian's highlight. The body may not start or end with
+/// whitespace, so `a == b == c` is left alone.
+static MARK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"==([^=\s](?:[^=]*[^=\s])?)==").unwrap());
 
 /// Parse a markdown source into a structural [`Note`].
 ///
@@ -25,7 +50,12 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
     let mut anki_tags: Vec<String> = Vec::new();
     let mut id: Option<String> = None;
     let mut model = "basic".to_string();
+    let mut order: Option<i64> = None;
+    let mut requires: Vec<String> = Vec::new();
     let mut warnings: Vec<String> = Vec::new();
+    // Raw YAML of the front-matter block while it is being read.
+    let mut metadata: Option<String> = None;
+    let mut front_matter: HashMap<String, String> = HashMap::new();
 
     // ---- Phase 1: Pre-scan tags that affect rendering.
     // We need to know if this is a cloze note BEFORE rendering starts
@@ -41,7 +71,7 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
     // Resolve Auto algorithm: need to know if both bold+italic exist.
     let resolved_cloze_algo = match cloze_pre {
         Some(ClozeAlgorithm::Auto) => {
-            let opts = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+            let opts = parser_options();
             let mut has_strong = false;
             let mut has_em = false;
             for event in Parser::new_ext(source, opts) {
@@ -67,8 +97,13 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
     // Holds (src, title, accumulated_alt_text).
     let mut in_image: Option<(String, String, String)> = None;
 
-    let opts = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
-    let parser = Parser::new_ext(source, opts);
+    // Footnote label -> number, in order of first appearance.
+    let mut footnotes: HashMap<String, u32> = HashMap::new();
+
+    // pulldown splits text at characters that might start markup (`[`
+    // in `[!note]`, for one); merge the pieces so callout markers and
+    // `==highlight==` can be matched in a single event.
+    let parser = TextMergeStream::new(Parser::new_ext(source, parser_options()));
 
     for event in parser {
         match event {
@@ -85,6 +120,7 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
                     level: heading_level_u8(level),
                     text: String::new(),
                     html: String::new(),
+                    math: Vec::new(),
                 };
             }
             Event::End(TagEnd::Heading(_)) => {
@@ -93,18 +129,22 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
 
             // ---- Paragraphs
             Event::Start(Tag::Paragraph) => {
-                // Don't flush if we're inside a blockquote — the paragraph
-                // content belongs to the blockquote's buffers.
-                if !matches!(state, ParseState::Blockquote { .. }) {
+                // Don't flush if we're inside a blockquote, callout, etc.
+                // — the paragraph content belongs to the container's buffers.
+                if !state.is_container() {
                     flush_block(&mut state, &mut blocks);
                     state = ParseState::Paragraph {
                         text: String::new(),
                         html: String::new(),
+                        math: Vec::new(),
                     };
                 }
             }
             Event::End(TagEnd::Paragraph) => {
-                if !matches!(state, ParseState::Blockquote { .. }) {
+                if let ParseState::Callout { in_title, .. } = &mut state {
+                    *in_title = false;
+                }
+                if !state.is_container() {
                     flush_block(&mut state, &mut blocks);
                 }
             }
@@ -117,61 +157,78 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
                     items: Vec::new(),
                     current_item_text: String::new(),
                     current_item_html: String::new(),
+                    current_item_checked: None,
                     html: if start.is_some() {
                         "<ol>".to_string()
                     } else {
                         "<ul>".to_string()
                     },
+                    math: Vec::new(),
                 };
             }
             Event::End(TagEnd::List(ordered)) => {
                 // Close any pending item.
-                if let ParseState::List {
-                    ref mut items,
-                    ref mut current_item_text,
-                    ref mut current_item_html,
-                    ref mut html,
-                    ..
-                } = state
-                {
-                    if !current_item_text.is_empty() || !current_item_html.is_empty() {
-                        html.push_str("<li>");
-                        html.push_str(current_item_html);
-                        html.push_str("</li>");
-                        items.push(ListItem {
-                            text: std::mem::take(current_item_text),
-                            html: std::mem::take(current_item_html),
-                        });
-                    }
+                close_list_item(&mut state);
+                if let ParseState::List { ref mut html, .. } = state {
                     html.push_str(if ordered { "</ol>" } else { "</ul>" });
                 }
                 flush_block(&mut state, &mut blocks);
             }
             Event::Start(Tag::Item) => {
                 // Close previous item if any.
-                if let ParseState::List {
-                    ref mut items,
-                    ref mut current_item_text,
-                    ref mut current_item_html,
-                    ref mut html,
-                    ..
-                } = state
-                {
-                    if !current_item_text.is_empty() || !current_item_html.is_empty() {
-                        html.push_str("<li>");
-                        html.push_str(current_item_html);
-                        html.push_str("</li>");
-                        items.push(ListItem {
-                            text: std::mem::take(current_item_text),
-                            html: std::mem::take(current_item_html),
-                        });
-                    }
-                }
+                close_list_item(&mut state);
             }
             Event::End(TagEnd::Item) => {
                 // Item content already accumulated; will be flushed on
                 // next Start(Item) or End(List).
             }
+            Event::TaskListMarker(done) => {
+                if let ParseState::List { ref mut current_item_checked, .. } = state {
+                    *current_item_checked = Some(done);
+                }
+            }
+
+            // ---- Definition lists
+            Event::Start(Tag::DefinitionList) => {
+                flush_block(&mut state, &mut blocks);
+                state = ParseState::DefinitionList {
+                    items: Vec::new(),
+                    current_text: String::new(),
+                    current_html: String::new(),
+                    html: "<dl>".to_string(),
+                    math: Vec::new(),
+                };
+            }
+            Event::End(TagEnd::DefinitionList) => {
+                if let ParseState::DefinitionList { ref mut html, .. } = state {
+                    html.push_str("</dl>");
+                }
+                flush_block(&mut state, &mut blocks);
+            }
+            Event::End(TagEnd::DefinitionListTitle) => close_definition_part(&mut state, true),
+            Event::End(TagEnd::DefinitionListDefinition) => {
+                close_definition_part(&mut state, false);
+            }
+
+            // ---- Footnotes
+            Event::FootnoteReference(label) => {
+                let n = footnote_number(&mut footnotes, &label);
+                push_text(&mut state, &format!("[{n}]"));
+                push_html(&mut state, &format!("<sup class=\"marki-footnote-ref\">{n}</sup>"));
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                flush_block(&mut state, &mut blocks);
+                state = ParseState::Footnote {
+                    number: footnote_number(&mut footnotes, &label),
+                    label: label.to_string(),
+                    text: String::new(),
+                    html: String::new(),
+                    math: Vec::new(),
+                };
+            }
+            Event::End(TagEnd::FootnoteDefinition) => {
+                flush_block(&mut state, &mut blocks);
+            }
 
             // ---- Blockquotes
             Event::Start(Tag::BlockQuote(_)) => {
@@ -179,6 +236,7 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
                 state = ParseState::Blockquote {
                     text: String::new(),
                     html: String::new(),
+                    math: Vec::new(),
                 };
             }
             Event::End(TagEnd::BlockQuote(_)) => {
@@ -188,15 +246,18 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
             // ---- Code blocks
             Event::Start(Tag::CodeBlock(kind)) => {
                 flush_block(&mut state, &mut blocks);
-                let lang = match kind {
+                let (lang, info) = match kind {
                     CodeBlockKind::Fenced(info) => {
-                        let l = info.split_whitespace().next().unwrap_or("").to_string();
-                        if l.is_empty() { None } else { Some(l) }
+                        let info = info.trim();
+                        let (l, rest) = info.split_once(char::is_whitespace).unwrap_or((info, ""));
+                        let lang = if l.is_empty() { None } else { Some(l.to_string()) };
+                        (lang, rest.trim().to_string())
                     }
-                    CodeBlockKind::Indented => None,
+                    CodeBlockKind::Indented => (None, String::new()),
                 };
                 state = ParseState::CodeBlock {
                     lang,
+                    info,
                     source: String::new(),
                 };
             }
@@ -209,10 +270,11 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
                 flush_block(&mut state, &mut blocks);
                 state = ParseState::Table {
                     html: "<table>".to_string(),
+                    math: Vec::new(),
                 };
             }
             Event::End(TagEnd::Table) => {
-                if let ParseState::Table { ref mut html } = state {
+                if let ParseState::Table { ref mut html, .. } = state {
                     html.push_str("</tbody></table>");
                 }
                 flush_block(&mut state, &mut blocks);
@@ -283,7 +345,7 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
 
             // ---- Inline code
             Event::Code(code) => {
-                let cleaned = strip_tags(&code, &mut anki_tags, &mut tags, &mut id, &mut model, &mut warnings);
+                let cleaned = strip_tags(&code, &mut anki_tags, &mut tags, &mut id, &mut model, &mut order, &mut requires, &mut warnings);
                 push_text(&mut state, &cleaned);
                 push_html(
                     &mut state,
@@ -291,6 +353,28 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
                 );
             }
 
+            // ---- Math spans
+            Event::InlineMath(ref src) | Event::DisplayMath(ref src) => {
+                let display = matches!(event, Event::DisplayMath(_));
+                if let Some((_, _, ref mut alt)) = in_image {
+                    alt.push_str(src);
+                } else {
+                    let delim = if display { "$$" } else { "$" };
+                    push_text(&mut state, &format!("{delim}{src}{delim}"));
+                    push_html(&mut state, &math_html(src, display));
+                    push_math(&mut state, MathSpan { source: src.to_string(), display });
+                }
+            }
+
+            // ---- Front-matter
+            Event::Start(Tag::MetadataBlock(_)) => metadata = Some(String::new()),
+            Event::Text(ref text) if metadata.is_some() => {
+                metadata.as_mut().unwrap().push_str(text);
+            }
+            Event::End(TagEnd::MetadataBlock(_)) => {
+                front_matter = parse_front_matter(&metadata.take().unwrap_or_default());
+            }
+
             // ---- Text content
             Event::Text(text) => {
                 if let Some((_, _, ref mut alt)) = in_image {
@@ -302,9 +386,10 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
                             source.push_str(&text);
                         }
                         _ => {
-                            let cleaned = strip_tags(&text, &mut anki_tags, &mut tags, &mut id, &mut model, &mut warnings);
-                            push_text(&mut state, &cleaned);
-                            push_html(&mut state, &escape_html(&cleaned));
+                            let rest = open_callout(&mut state, &text);
+                            let cleaned = strip_tags(rest, &mut anki_tags, &mut tags, &mut id, &mut model, &mut order, &mut requires, &mut warnings);
+                            push_text(&mut state, &MARK_REGEX.replace_all(&cleaned, "$1"));
+                            push_html(&mut state, &mark_html(&cleaned));
                         }
                     }
                 }
@@ -313,6 +398,8 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
             Event::SoftBreak | Event::HardBreak => {
                 match &mut state {
                     ParseState::CodeBlock { source, .. } => source.push('\n'),
+                    // The marker line ends the callout title.
+                    ParseState::Callout { in_title, .. } if *in_title => *in_title = false,
                     _ => {
                         push_text(&mut state, " ");
                         push_html(&mut state, "<br>");
@@ -320,7 +407,7 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
                 }
             }
 
-            // Ignore everything else (footnotes, etc.)
+            // Ignore everything else (raw HTML, metadata blocks, etc.)
             _ => {}
         }
     }
@@ -328,6 +415,16 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
     // Flush any trailing block.
     flush_block(&mut state, &mut blocks);
 
+    // An `#order(n)` tag beats the front-matter key.
+    if order.is_none()
+        && let Some(v) = front_matter.get("order")
+    {
+        match v.parse() {
+            Ok(n) => order = Some(n),
+            Err(_) => warnings.push(format!("front-matter `order: {v}` is not an integer")),
+        }
+    }
+
     // Deduplicate anki tags preserving order.
     let mut seen = std::collections::HashSet::new();
     anki_tags.retain(|t| seen.insert(t.clone()));
@@ -335,6 +432,8 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
     Note {
         id,
         model,
+        requires,
+        order,
         cloze_algorithm: resolved_cloze_algo,
         section_ranges: crate::note::section_ranges(&blocks),
         blocks,
@@ -342,6 +441,7 @@ pub fn parse_note(source: &str, source_path: PathBuf) -> Note {
         anki_tags,
         source: source.to_string(),
         source_path,
+        front_matter,
         warnings,
     }
 }
@@ -354,77 +454,295 @@ enum ParseState {
         level: u8,
         text: String,
         html: String,
+        math: Vec<MathSpan>,
     },
     Paragraph {
         text: String,
         html: String,
+        math: Vec<MathSpan>,
     },
     List {
         ordered: bool,
         items: Vec<ListItem>,
         current_item_text: String,
         current_item_html: String,
+        current_item_checked: Option<bool>,
         html: String,
+        math: Vec<MathSpan>,
     },
     CodeBlock {
         lang: Option<String>,
+        info: String,
         source: String,
     },
     Blockquote {
         text: String,
         html: String,
+        math: Vec<MathSpan>,
     },
     Table {
         html: String,
+        math: Vec<MathSpan>,
+    },
+    Callout {
+        kind: String,
+        fold: Option<bool>,
+        /// Still on the `[!kind] Title` line: inline content goes to
+        /// the title buffers.
+        in_title: bool,
+        title: String,
+        title_html: String,
+        text: String,
+        html: String,
+        math: Vec<MathSpan>,
+    },
+    Footnote {
+        label: String,
+        number: u32,
+        text: String,
+        html: String,
+        math: Vec<MathSpan>,
+    },
+    DefinitionList {
+        items: Vec<Definition>,
+        /// Term or definition being accumulated.
+        current_text: String,
+        current_html: String,
+        html: String,
+        math: Vec<MathSpan>,
     },
 }
 
+impl ParseState {
+    /// Blocks whose body is made of paragraphs: paragraph events inside
+    /// them feed the container's buffers instead of starting a new block.
+    fn is_container(&self) -> bool {
+        matches!(
+            self,
+            ParseState::Blockquote { .. }
+                | ParseState::Callout { .. }
+                | ParseState::Footnote { .. }
+                | ParseState::DefinitionList { .. }
+        )
+    }
+}
+
+fn parser_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_MATH
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_DEFINITION_LIST
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+}
+
+/// The top-level `key: value` pairs of a YAML front-matter block. Values
+/// lose surrounding quotes; nested mappings, lists and comments are
+/// skipped, since no key marki reads needs them.
+fn parse_front_matter(yaml: &str) -> HashMap<String, String> {
+    yaml.lines()
+        .filter(|line| !line.starts_with([' ', '\t', '#', '-']))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| {
+            let v = v.trim();
+            let v = v
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| v.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(v);
+            (k.trim().to_string(), v.to_string())
+        })
+        .filter(|(k, v)| !k.is_empty() && !v.is_empty())
+        .collect()
+}
+
+/// Escape `text` for HTML, wrapping each `==highlight==` in `<mark>`.
+/// A highlight cannot span other inline markup (`==**x**==` stays literal).
+fn mark_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for cap in MARK_REGEX.captures_iter(text) {
+        let m = cap.get(0).unwrap();
+        out.push_str(&escape_html(&text[last..m.start()]));
+        out.push_str("<mark>");
+        out.push_str(&escape_html(&cap[1]));
+        out.push_str("</mark>");
+        last = m.end();
+    }
+    out.push_str(&escape_html(&text[last..]));
+    out
+}
+
+/// Turn a still-empty blockquote whose first text is `[!kind]` into a
+/// callout. Returns the text after the marker, i.e. the start of the
+/// title; any other text is returned unchanged.
+fn open_callout<'t>(state: &mut ParseState, text: &'t str) -> &'t str {
+    let ParseState::Blockquote { text: quoted, html, .. } = state else {
+        return text;
+    };
+    if !quoted.is_empty() || !html.is_empty() {
+        return text;
+    }
+    let Some(cap) = CALLOUT_REGEX.captures(text) else {
+        return text;
+    };
+    let fold = match &cap[2] {
+        "+" => Some(true),
+        "-" => Some(false),
+        _ => None,
+    };
+    *state = ParseState::Callout {
+        kind: cap[1].to_ascii_lowercase(),
+        fold,
+        in_title: true,
+        title: String::new(),
+        title_html: String::new(),
+        text: String::new(),
+        html: String::new(),
+        math: Vec::new(),
+    };
+    &text[cap.get(0).unwrap().end()..]
+}
+
+/// Number for a footnote label, assigning the next one on first sight.
+fn footnote_number(numbers: &mut HashMap<String, u32>, label: &str) -> u32 {
+    let next = numbers.len() as u32 + 1;
+    *numbers.entry(label.to_string()).or_insert(next)
+}
+
+/// Move a list's pending item, if any, into `items` and its `<li>` into
+/// the list HTML.
+fn close_list_item(state: &mut ParseState) {
+    let ParseState::List {
+        items,
+        current_item_text,
+        current_item_html,
+        current_item_checked,
+        html,
+        ..
+    } = state
+    else {
+        return;
+    };
+    let checked = current_item_checked.take();
+    if current_item_text.is_empty() && current_item_html.is_empty() && checked.is_none() {
+        return;
+    }
+    match checked {
+        Some(done) => {
+            let attr = if done { " checked" } else { "" };
+            html.push_str("<li class=\"marki-task\" style=\"list-style:none;\">");
+            html.push_str(&format!("<input type=\"checkbox\" disabled{attr}> "));
+        }
+        None => html.push_str("<li>"),
+    }
+    html.push_str(current_item_html);
+    html.push_str("</li>");
+    items.push(ListItem {
+        text: std::mem::take(current_item_text),
+        html: std::mem::take(current_item_html),
+        checked,
+    });
+}
+
+/// Finish a definition-list term (`<dt>`) or definition (`<dd>`). A
+/// definition attaches to the most recent term.
+fn close_definition_part(state: &mut ParseState, is_term: bool) {
+    let ParseState::DefinitionList { items, current_text, current_html, html, .. } = state else {
+        return;
+    };
+    let text = std::mem::take(current_text).trim().to_string();
+    let part_html = std::mem::take(current_html).trim().to_string();
+    let tag = if is_term { "dt" } else { "dd" };
+    html.push_str(&format!("<{tag}>{part_html}</{tag}>"));
+    if is_term {
+        items.push(Definition { term: text, term_html: part_html, details: Vec::new() });
+    } else if let Some(last) = items.last_mut() {
+        last.details.push(ListItem { text, html: part_html, checked: None });
+    }
+}
+
+/// Opening tag of the MathJax wrapper around each span; the display
+/// variant adds `marki-math-display`. The renderer finds spans by it.
+pub(crate) const MATH_SPAN_OPEN: &str = "<span class=\"marki-math";
+
+/// MathJax markup for one span. The body is escaped, so the first
+/// `</span>` after [`MATH_SPAN_OPEN`] always closes it.
+fn math_html(src: &str, display: bool) -> String {
+    let body = escape_html(src);
+    if display {
+        format!("{MATH_SPAN_OPEN} marki-math-display\">\\[{body}\\]</span>")
+    } else {
+        format!("{MATH_SPAN_OPEN}\">\\({body}\\)</span>")
+    }
+}
+
 fn flush_block(
     state: &mut ParseState,
     blocks: &mut Vec<Block>,
 ) {
+    close_list_item(state);
     let old = std::mem::replace(state, ParseState::Idle);
     match old {
         ParseState::Idle => {}
-        ParseState::Heading { level, text, html } => {
+        ParseState::Heading { level, text, html, math } => {
             if !text.trim().is_empty() || !html.trim().is_empty() {
-                blocks.push(Block::Heading { level, text: text.trim().to_string(), html: html.trim().to_string() });
+                blocks.push(Block::Heading { level, text: text.trim().to_string(), html: html.trim().to_string(), math });
             }
         }
-        ParseState::Paragraph { text, html } => {
+        ParseState::Paragraph { text, html, math } => {
             // A paragraph that was entirely tags (stripped to empty) is dropped.
             if !text.trim().is_empty() || !html.trim().is_empty() {
-                blocks.push(Block::Paragraph { text: text.trim().to_string(), html: html.trim().to_string() });
+                blocks.push(Block::Paragraph { text: text.trim().to_string(), html: html.trim().to_string(), math });
             }
         }
-        ParseState::List { ordered, items, current_item_text, current_item_html, mut html } => {
-            let mut items = items;
-            if !current_item_text.is_empty() || !current_item_html.is_empty() {
-                html.push_str("<li>");
-                html.push_str(&current_item_html);
-                html.push_str("</li>");
-                items.push(ListItem {
-                    text: current_item_text,
-                    html: current_item_html,
-                });
-            }
+        ParseState::List { ordered, items, mut html, math, .. } => {
             if !html.ends_with("</ol>") && !html.ends_with("</ul>") {
                 html.push_str(if ordered { "</ol>" } else { "</ul>" });
             }
             if !items.is_empty() {
-                blocks.push(Block::List { items, ordered, html });
+                blocks.push(Block::List { items, ordered, html, math });
             }
         }
-        ParseState::CodeBlock { lang, source } => {
-            blocks.push(Block::CodeBlock { lang, source });
+        ParseState::CodeBlock { lang, info, source } => {
+            blocks.push(Block::CodeBlock { lang, info, source });
         }
-        ParseState::Blockquote { text, html } => {
+        ParseState::Blockquote { text, html, math } => {
             if !text.trim().is_empty() || !html.trim().is_empty() {
-                blocks.push(Block::Blockquote { text: text.trim().to_string(), html: html.trim().to_string() });
+                blocks.push(Block::Blockquote { text: text.trim().to_string(), html: html.trim().to_string(), math });
             }
         }
-        ParseState::Table { html } => {
-            blocks.push(Block::Table { html });
+        ParseState::Table { html, math } => {
+            blocks.push(Block::Table { html, math });
+        }
+        ParseState::Callout { kind, fold, title, title_html, text, html, math, .. } => {
+            blocks.push(Block::Callout {
+                kind,
+                title: title.trim().to_string(),
+                title_html: title_html.trim().to_string(),
+                fold,
+                text: text.trim().to_string(),
+                html: html.trim().to_string(),
+                math,
+            });
+        }
+        ParseState::Footnote { label, number, text, html, math } => {
+            blocks.push(Block::Footnote {
+                label,
+                number,
+                text: text.trim().to_string(),
+                html: html.trim().to_string(),
+                math,
+            });
+        }
+        ParseState::DefinitionList { items, mut html, math, .. } => {
+            if !items.is_empty() {
+                if !html.ends_with("</dl>") {
+                    html.push_str("</dl>");
+                }
+                blocks.push(Block::DefinitionList { items, html, math });
+            }
         }
     }
 }
@@ -435,7 +753,11 @@ fn push_html(state: &mut ParseState, s: &str) {
         ParseState::Paragraph { html, .. } => html.push_str(s),
         ParseState::List { current_item_html, .. } => current_item_html.push_str(s),
         ParseState::Blockquote { html, .. } => html.push_str(s),
-        ParseState::Table { html } => html.push_str(s),
+        ParseState::Table { html, .. } => html.push_str(s),
+        ParseState::Callout { in_title: true, title_html, .. } => title_html.push_str(s),
+        ParseState::Callout { html, .. } => html.push_str(s),
+        ParseState::Footnote { html, .. } => html.push_str(s),
+        ParseState::DefinitionList { current_html, .. } => current_html.push_str(s),
         ParseState::CodeBlock { source, .. } => source.push_str(s),
         ParseState::Idle => {}
     }
@@ -447,23 +769,44 @@ fn push_text(state: &mut ParseState, s: &str) {
         ParseState::Paragraph { text, .. } => text.push_str(s),
         ParseState::List { current_item_text, .. } => current_item_text.push_str(s),
         ParseState::Blockquote { text, .. } => text.push_str(s),
+        ParseState::Callout { in_title: true, title, .. } => title.push_str(s),
+        ParseState::Callout { text, .. } => text.push_str(s),
+        ParseState::Footnote { text, .. } => text.push_str(s),
+        ParseState::DefinitionList { current_text, .. } => current_text.push_str(s),
         ParseState::Table { .. } => {} // tables only track html
         ParseState::CodeBlock { .. } => {} // code blocks use source
         ParseState::Idle => {}
     }
 }
 
+fn push_math(state: &mut ParseState, span: MathSpan) {
+    match state {
+        ParseState::Heading { math, .. }
+        | ParseState::Paragraph { math, .. }
+        | ParseState::List { math, .. }
+        | ParseState::Blockquote { math, .. }
+        | ParseState::Table { math, .. }
+        | ParseState::Callout { math, .. }
+        | ParseState::Footnote { math, .. }
+        | ParseState::DefinitionList { math, .. } => math.push(span),
+        ParseState::CodeBlock { .. } | ParseState::Idle => {}
+    }
+}
+
 /// Strip `#tag` and `#tag(arg)` tokens from a text span. Tags are
 /// classified and routed to the appropriate collection.
 ///
 /// Uses offset-walking over regex matches to build the result in O(n)
 /// rather than repeated `String::replace` which is O(n*m).
+#[allow(clippy::too_many_arguments)]
 fn strip_tags(
     text: &str,
     anki_tags: &mut Vec<String>,
     tags: &mut HashMap<String, TagValue>,
     id: &mut Option<String>,
     model: &mut String,
+    order: &mut Option<i64>,
+    requires: &mut Vec<String>,
     warnings: &mut Vec<String>,
 ) -> String {
     let mut result = String::with_capacity(text.len());
@@ -492,6 +835,14 @@ fn strip_tags(
                     SystemTag::Cloze(_) => {
                         *model = "cloze".to_string();
                     }
+                    SystemTag::Order(n) => {
+                        *order = Some(n);
+                    }
+                    SystemTag::Requires(ref prereq) => {
+                        if !requires.contains(prereq) {
+                            requires.push(prereq.clone());
+                        }
+                    }
                 }
             }
             Parsed::AnkiTag(kw) => {
@@ -567,6 +918,21 @@ mod tests {
         assert_eq!(secs[1][0].text(), "Answer here");
     }
 
+    #[test]
+    fn order_from_tag_or_front_matter() {
+        let src = "---\norder: 4\ntitle: 'Chapter 1'\n---\n\nQuestion\n\n---\n\nAnswer\n";
+        let note = parse_note(src, PathBuf::new());
+        assert_eq!(note.order, Some(4));
+        assert_eq!(note.front_matter["title"], "Chapter 1");
+        // Front-matter is not a card section.
+        assert_eq!(note.sections().len(), 2);
+        assert_eq!(note.section(0)[0].text(), "Question");
+
+        let note = parse_note("---\norder: 4\n---\n\nQ #order(2)\n", PathBuf::new());
+        assert_eq!(note.order, Some(2));
+        assert!(note.anki_tags.is_empty());
+    }
+
     #[test]
     fn tags_extracted_and_stripped() {
         let src = "Where is Jamaica?\n\n#geography #country(JAM) #model(geo)\n";
@@ -812,4 +1178,132 @@ mod tests {
         assert_eq!(note.model, "cloze");
         assert_eq!(note.cloze_algorithm, ClozeAlgorithm::Duo);
     }
+
+    #[test]
+    fn fence_info_after_lang_is_kept() {
+        let note = parse_note("```rust {3-5} linenos\nfn x() {}\n```\n", PathBuf::new());
+        match &note.blocks[0] {
+            Block::CodeBlock { lang, info, .. } => {
+                assert_eq!(lang.as_deref(), Some("rust"));
+                assert_eq!(info, "{3-5} linenos");
+            }
+            other => panic!("expected code block, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn math_spans_recorded_and_mathjax_wrapped() {
+        let note = parse_note("Area $\\pi r^2$ and\n\n$$a < b$$\n", PathBuf::new());
+        let b = &note.blocks[0];
+        assert_eq!(b.text(), "Area $\\pi r^2$ and");
+        assert_eq!(b.html(), "Area <span class=\"marki-math\">\\(\\pi r^2\\)</span> and");
+        assert_eq!(b.math(), [MathSpan { source: "\\pi r^2".into(), display: false }]);
+
+        let d = &note.blocks[1];
+        assert_eq!(
+            d.html(),
+            "<span class=\"marki-math marki-math-display\">\\[a &lt; b\\]</span>"
+        );
+        assert!(d.math()[0].display);
+    }
+
+    #[test]
+    fn math_spans_are_opaque_to_tags_and_emphasis() {
+        let note = parse_note("$a*b*c$ #geo\n\n#cloze\n", PathBuf::new());
+        let b = &note.blocks[0];
+        assert_eq!(b.math()[0].source, "a*b*c");
+        assert!(!b.html().contains("{{c"), "got: {}", b.html());
+        assert!(note.anki_tags.contains(&"geo".to_string()));
+    }
+
+    #[test]
+    fn callout_with_title_and_fold() {
+        let note = parse_note("> [!Warning]- Hot *stove*\n> Do not touch.\n", PathBuf::new());
+        match &note.blocks[0] {
+            Block::Callout { kind, title, title_html, fold, text, html, .. } => {
+                assert_eq!(kind, "warning");
+                assert_eq!(title, "Hot stove");
+                assert_eq!(title_html, "Hot <em>stove</em>");
+                assert_eq!(*fold, Some(false));
+                assert_eq!(text, "Do not touch.");
+                assert_eq!(html, "Do not touch.");
+            }
+            other => panic!("expected callout, got {other:?}"),
+        }
+        assert_eq!(note.callouts().len(), 1);
+        assert!(note.blockquotes().is_empty());
+    }
+
+    #[test]
+    fn callout_marker_only_mid_quote_is_plain_text() {
+        let note = parse_note("> [!NOTE]\n> body\n\n> quote [!tip]\n", PathBuf::new());
+        match &note.blocks[0] {
+            Block::Callout { kind, title, fold, html, .. } => {
+                assert_eq!(kind, "note");
+                assert_eq!(title, "");
+                assert_eq!(*fold, None);
+                assert_eq!(html, "body");
+            }
+            other => panic!("expected callout, got {other:?}"),
+        }
+        assert_eq!(note.blocks[1].text(), "quote [!tip]");
+    }
+
+    #[test]
+    fn footnotes_numbered_by_first_reference() {
+        let src = "See[^b] and[^a] again[^b].\n\n[^a]: First def.\n[^b]: Second def.\n";
+        let note = parse_note(src, PathBuf::new());
+        let p = note.paragraph(0).unwrap();
+        assert_eq!(p.text(), "See[1] and[2] again[1].");
+        assert!(p.html().contains("<sup class=\"marki-footnote-ref\">1</sup>"));
+        let notes: Vec<_> = note
+            .footnotes()
+            .into_iter()
+            .map(|b| match b {
+                Block::Footnote { label, number, text, .. } => {
+                    (label.as_str(), *number, text.as_str())
+                }
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(notes, [("a", 2, "First def."), ("b", 1, "Second def.")]);
+    }
+
+    #[test]
+    fn task_list_items_carry_checked() {
+        let note = parse_note("- [ ] todo\n- [x] done\n- plain\n", PathBuf::new());
+        let Block::List { items, html, .. } = &note.blocks[0] else {
+            panic!("expected list");
+        };
+        let checked: Vec<_> = items.iter().map(|i| i.checked).collect();
+        assert_eq!(checked, [Some(false), Some(true), None]);
+        assert_eq!(items[1].text, "done");
+        assert!(
+            html.contains("<input type=\"checkbox\" disabled checked> done</li>"),
+            "got: {html}"
+        );
+        assert!(html.contains("<li>plain</li>"), "got: {html}");
+    }
+
+    #[test]
+    fn definition_list_groups_details_under_terms() {
+        let note = parse_note("Term\n: One\n: Two\n\nOther\n: Three\n", PathBuf::new());
+        let Block::DefinitionList { items, html, .. } = &note.blocks[0] else {
+            panic!("expected definition list, got {:?}", note.blocks);
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].term, "Term");
+        let details: Vec<_> = items[0].details.iter().map(|d| d.text.as_str()).collect();
+        assert_eq!(details, ["One", "Two"]);
+        assert!(html.starts_with("<dl><dt>Term</dt><dd>One</dd>"), "got: {html}");
+        assert!(html.ends_with("</dl>"), "got: {html}");
+    }
+
+    #[test]
+    fn highlight_becomes_mark() {
+        let note = parse_note("A ==salt & pepper== here, a == b == c.\n", PathBuf::new());
+        let p = note.paragraph(0).unwrap();
+        assert_eq!(p.text(), "A salt & pepper here, a == b == c.");
+        assert_eq!(p.html(), "A <mark>salt &amp; pepper</mark> here, a == b == c.");
+    }
 }
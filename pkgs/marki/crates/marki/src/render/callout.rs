@@ -0,0 +1,87 @@
+//! HTML for `> [!kind]` callouts.
+//!
+//! Cards use Anki's stock note types, so the styling is inline rather
+//! than in a stylesheet. Kinds and colours follow Obsidian's defaults;
+//! an unknown kind is drawn like `note`, as Obsidian does.
+
+/// Accent colour for a callout kind (or one of its aliases).
+fn accent(kind: &str) -> &'static str {
+    match kind {
+        "abstract" | "summary" | "tldr" | "tip" | "hint" | "important" => "#00bfbc",
+        "success" | "check" | "done" => "#08b94e",
+        "question" | "help" | "faq" | "warning" | "caution" | "attention" => "#ec7500",
+        "failure" | "fail" | "missing" | "danger" | "error" | "bug" => "#e93147",
+        "example" => "#7852ee",
+        "quote" | "cite" => "#9e9e9e",
+        _ => "#086ddd",
+    }
+}
+
+/// Title shown when the marker line has none: the kind, capitalised.
+fn default_title(kind: &str) -> String {
+    let mut chars = kind.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Full callout markup around an already-rendered title and body.
+/// Foldable callouts (`fold: Some(open)`) become `<details>`.
+pub(crate) fn callout_html(kind: &str, title_html: &str, fold: Option<bool>, body: &str) -> String {
+    let color = accent(kind);
+    let title = if title_html.is_empty() {
+        default_title(kind)
+    } else {
+        title_html.to_string()
+    };
+    // `kind` comes from `[A-Za-z][\w-]*`, so it is attribute-safe.
+    let frame = format!(
+        "class=\"marki-callout\" data-callout=\"{kind}\" style=\"border-left:4px solid {color};\
+         background:{color}1a;padding:0.5em 0.75em;margin:0.5em 0;\""
+    );
+    let title_style = format!("font-weight:bold;color:{color};");
+    let body = if body.is_empty() {
+        String::new()
+    } else {
+        format!("<div class=\"marki-callout-body\">{body}</div>")
+    };
+    match fold {
+        None => format!(
+            "<div {frame}><div class=\"marki-callout-title\" style=\"{title_style}\">{title}</div>\
+             {body}</div>"
+        ),
+        Some(open) => {
+            let open = if open { " open" } else { "" };
+            format!(
+                "<details {frame}{open}><summary class=\"marki-callout-title\" \
+                 style=\"{title_style}\">{title}</summary>{body}</details>"
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_callout_uses_kind_as_title() {
+        let html = callout_html("warning", "", None, "Mind the gap");
+        assert!(html.starts_with("<div class=\"marki-callout\" data-callout=\"warning\""));
+        assert!(html.contains("#ec7500"), "got: {html}");
+        assert!(html.contains(">Warning</div>"), "got: {html}");
+        assert!(html.contains("<div class=\"marki-callout-body\">Mind the gap</div>"));
+    }
+
+    #[test]
+    fn foldable_callout_is_details() {
+        let closed = callout_html("faq", "Why?", Some(false), "Because.");
+        assert!(closed.starts_with("<details "), "got: {closed}");
+        assert!(!closed.contains(" open>"), "got: {closed}");
+        assert!(closed.contains(">Why?</summary>"), "got: {closed}");
+        let open = callout_html("faq", "Why?", Some(true), "");
+        assert!(open.contains(" open>"), "got: {open}");
+        assert!(!open.contains("marki-callout-body"), "got: {open}");
+    }
+}
@@ -7,11 +7,16 @@
 //! to render each deferred block).
 
 use marki_render::{escape_html, Asset, Fragment, Input, RenderCtx, RenderError, Renderer};
+use marki_typst::TYPST_MATH_LANG;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::path::Path;
 
-use crate::highlighter::highlight_code;
-use crate::note::Block;
+use crate::highlighter::Highlighter;
+use crate::note::{Block, MathSpan};
+use crate::note_parser::MATH_SPAN_OPEN;
+
+mod callout;
 
 #[derive(Default)]
 pub struct Registry {
@@ -19,6 +24,8 @@ pub struct Registry {
     /// Snapshot of the keyset as `&'static str` so callers can pass it
     /// to the parser without per-call allocation.
     langs: Vec<&'static str>,
+    /// Syntax highlighting for code blocks no renderer claims.
+    highlighter: Highlighter,
 }
 
 impl Registry {
@@ -38,6 +45,37 @@ impl Registry {
         self.langs.push(lang);
     }
 
+    /// Replace the default highlighter (bundled grammars and theme).
+    pub fn set_highlighter(&mut self, highlighter: Highlighter) {
+        self.highlighter = highlighter;
+    }
+
+    pub fn highlighter(&self) -> &Highlighter {
+        &self.highlighter
+    }
+
+    /// Fingerprint of everything besides a note's own inputs that shapes
+    /// its rendered HTML: this build of marki (which renders plain
+    /// markdown), each registered renderer's [`Renderer::version`], and
+    /// the highlighter. Recorded per note so `marki diff` can tell a
+    /// renderer upgrade from an edit.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        let mut langs = self.langs.clone();
+        langs.sort_unstable();
+        for lang in langs {
+            hasher.update(b"\x00");
+            hasher.update(lang.as_bytes());
+            hasher.update(&self.renderers[lang].version().to_le_bytes());
+        }
+        if let Some(fp) = self.highlighter.fingerprint() {
+            hasher.update(b"\x00highlight\x00");
+            hasher.update(fp.as_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+
     /// Block tokens to hand to the parser's `external_langs` argument.
     pub fn external_langs(&self) -> &[&'static str] {
         &self.langs
@@ -76,8 +114,10 @@ impl Registry {
     ///
     /// Code blocks whose lang has a registered renderer are dispatched
     /// through it; `math`/`latex` become MathJax display math; every
-    /// other code block is syntax-highlighted; prose blocks are wrapped
-    /// in their element. A renderer's `reveal` output is collected
+    /// other code block is syntax-highlighted (honouring the fence's line
+    /// options); prose blocks are wrapped
+    /// in their element, with their `$…$` spans typeset by the
+    /// `typst-math` renderer when one is registered. A renderer's `reveal` output is collected
     /// separately so the caller can place it on the card back.
     ///
     /// Routing every path through here is what stops a `map` block from
@@ -92,7 +132,7 @@ impl Registry {
 
         for block in blocks {
             match block {
-                Block::CodeBlock { lang: Some(lang), source } if self.handles(lang) => {
+                Block::CodeBlock { lang: Some(lang), source, .. } if self.handles(lang) => {
                     match self.dispatch(lang, Input::Raw(source), source_path, cache_dir) {
                         Ok(frag) => {
                             out.html.push_str(&frag.html);
@@ -115,40 +155,104 @@ impl Registry {
                         }
                     }
                 }
-                Block::CodeBlock { lang: Some(lang), source }
+                Block::CodeBlock { lang: Some(lang), source, .. }
                     if lang == "math" || lang == "latex" =>
                 {
                     out.html.push_str("\\[");
                     out.html.push_str(source);
                     out.html.push_str("\\]");
                 }
-                Block::CodeBlock { lang: Some(lang), source } => {
+                Block::CodeBlock { lang: Some(lang), info, source } => {
                     // A leading `_` opts a fence out of external dispatch;
                     // strip it before choosing a highlighter grammar.
                     let effective = lang.strip_prefix('_').unwrap_or(lang);
-                    out.html.push_str(&highlight_code(source, effective));
+                    out.html.push_str(&self.highlighter.highlight(source, effective, info));
                 }
-                Block::CodeBlock { lang: None, source } => {
-                    out.html.push_str(&highlight_code(source, "txt"));
+                Block::CodeBlock { lang: None, info, source } => {
+                    out.html.push_str(&self.highlighter.highlight(source, "txt", info));
                 }
                 Block::ThematicBreak => {}
-                Block::Heading { html, level, .. } => {
+                Block::Heading { html, level, math, .. } => {
+                    let html = self.typeset_math(html, math, source_path, cache_dir, &mut out);
                     out.html.push_str(&format!("<h{level}>{html}</h{level}>"));
                 }
-                Block::Paragraph { html, .. } => {
+                Block::Paragraph { html, math, .. } => {
+                    let html = self.typeset_math(html, math, source_path, cache_dir, &mut out);
                     out.html.push_str(&format!("<p>{html}</p>"));
                 }
-                Block::List { html, .. } | Block::Table { html } => {
-                    out.html.push_str(html);
+                Block::List { html, math, .. }
+                | Block::Table { html, math }
+                | Block::DefinitionList { html, math, .. } => {
+                    let html = self.typeset_math(html, math, source_path, cache_dir, &mut out);
+                    out.html.push_str(&html);
                 }
-                Block::Blockquote { html, .. } => {
+                Block::Blockquote { html, math, .. } => {
+                    let html = self.typeset_math(html, math, source_path, cache_dir, &mut out);
                     out.html.push_str(&format!("<blockquote>{html}</blockquote>"));
                 }
+                Block::Callout { kind, title_html, fold, html, math, .. } => {
+                    // Title spans precede body spans in `math`, matching
+                    // their order in the assembled markup.
+                    let frame = callout::callout_html(kind, title_html, *fold, html);
+                    let frame = self.typeset_math(&frame, math, source_path, cache_dir, &mut out);
+                    out.html.push_str(&frame);
+                }
+                Block::Footnote { number, html, math, .. } => {
+                    let html = self.typeset_math(html, math, source_path, cache_dir, &mut out);
+                    out.html.push_str(&format!(
+                        "<div class=\"marki-footnote\" style=\"font-size:0.85em;\">\
+                         <sup>{number}</sup> {html}</div>"
+                    ));
+                }
             }
         }
 
         out
     }
+
+    /// Replace the parser's MathJax spans in `html` with Typst output, in
+    /// order, one per entry of `math`. Without a `typst-math` renderer the
+    /// HTML is returned untouched. A span that fails to compile keeps its
+    /// MathJax markup and is reported in `out.errors`.
+    fn typeset_math<'h>(
+        &self,
+        html: &'h str,
+        math: &[MathSpan],
+        source_path: &Path,
+        cache_dir: &Path,
+        out: &mut RenderedBlocks,
+    ) -> Cow<'h, str> {
+        if math.is_empty() || !self.handles(TYPST_MATH_LANG) {
+            return Cow::Borrowed(html);
+        }
+        const CLOSE: &str = "</span>";
+        let mut typeset = String::with_capacity(html.len());
+        let mut rest = html;
+        for span in math {
+            let Some(start) = rest.find(MATH_SPAN_OPEN) else { break };
+            let Some(len) = rest[start..].find(CLOSE) else { break };
+            let end = start + len + CLOSE.len();
+            typeset.push_str(&rest[..start]);
+
+            let mut spec = toml::Table::new();
+            spec.insert("source".into(), toml::Value::String(span.source.clone()));
+            spec.insert("display".into(), toml::Value::Boolean(span.display));
+            let input = Input::Spec(toml::Value::Table(spec));
+            match self.dispatch(TYPST_MATH_LANG, input, source_path, cache_dir) {
+                Ok(frag) => {
+                    typeset.push_str(&frag.html);
+                    out.assets.extend(frag.assets);
+                }
+                Err(e) => {
+                    out.errors.push(format!("math `{}`: {e}", span.source));
+                    typeset.push_str(&rest[start..end]);
+                }
+            }
+            rest = &rest[end..];
+        }
+        typeset.push_str(rest);
+        Cow::Owned(typeset)
+    }
 }
 
 /// HTML produced by [`Registry::render_blocks`], split into the front
@@ -188,6 +292,15 @@ mod tests {
         (PathBuf::from("/tmp/x.md"), PathBuf::from("/tmp/c"))
     }
 
+    #[test]
+    fn fingerprint_tracks_registered_renderers() {
+        let empty = Registry::new().fingerprint();
+        let mut reg = Registry::new();
+        reg.register(Box::new(Plain));
+        assert_ne!(reg.fingerprint(), empty);
+        assert_eq!(reg.fingerprint(), reg.fingerprint());
+    }
+
     #[test]
     fn registry_dispatches() {
         let mut reg = Registry::new();
@@ -237,6 +350,7 @@ mod tests {
 
         let blocks = vec![Block::CodeBlock {
             lang: Some("plain".into()),
+            info: String::new(),
             source: "payload\n".into(),
         }];
         let (src, cache) = paths();
@@ -247,12 +361,91 @@ mod tests {
         assert!(out.errors.is_empty());
     }
 
+    /// Stand-in for `TypstMathRenderer`: echoes the span it was given, and
+    /// fails on a source of `bad`.
+    struct FakeMath;
+    impl Renderer for FakeMath {
+        fn lang(&self) -> &'static str {
+            TYPST_MATH_LANG
+        }
+        fn render(
+            &self,
+            input: Input<'_>,
+            _ctx: &mut RenderCtx<'_>,
+        ) -> Result<Fragment, RenderError> {
+            let t = input.into_table()?;
+            let src = t["source"].as_str().unwrap();
+            if src == "bad" {
+                return Err(RenderError::Internal("boom".into()));
+            }
+            let display = t["display"].as_bool().unwrap();
+            Ok(Fragment {
+                html: format!("<img alt=\"{src}\" data-display=\"{display}\">"),
+                assets: vec![Asset {
+                    filename: format!("{src}.svg"),
+                    bytes: vec![],
+                    mime: marki_render::AssetMime::SvgXml,
+                }],
+                ..Default::default()
+            })
+        }
+    }
+
+    fn math_paragraph(source: &str) -> Vec<Block> {
+        let note = crate::note_parser::parse_note(source, PathBuf::from("x.md"));
+        note.blocks
+    }
+
+    #[test]
+    fn math_spans_stay_mathjax_without_typst() {
+        let reg = Registry::new();
+        let (src, cache) = paths();
+        let out = reg.render_blocks(&math_paragraph("Let $x$ be."), &src, &cache);
+        assert_eq!(out.html, "<p>Let <span class=\"marki-math\">\\(x\\)</span> be.</p>");
+    }
+
+    #[test]
+    fn math_spans_are_typeset_in_order() {
+        let mut reg = Registry::new();
+        reg.register(Box::new(FakeMath));
+        let (src, cache) = paths();
+        let blocks = math_paragraph("$a$ and $$b$$ then $c$");
+        let out = reg.render_blocks(&blocks, &src, &cache);
+
+        assert_eq!(
+            out.html,
+            "<p><img alt=\"a\" data-display=\"false\"> and \
+             <img alt=\"b\" data-display=\"true\"> then \
+             <img alt=\"c\" data-display=\"false\"></p>"
+        );
+        let names: Vec<_> = out.assets.iter().map(|a| a.filename.as_str()).collect();
+        assert_eq!(names, ["a.svg", "b.svg", "c.svg"]);
+        assert!(out.errors.is_empty());
+    }
+
+    #[test]
+    fn failed_math_span_falls_back_to_mathjax() {
+        let mut reg = Registry::new();
+        reg.register(Box::new(FakeMath));
+        let (src, cache) = paths();
+        let out = reg.render_blocks(&math_paragraph("- $bad$ then $ok$"), &src, &cache);
+
+        assert!(out.html.contains("<span class=\"marki-math\">\\(bad\\)</span>"));
+        assert!(out.html.contains("<img alt=\"ok\""));
+        assert_eq!(out.errors.len(), 1);
+        assert!(out.errors[0].contains("bad"));
+    }
+
     #[test]
     fn render_blocks_wraps_prose_and_highlights_unknown_code() {
         let reg = Registry::new();
         let blocks = vec![
-            Block::Paragraph { text: "hi".into(), html: "hi".into() },
-            Block::CodeBlock { lang: Some("rust".into()), source: "fn x(){}".into() },
+            Block::Paragraph { text: "hi".into(), html: "hi".into(), math: vec![] },
+            Block::CodeBlock {
+                lang: Some("rust".into()),
+                info: String::new(),
+                source: "fn x(){}".into(),
+            },
         ];
         let (src, cache) = paths();
         let out = reg.render_blocks(&blocks, &src, &cache);
@@ -9,18 +9,27 @@
 //!     of the note's blocks through the shared [`Registry::render_blocks`]
 //!     path, so external blocks are dispatched rather than dumped as raw
 //!     source.
+//!   * `ctx:media(src)` / `ctx:typst(src)` / `ctx:tts(text)` build a
+//!     `media`, `typst` or `tts` block from script-side values and render
+//!     it like `ctx:render`; `ctx:media` and `ctx:tts` also take a table
+//!     of the block's fields.
+//!   * `ctx:read_data(path)` loads a TOML, JSON or CSV file from the card
+//!     repo into a Lua table (see [`DataStore`]).
 //!
 //! Assets emitted while a script runs are accumulated here and drained by
 //! the sync engine after `generate()` returns.
 
 use marki_render::{Asset, Input};
-use mlua::{AnyUserData, UserData, UserDataMethods};
+use mlua::serde::SerializeOptions;
+use mlua::{AnyUserData, Lua, LuaSerdeExt, Table, UserData, UserDataMethods};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use crate::note::Note;
 use crate::render::Registry;
 
+use super::data::DataStore;
+
 /// The context object passed to model scripts as `ctx`.
 #[derive(Clone)]
 pub struct RenderContext {
@@ -31,6 +40,8 @@ pub struct RenderContext {
     /// clone (including the one handed to Lua) so the sync engine can
     /// drain them from its own handle after the script returns.
     accumulated_assets: Arc<Mutex<Vec<Asset>>>,
+    /// Backing store for `ctx:read_data`; reads fail when unset.
+    data: Option<Arc<DataStore>>,
 }
 
 impl RenderContext {
@@ -40,9 +51,16 @@ impl RenderContext {
             source_path,
             cache_dir,
             accumulated_assets: Arc::new(Mutex::new(Vec::new())),
+            data: None,
         }
     }
 
+    /// Let the script load data files through `store`.
+    pub fn with_data(mut self, store: Arc<DataStore>) -> Self {
+        self.data = Some(store);
+        self
+    }
+
     /// Drain all accumulated assets (called after script execution).
     pub fn take_assets(&self) -> Vec<Asset> {
         std::mem::take(&mut self.accumulated_assets.lock().unwrap())
@@ -61,26 +79,82 @@ impl RenderContext {
         self.push_assets(out.assets);
         out.html
     }
+
+    /// Dispatch one block and hand it to Lua as `{ front_html, back_html,
+    /// assets }`, keeping the assets for the sync engine.
+    fn dispatch_table(&self, lua: &Lua, lang: &str, input: Input<'_>) -> mlua::Result<Table> {
+        let frag = self
+            .registry
+            .dispatch(lang, input, &self.source_path, &self.cache_dir)
+            .map_err(|e| mlua::Error::runtime(format!("render({lang}): {e}")))?;
+
+        let asset_names: Vec<String> = frag.assets.iter().map(|a| a.filename.clone()).collect();
+        self.push_assets(frag.assets);
+
+        let t = lua.create_table()?;
+        t.set("front_html", frag.html)?;
+        t.set("back_html", frag.reveal)?;
+        t.set("assets", asset_names)?;
+        Ok(t)
+    }
+}
+
+/// A block spec from a script argument: a string fills `key`, a table is
+/// taken field by field.
+fn spec_arg(lua: &Lua, key: &str, arg: mlua::Value) -> mlua::Result<toml::Value> {
+    match arg {
+        mlua::Value::String(s) => {
+            let value = toml::Value::String(s.to_str()?.to_string());
+            let mut t = toml::Table::new();
+            t.insert(key.to_string(), value);
+            Ok(toml::Value::Table(t))
+        }
+        v @ mlua::Value::Table(_) => lua.from_value(v),
+        other => Err(mlua::Error::runtime(format!(
+            "expected a string or table, got {}",
+            other.type_name()
+        ))),
+    }
 }
 
 impl UserData for RenderContext {
     fn add_methods<M: UserDataMethods<Self>>(m: &mut M) {
         // ctx:render(lang, source) -> { front_html, back_html, assets }
         m.add_method("render", |lua, this, (lang, source): (String, String)| {
-            let frag = this
-                .registry
-                .dispatch(&lang, Input::Raw(&source), &this.source_path, &this.cache_dir)
-                .map_err(|e| mlua::Error::runtime(format!("render({lang}): {e}")))?;
-
-            let asset_names: Vec<String> =
-                frag.assets.iter().map(|a| a.filename.clone()).collect();
-            this.push_assets(frag.assets);
-
-            let t = lua.create_table()?;
-            t.set("front_html", frag.html)?;
-            t.set("back_html", frag.reveal)?;
-            t.set("assets", asset_names)?;
-            Ok(t)
+            this.dispatch_table(lua, &lang, Input::Raw(&source))
+        });
+
+        // ctx:media(src | { src = ..., ... }) -> { front_html, back_html, assets }
+        m.add_method("media", |lua, this, arg: mlua::Value| {
+            let spec = spec_arg(lua, "src", arg)?;
+            this.dispatch_table(lua, marki_media::MEDIA_LANG, Input::Spec(spec))
+        });
+
+        // ctx:typst(source) -> { front_html, back_html, assets }
+        m.add_method("typst", |lua, this, arg: mlua::Value| {
+            let spec = spec_arg(lua, "source", arg)?;
+            this.dispatch_table(lua, marki_typst::TYPST_LANG, Input::Spec(spec))
+        });
+
+        // ctx:tts(text | { text = ..., lang = ..., ... }) -> { front_html, back_html, assets }
+        m.add_method("tts", |lua, this, arg: mlua::Value| {
+            let spec = spec_arg(lua, "text", arg)?;
+            this.dispatch_table(lua, marki_tts::TTS_LANG, Input::Spec(spec))
+        });
+
+        // ctx:read_data(path) -> table
+        m.add_method("read_data", |lua, this, path: String| {
+            let store = this
+                .data
+                .as_ref()
+                .ok_or_else(|| mlua::Error::runtime("read_data: no data directory configured"))?;
+            let value = store
+                .read(&path)
+                .map_err(|e| mlua::Error::runtime(format!("read_data({path}): {e:#}")))?;
+            let opts = SerializeOptions::new()
+                .serialize_none_to_null(false)
+                .serialize_unit_to_null(false);
+            lua.to_value_with(&value, opts)
         });
 
         // ctx:section_html(note, n) -> string (1-based section index)
@@ -0,0 +1,163 @@
+//! Structured data files for model scripts (`ctx:read_data(path)`).
+//!
+//! Scripts may load TOML, JSON or CSV files that live in the card repo, so
+//! a model can enrich a note from a shared dataset instead of repeating
+//! the same facts in every card file. Reads are sandboxed to the cards
+//! directory and the `.marki/` directory, and each file is parsed at most
+//! once per sync cycle.
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Sandboxed loader for data files, with a per-cycle parse cache.
+#[derive(Debug, Default)]
+pub struct DataStore {
+    /// Directory relative paths are resolved against (the project root).
+    base: PathBuf,
+    /// Canonicalized directories reads must stay inside.
+    roots: Vec<PathBuf>,
+    cache: Mutex<HashMap<PathBuf, Value>>,
+}
+
+impl DataStore {
+    /// A store resolving relative paths against `base` and admitting only
+    /// files under one of `roots`. Roots that do not exist are dropped.
+    pub fn new(base: PathBuf, roots: &[PathBuf]) -> Self {
+        let roots = roots.iter().filter_map(|r| r.canonicalize().ok()).collect();
+        Self {
+            base,
+            roots,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forget every parsed file, so edits made between cycles are seen.
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Load and parse `path`, by extension: `.toml`, `.json` or `.csv`.
+    /// CSV files become an array of row tables keyed by the header row.
+    pub fn read(&self, path: &str) -> Result<Value> {
+        let joined = self.base.join(path);
+        let full = joined
+            .canonicalize()
+            .with_context(|| format!("data file {} not found", joined.display()))?;
+        if !self.roots.iter().any(|r| full.starts_with(r)) {
+            bail!("{path} is outside the cards and .marki directories");
+        }
+        if let Some(v) = self.cache.lock().unwrap().get(&full) {
+            return Ok(v.clone());
+        }
+        let value = parse_file(&full)?;
+        self.cache.lock().unwrap().insert(full, value.clone());
+        Ok(value)
+    }
+}
+
+fn parse_file(path: &Path) -> Result<Value> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match ext.to_ascii_lowercase().as_str() {
+        "toml" => {
+            let v: toml::Value =
+                toml::from_str(&text).with_context(|| format!("parse {}", path.display()))?;
+            Ok(toml_to_json(v))
+        }
+        "json" => serde_json::from_str(&text).with_context(|| format!("parse {}", path.display())),
+        "csv" => parse_csv(&text).with_context(|| format!("parse {}", path.display())),
+        _ => bail!(
+            "{}: unsupported data format (expected .toml, .json or .csv)",
+            path.display()
+        ),
+    }
+}
+
+/// TOML values map onto JSON one to one, except datetimes, which become
+/// their RFC 3339 string.
+fn toml_to_json(v: toml::Value) -> Value {
+    match v {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(a) => Value::Array(a.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => {
+            Value::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect())
+        }
+    }
+}
+
+/// RFC 4180 CSV: the first record names the columns, each later record
+/// becomes a table of strings. Quoted fields may hold commas, newlines and
+/// doubled quotes; missing trailing cells are left out of the row.
+fn parse_csv(text: &str) -> Result<Value> {
+    let mut records = marki_render::csv::records(text).map_err(anyhow::Error::msg)?.into_iter();
+    let Some(header) = records.next() else {
+        return Ok(Value::Array(Vec::new()));
+    };
+    let rows = records
+        .map(|rec| {
+            let row: Map<String, Value> = header
+                .iter()
+                .zip(rec)
+                .map(|(k, v)| (k.clone(), Value::String(v)))
+                .collect();
+            Value::Object(row)
+        })
+        .collect();
+    Ok(Value::Array(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows_are_keyed_by_header() {
+        let v =
+            parse_csv("name,capital\r\nFrance,Paris\n\n\"Korea, South\",\"Se\"\"oul\"\n").unwrap();
+        assert_eq!(
+            v,
+            serde_json::json!([
+                { "name": "France", "capital": "Paris" },
+                { "name": "Korea, South", "capital": "Se\"oul" },
+            ])
+        );
+        assert!(parse_csv("a\n\"open").is_err());
+        assert_eq!(parse_csv("").unwrap(), serde_json::json!([]));
+    }
+
+    #[test]
+    fn reads_are_confined_to_roots_and_cached() {
+        let dir = std::env::temp_dir().join(format!("marki-data-store-{}", std::process::id()));
+        let cards = dir.join("cards");
+        std::fs::create_dir_all(&cards).unwrap();
+        std::fs::write(cards.join("geo.toml"), "[France]\npopulation = 68\n").unwrap();
+        std::fs::write(dir.join("secret.json"), "{}").unwrap();
+
+        let store = DataStore::new(dir.clone(), std::slice::from_ref(&cards));
+        let v = store.read("cards/geo.toml").unwrap();
+        assert_eq!(v["France"]["population"], 68);
+        assert!(store.read("secret.json").is_err());
+        assert!(store.read("cards/../secret.json").is_err());
+
+        // Served from the cache until the cycle ends.
+        std::fs::write(cards.join("geo.toml"), "[France]\npopulation = 69\n").unwrap();
+        assert_eq!(
+            store.read("cards/geo.toml").unwrap()["France"]["population"],
+            68
+        );
+        store.clear();
+        assert_eq!(
+            store.read("cards/geo.toml").unwrap()["France"]["population"],
+            69
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
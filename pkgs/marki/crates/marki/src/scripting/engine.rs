@@ -38,6 +38,9 @@ pub struct CompiledModel {
     pub name: String,
     pub generate: Function,
     pub card_names: Vec<String>,
+    /// blake3 of the script source, recorded per note so `marki diff` can
+    /// attribute a change to the model script.
+    pub fingerprint: String,
     /// Modified time of the `.lua` file when it was loaded. Used to
     /// detect on-disk edits so the cache reloads only what changed,
     /// instead of being cleared wholesale every sync cycle.
@@ -53,6 +56,8 @@ pub struct ScriptEngine {
     /// Remaining instruction budget for the currently running script,
     /// charged down by the execution hook. Reset before each invocation.
     budget: Rc<Cell<i64>>,
+    /// Data files scripts may load through `ctx:read_data`.
+    data: Arc<super::data::DataStore>,
 }
 
 impl ScriptEngine {
@@ -86,9 +91,27 @@ impl ScriptEngine {
             lib_dir,
             compiled: HashMap::new(),
             budget,
+            data: Arc::default(),
         }
     }
 
+    /// Let scripts read data files under `roots`, resolving relative
+    /// paths against `base`. Without this, `ctx:read_data` admits nothing.
+    pub fn with_data_roots(mut self, base: PathBuf, roots: &[PathBuf]) -> Self {
+        self.data = Arc::new(super::data::DataStore::new(base, roots));
+        self
+    }
+
+    /// The store to attach to each note's render context.
+    pub fn data_store(&self) -> Arc<super::data::DataStore> {
+        Arc::clone(&self.data)
+    }
+
+    /// Start a sync cycle: data files are re-read on first use.
+    pub fn begin_cycle(&self) {
+        self.data.clear();
+    }
+
     fn reset_budget(&self) {
         self.budget.set(INSTRUCTION_BUDGET);
     }
@@ -148,6 +171,7 @@ impl ScriptEngine {
             name: name.to_string(),
             generate,
             card_names,
+            fingerprint: blake3::hash(source.as_bytes()).to_hex().to_string(),
             mtime,
         });
         self.compiled.insert(name.to_string(), Arc::clone(&compiled));
@@ -287,6 +311,96 @@ return M
         std::fs::remove_dir_all(&dir).ok();
     }
 
+    #[test]
+    fn lua_reads_shared_data_files() {
+        use crate::note_parser::parse_note;
+        use crate::render::Registry;
+        use crate::scripting::context::RenderContext;
+
+        let dir = std::env::temp_dir().join(format!("marki-lua-data-{}", std::process::id()));
+        let data = dir.join(".marki/data");
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(data.join("countries.csv"), "name,capital\nFrance,Paris\n").unwrap();
+        std::fs::write(
+            dir.join("geo.lua"),
+            r#"
+local M = {}
+function M.card_names() return { "Front" } end
+function M.generate(note, ctx)
+  local rows = ctx:read_data(".marki/data/countries.csv")
+  local ok = pcall(function() ctx:read_data("../outside.json") end)
+  return { Front = rows[1].name .. " " .. rows[1].capital .. " " .. tostring(ok) }
+end
+return M
+"#,
+        )
+        .unwrap();
+
+        let mut se = ScriptEngine::new(dir.clone(), None)
+            .with_data_roots(dir.clone(), &[dir.clone(), dir.join(".marki")]);
+        let compiled = se.load_model("geo").unwrap();
+        let ctx = RenderContext::new(
+            Arc::new(Registry::new()),
+            dir.join("x.md"),
+            PathBuf::from("/tmp"),
+        )
+        .with_data(se.data_store());
+        let note = parse_note("# France\n", dir.join("x.md"));
+        let out = se.execute(&compiled, note, ctx).unwrap();
+        assert_eq!(out.get("Front").unwrap(), "France Paris false");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn lua_sees_extended_blocks() {
+        use crate::note_parser::parse_note;
+        use crate::render::Registry;
+        use crate::scripting::context::RenderContext;
+
+        let dir = std::env::temp_dir().join("marki-lua-extended");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("ext.lua"),
+            r#"
+local M = {}
+function M.card_names() return { "Front" } end
+function M.generate(note, ctx)
+  local c = note:callouts()[1]
+  local tasks = note:lists()[1]:items()
+  local defs = note:definition_lists()[1]:items()
+  return {
+    Front = table.concat({
+      c:kind(), c:callout_kind(), c:title(),
+      tostring(tasks[1].checked), tostring(tasks[2].checked),
+      defs[1].term, defs[1].details[1].text,
+      note:footnotes()[1]:label(), tostring(note:paragraph(1):title()),
+    }, "|"),
+  }
+end
+return M
+"#,
+        )
+        .unwrap();
+
+        let mut se = ScriptEngine::new(dir.clone(), None);
+        let compiled = se.load_model("ext").unwrap();
+        let note = parse_note(
+            "> [!tip] Remember\n> this\n\n- [x] a\n- [ ] b\n\nTerm\n: Def\n\n\
+             Body[^n]\n\n[^n]: Foot\n",
+            PathBuf::from("/tmp/x.md"),
+        );
+        let ctx = RenderContext::new(
+            Arc::new(Registry::new()),
+            PathBuf::from("/tmp/x.md"),
+            PathBuf::from("/tmp"),
+        );
+        let out = se.execute(&compiled, note, ctx).unwrap();
+        assert_eq!(out.get("Front").unwrap(), "callout|tip|Remember|true|false|Term|Def|n|nil");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn instruction_budget_aborts_runaway_scripts() {
         use crate::note_parser::parse_note;
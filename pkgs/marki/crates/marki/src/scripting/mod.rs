@@ -9,5 +9,6 @@
 //! through `sync::engine::render_stock`.
 
 pub mod context;
+pub mod data;
 pub mod engine;
 pub mod types;
@@ -4,10 +4,14 @@
 //! calls: `note:heading(1)`, `note:code_block("map")`, `block:html()`,
 //! etc. Indices are 1-based, matching Lua convention -- `note:heading(1)`
 //! is the first heading. A missing element yields `nil`.
+//!
+//! `block:kind()` names the variant (`"paragraph"`, `"callout"`, …);
+//! variant-specific accessors such as `block:title()` or `block:items()`
+//! return `nil` on blocks they don't apply to.
 
-use mlua::{MetaMethod, UserData, UserDataMethods, Value};
+use mlua::{Lua, MetaMethod, Table, UserData, UserDataMethods, Value};
 
-use crate::note::{Block, Note, TagValue};
+use crate::note::{Block, ListItem, Note, TagValue};
 
 /// Convert a 1-based Lua index to a 0-based Rust index. Returns `None`
 /// for `n < 1` so callers can hand back `nil` instead of underflowing.
@@ -64,6 +68,15 @@ impl UserData for Note {
         m.add_method("blockquotes", |_, this, ()| {
             Ok(this.blockquotes().into_iter().cloned().collect::<Vec<Block>>())
         });
+        m.add_method("callouts", |_, this, ()| {
+            Ok(this.callouts().into_iter().cloned().collect::<Vec<Block>>())
+        });
+        m.add_method("footnotes", |_, this, ()| {
+            Ok(this.footnotes().into_iter().cloned().collect::<Vec<Block>>())
+        });
+        m.add_method("definition_lists", |_, this, ()| {
+            Ok(this.definition_lists().into_iter().cloned().collect::<Vec<Block>>())
+        });
 
         m.add_method("tag", |_, this, name: String| Ok(this.tag(&name).cloned()));
         m.add_method("has_tag", |_, this, name: String| Ok(this.has_tag(&name)));
@@ -76,9 +89,76 @@ impl UserData for Block {
         m.add_method("html", |_, this, ()| Ok(this.html().to_string()));
         m.add_method("lang", |_, this, ()| Ok(this.lang().map(str::to_string)));
         m.add_method("source", |_, this, ()| Ok(this.source().map(str::to_string)));
+        m.add_method("kind", |_, this, ()| Ok(this.kind()));
+
+        // Callouts: `callout_kind` is the `[!kind]` type, `title` the
+        // custom title ("" when the marker line has none).
+        m.add_method("callout_kind", |_, this, ()| {
+            Ok(match this {
+                Block::Callout { kind, .. } => Some(kind.clone()),
+                _ => None,
+            })
+        });
+        m.add_method("title", |_, this, ()| {
+            Ok(match this {
+                Block::Callout { title, .. } => Some(title.clone()),
+                _ => None,
+            })
+        });
+
+        // Footnotes.
+        m.add_method("label", |_, this, ()| {
+            Ok(match this {
+                Block::Footnote { label, .. } => Some(label.clone()),
+                _ => None,
+            })
+        });
+        m.add_method("number", |_, this, ()| {
+            Ok(match this {
+                Block::Footnote { number, .. } => Some(*number),
+                _ => None,
+            })
+        });
+
+        // Lists yield `{ text, html, checked }` (checked is nil outside
+        // task lists); definition lists yield `{ term, term_html, details }`
+        // with `details` a list of `{ text, html }`.
+        m.add_method("items", |lua, this, ()| match this {
+            Block::List { items, .. } => {
+                let t = lua.create_table()?;
+                for item in items {
+                    t.push(item_table(lua, item)?)?;
+                }
+                Ok(Some(t))
+            }
+            Block::DefinitionList { items, .. } => {
+                let t = lua.create_table()?;
+                for def in items {
+                    let entry = lua.create_table()?;
+                    entry.set("term", def.term.as_str())?;
+                    entry.set("term_html", def.term_html.as_str())?;
+                    let details = lua.create_table()?;
+                    for d in &def.details {
+                        details.push(item_table(lua, d)?)?;
+                    }
+                    entry.set("details", details)?;
+                    t.push(entry)?;
+                }
+                Ok(Some(t))
+            }
+            _ => Ok(None),
+        });
     }
 }
 
+fn item_table(lua: &Lua, item: &ListItem) -> mlua::Result<Table> {
+    let t = lua.create_table()?;
+    t.set("text", item.text.as_str())?;
+    t.set("html", item.html.as_str())?;
+    t.set("checked", item.checked)?;
+    Ok(t)
+}
+
 impl UserData for TagValue {
     fn add_methods<M: UserDataMethods<Self>>(m: &mut M) {
         m.add_method("is_bool", |_, this, ()| Ok(matches!(this, TagValue::Bool)));
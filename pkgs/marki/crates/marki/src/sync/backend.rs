@@ -0,0 +1,205 @@
+//! Where a reconcile cycle reads managed notes from and writes its plan to.
+//!
+//! [`Backend`] is what [`reconcile`](super::reconcile) and
+//! [`diff`](super::diff) see of a collection: the managed notes, a
+//! notetype's CSS, one batch of [`Writer`] edits, and media. Two backends
+//! exist. [`DirectBackend`] edits a collection file in place (the sync
+//! server's own file, next to its `media/` and `media.db`).
+//! [`ServerBackend`] reaches the collection as a sync client through
+//! [`marki_anki::remote::Remote`], for servers marki has no shell on.
+
+use anyhow::{Context, Result};
+use marki_anki::notetype::ModelSpec;
+use marki_anki::remote::Remote;
+use marki_anki::{Collection, NoteWriter, RawManagedNote};
+use marki_render::Asset;
+use std::path::PathBuf;
+
+use super::media;
+
+/// The edits a cycle makes inside one batch. Mirrors [`NoteWriter`]; see
+/// there for what each call writes.
+pub trait Writer {
+    fn ensure_model(&mut self, spec: &ModelSpec) -> Result<i64>;
+    fn deck_id_for(&mut self, human_name: &str) -> Result<i64>;
+    fn add_note(
+        &mut self,
+        mid: i64,
+        guid: &str,
+        fields: Vec<String>,
+        sort_field_idx: u32,
+        tags: &[String],
+        deck_id: i64,
+    ) -> Result<i64>;
+    fn update_note(
+        &mut self,
+        note_id: i64,
+        fields: Vec<String>,
+        sort_field_idx: u32,
+        tags: &[String],
+    ) -> Result<()>;
+    fn set_note_deck(&mut self, note_id: i64, deck_id: i64) -> Result<()>;
+    fn remove_note(&mut self, note_id: i64) -> Result<usize>;
+    fn suspend_note_cards(&mut self, note_id: i64) -> Result<usize>;
+    fn gate_note_cards(&mut self, note_id: i64) -> Result<usize>;
+    fn release_note_cards(&mut self, note_id: i64) -> Result<usize>;
+    fn add_tag_to_note(&mut self, note_id: i64, tag: &str) -> Result<()>;
+    fn remove_tag_from_note(&mut self, note_id: i64, tag: &str) -> Result<()>;
+    fn reposition_new_cards(&mut self, note_id: i64, pos: i64) -> Result<usize>;
+}
+
+impl Writer for NoteWriter<'_> {
+    fn ensure_model(&mut self, spec: &ModelSpec) -> Result<i64> {
+        NoteWriter::ensure_model(self, spec)
+    }
+
+    fn deck_id_for(&mut self, human_name: &str) -> Result<i64> {
+        NoteWriter::deck_id_for(self, human_name)
+    }
+
+    fn add_note(
+        &mut self,
+        mid: i64,
+        guid: &str,
+        fields: Vec<String>,
+        sort_field_idx: u32,
+        tags: &[String],
+        deck_id: i64,
+    ) -> Result<i64> {
+        NoteWriter::add_note(self, mid, guid, fields, sort_field_idx, tags, deck_id)
+    }
+
+    fn update_note(
+        &mut self,
+        note_id: i64,
+        fields: Vec<String>,
+        sort_field_idx: u32,
+        tags: &[String],
+    ) -> Result<()> {
+        NoteWriter::update_note(self, note_id, fields, sort_field_idx, tags)
+    }
+
+    fn set_note_deck(&mut self, note_id: i64, deck_id: i64) -> Result<()> {
+        NoteWriter::set_note_deck(self, note_id, deck_id)
+    }
+
+    fn remove_note(&mut self, note_id: i64) -> Result<usize> {
+        NoteWriter::remove_note(self, note_id)
+    }
+
+    fn suspend_note_cards(&mut self, note_id: i64) -> Result<usize> {
+        NoteWriter::suspend_note_cards(self, note_id)
+    }
+
+    fn gate_note_cards(&mut self, note_id: i64) -> Result<usize> {
+        NoteWriter::gate_note_cards(self, note_id)
+    }
+
+    fn release_note_cards(&mut self, note_id: i64) -> Result<usize> {
+        NoteWriter::release_note_cards(self, note_id)
+    }
+
+    fn add_tag_to_note(&mut self, note_id: i64, tag: &str) -> Result<()> {
+        NoteWriter::add_tag_to_note(self, note_id, tag)
+    }
+
+    fn remove_tag_from_note(&mut self, note_id: i64, tag: &str) -> Result<()> {
+        NoteWriter::remove_tag_from_note(self, note_id, tag)
+    }
+
+    fn reposition_new_cards(&mut self, note_id: i64, pos: i64) -> Result<usize> {
+        NoteWriter::reposition_new_cards(self, note_id, pos)
+    }
+}
+
+/// A collection a cycle can read and write.
+pub trait Backend {
+    /// Every note carrying `marker_tag`, as the collection holds it now.
+    fn managed_notes(&mut self, marker_tag: &str) -> Result<Vec<RawManagedNote>>;
+
+    /// The stored CSS of the notetype `name`, if it exists.
+    fn notetype_css(&mut self, name: &str) -> Result<Option<String>>;
+
+    /// Run `f` as one all-or-nothing batch. When this returns `Ok`, the
+    /// batch is in the collection (for a server, synced to it).
+    fn transact(&mut self, f: &mut dyn FnMut(&mut dyn Writer) -> Result<()>) -> Result<()>;
+
+    /// Store renderer assets where the collection's media lives.
+    fn push_media(&mut self, assets: &[Asset]) -> Result<()>;
+}
+
+/// A collection file edited in place, with its media directory and
+/// `media.db`.
+pub struct DirectBackend {
+    col: Collection,
+    media_dir: PathBuf,
+    media_db_path: PathBuf,
+}
+
+impl DirectBackend {
+    pub fn new(col: Collection, media_dir: PathBuf, media_db_path: PathBuf) -> Self {
+        Self {
+            col,
+            media_dir,
+            media_db_path,
+        }
+    }
+}
+
+impl Backend for DirectBackend {
+    fn managed_notes(&mut self, marker_tag: &str) -> Result<Vec<RawManagedNote>> {
+        self.col.managed_notes(marker_tag)
+    }
+
+    fn notetype_css(&mut self, name: &str) -> Result<Option<String>> {
+        self.col.notetype_css(name)
+    }
+
+    fn transact(&mut self, f: &mut dyn FnMut(&mut dyn Writer) -> Result<()>) -> Result<()> {
+        self.col.transact(|w| f(w))
+    }
+
+    fn push_media(&mut self, assets: &[Asset]) -> Result<()> {
+        media::push_all(assets, &self.media_dir, &self.media_db_path)
+    }
+}
+
+/// A collection behind a sync server. Reading the managed notes refreshes
+/// the local snapshot when another client synced; each batch is written to
+/// that same snapshot and synced right after.
+pub struct ServerBackend {
+    remote: Remote,
+}
+
+impl ServerBackend {
+    pub fn new(remote: Remote) -> Self {
+        Self { remote }
+    }
+}
+
+impl Backend for ServerBackend {
+    fn managed_notes(&mut self, marker_tag: &str) -> Result<Vec<RawManagedNote>> {
+        self.remote.collection()?.managed_notes(marker_tag)
+    }
+
+    fn notetype_css(&mut self, name: &str) -> Result<Option<String>> {
+        self.remote.snapshot()?.notetype_css(name)
+    }
+
+    fn transact(&mut self, f: &mut dyn FnMut(&mut dyn Writer) -> Result<()>) -> Result<()> {
+        // Write to the snapshot the notes were read from; if the server
+        // moved on meanwhile, the push refuses and the next cycle re-reads.
+        self.remote.snapshot()?.transact(|w| f(w))?;
+        self.remote.push().context("sync to server")
+    }
+
+    fn push_media(&mut self, assets: &[Asset]) -> Result<()> {
+        let files: Vec<(&str, &[u8])> = assets
+            .iter()
+            .map(|a| (a.filename.as_str(), a.bytes.as_slice()))
+            .collect();
+        let sent = self.remote.push_media(&files)?;
+        tracing::debug!(sent, "media synced");
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+//! Support for `marki diff`: input fingerprints and unified text diffs.
+//!
+//! The content hash says *whether* a note's rendered fields changed; the
+//! [`Inputs`] recorded next to it say *why*. Each push that writes a note
+//! stores short fingerprints of its source file, its model script and the
+//! renderer set, so the next diff can name which of them moved. CSS lives
+//! on the notetype rather than the note and is compared against the
+//! collection directly.
+
+/// Fingerprints of what a note's stored fields were rendered from, stored
+/// as `marki::inputs:<source>.<script>.<renderers>` (8 hex chars each).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inputs {
+    source: String,
+    script: String,
+    renderers: String,
+}
+
+impl Inputs {
+    /// Fingerprint the card's source text, its model script fingerprint
+    /// (empty for basic notes) and the renderer registry fingerprint.
+    pub fn new(source: &str, script: &str, renderers: &str) -> Self {
+        let short = |s: &str| blake3::hash(s.as_bytes()).to_hex()[..8].to_string();
+        Self {
+            source: short(source),
+            script: short(script),
+            renderers: short(renderers),
+        }
+    }
+
+    /// The tag payload, without the `marki::inputs:` prefix.
+    pub fn encode(&self) -> String {
+        format!("{}.{}.{}", self.source, self.script, self.renderers)
+    }
+
+    /// Parse a tag payload written by [`encode`](Self::encode).
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut parts = s.split('.');
+        let inputs = Self {
+            source: parts.next()?.to_string(),
+            script: parts.next()?.to_string(),
+            renderers: parts.next()?.to_string(),
+        };
+        parts.next().is_none().then_some(inputs)
+    }
+
+    /// Names of the inputs that differ from `recorded`, in a fixed order.
+    pub fn changed_since(&self, recorded: &Inputs) -> Vec<&'static str> {
+        let mut out = Vec::new();
+        if self.source != recorded.source {
+            out.push("source");
+        }
+        if self.script != recorded.script {
+            out.push("model script");
+        }
+        if self.renderers != recorded.renderers {
+            out.push("renderer version");
+        }
+        out
+    }
+}
+
+/// Lines of context around each hunk of [`unified`].
+const CONTEXT: usize = 3;
+
+/// Above this many line pairs the LCS table is skipped and the whole text
+/// is shown as replaced; fields are rarely anywhere near it.
+const MAX_TABLE: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Keep,
+    Del,
+    Add,
+}
+
+/// A unified diff of `old` against `new`, line by line: `@@` hunk headers
+/// followed by ` `/`-`/`+` lines. Empty when the texts are equal.
+pub fn unified(old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = line_ops(&a, &b);
+
+    // Positions (old line, new line) before each op, for hunk headers.
+    let mut pos = Vec::with_capacity(ops.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for op in &ops {
+        pos.push((i, j));
+        match op {
+            Op::Keep => {
+                i += 1;
+                j += 1;
+            }
+            Op::Del => i += 1,
+            Op::Add => j += 1,
+        }
+    }
+    pos.push((i, j));
+
+    let mut out = String::new();
+    let mut k = 0;
+    while k < ops.len() {
+        if ops[k] == Op::Keep {
+            k += 1;
+            continue;
+        }
+        // Grow the hunk while changes are within 2*CONTEXT of each other.
+        let start = k.saturating_sub(CONTEXT);
+        let mut end = k;
+        let mut last_change = k;
+        while end < ops.len() {
+            if ops[end] != Op::Keep {
+                last_change = end;
+            } else if end - last_change > 2 * CONTEXT {
+                break;
+            }
+            end += 1;
+        }
+        let end = (last_change + CONTEXT + 1).min(ops.len());
+
+        let (oi, nj) = pos[start];
+        let (oe, ne) = pos[end];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(oi, oe - oi),
+            range(nj, ne - nj)
+        ));
+        for (op, &(i, j)) in ops[start..end].iter().zip(&pos[start..end]) {
+            let (mark, line) = match op {
+                Op::Keep => (' ', a[i]),
+                Op::Del => ('-', a[i]),
+                Op::Add => ('+', b[j]),
+            };
+            out.push(mark);
+            out.push_str(line);
+            out.push('\n');
+        }
+        k = end;
+    }
+    out
+}
+
+/// `start,len` in unified-diff form: 1-based, with an empty range naming
+/// the line before it.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+/// Edit script turning `a` into `b`, from a longest-common-subsequence
+/// table. Deletions come before additions within a change.
+fn line_ops(a: &[&str], b: &[&str]) -> Vec<Op> {
+    if a.len().saturating_mul(b.len()) > MAX_TABLE {
+        let mut ops = vec![Op::Del; a.len()];
+        ops.extend(std::iter::repeat_n(Op::Add, b.len()));
+        return ops;
+    }
+    // lcs[i][j] = LCS length of a[i..] and b[j..].
+    let w = b.len() + 1;
+    let mut lcs = vec![0u32; (a.len() + 1) * w];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i * w + j] = if a[i] == b[j] {
+                lcs[(i + 1) * w + j + 1] + 1
+            } else {
+                lcs[(i + 1) * w + j].max(lcs[i * w + j + 1])
+            };
+        }
+    }
+    let mut ops = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            ops.push(Op::Keep);
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * w + j] >= lcs[i * w + j + 1] {
+            ops.push(Op::Del);
+            i += 1;
+        } else {
+            ops.push(Op::Add);
+            j += 1;
+        }
+    }
+    ops.extend(std::iter::repeat_n(Op::Del, a.len() - i));
+    ops.extend(std::iter::repeat_n(Op::Add, b.len() - j));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_round_trip_and_name_what_changed() {
+        let old = Inputs::new("# Q", "", "r1");
+        assert_eq!(Inputs::decode(&old.encode()), Some(old.clone()));
+        assert_eq!(Inputs::decode("a.b"), None);
+
+        let new = Inputs::new("# Q edited", "", "r2");
+        assert_eq!(new.changed_since(&old), vec!["source", "renderer version"]);
+        assert!(old.changed_since(&old).is_empty());
+    }
+
+    #[test]
+    fn unified_diff_marks_changed_lines_with_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+        let new = "a\nb\nc\nd\nE\nf\ng\nh\ni\n";
+        assert_eq!(unified(old, new), "@@ -2,7 +2,7 @@\n b\n c\n d\n-e\n+E\n f\n g\n h\n");
+        assert_eq!(unified(old, old), "");
+    }
+
+    #[test]
+    fn unified_diff_of_added_and_removed_text() {
+        assert_eq!(unified("", "<p>A</p>"), "@@ -0,0 +1 @@\n+<p>A</p>\n");
+        assert_eq!(unified("x\ny", "y"), "@@ -1,2 +1 @@\n-x\n y\n");
+    }
+
+    #[test]
+    fn distant_changes_get_separate_hunks() {
+        let old: String = (0..20).map(|i| format!("{i}\n")).collect();
+        let new: String = (0..20)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                17 => "seventeen\n".to_string(),
+                _ => format!("{i}\n"),
+            })
+            .collect();
+        let diff = unified(&old, &new);
+        assert_eq!(diff.matches("@@ -").count(), 2, "{diff}");
+    }
+}
@@ -1,7 +1,8 @@
 //! Reconciliation engine.
 //!
 //! Every note -- basic or `#model(name)` -- is materialized as a `marki:<name>`
-//! notetype in the collection and written directly through `marki-anki`.
+//! notetype in the collection and written through a [`Backend`]: the
+//! collection file itself, or a sync server.
 //!
 //! Identity: `#id(hex)` becomes the note's `guid`. Hash: blake3 over the
 //! rendered field values, stored as a `marki::hash:<hex>` tag.
@@ -14,22 +15,35 @@
 //!   * orphans are soft-deleted by default (suspend + `marki::orphan` tag),
 //!     hard-deleted only with `--prune`
 //!   * nothing is pruned at all during a cycle that had render errors
+//!   * a note with `#requires(id)` is kept suspended (and tagged
+//!     `marki::gated`) until every card of note `id` reaches
+//!     `prerequisite_interval`, then unsuspended on the next cycle. The
+//!     prerequisite may belong to another target, but must be published
+//!     into the same collection
+//!   * new notes are added in [`OrderKey`] order, so their new-card
+//!     positions follow the repo layout
 
 use anyhow::{Context, Result};
 use marki_anki::notetype::ModelSpec;
-use marki_anki::{Collection, NoteWriter, RawManagedNote};
+use marki_anki::RawManagedNote;
 use marki_render::Asset;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
 
-use crate::anki::model::{MARKER_TAG, ORPHAN_TAG, full_tag_set, hash_from_tags};
+use crate::anki::model::{
+    GATED_TAG, MARKER_TAG, ORPHAN_TAG, full_tag_set, hash_from_tags, inputs_from_tags,
+};
 use crate::note::Note;
 use crate::render::Registry;
 use crate::scan::{ScannedNote, deck_for};
 use crate::scripting::context::RenderContext;
 use crate::scripting::engine::ScriptEngine;
-use crate::sync::media;
+use crate::sync::diff::Inputs;
+use crate::sync::backend::{Backend, Writer};
+use crate::sync::order::OrderKey;
+use crate::sync::target::{Target, owner};
 
 /// The single card name a basic note's `marki:basic` notetype uses. Its two
 /// fields are `CardFront`/`CardBack`.
@@ -51,7 +65,7 @@ img {
 }
 "#;
 
-#[derive(Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Outcome {
     pub added: usize,
     pub updated: usize,
@@ -61,8 +75,16 @@ pub struct Outcome {
     pub quarantined: usize,
     /// Orphans left untouched because the cycle had errors (safety valve).
     pub skipped_prune: usize,
+    /// Notes suspended until a `#requires(...)` prerequisite is learned.
+    pub gated: usize,
+    /// Gated notes unsuspended because their prerequisites are learned.
+    pub released: usize,
     pub unformatted: usize,
     pub errors: Vec<String>,
+    /// Problems worth reporting that do not make the cycle unsafe, so
+    /// they leave orphan pruning on.
+    #[serde(default)]
+    pub warnings: Vec<String>,
 }
 
 /// A fully resolved local note ready for diffing against the collection.
@@ -76,6 +98,14 @@ struct Local {
     deck: String,
     assets: Vec<Asset>,
     hash: String,
+    /// What `fields` were rendered from, recorded alongside `hash`.
+    inputs: Inputs,
+    /// Where the note sorts among new cards.
+    order: OrderKey,
+    /// Marki ids this note's `#requires(...)` tags name.
+    requires: Vec<String>,
+    /// Whether the cards stay suspended this cycle; set by [`gate`].
+    gated: bool,
 }
 
 impl Local {
@@ -94,25 +124,246 @@ enum Plan<'a> {
     Update(&'a RawManagedNote, &'a Local, bool),
     /// Only the deck changed.
     Move(&'a RawManagedNote, &'a Local),
+    /// Suspend (`true`) or release (`false`) a note for its prerequisites.
+    Gate(&'a RawManagedNote, &'a Local, bool),
+}
+
+impl Plan<'_> {
+    fn local(&self) -> &Local {
+        match self {
+            Plan::Add(l)
+            | Plan::ModelChange(_, l)
+            | Plan::Update(_, l, _)
+            | Plan::Move(_, l)
+            | Plan::Gate(_, l, _) => l,
+        }
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn reconcile(
-    col: &mut Collection,
+    backend: &mut dyn Backend,
     root: &Path,
     notes: &[ScannedNote],
+    target: &Target,
     script_engine: &mut ScriptEngine,
     registry: &Arc<Registry>,
     cache_dir: &Path,
     models_dir: &Path,
-    media_dir: &Path,
-    media_db_path: &Path,
+    prerequisite_interval: u32,
     dry_run: bool,
     prune: bool,
 ) -> Result<Outcome> {
     let mut outcome = Outcome::default();
 
-    // ---- Phase 1: Build the local index.
+    // ---- Phase 1: Build the local index of the target's cards.
+    let scoped = in_target(notes, root, target);
+    let (mut local, seen_source_ids) = build_local_index(
+        root, &scoped, script_engine, registry, cache_dir, models_dir, &mut outcome,
+    );
+    for l in local.values_mut() {
+        l.deck = target.deck(&l.deck);
+    }
+
+    // ---- Phase 2: Pull remote state. Only notes this target wrote are
+    // its to update or orphan; other targets' notes are left alone.
+    let (remote, foreign) = owned_notes(backend, target)?;
+    let disk_ids: HashSet<&str> = notes.iter().filter_map(|sn| sn.note.id.as_deref()).collect();
+    gate(
+        &mut local,
+        &Prerequisites {
+            disk: &disk_ids,
+            in_target: &seen_source_ids,
+            owned: &remote,
+            foreign: &foreign,
+        },
+        prerequisite_interval,
+        &mut outcome,
+    );
+
+    // ---- Phase 3: Compute the plan (pure) and the orphan set.
+    let mut plan: Vec<Plan> = Vec::new();
+    for (guid, l) in &local {
+        if let Some(other) = foreign.get(guid) {
+            // A guid is unique per collection, so the note cannot be added
+            // twice; the targets' card sets overlap.
+            outcome.errors.push(format!(
+                "{}: note already published into this collection by {}",
+                l.path.display(),
+                describe_target(owner(&other.tags)),
+            ));
+            continue;
+        }
+        match remote.get(guid) {
+            Some(r) => {
+                let model_changed = l.model_name() != r.model_name;
+                let remote_hash = hash_from_tags(&r.tags).unwrap_or_default();
+                let content_changed = l.hash != remote_hash;
+                let deck_changed = l.deck != r.deck;
+
+                if model_changed {
+                    plan.push(Plan::ModelChange(r, l));
+                    outcome.updated += 1;
+                } else if content_changed {
+                    plan.push(Plan::Update(r, l, deck_changed));
+                    outcome.updated += 1;
+                } else if deck_changed {
+                    plan.push(Plan::Move(r, l));
+                    outcome.moved += 1;
+                }
+                // A re-added note is gated as it is added.
+                if !model_changed && l.gated != r.tags.iter().any(|t| t == GATED_TAG) {
+                    plan.push(Plan::Gate(r, l, l.gated));
+                    if l.gated {
+                        outcome.gated += 1;
+                    } else {
+                        outcome.released += 1;
+                    }
+                }
+            }
+            None => {
+                plan.push(Plan::Add(l));
+                outcome.added += 1;
+                outcome.gated += usize::from(l.gated);
+            }
+        }
+    }
+
+    // Adds draw the next new-card position, so apply in layout order.
+    plan.sort_by(|a, b| a.local().order.cmp(&b.local().order));
+
+    // An orphan is a managed note whose id is absent from disk. We filter by
+    // `seen_source_ids`, NOT merely "unmatched": a note whose source file
+    // exists but failed to render this cycle keeps its id in
+    // `seen_source_ids` and must be preserved.
+    let orphans: Vec<&RawManagedNote> = remote
+        .values()
+        .filter(|r| is_orphan(&r.guid, &seen_source_ids))
+        .collect();
+
+    // ---- Phase 4: Report or apply.
+    if dry_run {
+        account_orphans(&orphans, prune, &mut outcome);
+        return Ok(outcome);
+    }
+
+    // Push media before touching the collection so a media failure trips the
+    // orphan safety valve below (never prune during a cycle with errors).
+    let assets = collect_assets(&local);
+    if let Err(e) = backend.push_media(&assets) {
+        outcome.errors.push(format!("media push: {e:#}"));
+    }
+
+    apply(backend, &plan, &orphans, target, prune, &mut outcome)?;
+    Ok(outcome)
+}
+
+/// The cards `target` publishes.
+fn in_target<'a>(notes: &'a [ScannedNote], root: &Path, target: &Target) -> Vec<&'a ScannedNote> {
+    notes
+        .iter()
+        .filter(|sn| target.contains(sn.path.strip_prefix(root).unwrap_or(&sn.path)))
+        .collect()
+}
+
+/// Managed notes owned by `target`, keyed by guid, plus every other
+/// managed note in the collection.
+#[allow(clippy::type_complexity)]
+fn owned_notes(
+    backend: &mut dyn Backend,
+    target: &Target,
+) -> Result<(HashMap<String, RawManagedNote>, HashMap<String, RawManagedNote>)> {
+    let mut owned = HashMap::new();
+    let mut foreign = HashMap::new();
+    for n in backend.managed_notes(MARKER_TAG).context("read managed notes")? {
+        if target.owns(&n.tags) {
+            owned.insert(n.guid.clone(), n);
+        } else {
+            foreign.insert(n.guid.clone(), n);
+        }
+    }
+    Ok((owned, foreign))
+}
+
+fn describe_target(name: Option<&str>) -> String {
+    match name {
+        Some(n) => format!("target `{n}`"),
+        None => "the top-level `collection`".to_string(),
+    }
+}
+
+/// The tags to store on a note written for `target`.
+fn note_tags(l: &Local, target: &Target) -> Vec<String> {
+    let mut tags = full_tag_set(&l.anki_tags, &l.hash, &l.inputs.encode());
+    tags.extend(target.tag());
+    if l.gated {
+        tags.push(GATED_TAG.to_string());
+    }
+    tags
+}
+
+/// Where [`gate`] looks prerequisites up.
+struct Prerequisites<'a> {
+    /// Every marki id in the repo, whatever its target.
+    disk: &'a HashSet<&'a str>,
+    /// The ids this target publishes.
+    in_target: &'a HashSet<String>,
+    /// The collection's notes, this target's and everyone else's.
+    owned: &'a HashMap<String, RawManagedNote>,
+    foreign: &'a HashMap<String, RawManagedNote>,
+}
+
+/// Decide which notes stay suspended for their prerequisites. A
+/// prerequisite is learned once the collection holds it and every one of
+/// its cards has an interval of at least `min_interval` days; one added
+/// this cycle is not. It may come from any target publishing into this
+/// collection. A `#requires` naming an id no card in the repo has, or a
+/// card that only another collection gets, is a warning, and the note
+/// stays gated until it is fixed.
+fn gate(
+    local: &mut HashMap<String, Local>,
+    prereqs: &Prerequisites<'_>,
+    min_interval: u32,
+    outcome: &mut Outcome,
+) {
+    for l in local.values_mut() {
+        l.gated = false;
+        for id in &l.requires {
+            if !prereqs.disk.contains(id.as_str()) {
+                outcome.warnings.push(format!(
+                    "{}: #requires({id}): no card has this id",
+                    l.path.display()
+                ));
+                l.gated = true;
+                continue;
+            }
+            let published = prereqs.owned.get(id).or_else(|| prereqs.foreign.get(id));
+            if published.is_none() && !prereqs.in_target.contains(id) {
+                outcome.warnings.push(format!(
+                    "{}: #requires({id}): the card is outside this target and not in its \
+                     collection; prerequisites must be published into the same collection",
+                    l.path.display()
+                ));
+            }
+            let learned = published
+                .is_some_and(|r| r.min_interval.is_none_or(|ivl| ivl >= i64::from(min_interval)));
+            l.gated |= !learned;
+        }
+    }
+}
+
+/// Render every formatted note into a [`Local`], keyed by guid. Also returns
+/// every marki id present on disk, whether or not it rendered (see
+/// `is_orphan`). Render errors and unformatted files land in `outcome`.
+fn build_local_index(
+    root: &Path,
+    notes: &[&ScannedNote],
+    script_engine: &mut ScriptEngine,
+    registry: &Arc<Registry>,
+    cache_dir: &Path,
+    models_dir: &Path,
+    outcome: &mut Outcome,
+) -> (HashMap<String, Local>, HashSet<String>) {
     let mut local: HashMap<String, Local> = HashMap::new();
 
     // Every marki id present on disk this cycle, recorded *before* and
@@ -121,6 +372,7 @@ pub fn reconcile(
     // render failure for a deletion. This is the core data-loss guard.
     let mut seen_source_ids: HashSet<String> = HashSet::new();
 
+    script_engine.begin_cycle();
     for sn in notes {
         let note = &sn.note;
 
@@ -147,10 +399,10 @@ pub fn reconcile(
         }
 
         let result = if note.model == "basic" {
-            build_stock_local(sn, &guid, root, registry, cache_dir, models_dir, &mut outcome)
+            build_stock_local(sn, &guid, root, registry, cache_dir, models_dir, outcome)
         } else {
             build_custom_local(
-                sn, &guid, root, script_engine, registry, cache_dir, models_dir, &mut outcome,
+                sn, &guid, root, script_engine, registry, cache_dir, models_dir, outcome,
             )
         };
 
@@ -169,70 +421,12 @@ pub fn reconcile(
         }
     }
 
-    // ---- Phase 2: Pull remote state.
-    let remote_vec = col.managed_notes(MARKER_TAG).context("read managed notes")?;
-    let remote: HashMap<String, RawManagedNote> = remote_vec
-        .into_iter()
-        .map(|n| (n.guid.clone(), n))
-        .collect();
-
-    // ---- Phase 3: Compute the plan (pure) and the orphan set.
-    let mut plan: Vec<Plan> = Vec::new();
-    for (guid, l) in &local {
-        match remote.get(guid) {
-            Some(r) => {
-                let model_changed = l.model_name() != r.model_name;
-                let remote_hash = hash_from_tags(&r.tags).unwrap_or_default();
-                let content_changed = l.hash != remote_hash;
-                let deck_changed = l.deck != r.deck;
-
-                if model_changed {
-                    plan.push(Plan::ModelChange(r, l));
-                    outcome.updated += 1;
-                } else if content_changed {
-                    plan.push(Plan::Update(r, l, deck_changed));
-                    outcome.updated += 1;
-                } else if deck_changed {
-                    plan.push(Plan::Move(r, l));
-                    outcome.moved += 1;
-                }
-            }
-            None => {
-                plan.push(Plan::Add(l));
-                outcome.added += 1;
-            }
-        }
-    }
-
-    // An orphan is a managed note whose id is absent from disk. We filter by
-    // `seen_source_ids`, NOT merely "unmatched": a note whose source file
-    // exists but failed to render this cycle keeps its id in
-    // `seen_source_ids` and must be preserved.
-    let orphans: Vec<&RawManagedNote> = remote
-        .values()
-        .filter(|r| is_orphan(&r.guid, &seen_source_ids))
-        .collect();
-
-    // ---- Phase 4: Report or apply.
-    if dry_run {
-        account_orphans(&orphans, prune, &mut outcome);
-        return Ok(outcome);
-    }
-
-    // Push media before touching the collection so a media failure trips the
-    // orphan safety valve below (never prune during a cycle with errors).
-    let assets = collect_assets(&local);
-    if let Err(e) = media::push_all(&assets, media_dir, media_db_path) {
-        outcome.errors.push(format!("media push: {e:#}"));
-    }
-
-    apply(col, &plan, &orphans, prune, &mut outcome)?;
-    Ok(outcome)
+    (local, seen_source_ids)
 }
 
 /// Ensure a notetype exists, caching the resolved id per model per cycle.
 fn ensure_model_cached(
-    w: &mut NoteWriter,
+    w: &mut dyn Writer,
     cache: &mut HashMap<String, i64>,
     spec: &ModelSpec,
 ) -> Result<i64> {
@@ -247,16 +441,18 @@ fn ensure_model_cached(
 
 /// Apply the plan and orphan handling inside a single exclusive transaction.
 fn apply(
-    col: &mut Collection,
+    backend: &mut dyn Backend,
     plan: &[Plan],
     orphans: &[&RawManagedNote],
+    target: &Target,
     prune: bool,
     outcome: &mut Outcome,
 ) -> Result<()> {
     // Read outside the closure -- the safety valve depends on render errors.
     let had_errors = !outcome.errors.is_empty();
 
-    let (deleted, quarantined, skipped_prune) = col.transact(|w| {
+    let (mut deleted, mut quarantined, mut skipped_prune) = (0, 0, 0);
+    backend.transact(&mut |w| {
         let mut ensured: HashMap<String, i64> = HashMap::new();
 
         for p in plan {
@@ -264,16 +460,22 @@ fn apply(
                 Plan::Add(l) => {
                     let mid = ensure_model_cached(w, &mut ensured, &l.spec)?;
                     let did = w.deck_id_for(&l.deck)?;
-                    let tags = full_tag_set(&l.anki_tags, &l.hash);
-                    w.add_note(mid, &l.guid, l.fields.clone(), 0, &tags, did)?;
+                    let tags = note_tags(l, target);
+                    let nid = w.add_note(mid, &l.guid, l.fields.clone(), 0, &tags, did)?;
+                    if l.gated {
+                        w.gate_note_cards(nid)?;
+                    }
                     tracing::debug!(path = %l.path.display(), id = %l.guid, "add");
                 }
                 Plan::ModelChange(r, l) => {
                     w.remove_note(r.note_id)?;
                     let mid = ensure_model_cached(w, &mut ensured, &l.spec)?;
                     let did = w.deck_id_for(&l.deck)?;
-                    let tags = full_tag_set(&l.anki_tags, &l.hash);
-                    w.add_note(mid, &l.guid, l.fields.clone(), 0, &tags, did)?;
+                    let tags = note_tags(l, target);
+                    let nid = w.add_note(mid, &l.guid, l.fields.clone(), 0, &tags, did)?;
+                    if l.gated {
+                        w.gate_note_cards(nid)?;
+                    }
                     tracing::info!(
                         path = %l.path.display(),
                         from = %r.model_name,
@@ -285,7 +487,7 @@ fn apply(
                     // Ensure the model in case the script appended a card
                     // (new fields/templates) since the note was last written.
                     ensure_model_cached(w, &mut ensured, &l.spec)?;
-                    let tags = full_tag_set(&l.anki_tags, &l.hash);
+                    let tags = note_tags(l, target);
                     w.update_note(r.note_id, l.fields.clone(), 0, &tags)?;
                     if *deck_changed {
                         let did = w.deck_id_for(&l.deck)?;
@@ -298,12 +500,22 @@ fn apply(
                     w.set_note_deck(r.note_id, did)?;
                     tracing::debug!(path = %l.path.display(), to = %l.deck, "move");
                 }
+                Plan::Gate(r, l, true) => {
+                    w.gate_note_cards(r.note_id)?;
+                    w.add_tag_to_note(r.note_id, GATED_TAG)?;
+                    tracing::debug!(path = %l.path.display(), "gated");
+                }
+                Plan::Gate(r, l, false) => {
+                    w.release_note_cards(r.note_id)?;
+                    w.remove_tag_from_note(r.note_id, GATED_TAG)?;
+                    tracing::debug!(path = %l.path.display(), "released");
+                }
             }
         }
 
         // Orphans: notes with no matching source file this cycle.
         if orphans.is_empty() {
-            return Ok((0usize, 0usize, 0usize));
+            return Ok(());
         }
         if had_errors {
             // Safety valve: a cycle that hit errors may have failed to render
@@ -314,22 +526,24 @@ fn apply(
                  re-run after fixing them",
                 orphans.len()
             );
-            return Ok((0, 0, orphans.len()));
+            skipped_prune = orphans.len();
+            return Ok(());
         }
         if prune {
             for r in orphans {
                 w.remove_note(r.note_id)?;
             }
             tracing::debug!(count = orphans.len(), "deleted orphaned notes");
-            Ok((orphans.len(), 0, 0))
+            deleted = orphans.len();
         } else {
             for r in orphans {
                 w.suspend_note_cards(r.note_id)?;
                 w.add_tag_to_note(r.note_id, ORPHAN_TAG)?;
             }
             tracing::debug!(count = orphans.len(), "quarantined orphaned notes");
-            Ok((0, orphans.len(), 0))
+            quarantined = orphans.len();
         }
+        Ok(())
     })?;
 
     outcome.deleted = deleted;
@@ -429,6 +643,17 @@ pub fn render_stock(
     StockRenderResult { fields, assets, errors }
 }
 
+/// The `marki:basic` notetype: one `Card` template, styled by
+/// `<models_dir>/basic.css`. Shared with `marki import`, which converts
+/// adopted notes onto it.
+pub(crate) fn basic_spec(models_dir: &Path) -> ModelSpec {
+    ModelSpec {
+        name: "basic".into(),
+        css: load_model_css(models_dir, "basic"),
+        card_names: vec![BASIC_CARD_NAME.to_string()],
+    }
+}
+
 /// Build a [`Local`] for a basic note. Its `marki:basic` notetype has a single
 /// `Card` template, so the front/back HTML map straight to `CardFront`/`CardBack`.
 #[allow(clippy::too_many_arguments)]
@@ -448,14 +673,10 @@ fn build_stock_local(
     }
 
     let fields: Vec<String> = result.fields.into_iter().map(|(_, v)| v).collect();
-    let hash = compute_hash(&fields);
+    let hash = compute_hash(&fields, registry.highlighter().fingerprint());
     let deck = deck_for(root, &sn.path);
 
-    let spec = ModelSpec {
-        name: "basic".into(),
-        css: load_model_css(models_dir, "basic"),
-        card_names: vec![BASIC_CARD_NAME.to_string()],
-    };
+    let spec = basic_spec(models_dir);
 
     Some(Local {
         path: sn.path.clone(),
@@ -466,6 +687,10 @@ fn build_stock_local(
         deck,
         assets: result.assets,
         hash,
+        inputs: Inputs::new(&sn.source, "", &registry.fingerprint()),
+        order: OrderKey::new(root, &sn.path, sn.note.order),
+        requires: sn.note.requires.clone(),
+        gated: false,
     })
 }
 
@@ -505,7 +730,8 @@ fn build_custom_local(
         card_names: model.card_names.clone(),
     };
 
-    let ctx = RenderContext::new(Arc::clone(registry), sn.path.clone(), cache_dir.to_path_buf());
+    let ctx = RenderContext::new(Arc::clone(registry), sn.path.clone(), cache_dir.to_path_buf())
+        .with_data(script_engine.data_store());
     let model_output = match script_engine.execute(&model, note.clone(), ctx.clone()) {
         Ok(o) => o,
         Err(e) => {
@@ -522,7 +748,7 @@ fn build_custom_local(
         .iter()
         .map(|name| model_output.get(name).cloned().unwrap_or_default())
         .collect();
-    let hash = compute_hash(&fields);
+    let hash = compute_hash(&fields, registry.highlighter().fingerprint());
     let deck = deck_for(root, &sn.path);
 
     Some(Local {
@@ -534,9 +760,193 @@ fn build_custom_local(
         deck,
         assets,
         hash,
+        inputs: Inputs::new(&sn.source, &model.fingerprint, &registry.fingerprint()),
+        order: OrderKey::new(root, &sn.path, note.order),
+        requires: note.requires.clone(),
+        gated: false,
     })
 }
 
+/// One note whose stored fields the next push would rewrite, as reported
+/// by [`diff`].
+pub struct NoteDiff {
+    pub path: std::path::PathBuf,
+    /// Notetype the note renders to, e.g. `marki:basic`.
+    pub model: String,
+    /// Notetype in the collection when it differs from `model` (the push
+    /// removes and re-adds the note), else `None`.
+    pub old_model: Option<String>,
+    /// False for a note the collection does not have yet.
+    pub exists: bool,
+    /// Inputs that differ from those the stored fields were rendered
+    /// from, plus `css` when the notetype's CSS changes too. `None` when
+    /// the collection has no record (a new note, or one last written
+    /// before inputs were recorded).
+    pub changed: Option<Vec<&'static str>>,
+    /// `(field name, stored HTML, rendered HTML)` for every field whose
+    /// value changes. Fields beyond the stored note's count are compared
+    /// against empty.
+    pub fields: Vec<(String, String, String)>,
+}
+
+/// Everything [`diff`] found.
+#[derive(Default)]
+pub struct DiffReport {
+    pub notes: Vec<NoteDiff>,
+    /// `(notetype, stored CSS, new CSS)` for notetypes whose CSS changes.
+    pub css: Vec<(String, String, String)>,
+    /// Render errors; the affected notes are missing from `notes`.
+    pub errors: Vec<String>,
+    /// Files without an `#id(...)` yet, which a push would skip.
+    pub unformatted: usize,
+}
+
+/// Render `notes` and compare them with the collection without writing
+/// anything: every note whose fields a push would change, with the old and
+/// new values, and every notetype whose CSS would change. Notes whose only
+/// change is their deck are not reported.
+#[allow(clippy::too_many_arguments)]
+pub fn diff(
+    backend: &mut dyn Backend,
+    root: &Path,
+    notes: &[ScannedNote],
+    target: &Target,
+    script_engine: &mut ScriptEngine,
+    registry: &Arc<Registry>,
+    cache_dir: &Path,
+    models_dir: &Path,
+) -> Result<DiffReport> {
+    let mut outcome = Outcome::default();
+    let notes = in_target(notes, root, target);
+    let (local, _) = build_local_index(
+        root, &notes, script_engine, registry, cache_dir, models_dir, &mut outcome,
+    );
+    let (remote, _) = owned_notes(backend, target)?;
+
+    let mut report = DiffReport {
+        errors: outcome.errors,
+        unformatted: outcome.unformatted,
+        ..DiffReport::default()
+    };
+
+    // Notetype CSS, once per model.
+    let mut css_changed: HashSet<String> = HashSet::new();
+    let mut css_seen: HashSet<String> = HashSet::new();
+    for l in local.values() {
+        let name = l.model_name();
+        if !css_seen.insert(name.clone()) {
+            continue;
+        }
+        if let Some(old) = backend.notetype_css(&name)?
+            && old != l.spec.css
+        {
+            css_changed.insert(name.clone());
+            report.css.push((name, old, l.spec.css.clone()));
+        }
+    }
+    report.css.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for l in local.values() {
+        let field_names = l.spec.field_names();
+        let model = l.model_name();
+        let Some(r) = remote.get(&l.guid) else {
+            report.notes.push(NoteDiff {
+                path: l.path.clone(),
+                model,
+                old_model: None,
+                exists: false,
+                changed: None,
+                fields: field_names
+                    .into_iter()
+                    .zip(&l.fields)
+                    .filter(|(_, new)| !new.is_empty())
+                    .map(|(name, new)| (name, String::new(), new.clone()))
+                    .collect(),
+            });
+            continue;
+        };
+        let model_changed = model != r.model_name;
+        if !model_changed && hash_from_tags(&r.tags).as_deref() == Some(l.hash.as_str()) {
+            continue;
+        }
+        let fields: Vec<(String, String, String)> = field_names
+            .into_iter()
+            .zip(&l.fields)
+            .enumerate()
+            .filter_map(|(i, (name, new))| {
+                let old = r.fields.get(i).cloned().unwrap_or_default();
+                (old != *new).then(|| (name, old, new.clone()))
+            })
+            .collect();
+        if fields.is_empty() && !model_changed {
+            continue;
+        }
+        let changed = inputs_from_tags(&r.tags)
+            .and_then(|t| Inputs::decode(&t))
+            .map(|recorded| {
+                let mut names = l.inputs.changed_since(&recorded);
+                if css_changed.contains(&model) {
+                    names.push("css");
+                }
+                names
+            });
+        report.notes.push(NoteDiff {
+            path: l.path.clone(),
+            old_model: model_changed.then(|| r.model_name.clone()),
+            model,
+            exists: true,
+            changed,
+            fields,
+        });
+    }
+    report.notes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(report)
+}
+
+/// Give the target's unstudied notes consecutive new-card positions in
+/// [`OrderKey`] order, starting from the lowest position they hold now.
+/// Studied cards and notes not on disk are left alone. Returns how many
+/// notes move; with `dry_run` nothing is written.
+pub fn reorder(
+    backend: &mut dyn Backend,
+    root: &Path,
+    notes: &[ScannedNote],
+    target: &Target,
+    dry_run: bool,
+) -> Result<usize> {
+    let (remote, _) = owned_notes(backend, target)?;
+    let mut seen = HashSet::new();
+    let mut queue: Vec<(OrderKey, &RawManagedNote)> = in_target(notes, root, target)
+        .into_iter()
+        .filter_map(|sn| {
+            let r = remote.get(sn.note.id.as_ref()?)?;
+            r.new_position?;
+            // A duplicated id is reported by push; place the note once.
+            seen.insert(r.note_id)
+                .then(|| (OrderKey::new(root, &sn.path, sn.note.order), r))
+        })
+        .collect();
+    queue.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let Some(start) = queue.iter().filter_map(|(_, r)| r.new_position).min() else {
+        return Ok(0);
+    };
+    let moves: Vec<(i64, i64)> = (start..)
+        .zip(&queue)
+        .filter(|(pos, (_, r))| r.new_position != Some(*pos))
+        .map(|(pos, (_, r))| (r.note_id, pos))
+        .collect();
+    if !dry_run && !moves.is_empty() {
+        backend.transact(&mut |w| {
+            for &(note_id, pos) in &moves {
+                w.reposition_new_cards(note_id, pos)?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(moves.len())
+}
+
 /// Decide whether a managed note is a true orphan: its id is absent from disk
 /// this cycle. A note whose source `.md` still exists but merely *failed to
 /// render* keeps its id in `seen_source_ids` and is NOT an orphan -- the
@@ -545,13 +955,19 @@ fn is_orphan(guid: &str, seen_source_ids: &HashSet<String>) -> bool {
     !seen_source_ids.contains(guid)
 }
 
-/// Hash over all field values, in order.
-fn compute_hash(fields: &[String]) -> String {
+/// Hash over all field values, in order, plus the highlighter's
+/// fingerprint when highlighting is configured -- so switching theme or
+/// grammars re-pushes every note.
+fn compute_hash(fields: &[String], highlight: Option<&str>) -> String {
     let mut hasher = blake3::Hasher::new();
     for value in fields {
         hasher.update(value.as_bytes());
         hasher.update(b"\x00");
     }
+    if let Some(fp) = highlight {
+        hasher.update(b"highlight\x00");
+        hasher.update(fp.as_bytes());
+    }
     let hash = hasher.finalize();
     hash.to_hex()[..16].to_string()
 }
@@ -563,22 +979,29 @@ mod tests {
     #[test]
     fn hash_changes_on_field_value() {
         assert_ne!(
-            compute_hash(&["Hello".into()]),
-            compute_hash(&["World".into()])
+            compute_hash(&["Hello".into()], None),
+            compute_hash(&["World".into()], None)
         );
     }
 
+    #[test]
+    fn hash_changes_with_highlighter_fingerprint() {
+        let f = vec!["<p>Q</p>".to_string()];
+        assert_ne!(compute_hash(&f, None), compute_hash(&f, Some("abc")));
+        assert_ne!(compute_hash(&f, Some("abc")), compute_hash(&f, Some("def")));
+    }
+
     #[test]
     fn hash_stable_for_same_input() {
         let f = vec!["<p>Q</p>".to_string(), "<p>A</p>".to_string()];
-        assert_eq!(compute_hash(&f), compute_hash(&f));
+        assert_eq!(compute_hash(&f, None), compute_hash(&f, None));
     }
 
     #[test]
     fn hash_sensitive_to_field_order() {
         assert_ne!(
-            compute_hash(&["X".into(), "Y".into()]),
-            compute_hash(&["Y".into(), "X".into()])
+            compute_hash(&["X".into(), "Y".into()], None),
+            compute_hash(&["Y".into(), "X".into()], None)
         );
     }
 
@@ -601,6 +1024,117 @@ mod tests {
         assert!(is_orphan("deleted-off-disk", &seen));
     }
 
+    // ---- Prerequisite gating ----
+
+    fn local(guid: &str, requires: &[&str]) -> Local {
+        Local {
+            path: format!("{guid}.md").into(),
+            guid: guid.into(),
+            spec: ModelSpec {
+                name: "basic".into(),
+                css: String::new(),
+                card_names: vec![BASIC_CARD_NAME.into()],
+            },
+            fields: Vec::new(),
+            anki_tags: Vec::new(),
+            deck: "Default".into(),
+            assets: Vec::new(),
+            hash: String::new(),
+            inputs: Inputs::new("", "", ""),
+            order: OrderKey::new(Path::new(""), Path::new(guid), None),
+            requires: requires.iter().map(|r| r.to_string()).collect(),
+            gated: false,
+        }
+    }
+
+    fn managed(guid: &str, min_interval: Option<i64>) -> RawManagedNote {
+        RawManagedNote {
+            note_id: 1,
+            guid: guid.into(),
+            mid: 1,
+            model_name: "marki:basic".into(),
+            tags: vec![MARKER_TAG.into()],
+            fields: Vec::new(),
+            deck: "Default".into(),
+            card_ids: vec![1],
+            new_position: None,
+            min_interval,
+        }
+    }
+
+    fn by_guid(notes: Vec<RawManagedNote>) -> HashMap<String, RawManagedNote> {
+        notes.into_iter().map(|r| (r.guid.clone(), r)).collect()
+    }
+
+    #[test]
+    fn notes_wait_for_learned_prerequisites() {
+        let mut local: HashMap<String, Local> = [
+            ("hablar-yo", &["hablar", "ser"][..]),
+            ("ser-yo", &["ser"][..]),
+            ("comer-yo", &["comer"][..]),
+            ("typo", &["nope"][..]),
+        ]
+        .into_iter()
+        .map(|(g, r)| (g.to_string(), local(g, r)))
+        .collect();
+        let remote = by_guid(vec![managed("hablar", Some(30)), managed("ser", Some(3))]);
+        // `comer` is on disk but not in the collection yet.
+        let seen: HashSet<String> = ["hablar", "ser", "comer", "hablar-yo", "ser-yo"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let disk: HashSet<&str> = seen.iter().map(String::as_str).collect();
+        let prereqs = Prerequisites {
+            disk: &disk,
+            in_target: &seen,
+            owned: &remote,
+            foreign: &HashMap::new(),
+        };
+
+        let mut outcome = Outcome::default();
+        gate(&mut local, &prereqs, 21, &mut outcome);
+        assert!(local["hablar-yo"].gated, "one unlearned prerequisite is enough");
+        assert!(local["comer-yo"].gated);
+        assert!(local["typo"].gated);
+        // A typo is reported without tripping the prune safety valve.
+        assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+        assert_eq!(outcome.warnings.len(), 1, "{:?}", outcome.warnings);
+
+        gate(&mut local, &prereqs, 3, &mut outcome);
+        assert!(!local["hablar-yo"].gated && !local["ser-yo"].gated);
+        assert!(note_tags(&local["comer-yo"], &Target::all()).contains(&GATED_TAG.to_string()));
+    }
+
+    #[test]
+    fn prerequisites_may_come_from_other_targets() {
+        let mut local: HashMap<String, Local> = [
+            ("hablar-yo", &["hablar"][..]),
+            ("comer-yo", &["comer"][..]),
+        ]
+        .into_iter()
+        .map(|(g, r)| (g.to_string(), local(g, r)))
+        .collect();
+        // Both prerequisites live outside this target; another target has
+        // published `hablar` into the same collection, `comer` nowhere here.
+        let seen: HashSet<String> = ["hablar-yo", "comer-yo"].into_iter().map(String::from).collect();
+        let disk: HashSet<&str> = ["hablar-yo", "comer-yo", "hablar", "comer"].into();
+        let foreign = by_guid(vec![managed("hablar", Some(30))]);
+        let prereqs = Prerequisites {
+            disk: &disk,
+            in_target: &seen,
+            owned: &HashMap::new(),
+            foreign: &foreign,
+        };
+
+        let mut outcome = Outcome::default();
+        gate(&mut local, &prereqs, 21, &mut outcome);
+        assert!(!local["hablar-yo"].gated);
+        assert!(local["comer-yo"].gated);
+        assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+        assert_eq!(outcome.warnings.len(), 1, "{:?}", outcome.warnings);
+        assert!(outcome.warnings[0].contains("same collection"), "{:?}", outcome.warnings);
+    }
+
     // ---- Stock rendering ----
 
     fn stock(src: &str) -> StockRenderResult {
@@ -1,6 +1,12 @@
 //! Reconciliation: scan the disk, read the collection, apply the diff.
 
+pub mod backend;
+pub mod diff;
 pub mod engine;
 pub mod media;
+pub mod order;
+pub mod target;
 
-pub use engine::{Outcome, reconcile, render_stock};
+pub use backend::{Backend, DirectBackend, ServerBackend};
+pub use target::Target;
+pub use engine::{DiffReport, NoteDiff, Outcome, diff, reconcile, render_stock, reorder};
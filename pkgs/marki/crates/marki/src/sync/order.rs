@@ -0,0 +1,141 @@
+//! The order new cards are introduced in.
+//!
+//! Anki shows new cards by position (`cards.due`), which the writer hands
+//! out as notes are added. [`OrderKey`] ties that to the repo layout:
+//! within a directory, notes with an `#order(n)` tag or front-matter
+//! `order: n` come first, by `n`, then the rest by file name. A
+//! directory's own notes come before its subdirectories, and
+//! subdirectories follow each other by name. Names compare naturally, so
+//! `chapter-2` sorts before `chapter-10`. A push adds new notes in this
+//! order; `marki reorder` re-applies it to cards not yet studied.
+
+use std::cmp::Ordering;
+use std::path::{Component, Path};
+
+/// Where a note sorts among new cards. See the module docs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderKey {
+    dirs: Vec<String>,
+    order: Option<i64>,
+    file: String,
+}
+
+impl OrderKey {
+    /// The key of the card at `path` under `root`, with its explicit order.
+    pub fn new(root: &Path, path: &Path, order: Option<i64>) -> Self {
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        let mut names: Vec<String> = rel
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let file = names.pop().unwrap_or_default();
+        Self {
+            dirs: names,
+            order,
+            file,
+        }
+    }
+}
+
+impl Ord for OrderKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Compare directories first; a shorter prefix (the parent's own
+        // notes) sorts before its subdirectories.
+        for (a, b) in self.dirs.iter().zip(&other.dirs) {
+            match natural_cmp(a, b) {
+                Ordering::Equal => {}
+                ord => return ord,
+            }
+        }
+        self.dirs
+            .len()
+            .cmp(&other.dirs.len())
+            .then_with(|| match (self.order, other.order) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+            .then_with(|| natural_cmp(&self.file, &other.file))
+    }
+}
+
+impl PartialOrd for OrderKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compare names with digit runs taken as numbers: `ch2 < ch10`. Names
+/// that only differ in leading zeros fall back to plain string order.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a, b);
+    while let (Some(cx), Some(cy)) = (x.chars().next(), y.chars().next()) {
+        if cx.is_ascii_digit() && cy.is_ascii_digit() {
+            let (nx, rx) = split_digits(x);
+            let (ny, ry) = split_digits(y);
+            let (nx, ny) = (nx.trim_start_matches('0'), ny.trim_start_matches('0'));
+            match nx.len().cmp(&ny.len()).then_with(|| nx.cmp(ny)) {
+                Ordering::Equal => (x, y) = (rx, ry),
+                ord => return ord,
+            }
+        } else if cx != cy {
+            return cx.cmp(&cy);
+        } else {
+            (x, y) = (&x[cx.len_utf8()..], &y[cy.len_utf8()..]);
+        }
+    }
+    x.len().cmp(&y.len()).then_with(|| a.cmp(b))
+}
+
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(cards: &[(&str, Option<i64>)]) -> Vec<String> {
+        let root = Path::new("/cards");
+        let mut keys: Vec<(OrderKey, &str)> = cards
+            .iter()
+            .map(|(p, o)| (OrderKey::new(root, &root.join(p), *o), *p))
+            .collect();
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+        keys.into_iter().map(|(_, p)| p.to_string()).collect()
+    }
+
+    #[test]
+    fn names_compare_naturally() {
+        assert_eq!(natural_cmp("ch2", "ch10"), Ordering::Less);
+        assert_eq!(natural_cmp("ch10", "ch10b"), Ordering::Less);
+        assert_eq!(natural_cmp("b", "a10"), Ordering::Greater);
+        assert_eq!(natural_cmp("07", "7"), Ordering::Less);
+    }
+
+    #[test]
+    fn layout_then_explicit_order_then_name() {
+        let order = sorted(&[
+            ("spanish/chapter-10/verbs.md", None),
+            ("spanish/chapter-2/greetings.md", None),
+            ("spanish/chapter-2/numbers.md", Some(1)),
+            ("spanish/chapter-2/alphabet.md", None),
+            ("spanish/intro.md", None),
+        ]);
+        assert_eq!(
+            order,
+            [
+                "spanish/intro.md",
+                "spanish/chapter-2/numbers.md",
+                "spanish/chapter-2/alphabet.md",
+                "spanish/chapter-2/greetings.md",
+                "spanish/chapter-10/verbs.md",
+            ]
+        );
+    }
+}
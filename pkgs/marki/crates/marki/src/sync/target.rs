@@ -0,0 +1,143 @@
+//! Which part of the repo a cycle publishes, and which notes it owns.
+//!
+//! A repo can publish into several collections, each a named
+//! `[targets.<name>]` section (see [`crate::config::TargetConfig`]). A
+//! target publishes the cards its `include`/`exclude` globs select, under
+//! its deck prefix, and owns only the notes it wrote: those carry a
+//! `marki::target:<name>` tag. Orphan detection only ever looks at owned
+//! notes, so two targets sharing a collection never quarantine each
+//! other's notes. The top-level `collection` is the unnamed target: the
+//! whole repo, no prefix, notes without a target tag.
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+use crate::anki::model::TARGET_TAG_PREFIX;
+use crate::config::TargetConfig;
+
+/// A compiled target: its name, deck prefix and card filter.
+#[derive(Debug, Clone, Default)]
+pub struct Target {
+    name: Option<String>,
+    deck_prefix: Option<String>,
+    /// `None` means every card.
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Target {
+    /// The unnamed target: every card, no prefix.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Compile `[targets.<name>]`. Fails naming the first bad glob.
+    pub fn compile(name: &str, cfg: &TargetConfig) -> Result<Self> {
+        let include = if cfg.include.is_empty() {
+            None
+        } else {
+            Some(glob_set(name, "include", &cfg.include)?)
+        };
+        let deck_prefix = cfg
+            .deck_prefix
+            .as_deref()
+            .map(|p| p.trim_matches(':').to_string())
+            .filter(|p| !p.is_empty());
+        Ok(Self {
+            name: Some(name.to_string()),
+            deck_prefix,
+            include,
+            exclude: glob_set(name, "exclude", &cfg.exclude)?,
+        })
+    }
+
+    /// The target's name; `None` for the unnamed target.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Whether the card at `rel` (relative to `cards_dir`) is published
+    /// by this target.
+    pub fn contains(&self, rel: &Path) -> bool {
+        self.include.as_ref().is_none_or(|set| set.is_match(rel)) && !self.exclude.is_match(rel)
+    }
+
+    /// The deck a card in `deck` lands in: `<prefix>::<deck>`. Cards at the
+    /// repo root (deck `Default`) land in the prefix deck itself.
+    pub fn deck(&self, deck: &str) -> String {
+        match &self.deck_prefix {
+            None => deck.to_string(),
+            Some(prefix) if deck == "Default" => prefix.clone(),
+            Some(prefix) => format!("{prefix}::{deck}"),
+        }
+    }
+
+    /// The tag marking a note as this target's, if it is a named one.
+    pub fn tag(&self) -> Option<String> {
+        self.name
+            .as_ref()
+            .map(|n| format!("{TARGET_TAG_PREFIX}{n}"))
+    }
+
+    /// Whether a managed note with `tags` was written by this target.
+    pub fn owns(&self, tags: &[String]) -> bool {
+        owner(tags) == self.name()
+    }
+}
+
+/// The named target a managed note belongs to, from its tags.
+pub fn owner(tags: &[String]) -> Option<&str> {
+    tags.iter().find_map(|t| t.strip_prefix(TARGET_TAG_PREFIX))
+}
+
+fn glob_set(target: &str, key: &str, patterns: &[String]) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for p in patterns {
+        let glob = Glob::new(p).with_context(|| format!("targets.{target}.{key}: `{p}`"))?;
+        set.add(glob);
+    }
+    set.build()
+        .with_context(|| format!("targets.{target}.{key}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn family() -> Target {
+        let cfg = TargetConfig {
+            deck_prefix: Some("Family::".into()),
+            include: vec!["geography/**".into()],
+            exclude: vec!["**/draft-*.md".into()],
+            ..TargetConfig::default()
+        };
+        Target::compile("family", &cfg).unwrap()
+    }
+
+    #[test]
+    fn globs_select_cards() {
+        let t = family();
+        assert!(t.contains(Path::new("geography/europe/paris.md")));
+        assert!(!t.contains(Path::new("geography/draft-lima.md")));
+        assert!(!t.contains(Path::new("math/primes.md")));
+        assert!(Target::all().contains(Path::new("math/primes.md")));
+    }
+
+    #[test]
+    fn decks_get_the_prefix() {
+        let t = family();
+        assert_eq!(t.deck("geography::europe"), "Family::geography::europe");
+        assert_eq!(t.deck("Default"), "Family");
+        assert_eq!(Target::all().deck("geography"), "geography");
+    }
+
+    #[test]
+    fn ownership_follows_the_target_tag() {
+        let t = family();
+        let tagged = vec!["marki".to_string(), t.tag().unwrap()];
+        let untagged = vec!["marki".to_string()];
+        assert!(t.owns(&tagged) && !t.owns(&untagged));
+        assert!(Target::all().owns(&untagged) && !Target::all().owns(&tagged));
+    }
+}
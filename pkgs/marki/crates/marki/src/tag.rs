@@ -53,6 +53,15 @@ pub enum SystemTag {
 
     /// `#basic` -- explicit basic model (default).
     Basic,
+
+    /// `#requires(<id>)` -- keep this note's cards suspended until the note
+    /// with marki id `<id>` is learned (see `prerequisite_interval`). May
+    /// appear more than once.
+    Requires(NoteId),
+
+    /// `#order(<n>)` -- the note's place among the new cards of its
+    /// directory, ahead of file-name order. Lower comes first.
+    Order(i64),
 }
 
 impl FromStr for SystemTag {
@@ -85,10 +94,11 @@ impl FromStr for SystemTag {
         };
 
         match keyword {
-            // Id and Model take the argument verbatim, so there is no
-            // way for them to fail beyond being absent.
+            // Id, Model and Requires take the argument verbatim, so there
+            // is no way for them to fail beyond being absent.
             "id" => Ok(SystemTag::Id(require("id")?.to_string())),
             "model" => Ok(SystemTag::Model(require("model")?.to_string())),
+            "requires" => Ok(SystemTag::Requires(require("requires")?.to_string())),
             "cloze" => match arg {
                 None => Ok(SystemTag::Cloze(None)),
                 Some(a) => a
@@ -103,6 +113,13 @@ impl FromStr for SystemTag {
                 forbid("basic")?;
                 Ok(SystemTag::Basic)
             }
+            "order" => {
+                let a = require("order")?;
+                a.trim().parse().map(SystemTag::Order).map_err(|_| TagParseError::BadArg {
+                    tag: "order".to_string(),
+                    arg: a.to_string(),
+                })
+            }
             other => Err(TagParseError::Unknown(other.to_string())),
         }
     }
@@ -207,6 +224,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn requires_names_a_note() {
+        assert_eq!(
+            parse_token("#requires(9f3a)"),
+            Parsed::System(SystemTag::Requires("9f3a".into()))
+        );
+        let r = parse_token("#requires");
+        assert!(matches!(r, Parsed::Error(TagParseError::MissingArg(_))));
+    }
+
+    #[test]
+    fn order_takes_an_integer() {
+        assert_eq!(parse_token("#order(-2)"), Parsed::System(SystemTag::Order(-2)));
+        let r = parse_token("#order(first)");
+        assert!(matches!(r, Parsed::Error(TagParseError::BadArg { .. })));
+    }
+
     #[test]
     fn unit_with_args_errors() {
         let r = parse_token("#basic(foo)");
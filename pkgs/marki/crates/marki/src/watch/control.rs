@@ -0,0 +1,243 @@
+//! The `marki watch` control socket.
+//!
+//! A Unix stream socket taking one command per connection: the client
+//! writes a single line (`status`, `push`, `pause`, `resume` or `reload`)
+//! and reads back a single JSON [`Reply`] line. `status`, `pause` and
+//! `resume` are answered straight from the shared [`Status`], even while a
+//! cycle runs. `push` and `reload` are handed to the watch loop, and the
+//! reply is sent once the cycle they trigger has finished, so `marki ctl
+//! push` returning means the cards are in the collection.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, channel};
+use std::sync::{Arc, Mutex};
+
+use super::{Msg, Status, Tick};
+
+/// A control command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Request {
+    /// Report the daemon's state and the last cycle's outcome.
+    Status,
+    /// Run a cycle now and report its outcome.
+    Push,
+    /// Stop syncing on changes and heartbeats; changes are counted.
+    Pause,
+    /// Sync again, starting with anything changed while paused.
+    Resume,
+    /// Re-read the config and models, then run a cycle.
+    Reload,
+}
+
+impl Request {
+    fn as_str(self) -> &'static str {
+        match self {
+            Request::Status => "status",
+            Request::Push => "push",
+            Request::Pause => "pause",
+            Request::Resume => "resume",
+            Request::Reload => "reload",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [
+            Self::Status,
+            Self::Push,
+            Self::Pause,
+            Self::Resume,
+            Self::Reload,
+        ]
+        .into_iter()
+        .find(|r| r.as_str() == s)
+    }
+}
+
+/// The answer to one request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Reply {
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub status: Option<Status>,
+}
+
+/// Send `req` to the daemon listening on `path` and wait for its status.
+pub fn request(path: &Path, req: Request) -> Result<Status> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("connect to {} (is `marki watch` running?)", path.display()))?;
+    writeln!(stream, "{}", req.as_str()).context("send request")?;
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .context("read reply")?;
+    let reply: Reply = serde_json::from_str(&line).context("decode reply")?;
+    if let Some(e) = reply.error {
+        bail!("{e}");
+    }
+    reply.status.context("reply carried no status")
+}
+
+/// A listening control socket. Dropping it removes the socket file.
+pub(super) struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Listen on `path`, answering from `state` and forwarding `push`/`reload`
+/// to the watch loop through `tx`. Refuses to take over a socket another
+/// daemon still answers on; a stale one is replaced.
+pub(super) fn serve(path: &Path, tx: Sender<Msg>, state: Arc<Mutex<Status>>) -> Result<Server> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!(
+                "another `marki watch` is already listening on {}",
+                path.display()
+            );
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("remove stale socket {}", path.display()))?;
+    }
+    let listener = bind_private(path)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let (tx, state) = (tx.clone(), Arc::clone(&state));
+            // One thread per client, so a `push` waiting on its cycle never
+            // holds up a `status`.
+            std::thread::spawn(move || {
+                if let Err(e) = handle(stream, &tx, &state) {
+                    tracing::debug!("control client: {e:#}");
+                }
+            });
+        }
+    });
+    Ok(Server {
+        path: path.to_path_buf(),
+    })
+}
+
+/// Bind `path` so that no other user can ever connect: anyone who can
+/// push, pause or stop syncing. The socket is bound inside a fresh 0700
+/// staging directory, restricted to 0600 there, and only then renamed
+/// into place, so it is never reachable with the umask's mode.
+fn bind_private(path: &Path) -> Result<UnixListener> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let private = || {
+        let mut b = std::fs::DirBuilder::new();
+        b.mode(0o700);
+        b
+    };
+    private()
+        .recursive(true)
+        .create(dir)
+        .with_context(|| format!("create {}", dir.display()))?;
+    let staging = dir.join(format!(".marki-ctl-{}", std::process::id()));
+    std::fs::remove_dir_all(&staging).ok();
+    private()
+        .create(&staging)
+        .with_context(|| format!("create {}", staging.display()))?;
+
+    let staged = staging.join("sock");
+    let bound = UnixListener::bind(&staged)
+        .with_context(|| format!("bind {}", path.display()))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("restrict {}", path.display()))?;
+            std::fs::rename(&staged, path)
+                .with_context(|| format!("move socket to {}", path.display()))?;
+            Ok(listener)
+        });
+    std::fs::remove_dir_all(&staging).ok();
+    bound
+}
+
+fn handle(stream: UnixStream, tx: &Sender<Msg>, state: &Mutex<Status>) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let reply = match Request::parse(line.trim()) {
+        None => Reply {
+            error: Some(format!("unknown command `{}`", line.trim())),
+            status: None,
+        },
+        Some(req) => match answer(req, tx, state) {
+            Ok(status) => Reply {
+                error: None,
+                status: Some(status),
+            },
+            Err(e) => Reply {
+                error: Some(format!("{e:#}")),
+                status: None,
+            },
+        },
+    };
+    let mut out = serde_json::to_string(&reply)?;
+    out.push('\n');
+    (&stream).write_all(out.as_bytes())?;
+    Ok(())
+}
+
+fn answer(req: Request, tx: &Sender<Msg>, state: &Mutex<Status>) -> Result<Status> {
+    let tick = match req {
+        Request::Status => return Ok(state.lock().unwrap().clone()),
+        Request::Pause => {
+            let mut s = state.lock().unwrap();
+            s.paused = true;
+            return Ok(s.clone());
+        }
+        Request::Resume => {
+            let mut s = state.lock().unwrap();
+            s.paused = false;
+            if s.pending_events > 0 {
+                tx.send(Msg::Resume).ok();
+            }
+            return Ok(s.clone());
+        }
+        Request::Push => Tick::Push,
+        Request::Reload => Tick::Reload,
+    };
+    let (reply_tx, reply_rx) = channel();
+    tx.send(Msg::Control(tick, reply_tx))
+        .context("watch loop has stopped")?;
+    reply_rx.recv().context("watch loop has stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_round_trip_through_the_socket() {
+        let path = std::env::temp_dir().join(format!("marki-ctl-{}.sock", std::process::id()));
+        let state = Arc::new(Mutex::new(Status::default()));
+        let (tx, rx) = channel();
+        let _server = serve(&path, tx, Arc::clone(&state)).unwrap();
+        assert!(serve(&path, channel().0, Arc::clone(&state)).is_err());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600, "only the daemon's user may connect");
+
+        // Stand in for the watch loop: answer one push.
+        let loop_state = Arc::clone(&state);
+        std::thread::spawn(move || {
+            if let Ok(Msg::Control(Tick::Push, reply)) = rx.recv() {
+                loop_state.lock().unwrap().cycles += 1;
+                reply.send(loop_state.lock().unwrap().clone()).unwrap();
+            }
+        });
+
+        assert!(request(&path, Request::Pause).unwrap().paused);
+        assert!(request(&path, Request::Status).unwrap().paused);
+        assert_eq!(request(&path, Request::Push).unwrap().cycles, 1);
+        assert!(!request(&path, Request::Resume).unwrap().paused);
+    }
+}
@@ -1,48 +1,176 @@
 //! Filesystem watcher with debouncing + periodic heartbeat timer.
+//!
+//! While it runs, the watcher keeps a [`Status`] (paused?, changes waiting,
+//! the last cycle's [`Outcome`]) that the [`control`] socket reports, and
+//! takes `push`/`reload` requests from it. Under systemd it also reports
+//! readiness, status lines and watchdog pings (see [`systemd`]).
+
+pub mod control;
+pub mod systemd;
 
 use anyhow::Result;
 use notify::RecursiveMode;
 use notify::event::EventKind;
 use notify_debouncer_full::{DebouncedEvent, new_debouncer};
-use std::path::{Component, Path};
-use std::sync::mpsc::{RecvTimeoutError, channel};
-use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{RecvTimeoutError, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::sync::Outcome;
 
+/// Why a cycle runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tick {
     Filesystem,
     Heartbeat,
+    /// `push` over the control socket.
+    Push,
+    /// `reload` over the control socket: the handler re-reads its config
+    /// and models before the cycle.
+    Reload,
+}
+
+impl Tick {
+    fn name(self) -> &'static str {
+        match self {
+            Tick::Filesystem => "filesystem",
+            Tick::Heartbeat => "heartbeat",
+            Tick::Push => "push",
+            Tick::Reload => "reload",
+        }
+    }
+}
+
+/// What the control socket reports about a running watcher.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Status {
+    /// Changes and heartbeats trigger no cycles while paused.
+    pub paused: bool,
+    /// A cycle is running right now.
+    pub running: bool,
+    /// Changed paths seen since the last cycle started, i.e. not synced yet.
+    pub pending_events: usize,
+    /// Cycles run since startup.
+    pub cycles: u64,
+    pub last_cycle: Option<CycleStatus>,
 }
 
-/// Block the calling thread and emit a `Tick` whenever either the
-/// filesystem produces debounced events or the heartbeat timer fires.
-/// `handler` is called with the reason and must return `Ok(true)` to keep
-/// running or `Ok(false)` to exit cleanly.
-pub fn run<F>(
-    root: &Path,
-    debounce: Duration,
-    heartbeat: Duration,
-    mut handler: F,
-) -> Result<()>
+/// How the most recent cycle went.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CycleStatus {
+    /// `filesystem`, `heartbeat`, `push` or `reload`.
+    pub trigger: String,
+    /// When it finished, in Unix seconds.
+    pub finished_at: u64,
+    pub duration_ms: u64,
+    /// `None` when the cycle failed outright; see `failure`.
+    pub outcome: Option<Outcome>,
+    pub failure: Option<String>,
+}
+
+/// Watcher settings.
+pub struct Options {
+    pub debounce: Duration,
+    pub heartbeat: Duration,
+    /// Serve the control socket at this path.
+    pub control_socket: Option<PathBuf>,
+}
+
+/// What the watch loop waits on.
+enum Msg {
+    Fs(Result<Vec<DebouncedEvent>, Vec<notify::Error>>),
+    /// A `push`/`reload` request; the status is sent back after the cycle.
+    Control(Tick, Sender<Status>),
+    /// Resumed with changes pending.
+    Resume,
+}
+
+/// Block the calling thread and run `handler` whenever the filesystem
+/// produces debounced events, the heartbeat timer fires, or the control
+/// socket asks for a cycle. `handler` runs one cycle and returns its
+/// outcome; a failed cycle is logged and recorded, and watching goes on.
+pub fn run<F>(root: &Path, opts: &Options, mut handler: F) -> Result<()>
 where
-    F: FnMut(Tick) -> Result<bool>,
+    F: FnMut(Tick) -> Result<Outcome>,
 {
-    let (tx, rx) = channel::<Result<Vec<DebouncedEvent>, Vec<notify::Error>>>();
-    let mut debouncer = new_debouncer(debounce, None, move |res| {
-        let _ = tx.send(res);
+    let (tx, rx) = channel::<Msg>();
+    let fs_tx = tx.clone();
+    let mut debouncer = new_debouncer(opts.debounce, None, move |res| {
+        let _ = fs_tx.send(Msg::Fs(res));
     })?;
     debouncer.watch(root, RecursiveMode::Recursive)?;
 
-    // Initial run: treat startup as a heartbeat.
-    if !handler(Tick::Heartbeat)? {
-        return Ok(());
+    let state = Arc::new(Mutex::new(Status::default()));
+    let _server = match &opts.control_socket {
+        Some(path) => {
+            let server = control::serve(path, tx, Arc::clone(&state))?;
+            tracing::info!(socket = %path.display(), "control socket listening");
+            Some(server)
+        }
+        None => None,
+    };
+    let notifier = systemd::Notifier::from_env().map(Arc::new);
+    // Fed from its own thread, which pings only while this loop keeps
+    // beating (see `systemd::Liveness`).
+    let live = Arc::new(systemd::Liveness::default());
+    let _watchdog = notifier.as_ref().and_then(|n| n.spawn_watchdog(Arc::clone(&live)));
+    // The loop wakes at least this often, so an idle wait is a beat too.
+    let beat_every = notifier
+        .as_ref()
+        .and_then(|n| n.watchdog_interval())
+        .unwrap_or(opts.heartbeat);
+    // Watching and the control socket are up, so report ready now rather
+    // than after the first cycle, which may outlast `TimeoutStartSec=`.
+    if let Some(n) = &notifier {
+        n.notify("READY=1");
     }
-    let mut next_heartbeat = Instant::now() + heartbeat;
+
+    let mut cycle = |tick: Tick| {
+        {
+            let mut s = state.lock().unwrap();
+            s.running = true;
+            s.pending_events = 0;
+        }
+        live.cycle(true);
+        let started = Instant::now();
+        let result = handler(tick);
+        live.cycle(false);
+        if let Err(e) = &result {
+            tracing::error!("cycle failed: {e:#}");
+        }
+        let status = CycleStatus {
+            trigger: tick.name().to_string(),
+            finished_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            duration_ms: started.elapsed().as_millis() as u64,
+            failure: result.as_ref().err().map(|e| format!("{e:#}")),
+            outcome: result.ok(),
+        };
+        if let Some(n) = &notifier {
+            n.notify(&format!("STATUS={}", summary(&status)));
+        }
+        let mut s = state.lock().unwrap();
+        s.running = false;
+        s.cycles += 1;
+        s.last_cycle = Some(status);
+        s.clone()
+    };
+
+    // Initial run: treat startup as a heartbeat.
+    cycle(Tick::Heartbeat);
+    let mut next_heartbeat = Instant::now() + opts.heartbeat;
 
     loop {
         let now = Instant::now();
-        let wait = next_heartbeat.saturating_duration_since(now);
-        match rx.recv_timeout(wait) {
-            Ok(Ok(events)) => {
+        let paused = || state.lock().unwrap().paused;
+        let wait = next_heartbeat.saturating_duration_since(now).min(beat_every);
+        let msg = rx.recv_timeout(wait);
+        live.beat();
+        match msg {
+            Ok(Msg::Fs(Ok(events))) => {
                 // Two filters:
                 //
                 // 1. Event kind: drop read-only events (Access, Open,
@@ -54,33 +182,70 @@ where
                 // 2. Path: drop events inside hidden directories
                 //    (.git, .direnv, …) — git operations churn
                 //    thousands of object writes we don't care about.
-                let dominated = events.iter().any(|e| {
-                    is_write_event(&e.event.kind)
-                        && events_path_worth_scanning(&e.paths)
-                });
-                if !dominated {
+                let changed = events
+                    .iter()
+                    .filter(|e| {
+                        is_write_event(&e.event.kind) && events_path_worth_scanning(&e.paths)
+                    })
+                    .count();
+                if changed == 0 {
                     continue;
                 }
-                if !handler(Tick::Filesystem)? {
-                    return Ok(());
+                state.lock().unwrap().pending_events += changed;
+                if paused() {
+                    continue;
                 }
-                next_heartbeat = Instant::now() + heartbeat;
+                cycle(Tick::Filesystem);
+                next_heartbeat = Instant::now() + opts.heartbeat;
             }
-            Ok(Err(errs)) => {
+            Ok(Msg::Fs(Err(errs))) => {
                 for e in errs {
                     tracing::warn!("watcher error: {e}");
                 }
             }
-            Err(RecvTimeoutError::Timeout) => {
-                if !handler(Tick::Heartbeat)? {
-                    return Ok(());
+            Ok(Msg::Control(tick, reply)) => {
+                tracing::info!("cycle: {} requested over the control socket", tick.name());
+                let _ = reply.send(cycle(tick));
+                next_heartbeat = Instant::now() + opts.heartbeat;
+            }
+            Ok(Msg::Resume) => {
+                if !paused() && state.lock().unwrap().pending_events > 0 {
+                    cycle(Tick::Filesystem);
+                    next_heartbeat = Instant::now() + opts.heartbeat;
                 }
-                next_heartbeat = Instant::now() + heartbeat;
             }
+            Err(RecvTimeoutError::Timeout) => {}
             Err(RecvTimeoutError::Disconnected) => {
                 anyhow::bail!("watcher channel disconnected");
             }
         }
+
+        // Checked after every wakeup: a busy filesystem must not starve
+        // the heartbeat.
+        if Instant::now() >= next_heartbeat {
+            if !paused() {
+                cycle(Tick::Heartbeat);
+            }
+            next_heartbeat = Instant::now() + opts.heartbeat;
+        }
+    }
+}
+
+/// One-line summary of a cycle, for `STATUS=` and `marki ctl status`.
+pub fn summary(c: &CycleStatus) -> String {
+    match (&c.outcome, &c.failure) {
+        (Some(o), _) => format!(
+            "{} cycle: +{} ~{} ->{} -{} (quarantined {}, {} errors)",
+            c.trigger,
+            o.added,
+            o.updated,
+            o.moved,
+            o.deleted,
+            o.quarantined,
+            o.errors.len()
+        ),
+        (None, Some(e)) => format!("{} cycle failed: {e}", c.trigger),
+        (None, None) => format!("{} cycle", c.trigger),
     }
 }
 
@@ -100,10 +265,7 @@ fn is_write_event(kind: &EventKind) -> bool {
 fn events_path_worth_scanning(paths: &[std::path::PathBuf]) -> bool {
     paths.iter().any(|p| {
         !p.components().any(|c| match c {
-            Component::Normal(name) => name
-                .to_str()
-                .map(|s| s.starts_with('.'))
-                .unwrap_or(false),
+            Component::Normal(name) => name.to_str().map(|s| s.starts_with('.')).unwrap_or(false),
             _ => false,
         })
     })
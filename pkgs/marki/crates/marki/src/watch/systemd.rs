@@ -0,0 +1,221 @@
+//! systemd service notifications (`sd_notify`).
+//!
+//! Under `Type=notify`, systemd passes a datagram socket in
+//! `$NOTIFY_SOCKET` and waits for `READY=1` before it considers the
+//! service started. With `WatchdogSec=` it also sets `$WATCHDOG_USEC` and
+//! restarts the service when no `WATCHDOG=1` arrives within that time.
+//! `STATUS=…` lines show up in `systemctl status`. Outside systemd none of
+//! the variables are set and nothing is sent.
+//!
+//! The watchdog is fed from a thread of its own, but only while the watch
+//! loop reports progress through [`Liveness`]: a wedged loop stops the
+//! pings and gets restarted. A cycle in progress counts as progress for up
+//! to [`CYCLE_BUDGET`], so a first full render or a long sync-server
+//! download is not mistaken for a hang.
+
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::mpsc::{RecvTimeoutError, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long one cycle may run before the watchdog treats the loop as
+/// wedged.
+pub const CYCLE_BUDGET: Duration = Duration::from_secs(30 * 60);
+
+/// A connection to the service manager's notification socket.
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: String,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// The notifier systemd asked for, if any.
+    pub fn from_env() -> Option<Self> {
+        let addr = std::env::var("NOTIFY_SOCKET").ok()?;
+        // A watchdog meant for another process (say, a wrapper script) is
+        // not ours to feed.
+        let ours = std::env::var("WATCHDOG_PID")
+            .ok()
+            .is_none_or(|pid| pid.parse() == Ok(std::process::id()));
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|us| us.parse().ok())
+            .filter(|_| ours)
+            .map(Duration::from_micros);
+        Self::new(addr, watchdog)
+    }
+
+    fn new(addr: String, watchdog: Option<Duration>) -> Option<Self> {
+        let socket = UnixDatagram::unbound()
+            .inspect_err(|e| tracing::warn!("sd_notify: {e}"))
+            .ok()?;
+        Some(Self {
+            socket,
+            addr,
+            watchdog,
+        })
+    }
+
+    /// How often to send `WATCHDOG=1`: half the configured timeout, as
+    /// sd_watchdog_enabled(3) recommends.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|t| t / 2)
+    }
+
+    /// Every [`Self::watchdog_interval`] until the returned guard is
+    /// dropped, send `WATCHDOG=1` if `live` shows the watch loop is still
+    /// making progress. `None` without a watchdog.
+    pub fn spawn_watchdog(self: &Arc<Self>, live: Arc<Liveness>) -> Option<Watchdog> {
+        let timeout = self.watchdog?;
+        let every = timeout / 2;
+        let (stop, stopped) = channel::<()>();
+        let n = Arc::clone(self);
+        std::thread::spawn(move || {
+            let mut stalled = false;
+            loop {
+                if live.healthy(Instant::now(), timeout) {
+                    n.notify("WATCHDOG=1");
+                    stalled = false;
+                } else if !stalled {
+                    tracing::error!("watch loop stalled; letting the systemd watchdog expire");
+                    stalled = true;
+                }
+                if let Err(RecvTimeoutError::Disconnected) | Ok(()) = stopped.recv_timeout(every) {
+                    break;
+                }
+            }
+        });
+        Some(Watchdog { _stop: stop })
+    }
+
+    /// Send one notification, e.g. `READY=1`. Failures are logged only; a
+    /// missed notification must never stop a sync.
+    pub fn notify(&self, state: &str) {
+        if let Err(e) = self.send(state.as_bytes()) {
+            tracing::debug!("sd_notify {state:?}: {e}");
+        }
+    }
+
+    fn send(&self, msg: &[u8]) -> std::io::Result<usize> {
+        // `@name` is a Linux abstract-namespace address.
+        #[cfg(target_os = "linux")]
+        if let Some(name) = self.addr.strip_prefix('@') {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            return self.socket.send_to_addr(msg, &addr);
+        }
+        self.socket.send_to(msg, PathBuf::from(&self.addr))
+    }
+}
+
+/// The watch loop's progress, read by the watchdog thread.
+pub struct Liveness {
+    state: Mutex<Progress>,
+}
+
+struct Progress {
+    /// The loop's last wakeup.
+    beat: Instant,
+    /// When the running cycle started, if one is running.
+    cycle: Option<Instant>,
+}
+
+impl Default for Liveness {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(Progress {
+                beat: Instant::now(),
+                cycle: None,
+            }),
+        }
+    }
+}
+
+impl Liveness {
+    /// The loop woke up and is waiting again.
+    pub fn beat(&self) {
+        self.state.lock().unwrap().beat = Instant::now();
+    }
+
+    /// A cycle starts (`true`) or has finished (`false`).
+    pub fn cycle(&self, running: bool) {
+        let mut p = self.state.lock().unwrap();
+        p.beat = Instant::now();
+        p.cycle = running.then_some(p.beat);
+    }
+
+    /// Whether the loop beat within `timeout` of `now`, or is inside a
+    /// cycle that has not yet outrun [`CYCLE_BUDGET`].
+    fn healthy(&self, now: Instant, timeout: Duration) -> bool {
+        let p = self.state.lock().unwrap();
+        match p.cycle {
+            Some(started) => now.saturating_duration_since(started) < CYCLE_BUDGET,
+            None => now.saturating_duration_since(p.beat) < timeout,
+        }
+    }
+}
+
+/// Keeps the watchdog thread pinging; dropping it stops the thread.
+pub struct Watchdog {
+    _stop: Sender<()>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_reach_the_socket() {
+        let path = std::env::temp_dir().join(format!("marki-notify-{}.sock", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let manager = UnixDatagram::bind(&path).unwrap();
+        let n = Notifier::new(path.display().to_string(), Some(Duration::from_secs(30))).unwrap();
+        assert_eq!(n.watchdog_interval(), Some(Duration::from_secs(15)));
+
+        n.notify("READY=1");
+        let mut buf = [0; 64];
+        let len = manager.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn watchdog_pings_until_dropped() {
+        let path = std::env::temp_dir().join(format!("marki-watchdog-{}.sock", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let manager = UnixDatagram::bind(&path).unwrap();
+        manager.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let n = Notifier::new(path.display().to_string(), Some(Duration::from_millis(40)));
+        let n = Arc::new(n.unwrap());
+
+        let live = Arc::new(Liveness::default());
+        let guard = n.spawn_watchdog(Arc::clone(&live)).unwrap();
+        let mut buf = [0; 64];
+        for _ in 0..3 {
+            live.beat();
+            let len = manager.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], b"WATCHDOG=1");
+        }
+        drop(guard);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn liveness_needs_recent_beats_or_a_running_cycle() {
+        let timeout = Duration::from_secs(30);
+        let live = Liveness::default();
+        let now = Instant::now();
+        assert!(live.healthy(now, timeout));
+        assert!(!live.healthy(now + timeout * 2, timeout), "a wedged loop");
+
+        live.cycle(true);
+        let now = Instant::now();
+        assert!(live.healthy(now + timeout * 2, timeout), "a long cycle");
+        assert!(!live.healthy(now + CYCLE_BUDGET * 2, timeout), "a wedged cycle");
+
+        live.cycle(false);
+        assert!(!live.healthy(Instant::now() + timeout * 2, timeout));
+    }
+}
@@ -4,6 +4,8 @@
 //! `<figure class="table">`, marks internal links with `class="reference-link"`,
 //! and renders admonitions as `<aside class="admonition ...">`. Those are applied
 //! as DOM fixups after rendering, so this stays the exact inverse of `to_md`.
+//! Math is the exception: `$…$` is already an event of its own, so it is mapped
+//! to CKEditor's `math-tex` span while the events stream past.
 
 use pulldown_cmark::{Event, Options, Parser, html};
 
@@ -27,6 +29,7 @@ pub fn markdown_to_html_reporting(md: &str) -> (String, bool) {
     options.insert(Options::ENABLE_TABLES);
     options.insert(Options::ENABLE_STRIKETHROUGH);
     options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_MATH);
 
     // Every newline the user types is a hard break, not CommonMark's usual
     // soft one -- `to_md`'s `br` rule relies on this to round-trip a literal
@@ -34,6 +37,8 @@ pub fn markdown_to_html_reporting(md: &str) -> (String, bool) {
     // trailing spaces the source never had.
     let parser = Parser::new_ext(md, options).map(|event| match event {
         Event::SoftBreak => Event::HardBreak,
+        Event::InlineMath(tex) => Event::InlineHtml(math_tex(&tex, "\\(", "\\)").into()),
+        Event::DisplayMath(tex) => Event::InlineHtml(math_tex(&tex, "\\[", "\\]").into()),
         other => other,
     });
     let mut raw = String::new();
@@ -56,6 +61,19 @@ pub fn markdown_to_html_reporting(md: &str) -> (String, bool) {
     (dom::serialize(&nodes).trim_end().to_string(), degraded)
 }
 
+/// `$tex$` -> `<span class="math-tex">\(tex\)</span>`, and `$$tex$$` the same
+/// with `\[ \]`: the inverse of `to_md::math`. Built as a node and serialised
+/// so the TeX is entity-escaped exactly as the DOM comparison expects.
+fn math_tex(tex: &str, open: &str, close: &str) -> String {
+    dom::serialize(&[Node::Element(Element {
+        name: "span".into(),
+        attrs: [("class".to_string(), "math-tex".to_string())]
+            .into_iter()
+            .collect(),
+        children: vec![Node::Text(format!("{open}{tex}{close}"))],
+    })])
+}
+
 /// The last line of defence against writing markup CKEditor's schema rejects.
 ///
 /// Every other fixup in this module is safe to fail: bailing out just leaves
@@ -655,4 +673,16 @@ mod tests {
     fn a_blank_line_still_separates_paragraphs_rather_than_becoming_a_br() {
         assert_eq!(markdown_to_html("a\n\nb"), "<p>a</p>\n<p>b</p>");
     }
+
+    #[test]
+    fn dollar_math_becomes_a_math_tex_span() {
+        assert_eq!(
+            markdown_to_html("so $a<b$ and"),
+            r#"<p>so <span class="math-tex">\(a&lt;b\)</span> and</p>"#
+        );
+        assert_eq!(
+            markdown_to_html("$$\\sum_i x_i$$"),
+            r#"<p><span class="math-tex">\[\sum_i x_i\]</span></p>"#
+        );
+    }
 }
@@ -683,10 +683,45 @@ fn inline_rule(e: &Element, mode: Escaping, literal_br: bool) -> Option<String>
         }
         "a" => anchor(e, mode, literal_br),
         "img" => image(e, mode),
+        "span" => math(e, literal_br),
         _ => None,
     }
 }
 
+/// CKEditor's math, `<span class="math-tex">\(tex\)</span>` inline and
+/// `\[tex\]` for display, becomes `$tex$` / `$$tex$$`. The TeX goes through
+/// unescaped: math is literal on the way back, like a code span.
+///
+/// TeX the dollar syntax cannot delimit -- a `$`, a backtick, a line break,
+/// inline math with whitespace at either edge (a `$` next to a space neither
+/// opens nor closes), or a pipe inside a table cell -- is declined and rides
+/// along as raw HTML via `inline_verbatim`, as all math used to.
+fn math(e: &Element, literal_br: bool) -> Option<String> {
+    if e.attrs.len() != 1 || e.attr("class") != Some("math-tex") {
+        return None;
+    }
+    let [Node::Text(text)] = e.children.as_slice() else {
+        return None;
+    };
+    let between = |open: &str, close: &str| text.strip_prefix(open)?.strip_suffix(close);
+    let (tex, delim) = match (between("\\(", "\\)"), between("\\[", "\\]")) {
+        (Some(tex), _) => (tex, "$"),
+        (None, Some(tex)) => (tex, "$$"),
+        (None, None) => return None,
+    };
+    if tex.trim().is_empty()
+        || tex.contains(['$', '`', '\n', '\r'])
+        || (literal_br && tex.contains('|'))
+    {
+        return None;
+    }
+    if delim == "$" && (tex.starts_with(char::is_whitespace) || tex.ends_with(char::is_whitespace))
+    {
+        return None;
+    }
+    Some(format!("{delim}{tex}{delim}"))
+}
+
 fn wrap(e: &Element, delim: &str, mode: Escaping, literal_br: bool) -> Option<String> {
     if !e.attrs.is_empty() {
         return None;
@@ -797,7 +832,7 @@ fn escape_markdown(s: &str, mode: Escaping) -> String {
         in_ws = false;
         let escape = match ch {
             '\\' => true,
-            '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~' => mode == Escaping::Full,
+            '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~' | '$' => mode == Escaping::Full,
             _ => false,
         };
         if escape {
@@ -885,7 +920,7 @@ mod tests {
 
     #[test]
     fn bare_leaves_ordinary_markdown_active_characters_alone() {
-        for ch in ['`', '*', '_', '[', ']', '<', '>', '|', '~'] {
+        for ch in ['`', '*', '_', '[', ']', '<', '>', '|', '~', '$'] {
             let s = format!("a{ch}b");
             assert_eq!(escape_markdown(&s, Escaping::Bare), s, "char {ch:?}");
         }
@@ -899,8 +934,8 @@ mod tests {
     #[test]
     fn full_escapes_every_markdown_active_character() {
         assert_eq!(
-            escape_markdown("`*_[]<>|~\\", Escaping::Full),
-            "\\`\\*\\_\\[\\]\\<\\>\\|\\~\\\\"
+            escape_markdown("`*_[]<>|~$\\", Escaping::Full),
+            "\\`\\*\\_\\[\\]\\<\\>\\|\\~\\$\\\\"
         );
     }
 
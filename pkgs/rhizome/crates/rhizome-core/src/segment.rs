@@ -1017,13 +1017,43 @@ mod tests {
     }
 
     #[test]
-    fn inline_math_spans_ride_along_as_html() {
+    fn inline_math_becomes_dollar_math() {
         let html = r#"<p>we have <span class="math-tex">\(62^{12}\)</span> unique IDs</p>"#;
         let block = segment(html).blocks().next().unwrap().clone();
         assert!(block.is_transparent(), "{:?}", block.kind);
+        assert_eq!(block.markdown().unwrap(), "we have $62^{12}$ unique IDs");
+    }
+
+    #[test]
+    fn display_math_becomes_double_dollar_math() {
+        let html = r#"<p><span class="math-tex">\[\frac{a}{b} &lt; \sqrt{c}\]</span></p>"#;
+        let block = segment(html).blocks().next().unwrap().clone();
+        assert!(block.is_transparent(), "{:?}", block.kind);
+        assert_eq!(block.markdown().unwrap(), r"$$\frac{a}{b} < \sqrt{c}$$");
+    }
+
+    /// TeX the dollar syntax cannot delimit keeps riding along as raw HTML
+    /// rather than costing the paragraph.
+    #[test]
+    fn undelimitable_math_still_rides_along_as_html() {
+        let html = r#"<p>cost <span class="math-tex">\( \$5 \)</span> each</p>"#;
+        let block = segment(html).blocks().next().unwrap().clone();
+        assert!(block.is_transparent(), "{:?}", block.kind);
         assert!(block.markdown().unwrap().contains("math-tex"));
     }
 
+    /// With `$…$` live on the way back, a pair of literal dollars in prose
+    /// would read as math; the proof catches it and `Full` escapes them.
+    #[test]
+    fn literal_dollars_in_prose_stay_literal() {
+        for html in ["<p>from $5 to $10</p>", "<p>a$b and c$d</p>"] {
+            let block = segment(html).blocks().next().unwrap().clone();
+            assert!(block.is_transparent(), "{html}: {:?}", block.kind);
+            let md = block.markdown().unwrap();
+            assert!(dom::equivalent(html, &markdown_to_html(md)), "{html}: {md}");
+        }
+    }
+
     /// The limit of the escape hatch: an unknown *block* element is not inline,
     /// so the block goes opaque rather than being silently mangled.
     #[test]